@@ -0,0 +1,88 @@
+//! Kernel micro-benchmarks; run with `cargo bench --bench kernel`.
+#![feature(test)]
+
+extern crate test;
+
+use test::{black_box, Bencher};
+
+use ypir::client::pack_query;
+use ypir::kernel::{fast_batched_dot_product_with_kernel, KernelKind, TransposedDb};
+use ypir::util::test_params;
+
+const B_ROWS: usize = 1 << 12;
+const B_COLS: usize = 256;
+
+fn random_db() -> Vec<u8> {
+    (0..B_ROWS * B_COLS).map(|_| fastrand::u8(..)).collect()
+}
+
+fn random_a() -> Vec<u64> {
+    (0..B_ROWS).map(|_| fastrand::u64(..)).collect()
+}
+
+/// The scalar kernel's accumulation loop, reading each element with `load`.
+#[inline(always)]
+fn accumulate(a: &[u64], b_t: &[u8], out: &mut [u64], load: impl Fn(&[u8], usize) -> u64) {
+    for (j, o) in out.iter_mut().enumerate() {
+        let (mut sum_lo, mut sum_hi) = (0u64, 0u64);
+        for (k, &a_val) in a.iter().enumerate() {
+            let b = load(b_t, j * B_ROWS + k);
+            sum_lo = sum_lo.wrapping_add((a_val & 0xFFFF_FFFF).wrapping_mul(b));
+            sum_hi = sum_hi.wrapping_add((a_val >> 32).wrapping_mul(b));
+        }
+        *o = sum_lo ^ sum_hi;
+    }
+}
+
+/// The load the scalar kernel used before u8 elements were widened
+/// directly: every element through `ToM512`.
+#[cfg(not(target_feature = "avx512f"))]
+#[bench]
+fn bench_u8_load_via_to_m512(b: &mut Bencher) {
+    use ypir::server::ToM512;
+
+    let (a, b_t) = (random_a(), random_db());
+    let mut out = vec![0u64; B_COLS];
+    b.iter(|| {
+        accumulate(&a, black_box(&b_t), &mut out, |b_t, i| unsafe {
+            b_t.as_ptr().add(i).to_m512()
+        });
+        black_box(&out);
+    });
+}
+
+/// The load the scalar kernel uses now: a plain widening read.
+#[bench]
+fn bench_u8_load_widened(b: &mut Bencher) {
+    let (a, b_t) = (random_a(), random_db());
+    let mut out = vec![0u64; B_COLS];
+    b.iter(|| {
+        accumulate(&a, black_box(&b_t), &mut out, |b_t, i| unsafe {
+            *b_t.as_ptr().add(i) as u64
+        });
+        black_box(&out);
+    });
+}
+
+#[bench]
+fn bench_scalar_kernel_u8(b: &mut Bencher) {
+    let params = test_params();
+    let a = (0..B_ROWS)
+        .map(|_| fastrand::u64(0..params.modulus))
+        .collect::<Vec<_>>();
+    let a_packed = pack_query(&params, &a);
+    let b_t = random_db();
+    let mut c = vec![0u64; B_COLS];
+    b.iter(|| {
+        c.fill(0);
+        fast_batched_dot_product_with_kernel::<1, u8>(
+            KernelKind::Scalar,
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            B_ROWS,
+            TransposedDb::new(black_box(&b_t), B_ROWS, B_COLS),
+        );
+        black_box(&c);
+    });
+}
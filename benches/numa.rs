@@ -0,0 +1,47 @@
+//! NUMA-split answers against the single-copy server; run with
+//! `cargo bench --bench numa`. The split only pays off on a host with
+//! several NUMA nodes; elsewhere it measures the per-node threading alone.
+#![feature(test)]
+
+extern crate test;
+
+use test::{black_box, Bencher};
+
+use spiral_rs::aligned_memory::AlignedMemory64;
+use spiral_rs::params::Params;
+use ypir::client::pack_query;
+use ypir::db::db_num_bytes;
+use ypir::numa::NumaServer;
+use ypir::server::{DbRowsPadded, YServer};
+use ypir::util::test_params;
+
+fn random_server(params: &Params) -> YServer<'_, u8> {
+    let db = (0..db_num_bytes(params, false))
+        .map(|_| fastrand::u8(..))
+        .collect::<Vec<_>>();
+    YServer::<u8>::new(params, db.iter().copied(), false, false, true)
+}
+
+fn random_packed_query(params: &Params) -> AlignedMemory64 {
+    let query = (0..params.db_rows_padded())
+        .map(|_| fastrand::u64(0..params.modulus))
+        .collect::<Vec<_>>();
+    pack_query(params, &query)
+}
+
+#[bench]
+fn bench_answer_single_copy(b: &mut Bencher) {
+    let params = test_params();
+    let server = random_server(&params);
+    let packed = random_packed_query(&params);
+    b.iter(|| black_box(server.answer_query(black_box(packed.as_slice()))));
+}
+
+#[bench]
+fn bench_answer_numa_split(b: &mut Bencher) {
+    let params = test_params();
+    let server = random_server(&params);
+    let numa = NumaServer::new(&params, &server);
+    let packed = random_packed_query(&params);
+    b.iter(|| black_box(numa.answer_query(black_box(packed.as_slice()))));
+}
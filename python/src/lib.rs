@@ -1,242 +1,3876 @@
-use pyo3::exceptions::PyValueError;
+// Everything here is PyO3 glue; without the `python` feature the crate is
+// empty and builds without a Python toolchain.
+#![cfg(feature = "python")]
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use pyo3::buffer::PyBuffer;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyDict;
 
 use spiral_rs::aligned_memory::AlignedMemory64;
 use spiral_rs::client::Client as SpiralClient;
 use spiral_rs::params::Params as SpiralParams;
 
-use ypir::client::{pack_query, YClient};
-use ypir::params::{params_for_scenario, params_for_scenario_simplepir};
-use ypir::server::YServer;
+use ypir::bits::{bytes_to_u64s, u64s_to_bytes, Endianness};
+use ypir::cache::ResponseCache;
+use ypir::client::{
+    decode_threshold, export_secret_key, pack_query, public_material, secret_key_len, ClientCost,
+    DeadlineExceeded, PublicSeeds, YClient, DEFAULT_MAX_NOISE_RATIO,
+};
+use ypir::db::{
+    build_db_permuted, db_capacity, db_dims, db_num_bytes, db_subrange, dequantize,
+    logical_to_physical, permute_index, physical_to_logical, quantize_items,
+    row_tenants as ypir_row_tenants, span_row_ranges, transpose_db as ypir_transpose_db,
+    transpose_db_stream, write_db_item, BuildDbError, ItemVersions, VarlenManifest, WrittenItems,
+};
+use ypir::kernel::{
+    active_kernel as ypir_active_kernel, dot_product_checked, fast_batched_dot_product_repacked,
+    repack_db_u8_to_u32 as ypir_repack_db_u8_to_u32, set_kernel as ypir_set_kernel,
+    verify_kernel as ypir_verify_kernel, KernelCost, KernelKind,
+};
+use ypir::measurement::{pack_pub_params_size_bytes, peak_resident_growth};
+use ypir::numa::NumaServer;
+use ypir::params::{
+    crt_moduli, params_diff, params_fingerprint_with_seeds, params_for_scenario,
+    params_for_scenario_simplepir, params_with_crt_limbs, validate_params, MAX_QUERY_LIMBS,
+};
+use ypir::pool::{RoundRobinPool, WorkerPool};
+use ypir::reference::reference_fetch as ypir_reference_fetch;
+use ypir::server::{
+    answer_by_instance, db_layout, expansion_ratio, instance_db_bytes, pack_response,
+    packed_response_size_bytes, preview_columns, response_size_bytes, restrict_query_to_tenant,
+    unpack_response, CancelToken, DbRowsPadded, MultiTenantServer, QueryError, ServerMemory,
+    YServer, YServerBuilder, DB_ALIGNMENT,
+};
+use ypir::shard::{
+    combine_answers as ypir_combine_answers, shard_for_index as ypir_shard_for_index, split_query,
+    ShardError, ShardServer,
+};
+use ypir::stream::{
+    answer_file, answer_stream, answer_with_provider as ypir_answer_with_provider, fragment,
+    length_prefixed, query_digest as ypir_query_digest, reassemble, strip_length_prefix,
+    tag_response as ypir_tag_response, update_item_in_file as ypir_update_item_in_file,
+    verify_response_for_query as ypir_verify_response_for_query, Transcript,
+};
+use ypir::testing::{
+    benchmark_roundtrip as ypir_benchmark_roundtrip, expected_item as ypir_expected_item,
+    fixture_db, fixture_item as ypir_fixture_item,
+};
+
+create_exception!(ypir_rs, YpirError, PyException, "Base class for ypir_rs errors.");
+create_exception!(
+    ypir_rs,
+    YpirSizeError,
+    YpirError,
+    "Input does not fit the database (too many or too large items)."
+);
+create_exception!(
+    ypir_rs,
+    YpirVersionError,
+    YpirError,
+    "A compare-and-swap update found the item at a different version."
+);
+create_exception!(ypir_rs, YpirParamsError, YpirError, "Params are internally inconsistent.");
+
+// ---------- helpers: bytes <-> u64 words ----------
+
+unsafe fn shrink_client_lifetime<'a>(
+    c: &'a mut SpiralClient<'static>,
+) -> &'a mut SpiralClient<'a> {
+    std::mem::transmute::<&'a mut SpiralClient<'static>, &'a mut SpiralClient<'a>>(c)
+}
+
+unsafe fn shrink_params_lifetime<'a>(
+    p: &'static SpiralParams,
+) -> &'a SpiralParams {
+    std::mem::transmute::<&'static SpiralParams, &'a SpiralParams>(p)
+}
+
+fn parse_endianness(s: &str) -> PyResult<Endianness> {
+    Endianness::parse(s).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "unsupported endianness {:?} (expected \"little\" or \"big\")",
+            s
+        ))
+    })
+}
+
+fn bytes_to_u64(b: &[u8], endianness: Endianness) -> PyResult<Vec<u64>> {
+    bytes_to_u64s(b, endianness)
+        .ok_or_else(|| PyValueError::new_err("byte length must be multiple of 8"))
+}
+
+fn u64_to_bytes(words: &[u64], endianness: Endianness) -> Vec<u8> {
+    u64s_to_bytes(words, endianness)
+}
+
+fn aligned64_to_bytes(mem: &AlignedMemory64, endianness: Endianness) -> Vec<u8> {
+    u64_to_bytes(mem.as_slice(), endianness)
+}
+
+/// Decoded plaintext words as bytes, always little-endian: `endianness`
+/// arguments set the byte order of queries and responses on the wire, not
+/// of what a decode returns.
+fn decoded_to_bytes(words: &[u64]) -> Vec<u8> {
+    u64_to_bytes(words, Endianness::Little)
+}
+
+fn client_query_words(
+    params: &'static SpiralParams,
+    client: &mut SpiralClient<'static>,
+    seeds: &PublicSeeds,
+    public_seed_idx: u8,
+    dim_log2: usize,
+    packing: bool,
+    index_row: usize,
+    pack: bool,
+) -> Vec<u64> {
+    let q_words: Vec<u64> = unsafe {
+        let inner = shrink_client_lifetime(client);
+        let params = shrink_params_lifetime(params);
+        let y = YClient::new(inner, params).with_public_seeds(seeds.clone());
+        y.generate_query(public_seed_idx, dim_log2, packing, index_row)
+    };
+
+    if pack {
+        pack_query(params, &q_words).as_slice().to_vec()
+    } else {
+        q_words
+    }
+}
+
+fn client_query_bytes(
+    params: &'static SpiralParams,
+    client: &mut SpiralClient<'static>,
+    seeds: &PublicSeeds,
+    public_seed_idx: u8,
+    dim_log2: usize,
+    packing: bool,
+    index_row: usize,
+    pack: bool,
+    endianness: Endianness,
+) -> Vec<u8> {
+    let q_words = client_query_words(
+        params,
+        client,
+        seeds,
+        public_seed_idx,
+        dim_log2,
+        packing,
+        index_row,
+        pack,
+    );
+    u64_to_bytes(&q_words, endianness)
+}
+
+fn client_extract_words(
+    params: &'static SpiralParams,
+    client: &mut SpiralClient<'static>,
+    resp_words: &[u64],
+) -> (Vec<u64>, f64) {
+    unsafe {
+        let inner = shrink_client_lifetime(client);
+        let params = shrink_params_lifetime(params);
+        let y = YClient::new(inner, params);
+        y.decode_response_with_noise(resp_words)
+    }
+}
+
+fn client_extract_words_by(
+    params: &'static SpiralParams,
+    client: &mut SpiralClient<'static>,
+    resp_words: &[u64],
+    deadline: Option<Instant>,
+    fast: bool,
+) -> Result<Vec<u64>, DeadlineExceeded> {
+    unsafe {
+        let inner = shrink_client_lifetime(client);
+        let params = shrink_params_lifetime(params);
+        let y = YClient::new(inner, params);
+        match (deadline, fast) {
+            (None, false) => Ok(y.decode_response(resp_words)),
+            (None, true) => Ok(y.decode_response_fast(resp_words)),
+            (Some(deadline), false) => y.decode_response_by(resp_words, deadline),
+            (Some(deadline), true) => y.decode_response_fast_by(resp_words, deadline),
+        }
+    }
+}
+
+fn unpack_response_words(
+    params: &SpiralParams,
+    is_simplepir: bool,
+    packed: &[u8],
+) -> PyResult<Vec<u64>> {
+    unpack_response(params, is_simplepir, packed).ok_or_else(|| {
+        YpirSizeError::new_err(format!(
+            "packed response is {} bytes, expected {}",
+            packed.len(),
+            packed_response_size_bytes(params, is_simplepir)
+        ))
+    })
+}
+
+/// Leads a query made with `query(..., response_packing=...)`, plus 1 if the
+/// response should be bit-packed. The high limb is above every CRT modulus,
+/// so the header can't be mistaken for a packed query word.
+const RESPONSE_MODE_TAG: u64 = 0x5950_4952_0000_5250;
+
+fn with_response_mode(packed_words: &[u64], response_packing: bool) -> Vec<u64> {
+    let mut out = Vec::with_capacity(packed_words.len() + 1);
+    out.push(RESPONSE_MODE_TAG | response_packing as u64);
+    out.extend_from_slice(packed_words);
+    out
+}
+
+/// The response mode in the header of `packed_query_bytes`, if it has one,
+/// and the query without it. The header is only recognized in front of a
+/// query of the expected `query_words` length.
+fn split_response_mode(
+    packed_query_bytes: &[u8],
+    query_words: usize,
+    endianness: Endianness,
+) -> PyResult<(Option<bool>, &[u8])> {
+    const WORD: usize = std::mem::size_of::<u64>();
+    if packed_query_bytes.len() != (query_words + 1) * WORD {
+        return Ok((None, packed_query_bytes));
+    }
+    let (header, rest) = packed_query_bytes.split_at(WORD);
+    let header = bytes_to_u64(header, endianness)?[0];
+    if header & !1 != RESPONSE_MODE_TAG {
+        return Ok((None, packed_query_bytes));
+    }
+    Ok((Some(header & 1 == 1), rest))
+}
+
+/// Decoded coefficients as item bytes, one byte per coefficient.
+fn coeffs_to_item_bytes(params: &SpiralParams, coeffs: &[u64]) -> PyResult<Vec<u8>> {
+    if params.pt_modulus > 256 {
+        return Err(YpirError::new_err(format!(
+            "pt_modulus {} does not fit one byte per coefficient",
+            params.pt_modulus
+        )));
+    }
+    Ok(coeffs.iter().map(|&c| c as u8).collect())
+}
+
+// ---------- Python-exposed wrapper types ----------
+// IMPORTANT: mark unsendable so PyO3 does NOT require Send/Sync.
+
+#[pyclass(unsendable)]
+#[derive(Clone)]
+struct PyYpirParams {
+    params: &'static SpiralParams,
+    is_simplepir: bool,
+    item_size_bits: usize,
+    num_items: usize,
+    public_seeds: PublicSeeds,
+}
+
+#[pymethods]
+impl PyYpirParams {
+    fn __repr__(&self) -> String {
+        format!(
+            "PyYpirParams(simplepir={}, num_items={}, item_size_bits={})",
+            self.is_simplepir, self.num_items, self.item_size_bits
+        )
+    }
+
+    /// Number of items the params were requested for.
+    #[getter]
+    fn num_items(&self) -> usize {
+        self.num_items
+    }
+
+    /// The fields on which these params and `other` differ, as a dict of
+    /// field name to `(self_value, other_value)` strings: the mode, every
+    /// fingerprinted spiral field (dims, instances, moduli, ...), the item
+    /// size, the public seed table and `num_items`. Empty when they match.
+    fn diff<'py>(&self, py: Python<'py>, other: &PyYpirParams) -> PyResult<Bound<'py, PyDict>> {
+        let out = PyDict::new(py);
+        for d in params_diff(
+            self.params,
+            self.is_simplepir,
+            self.item_size_bits,
+            other.params,
+            other.is_simplepir,
+            other.item_size_bits,
+        ) {
+            out.set_item(d.field, (d.left, d.right))?;
+        }
+        if self.public_seeds != other.public_seeds {
+            // seed index and the first bytes of each replaced seed
+            let seeds = |p: &PyYpirParams| {
+                let overrides = p.public_seeds.overrides().iter().map(|(idx, seed)| {
+                    let prefix = u32::from_be_bytes(seed[..4].try_into().unwrap());
+                    format!("{}:{:08x}", idx, prefix)
+                });
+                format!("[{}]", overrides.collect::<Vec<_>>().join(", "))
+            };
+            out.set_item("public_seeds", (seeds(self), seeds(other)))?;
+        }
+        // requested, not fingerprinted: the same params can serve both counts
+        if self.num_items != other.num_items {
+            let counts = (self.num_items.to_string(), other.num_items.to_string());
+            out.set_item("num_items", counts)?;
+        }
+        Ok(out)
+    }
+
+    /// Check the params are internally consistent for their mode (moduli,
+    /// dimensions, instances); raises `YpirParamsError` naming the first
+    /// problem found. `params_for` output always passes.
+    fn validate(&self) -> PyResult<()> {
+        validate_params(self.params, self.is_simplepir)
+            .map_err(|e| YpirParamsError::new_err(e.to_string()))
+    }
+
+    /// Number of items the database can actually hold (at least `num_items`).
+    fn capacity(&self) -> usize {
+        db_capacity(self.params, self.is_simplepir, self.item_size_bytes())
+    }
+
+    /// Database row holding item `index`; padding rows never shift items.
+    fn logical_to_physical(&self, index: usize) -> PyResult<usize> {
+        logical_to_physical(self.params, self.is_simplepir, self.item_size_bytes(), index)
+            .ok_or_else(|| {
+                YpirSizeError::new_err(format!(
+                    "item index {} out of range for a database of {} items",
+                    index,
+                    self.capacity()
+                ))
+            })
+    }
+
+    /// Slot of item `logical` in a database built with
+    /// `build_db(..., permutation_seed=seed)`.
+    fn permute_index(&self, seed: u64, logical: usize) -> PyResult<usize> {
+        permute_index(self.params, self.is_simplepir, self.item_size_bytes(), seed, logical)
+            .ok_or_else(|| {
+                YpirSizeError::new_err(format!(
+                    "item index {} out of range for a database of {} items",
+                    logical,
+                    self.capacity()
+                ))
+            })
+    }
+
+    /// First item starting in database row `row`; raises for padding rows
+    /// and rows no item starts in.
+    fn physical_to_logical(&self, row: usize) -> PyResult<usize> {
+        physical_to_logical(self.params, self.is_simplepir, self.item_size_bytes(), row)
+            .ok_or_else(|| YpirSizeError::new_err(format!("no item starts in row {}", row)))
+    }
+
+    /// The ciphertext modulus q (the product of `crt_moduli()`).
+    fn modulus(&self) -> u64 {
+        self.params.modulus
+    }
+
+    /// The plaintext modulus p.
+    fn plaintext_modulus(&self) -> u64 {
+        self.params.pt_modulus
+    }
+
+    /// Smallest decrypted value (mod q) that decodes to 1 rather than 0,
+    /// `ceil(ceil(q / 2) / p)`; values decode to `round(value * p / q) % p`.
+    fn decode_threshold(&self) -> u64 {
+        decode_threshold(self.params.modulus, self.params.pt_modulus)
+    }
+
+    /// Bytes of public packing material a client uploads alongside its
+    /// queries (counted at `modulus` bits per coefficient, as in the
+    /// measurements).
+    fn public_material_len(&self) -> usize {
+        pack_pub_params_size_bytes(self.params)
+    }
+
+    /// LWE ciphertexts YPIR's ring-packing step folds into one RLWE
+    /// ciphertext: the ring dimension, `poly_len`.
+    fn packing_factor(&self) -> usize {
+        self.params.poly_len
+    }
+
+    /// Bytes of one `answer()` response.
+    fn response_size_bytes(&self) -> usize {
+        response_size_bytes(self.params, self.is_simplepir)
+    }
+
+    /// Bytes of a response from `answer(..., response_packing=True)`.
+    fn packed_response_size_bytes(&self) -> usize {
+        packed_response_size_bytes(self.params, self.is_simplepir)
+    }
+
+    /// `response_size_bytes() / item_size_bytes`: response bytes downloaded
+    /// per item byte, from the geometry alone. `packing` in `query` doesn't
+    /// change it, since `answer` returns the unpacked response either way.
+    fn expansion_ratio(&self) -> f64 {
+        expansion_ratio(self.params, self.is_simplepir, self.item_size_bytes())
+    }
+
+    /// The CRT factors of `modulus()`.
+    fn crt_moduli(&self) -> Vec<u64> {
+        crt_moduli(self.params).to_vec()
+    }
+
+    /// Theoretical cost of one `answer()` (or of `batch` batched queries):
+    /// a dict with `multiplies`, `adds`, `db_bytes_read` and `reductions`.
+    #[pyo3(signature = (batch=1))]
+    fn answer_cost<'py>(&self, py: Python<'py>, batch: usize) -> PyResult<Bound<'py, PyDict>> {
+        let cost = KernelCost::for_params(self.params, self.is_simplepir, batch, 1);
+        let out = PyDict::new(py);
+        out.set_item("multiplies", cost.multiplies)?;
+        out.set_item("adds", cost.adds)?;
+        out.set_item("db_bytes_read", cost.db_bytes_read)?;
+        out.set_item("reductions", cost.reductions)?;
+        Ok(out)
+    }
+
+    /// Estimated client cost of one `query()` and one `extract()`: a dict with
+    /// `query_ntts`, `query_multiplies`, `query_samples`, `decode_multiplies`
+    /// (full-format responses only) and `decode_rescales`. `packing=False`
+    /// counts the seed-0 LWE query, which needs no NTTs.
+    #[pyo3(signature = (packing=true))]
+    fn client_cost<'py>(&self, py: Python<'py>, packing: bool) -> PyResult<Bound<'py, PyDict>> {
+        let cost = ClientCost::new(self.params, packing);
+        let out = PyDict::new(py);
+        out.set_item("query_ntts", cost.query_ntts)?;
+        out.set_item("query_multiplies", cost.query_multiplies)?;
+        out.set_item("query_samples", cost.query_samples)?;
+        out.set_item("decode_multiplies", cost.decode_multiplies)?;
+        out.set_item("decode_rescales", cost.decode_rescales)?;
+        Ok(out)
+    }
+
+    /// Stable hash of the params, mode and item size; equal on both sides of a
+    /// handshake iff client and server were built from identical params.
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint_bytes().to_vec()
+    }
+
+    /// A copy of these params with the public seed `idx` replaced by the 32
+    /// `seed_bytes`, for rotating a compromised seed. Clients and servers
+    /// built from the result use the new seed; the fingerprint changes so
+    /// parties on different seeds are told apart.
+    fn with_public_seed(&self, idx: u8, seed_bytes: &[u8]) -> PyResult<PyYpirParams> {
+        let seed: [u8; 32] = seed_bytes.try_into().map_err(|_| {
+            PyValueError::new_err(format!(
+                "seed must be 32 bytes, got {}",
+                seed_bytes.len()
+            ))
+        })?;
+        let mut out = self.clone();
+        out.public_seeds = out.public_seeds.with_seed(idx, seed);
+        Ok(out)
+    }
+
+    /// Strides (in bytes) and alignment of the transposed database layout that
+    /// `server_new(..., inp_transposed=True, ...)` expects for `element_type`
+    /// ("u8", "u16" or "u32").
+    #[pyo3(signature = (element_type, pad_rows=true))]
+    fn layout_info<'py>(
+        &self,
+        py: Python<'py>,
+        element_type: &str,
+        pad_rows: bool,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let element_bytes = element_type_bytes(element_type)?;
+        let layout = db_layout(self.params, self.is_simplepir, pad_rows, element_bytes);
+
+        let out = PyDict::new(py);
+        out.set_item("row_stride", layout.row_stride)?;
+        out.set_item("col_stride", layout.col_stride)?;
+        out.set_item("alignment", layout.alignment)?;
+        out.set_item("element_bytes", layout.element_bytes)?;
+        out.set_item("db_rows", layout.db_rows_padded)?;
+        out.set_item("db_cols", layout.db_cols)?;
+        Ok(out)
+    }
+
+    /// Estimated peak memory, in bytes, of `server_new` with `element_type`
+    /// elements plus `num_threads` concurrent answers: the server's database,
+    /// the input copy held while building, and per-answer buffers.
+    #[pyo3(signature = (element_type="u8", num_threads=1, pad_rows=true))]
+    fn server_memory_bytes(
+        &self,
+        element_type: &str,
+        num_threads: usize,
+        pad_rows: bool,
+    ) -> PyResult<usize> {
+        let element_bytes = element_type_bytes(element_type)?;
+        let memory = ServerMemory::new(
+            self.params,
+            self.is_simplepir,
+            pad_rows,
+            element_bytes,
+            num_threads,
+        );
+        Ok(memory.total())
+    }
+}
+
+fn element_type_bytes(element_type: &str) -> PyResult<usize> {
+    match element_type {
+        "u8" => Ok(1),
+        "u16" => Ok(2),
+        "u32" => Ok(4),
+        _ => Err(PyValueError::new_err(format!(
+            "unsupported element_type {:?} (expected \"u8\", \"u16\" or \"u32\")",
+            element_type
+        ))),
+    }
+}
+
+impl PyYpirParams {
+    fn fingerprint_bytes(&self) -> [u8; 32] {
+        params_fingerprint_with_seeds(
+            self.params,
+            self.is_simplepir,
+            self.item_size_bits,
+            &self.public_seeds,
+        )
+    }
+
+    fn item_size_bytes(&self) -> usize {
+        self.item_size_bits / 8
+    }
+}
+
+#[pyclass(unsendable)]
+struct PyYpirClient {
+    params: &'static SpiralParams,
+    inner: SpiralClient<'static>,
+    fingerprint: [u8; 32],
+    is_simplepir: bool,
+    item_size: usize,
+    seeds: PublicSeeds,
+    // the seeds of the params the client was built with, for reset()
+    params_seeds: PublicSeeds,
+    // set once secret keys exist; querying or decoding without them would
+    // silently produce garbage
+    keys_ready: bool,
+}
+
+#[pymethods]
+impl PyYpirClient {
+    /// Fingerprint of the params this client was built with.
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint.to_vec()
+    }
+
+    /// The client's secret key, for session storage; keep it private.
+    fn export_keys(&self) -> PyResult<Vec<u8>> {
+        self.check_keys()?;
+        Ok(export_secret_key(&self.inner))
+    }
+
+    /// The public packing material for the current keys,
+    /// `params.public_material_len()` bytes.
+    ///
+    /// Derived from the secret keys alone, so every call returns the same
+    /// bytes and the material can be re-sent to a server that lost it.
+    fn public_material(&self) -> PyResult<Vec<u8>> {
+        self.check_keys()?;
+        Ok(public_material(self.params, &self.inner))
+    }
+
+    /// Whether `packed_query_bytes`, from `query(client, 0,
+    /// params_db_dim_1(params), True, index_row, True)`, decrypts to a
+    /// selection vector that is 1 at row `expected_index` and 0 elsewhere
+    /// (within noise). A debug check of the query generator; costs about one
+    /// query generation.
+    #[cfg(feature = "debug")]
+    #[pyo3(signature = (packed_query_bytes, expected_index, endianness="little"))]
+    fn debug_decrypt_query(
+        &mut self,
+        packed_query_bytes: Vec<u8>,
+        expected_index: usize,
+        endianness: &str,
+    ) -> PyResult<bool> {
+        self.check_keys()?;
+        let words = bytes_to_u64(&packed_query_bytes, parse_endianness(endianness)?)?;
+        let ok = unsafe {
+            let inner = shrink_client_lifetime(&mut self.inner);
+            let params = shrink_params_lifetime(self.params);
+            let y = YClient::new(inner, params).with_public_seeds(self.seeds.clone());
+            y.debug_decrypt_query(&words, expected_index)
+        };
+        Ok(ok)
+    }
+
+    /// Length of `export_keys()`, without exporting anything.
+    fn key_bytes_len(&self) -> usize {
+        secret_key_len(self.params)
+    }
+
+    /// Discard the secret keys; later queries, decodes and `export_keys()`
+    /// raise `YpirError`.
+    ///
+    /// Built with the `zeroize` feature, the key buffer is also overwritten
+    /// with zeros, here and when the client is dropped. That only covers
+    /// memory this module controls: spiral's derived key forms, keys already
+    /// exported to Python and copies the allocator or OS made are untouched.
+    fn close(&mut self) {
+        #[cfg(feature = "zeroize")]
+        self.scrub_keys();
+        self.keys_ready = false;
+    }
+
+    /// Return the client to the state `client_new` left it in, keeping its
+    /// secret keys, so later queries and extracts start from a clean slate.
+    /// Raises `YpirError` if the keys were discarded by `close()`.
+    ///
+    /// The public seeds revert to those of the params the client was built
+    /// with. The client caches no `QueryContext`, hint or other query state
+    /// between calls, so that is all there is to clear; any such cache added
+    /// later must be dropped here too.
+    fn reset(&mut self) -> PyResult<()> {
+        self.check_keys()?;
+        self.seeds = self.params_seeds.clone();
+        Ok(())
+    }
+
+    /// Iterate over every item of `server`, in index order, by fetching each
+    /// one with a private query (`local_fetch`). Trailing zero padding is
+    /// stripped unless `trim=False`, as for `server.get_item`.
+    ///
+    /// This is for full dumps of small databases: the server sees a query
+    /// for every index, so which items the client wanted is no longer
+    /// hidden, and it costs one full query and answer per row of every item.
+    /// The key and params fingerprint checks are done once, here; the
+    /// client is borrowed for each item, so it stays usable between items.
+    #[pyo3(signature = (server, public_seed_idx=0, packing=true, trim=true))]
+    fn scan(
+        slf: Bound<'_, Self>,
+        server: Bound<'_, PyYpirServer>,
+        public_seed_idx: u8,
+        packing: bool,
+        trim: bool,
+    ) -> PyResult<PyScan> {
+        let end = {
+            let client = slf.borrow();
+            client.check_keys()?;
+            let server = server.borrow();
+            if client.fingerprint != server.fingerprint {
+                return Err(PyValueError::new_err(
+                    "params fingerprint mismatch: client and server use different params",
+                ));
+            }
+            db_capacity(server.params, server.is_simplepir, server.item_size)
+        };
+        Ok(PyScan {
+            client: slf.unbind(),
+            server: server.unbind(),
+            next: 0,
+            end,
+            public_seed_idx,
+            packing,
+            trim,
+        })
+    }
+}
+
+impl PyYpirClient {
+    #[cfg(feature = "zeroize")]
+    fn scrub_keys(&mut self) {
+        ypir::client::scrub_secret_key(&mut self.inner);
+    }
+
+    fn check_keys(&self) -> PyResult<()> {
+        if !self.keys_ready {
+            return Err(YpirError::new_err(
+                "client has no secret keys; generate or import keys first",
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for PyYpirClient {
+    fn drop(&mut self) {
+        if self.keys_ready {
+            self.scrub_keys();
+        }
+    }
+}
+
+struct PooledClient(SpiralClient<'static>);
+
+// SAFETY: a pooled client is only ever reached through its slot's Mutex, so
+// it is never used from two threads at once; its params are leaked and
+// never mutated.
+unsafe impl Send for PooledClient {}
+
+/// Several independently keyed clients that can be used from multiple
+/// threads at once. Unlike `PyYpirClient`, this class is sendable.
+///
+/// `query` hands out clients round-robin and returns the slot it used; the
+/// response to that query can only be decoded by the same client, so it
+/// must be passed back to `extract` with that slot.
+#[pyclass(name = "ClientPool")]
+struct PyClientPool {
+    params: &'static SpiralParams,
+    clients: RoundRobinPool<PooledClient>,
+    fingerprint: [u8; 32],
+    seeds: PublicSeeds,
+}
+
+#[pymethods]
+impl PyClientPool {
+    #[new]
+    fn new(params: &PyYpirParams, size: usize) -> PyResult<Self> {
+        if size == 0 {
+            return Err(PyValueError::new_err("pool size must be at least 1"));
+        }
+        let clients = (0..size)
+            .map(|_| {
+                let mut c = SpiralClient::init(params.params);
+                c.generate_secret_keys();
+                PooledClient(c)
+            })
+            .collect();
+        Ok(Self {
+            params: params.params,
+            clients: RoundRobinPool::new(clients),
+            fingerprint: params.fingerprint_bytes(),
+            seeds: params.public_seeds.clone(),
+        })
+    }
+
+    fn __len__(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint.to_vec()
+    }
+
+    /// Generate a query on the next client; returns `(slot, query_bytes)`.
+    /// Releases the GIL while the query is generated.
+    #[pyo3(signature = (public_seed_idx, dim_log2, packing, index_row, pack, endianness="little"))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        public_seed_idx: u8,
+        dim_log2: usize,
+        packing: bool,
+        index_row: usize,
+        pack: bool,
+        endianness: &str,
+    ) -> PyResult<(usize, Vec<u8>)> {
+        let endianness = parse_endianness(endianness)?;
+        Ok(py.detach(|| {
+            let (slot, mut client) = self.clients.acquire();
+            let q = client_query_bytes(
+                self.params,
+                &mut client.0,
+                &self.seeds,
+                public_seed_idx,
+                dim_log2,
+                packing,
+                index_row,
+                pack,
+                endianness,
+            );
+            (slot, q)
+        }))
+    }
+
+    /// Decode a response with the client in `slot`, which must be the slot
+    /// returned by the `query` that produced it.
+    #[pyo3(signature = (slot, response_bytes, endianness="little"))]
+    fn extract(
+        &self,
+        py: Python<'_>,
+        slot: usize,
+        response_bytes: Vec<u8>,
+        endianness: &str,
+    ) -> PyResult<Vec<u8>> {
+        let endianness = parse_endianness(endianness)?;
+        if slot >= self.clients.len() {
+            return Err(PyValueError::new_err(format!(
+                "slot {} out of range for pool of {}",
+                slot,
+                self.clients.len()
+            )));
+        }
+        let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+        let out = py.detach(|| {
+            let mut client = self.clients.get(slot);
+            client_extract_words(self.params, &mut client.0, &resp_words).0
+        });
+        Ok(decoded_to_bytes(&out))
+    }
+}
+
+/// A server that can be read from other threads (see `answer_async`).
+///
+/// The second field keeps alive the Python buffer a server built in place
+/// (see `server_new`) reads its database from.
+#[derive(Clone)]
+struct SharedServer(YServer<'static, u8>, Option<Arc<PyBuffer<u8>>>);
+
+// SAFETY: YServer has no interior mutability and answering only reads it;
+// its params are leaked and never mutated.
+unsafe impl Send for SharedServer {}
+unsafe impl Sync for SharedServer {}
+
+impl Deref for SharedServer {
+    type Target = YServer<'static, u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Cancels an `answer(..., cancel=token)` in progress when `cancel()` is
+/// called, e.g. from the thread that noticed the client disconnect.
+#[pyclass(name = "CancelToken")]
+struct PyCancelToken {
+    inner: CancelToken,
+}
+
+#[pymethods]
+impl PyCancelToken {
+    #[new]
+    fn new() -> Self {
+        Self {
+            inner: CancelToken::new(),
+        }
+    }
+
+    fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+/// What `server.status()` reports.
+#[pyclass(name = "ServerStatus")]
+struct PyServerStatus {
+    num_items: usize,
+    item_size_bytes: usize,
+    db_bytes: usize,
+    active_kernel: &'static str,
+    num_threads: usize,
+    is_simplepir: bool,
+    db_copies: usize,
+}
+
+#[pymethods]
+impl PyServerStatus {
+    /// Items the server's params were requested for (`params.num_items`).
+    #[getter]
+    fn num_items(&self) -> usize {
+        self.num_items
+    }
+
+    #[getter]
+    fn item_size_bytes(&self) -> usize {
+        self.item_size_bytes
+    }
+
+    /// Bytes of the (transposed, padded) database the server holds.
+    #[getter]
+    fn db_bytes(&self) -> usize {
+        self.db_bytes
+    }
+
+    /// The kernel answering queries, as `active_kernel()` names it.
+    #[getter]
+    fn active_kernel(&self) -> &'static str {
+        self.active_kernel
+    }
+
+    /// Threads one `answer()` runs on: one per NUMA node for a server built
+    /// with `numa=True`, else 1. Beyond that, concurrency comes from
+    /// answering on several threads at once (e.g. `answer_async`).
+    #[getter]
+    fn num_threads(&self) -> usize {
+        self.num_threads
+    }
+
+    #[getter]
+    fn is_simplepir(&self) -> bool {
+        self.is_simplepir
+    }
+
+    /// Whole-database copies writes have made so far because the buffer was
+    /// shared (see `update_item`); each cost about `db_bytes` bytes.
+    #[getter]
+    fn db_copies(&self) -> usize {
+        self.db_copies
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "ServerStatus(num_items={}, item_size_bytes={}, db_bytes={}, active_kernel={:?}, \
+             num_threads={}, is_simplepir={}, db_copies={})",
+            self.num_items,
+            self.item_size_bytes,
+            self.db_bytes,
+            self.active_kernel,
+            self.num_threads(),
+            self.is_simplepir,
+            self.db_copies
+        )
+    }
+}
+
+#[pyclass(unsendable)]
+struct PyYpirServer {
+    params: &'static SpiralParams,
+    inner: Arc<SharedServer>,
+    cache: ResponseCache,
+    fingerprint: [u8; 32],
+    is_simplepir: bool,
+    item_size: usize,
+    num_items: usize,
+    versions: ItemVersions,
+    written: WrittenItems,
+    // tenant of each database row; once set, every answer needs a token
+    row_tenants: Option<Vec<Option<u32>>>,
+    // the next database version, being built by `stage`
+    staged: Option<StagedBuild>,
+    // per-node copies of the database (`server_new(..., numa=True)`),
+    // rebuilt after every write
+    numa: Option<NumaServer<'static>>,
+    // the database buffer is mlocked; kept up across copy-on-write updates
+    locked: bool,
+    // copy-on-write copies of the database made by writes
+    db_copies: usize,
+}
+
+#[pymethods]
+impl PyYpirServer {
+    /// Fingerprint of the params this server was built with.
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint.to_vec()
+    }
+
+    /// Server configuration in one call, for health checks and dashboards.
+    fn status(&self) -> PyServerStatus {
+        PyServerStatus {
+            num_items: self.num_items,
+            item_size_bytes: self.item_size,
+            db_bytes: self.inner.db().len(),
+            active_kernel: ypir_active_kernel().name(),
+            num_threads: self.numa.as_ref().map_or(1, |numa| numa.num_nodes()),
+            is_simplepir: self.is_simplepir,
+            db_copies: self.db_copies,
+        }
+    }
+
+    /// Number of `answer()` calls served from the response cache.
+    fn cache_hits(&self) -> usize {
+        self.cache.hits()
+    }
+
+    fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Pin the database in physical memory (`mlock`/`VirtualLock`) so it is
+    /// never paged out. Raises `YpirError` if the OS refuses, typically
+    /// because the database exceeds `RLIMIT_MEMLOCK` (`ulimit -l`).
+    fn lock_memory(&mut self) -> PyResult<()> {
+        self.lock()
+    }
+
+    /// Whether the database is currently pinned by `lock_memory`.
+    fn memory_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Touch the whole database once so the first `answer()` doesn't pay for
+    /// cold pages; returns the number of bytes touched. Intended for
+    /// readiness probes.
+    fn warmup(&self) -> usize {
+        self.inner.warmup()
+    }
+
+    /// Run the validation `answer()` does (byte length, fingerprint, query
+    /// size, tenant token) and raise the same error it would, without
+    /// computing anything.
+    #[pyo3(signature = (packed_query_bytes, fingerprint=None, endianness="little", token=None))]
+    fn check_query(
+        &self,
+        packed_query_bytes: Vec<u8>,
+        fingerprint: Option<Vec<u8>>,
+        endianness: &str,
+        token: Option<u32>,
+    ) -> PyResult<()> {
+        let endianness = parse_endianness(endianness)?;
+        let fingerprint = fingerprint.as_deref();
+        self.checked_query_words(&packed_query_bytes, fingerprint, endianness, token)?;
+        Ok(())
+    }
+
+    /// Tag each database row with the tenant allowed to read it (as from
+    /// `row_tenants`), or `None` to drop the tags.
+    ///
+    /// While tags are set, `answer` and `check_query` need a `token`, the
+    /// tenant the caller has been authenticated as, and answer over that
+    /// tenant's rows only: any other row decodes to zeros. Answer paths that
+    /// take no token raise `YpirError`. Clears the response cache.
+    fn set_row_tenants(&mut self, row_tenants: Option<Vec<Option<u32>>>) -> PyResult<()> {
+        if let Some(tags) = &row_tenants {
+            let (db_rows, _) = db_dims(self.params, self.is_simplepir);
+            if tags.len() != db_rows {
+                return Err(YpirSizeError::new_err(format!(
+                    "{} row tenant tags, expected {}",
+                    tags.len(),
+                    db_rows
+                )));
+            }
+        }
+        self.row_tenants = row_tenants;
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Read item `index` straight from the database (a plaintext operator
+    /// read, not a PIR query). Trailing zero padding is stripped unless
+    /// `trim=False`, so items that really end in zero bytes need the latter.
+    #[pyo3(signature = (index, trim=true))]
+    fn get_item(&self, index: usize, trim: bool) -> PyResult<Vec<u8>> {
+        self.check_index(index)?;
+        let mut item = self.inner.get_item(index, self.item_size);
+        if trim {
+            let len = item.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            item.truncate(len);
+        }
+        Ok(item)
+    }
+
+    /// Current version of item `index`; every item starts at 0 and each
+    /// update bumps it by one.
+    fn item_version(&self, index: usize) -> PyResult<u32> {
+        self.check_index(index)?;
+        Ok(self.versions.get(index))
+    }
+
+    /// Largest valid item index; indices start at 0.
+    fn max_index(&self) -> usize {
+        db_capacity(self.params, self.is_simplepir, self.item_size) - 1
+    }
+
+    /// Whether item `index` was ever written (a plaintext operator check).
+    /// Only servers from `server_new_sparse` track this; an unwritten slot
+    /// and an item of zero bytes both decode to zeros, and every item of a
+    /// database loaded whole counts as written. Updates mark items written.
+    fn is_written(&self, index: usize) -> PyResult<bool> {
+        self.check_index(index)?;
+        Ok(self.written.is_written(index))
+    }
+
+    /// Overwrite item `index` (zero-padded to the item size) and return its
+    /// new version. Clears the response cache.
+    ///
+    /// Writes are copy-on-write: while an `answer_async` call is in flight,
+    /// the update first copies the whole database, costing time and memory
+    /// in proportion to its size (as does the first write to a server over
+    /// borrowed memory, e.g. `server_from_shared`). Otherwise it writes in
+    /// place.
+    fn update_item(&mut self, index: usize, item: &[u8]) -> PyResult<u32> {
+        self.check_item(index, item)?;
+        self.write_item(index, item);
+        Ok(self.versions.bump(index))
+    }
+
+    /// Overwrite the contiguous items `start_index, start_index + 1, ...`
+    /// with `items` (each zero-padded to the item size) in one pass over the
+    /// database, e.g. for a bulk refresh of a key range. Every item is
+    /// checked before anything is written. Returns the new versions and
+    /// clears the response cache.
+    fn update_range(&mut self, start_index: usize, items: Vec<Vec<u8>>) -> PyResult<Vec<u32>> {
+        for (i, item) in items.iter().enumerate() {
+            self.check_item(start_index + i, item)?;
+        }
+        let item_refs = items.iter().map(|item| item.as_slice()).collect::<Vec<_>>();
+        self.write_items(start_index, &item_refs);
+        Ok((start_index..start_index + items.len())
+            .map(|index| self.versions.bump(index))
+            .collect())
+    }
+
+    /// Like `update_item`, but only if the item is still at
+    /// `expected_version`; raises `YpirVersionError` otherwise, without
+    /// writing or copying anything. A successful update has `update_item`'s
+    /// copy cost.
+    fn update_item_cas(
+        &mut self,
+        index: usize,
+        item: &[u8],
+        expected_version: u32,
+    ) -> PyResult<u32> {
+        self.check_item(index, item)?;
+        self.versions
+            .check(index, expected_version)
+            .map_err(|e| YpirVersionError::new_err(e.to_string()))?;
+        self.write_item(index, item);
+        Ok(self.versions.bump(index))
+    }
+
+    /// Start building the server's layout for `new_db_bytes` (a row-major
+    /// database, `required_db_bytes(params)` long, or transposed with
+    /// `inp_transposed=True`) in the background, while the current database
+    /// keeps answering. Staging again discards the previous stage.
+    ///
+    /// Builds share a pool of one thread per CPU, so staging on many servers
+    /// at once queues rather than starting a thread each; a discarded stage
+    /// that hasn't started is skipped.
+    ///
+    /// The build doesn't hold the current database, so `update_item` and
+    /// friends keep writing it in place meanwhile; those writes are not
+    /// carried into the staged database.
+    #[pyo3(signature = (new_db_bytes, inp_transposed=false))]
+    fn stage(&mut self, new_db_bytes: Vec<u8>, inp_transposed: bool) -> PyResult<()> {
+        let layout = db_layout(self.params, self.is_simplepir, self.inner.pad_rows(), 1);
+        let expected = if inp_transposed {
+            layout.total_bytes()
+        } else {
+            db_num_bytes(self.params, self.is_simplepir)
+        };
+        if new_db_bytes.len() != expected {
+            return Err(YpirSizeError::new_err(format!(
+                "staged database is {} bytes, expected {}",
+                new_db_bytes.len(),
+                expected
+            )));
+        }
+        // only the settings, not the server: holding it would make every
+        // write during the build copy the database
+        let (params, is_simplepir) = (self.params, self.is_simplepir);
+        let pad_rows = self.inner.pad_rows();
+        let seeds = self.inner.public_seeds().clone();
+        let discarded = Arc::new(AtomicBool::new(false));
+        let (sender, result) = mpsc::channel();
+        let skip = Arc::clone(&discarded);
+        stage_pool().execute(move || {
+            if skip.load(Ordering::Relaxed) {
+                return;
+            }
+            let db = new_db_bytes.into_iter();
+            let mut s = YServer::new(params, db, is_simplepir, inp_transposed, pad_rows);
+            s.set_public_seeds(seeds);
+            // the receiver is gone if the server was dropped meanwhile
+            let _ = sender.send(SharedServer(s, None));
+        });
+        // replacing a previous stage drops it, which discards it
+        self.staged = Some(StagedBuild { result, discarded });
+        Ok(())
+    }
+
+    /// Wait for the staged database to finish building, then switch to it in
+    /// one step: later answers see only the new data, while `answer_async`
+    /// calls already running finish on the old. Raises `YpirError` if
+    /// nothing is staged.
+    ///
+    /// Committing clears the response cache and counts every item as
+    /// written (`is_written` is true for all of them, as for a database
+    /// loaded whole), since the new database says nothing about which slots
+    /// were filled. Item versions are kept, not reset: each only ever
+    /// increases, so an `update_item_cas` against a version read before the
+    /// commit still succeeds only if the item wasn't updated since.
+    fn commit(&mut self, py: Python<'_>) -> PyResult<()> {
+        let Some(staged) = self.staged.take() else {
+            return Err(YpirError::new_err("no database staged; call stage() first"));
+        };
+        let server = py
+            .detach(|| staged.result.recv())
+            .map_err(|_| YpirError::new_err("building the staged database panicked"))?;
+        self.inner = Arc::new(server);
+        self.written = WrittenItems::all();
+        self.cache.clear();
+        self.refresh_numa();
+        if self.locked && self.inner.0.lock_memory().is_err() {
+            self.locked = false;
+        }
+        Ok(())
+    }
+
+    /// The column-major database buffer the kernel actually reads; compare
+    /// against `transpose_db(params, row_major_bytes)`.
+    #[cfg(feature = "debug")]
+    fn dump_transposed(&self) -> Vec<u8> {
+        self.inner.db().to_vec()
+    }
+}
+
+// a database being built by `stage` on `stage_pool()`
+struct StagedBuild {
+    result: mpsc::Receiver<SharedServer>,
+    // set once the stage is replaced or dropped, so a queued build is skipped
+    discarded: Arc<AtomicBool>,
+}
+
+impl Drop for StagedBuild {
+    fn drop(&mut self) {
+        self.discarded.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Workers for `stage`, one per CPU, started on first use.
+fn stage_pool() -> &'static WorkerPool {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        WorkerPool::new(std::thread::available_parallelism().map_or(1, |n| n.get()))
+    })
+}
+
+impl PyYpirServer {
+    fn new(params: &PyYpirParams, s: YServer<'static, u8>, cache_size: usize) -> Self {
+        Self::with_owner(params, s, cache_size, None)
+    }
+
+    fn with_owner(
+        params: &PyYpirParams,
+        mut s: YServer<'static, u8>,
+        cache_size: usize,
+        owner: Option<Arc<PyBuffer<u8>>>,
+    ) -> Self {
+        s.set_public_seeds(params.public_seeds.clone());
+        Self {
+            params: params.params,
+            inner: Arc::new(SharedServer(s, owner)),
+            cache: ResponseCache::new(cache_size),
+            fingerprint: params.fingerprint_bytes(),
+            is_simplepir: params.is_simplepir,
+            item_size: params.item_size_bytes(),
+            num_items: params.num_items,
+            versions: ItemVersions::new(),
+            written: WrittenItems::all(),
+            row_tenants: None,
+            staged: None,
+            numa: None,
+            locked: false,
+            db_copies: 0,
+        }
+    }
+
+    fn lock(&mut self) -> PyResult<()> {
+        self.inner.0.lock_memory().map_err(|e| {
+            YpirError::new_err(format!(
+                "could not lock the database in memory: {} (is RLIMIT_MEMLOCK too low?)",
+                e
+            ))
+        })?;
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Parses and validates a packed query, restricted to tenant `token`'s
+    /// rows (see `set_row_tenants`); shared by `answer` and `check_query`.
+    fn checked_query_words(
+        &self,
+        packed_query_bytes: &[u8],
+        fingerprint: Option<&[u8]>,
+        endianness: Endianness,
+        token: Option<u32>,
+    ) -> PyResult<Vec<u64>> {
+        if let Some(fp) = fingerprint {
+            if fp != self.fingerprint.as_slice() {
+                return Err(PyValueError::new_err(
+                    "params fingerprint mismatch: client and server use different params",
+                ));
+            }
+        }
+        let mut packed_words = bytes_to_u64(packed_query_bytes, endianness)?;
+        self.inner.check_query(&packed_words).map_err(query_err)?;
+        match (&self.row_tenants, token) {
+            (Some(tags), Some(tenant)) => restrict_query_to_tenant(&mut packed_words, tags, tenant),
+            (None, Some(_)) => {
+                return Err(PyValueError::new_err(
+                    "token given, but the server's rows have no tenant tags",
+                ))
+            }
+            (_, None) => self.check_untagged()?,
+        }
+        Ok(packed_words)
+    }
+
+    /// Fails for answer paths without a token once rows are tenant-tagged.
+    fn check_untagged(&self) -> PyResult<()> {
+        if self.row_tenants.is_some() {
+            return Err(YpirError::new_err(
+                "the server's rows are tenant-tagged; answer with a token",
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_index(&self, index: usize) -> PyResult<()> {
+        let capacity = db_capacity(self.params, self.is_simplepir, self.item_size);
+        if index >= capacity {
+            return Err(YpirSizeError::new_err(format!(
+                "item index {} out of range for a database of {} items",
+                index, capacity
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_item(&self, index: usize, item: &[u8]) -> PyResult<()> {
+        self.check_index(index)?;
+        if item.len() > self.item_size {
+            return Err(YpirSizeError::new_err(format!(
+                "item is {} bytes, larger than the item size of {} bytes",
+                item.len(),
+                self.item_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Answer to a checked packed query, on the per-node copies if the
+    /// server has them.
+    fn answer_packed(&self, packed_words: &[u64]) -> AlignedMemory64 {
+        match &self.numa {
+            Some(numa) => numa.answer_query(packed_words),
+            None => self.inner.answer_query(packed_words),
+        }
+    }
+
+    fn refresh_numa(&mut self) {
+        if self.numa.is_some() {
+            self.numa = Some(NumaServer::new(self.params, &self.inner.0));
+        }
+    }
+
+    fn write_item(&mut self, index: usize, item: &[u8]) {
+        self.write_items(index, &[item]);
+    }
+
+    /// Copy-on-write: in-flight `answer_async` calls keep reading the old
+    /// database.
+    fn write_items(&mut self, start_index: usize, items: &[&[u8]]) {
+        let shared = Arc::strong_count(&self.inner) > 1 || self.inner.db_is_shared();
+        Arc::make_mut(&mut self.inner)
+            .0
+            .update_range(start_index, self.item_size, items);
+        for index in start_index..start_index + items.len() {
+            self.written.mark(index);
+        }
+        if shared {
+            self.db_copies += 1;
+        }
+        self.cache.clear();
+        self.refresh_numa();
+        if shared && self.locked {
+            // make_mut (or from_shared storage) copied the buffer; the copy
+            // is not locked yet
+            if self.inner.0.lock_memory().is_err() {
+                self.locked = false;
+            }
+        }
+    }
+}
+
+/// Many small same-shaped databases behind one server (see
+/// `server_new_multi`); `answer` takes the tenant to answer against. The
+/// tenant id is public, only the queried item stays private.
+#[pyclass(unsendable, name = "MultiTenantServer")]
+struct PyMultiTenantServer {
+    inner: MultiTenantServer<'static>,
+    fingerprint: [u8; 32],
+}
+
+#[pymethods]
+impl PyMultiTenantServer {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint.to_vec()
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.tenants()
+    }
+
+    /// Answer a packed query against tenant `tenant_id`'s database.
+    #[pyo3(signature = (packed_query_bytes, tenant_id, fingerprint=None, endianness="little"))]
+    fn answer(
+        &self,
+        packed_query_bytes: &[u8],
+        tenant_id: usize,
+        fingerprint: Option<&[u8]>,
+        endianness: &str,
+    ) -> PyResult<Vec<u8>> {
+        let endianness = parse_endianness(endianness)?;
+        if let Some(fp) = fingerprint {
+            if fp != self.fingerprint.as_slice() {
+                return Err(PyValueError::new_err(
+                    "params fingerprint mismatch: client and server use different params",
+                ));
+            }
+        }
+        let packed_words = bytes_to_u64(packed_query_bytes, endianness)?;
+        let resp = self
+            .inner
+            .answer_query(tenant_id, &packed_words)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+        Ok(aligned64_to_bytes(&resp, endianness))
+    }
+}
+
+/// Build one server over several tenants' row-major databases, each
+/// `required_db_bytes(params)` long; amortizes per-server overhead across
+/// many tiny databases.
+#[pyfunction]
+fn server_new_multi(
+    params: &PyYpirParams,
+    tenant_dbs: Vec<Vec<u8>>,
+) -> PyResult<PyMultiTenantServer> {
+    let dbs = tenant_dbs.iter().map(|x| x.as_slice()).collect::<Vec<_>>();
+    let inner = MultiTenantServer::new(params.params, params.is_simplepir, &dbs)
+        .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+    Ok(PyMultiTenantServer {
+        inner,
+        fingerprint: params.fingerprint_bytes(),
+    })
+}
+
+/// A SimplePIR server over one file per instance (see
+/// `server_from_instance_files`). Each file is read into memory; after one
+/// instance's file changes, `reload_instance` picks it up without touching
+/// the others.
+#[pyclass(unsendable, name = "InstanceFilesServer")]
+struct PyInstanceFilesServer {
+    params: &'static SpiralParams,
+    paths: Vec<String>,
+    dbs: Vec<Vec<u8>>,
+    fingerprint: [u8; 32],
+}
+
+#[pymethods]
+impl PyInstanceFilesServer {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.fingerprint.to_vec()
+    }
+
+    fn __len__(&self) -> usize {
+        self.dbs.len()
+    }
+
+    /// Re-read instance `instance`'s file.
+    fn reload_instance(&mut self, instance: usize) -> PyResult<()> {
+        let Some(path) = self.paths.get(instance) else {
+            return Err(YpirSizeError::new_err(format!(
+                "instance {} out of range for {} instances",
+                instance,
+                self.paths.len()
+            )));
+        };
+        self.dbs[instance] = read_instance_file(self.params, instance, path)?;
+        Ok(())
+    }
+
+    /// Answer a packed query; equal to `answer` on a server over the whole
+    /// database.
+    #[pyo3(signature = (packed_query_bytes, fingerprint=None, endianness="little"))]
+    fn answer(
+        &self,
+        packed_query_bytes: &[u8],
+        fingerprint: Option<&[u8]>,
+        endianness: &str,
+    ) -> PyResult<Vec<u8>> {
+        let endianness = parse_endianness(endianness)?;
+        if let Some(fp) = fingerprint {
+            if fp != self.fingerprint.as_slice() {
+                return Err(PyValueError::new_err(
+                    "params fingerprint mismatch: client and server use different params",
+                ));
+            }
+        }
+        let packed_words = bytes_to_u64(packed_query_bytes, endianness)?;
+        let dbs = self.dbs.iter().map(|db| db.as_slice()).collect::<Vec<_>>();
+        let resp = answer_by_instance(self.params, &packed_words, &dbs)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+        Ok(aligned64_to_bytes(&resp, endianness))
+    }
+}
+
+fn read_instance_file(params: &SpiralParams, instance: usize, path: &str) -> PyResult<Vec<u8>> {
+    let db = std::fs::read(path)?;
+    let expected = instance_db_bytes(params);
+    if db.len() != expected {
+        return Err(YpirSizeError::new_err(format!(
+            "instance {} file {} is {} bytes, expected {}",
+            instance,
+            path,
+            db.len(),
+            expected
+        )));
+    }
+    Ok(db)
+}
+
+/// Build a SimplePIR server from one file per instance, in instance order.
+/// Instance `i`'s file holds columns `i * poly_len..(i + 1) * poly_len` of
+/// the transposed, row-padded database (`layout_info("u8")`), i.e. that
+/// slice of what `dump_transposed` writes; answers concatenate the
+/// per-instance results.
+#[pyfunction]
+fn server_from_instance_files(
+    params: &PyYpirParams,
+    paths: Vec<String>,
+) -> PyResult<PyInstanceFilesServer> {
+    if !params.is_simplepir {
+        return Err(PyValueError::new_err("instance files need SimplePIR params"));
+    }
+    if paths.len() != params.params.instances {
+        return Err(YpirSizeError::new_err(format!(
+            "{} instance files given, params have {} instances",
+            paths.len(),
+            params.params.instances
+        )));
+    }
+    let dbs = paths
+        .iter()
+        .enumerate()
+        .map(|(instance, path)| read_instance_file(params.params, instance, path))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(PyInstanceFilesServer {
+        params: params.params,
+        paths,
+        dbs,
+        fingerprint: params.fingerprint_bytes(),
+    })
+}
+
+/// Query, answer and extract as one chain of typed steps:
+///
+///     item = Pipeline(client).query(index).answer(server).extract()
+///
+/// Each step carries the client it started from, so the response is always
+/// decoded with the client that made the query, and `answer` refuses a server
+/// whose params differ from the client's.
+#[pyclass(unsendable, name = "Pipeline")]
+struct PyPipeline {
+    client: Py<PyYpirClient>,
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[new]
+    fn new(client: Py<PyYpirClient>) -> Self {
+        Self { client }
+    }
+
+    /// Generate a packed query for logical item `index`.
+    ///
+    /// `response_packing=True` asks for a bit-packed response (see `answer`):
+    /// smaller on the wire, a little more work to produce and decode. The
+    /// mode goes in the header of `query_bytes()`, so a remote `answer`
+    /// follows it; `response()` then expects it.
+    #[pyo3(signature = (index, public_seed_idx=0, response_packing=false))]
+    fn query(
+        &self,
+        py: Python<'_>,
+        index: usize,
+        public_seed_idx: u8,
+        response_packing: bool,
+    ) -> PyResult<PyPipelineQuery> {
+        let mut client = self.client.borrow_mut(py);
+        client.check_keys()?;
+        let params = client.params;
+        let row = logical_to_physical(params, client.is_simplepir, client.item_size, index)
+            .ok_or_else(|| YpirSizeError::new_err(format!("item index {} out of range", index)))?;
+        let client = &mut *client;
+        let words = client_query_words(
+            params,
+            &mut client.inner,
+            &client.seeds,
+            public_seed_idx,
+            params.db_dim_1,
+            true,
+            row,
+            true,
+        );
+        Ok(PyPipelineQuery {
+            client: self.client.clone_ref(py),
+            index,
+            words,
+            response_packing,
+        })
+    }
+}
+
+/// A `Pipeline` query waiting to be answered.
+#[pyclass(unsendable, name = "PipelineQuery")]
+struct PyPipelineQuery {
+    client: Py<PyYpirClient>,
+    index: usize,
+    words: Vec<u64>,
+    response_packing: bool,
+}
+
+#[pymethods]
+impl PyPipelineQuery {
+    /// The packed query with its response-mode header (as from
+    /// `query(..., response_packing=...)`), for sending to a remote server;
+    /// its response can be picked back up with `response(response_bytes)`.
+    #[pyo3(signature = (endianness="little"))]
+    fn query_bytes(&self, endianness: &str) -> PyResult<Vec<u8>> {
+        let words = with_response_mode(&self.words, self.response_packing);
+        Ok(u64_to_bytes(&words, parse_endianness(endianness)?))
+    }
+
+    fn answer(&self, py: Python<'_>, server: &PyYpirServer) -> PyResult<PyPipelineResponse> {
+        let fingerprint = self.client.borrow(py).fingerprint;
+        if fingerprint != server.fingerprint {
+            return Err(PyValueError::new_err(
+                "params fingerprint mismatch: client and server use different params",
+            ));
+        }
+        server.check_untagged()?;
+        server.inner.check_query(&self.words).map_err(query_err)?;
+        // packing only changes the bytes on the wire, and there are none here
+        let words = server.inner.answer_query(&self.words).as_slice().to_vec();
+        Ok(self.with_response(py, words))
+    }
+
+    /// Continue from a response obtained elsewhere (see `query_bytes`),
+    /// bit-packed if the query asked for `response_packing`.
+    #[pyo3(signature = (response_bytes, endianness="little"))]
+    fn response(
+        &self,
+        py: Python<'_>,
+        response_bytes: &[u8],
+        endianness: &str,
+    ) -> PyResult<PyPipelineResponse> {
+        let endianness = parse_endianness(endianness)?;
+        let words = if self.response_packing {
+            let client = self.client.borrow(py);
+            unpack_response_words(client.params, client.is_simplepir, response_bytes)?
+        } else {
+            bytes_to_u64(response_bytes, endianness)?
+        };
+        Ok(self.with_response(py, words))
+    }
+}
+
+impl PyPipelineQuery {
+    fn with_response(&self, py: Python<'_>, words: Vec<u64>) -> PyPipelineResponse {
+        PyPipelineResponse {
+            client: self.client.clone_ref(py),
+            index: self.index,
+            words,
+        }
+    }
+}
+
+/// A `Pipeline` response waiting to be decoded.
+#[pyclass(unsendable, name = "PipelineResponse")]
+struct PyPipelineResponse {
+    client: Py<PyYpirClient>,
+    index: usize,
+    words: Vec<u64>,
+}
+
+#[pymethods]
+impl PyPipelineResponse {
+    /// Decode the queried item's bytes.
+    fn extract(&self, py: Python<'_>) -> PyResult<Vec<u8>> {
+        let mut client = self.client.borrow_mut(py);
+        client.check_keys()?;
+        let p = client.params;
+        let (_, db_cols) = db_dims(p, client.is_simplepir);
+        let start = self.index * client.item_size % db_cols;
+        let end = start + client.item_size;
+        if end > db_cols {
+            return Err(YpirSizeError::new_err(format!(
+                "item {} spans two rows and cannot be read with one query",
+                self.index
+            )));
+        }
+        let (out, _) = unsafe {
+            let inner = shrink_client_lifetime(&mut client.inner);
+            let params = shrink_params_lifetime(p);
+            let y = YClient::new(inner, params);
+            y.decode_response_range(&self.words, start..end)
+        };
+        coeffs_to_item_bytes(p, &out)
+    }
+}
+
+/// One shard of a row-sharded database (see `server_new_shard`); `answer`
+/// takes this shard's slice of a query from `split_query_for_shards`.
+#[pyclass(unsendable, name = "ShardServer")]
+struct PyShardServer {
+    inner: ShardServer<'static>,
+}
+
+#[pymethods]
+impl PyShardServer {
+    /// The database rows this shard holds, as `(start, end)`.
+    fn rows(&self) -> (usize, usize) {
+        let rows = self.inner.rows();
+        (rows.start, rows.end)
+    }
+
+    /// Partial answer to this shard's query slice; add the shards' answers up
+    /// with `combine_answers`.
+    #[pyo3(signature = (query_slice_bytes, endianness="little"))]
+    fn answer(&self, query_slice_bytes: &[u8], endianness: &str) -> PyResult<Vec<u8>> {
+        let endianness = parse_endianness(endianness)?;
+        let words = bytes_to_u64(query_slice_bytes, endianness)?;
+        let resp = self.inner.answer_query(&words).map_err(shard_err)?;
+        Ok(aligned64_to_bytes(&resp, endianness))
+    }
+}
+
+/// Build shard `shard` of `num_shards` from its rows of the row-major
+/// database (`shard_db_bytes`, bytes `start * cols..end * cols` where
+/// `(start, end)` is the shard's `rows()`).
+#[pyfunction]
+fn server_new_shard(
+    params: &PyYpirParams,
+    num_shards: usize,
+    shard: usize,
+    shard_db_bytes: &[u8],
+) -> PyResult<PyShardServer> {
+    let inner =
+        ShardServer::new(params.params, params.is_simplepir, num_shards, shard, shard_db_bytes)
+            .map_err(shard_err)?;
+    Ok(PyShardServer { inner })
+}
+
+/// The shard holding logical item `index`. Querying only that shard reveals
+/// it; to keep the index private send every shard its slice.
+#[pyfunction]
+fn shard_for_index(params: &PyYpirParams, num_shards: usize, index: usize) -> PyResult<usize> {
+    ypir_shard_for_index(
+        params.params,
+        params.is_simplepir,
+        params.item_size_bytes(),
+        num_shards,
+        index,
+    )
+    .ok_or_else(|| YpirSizeError::new_err(format!("item index {} out of range", index)))
+}
+
+/// Split a packed query into `{shard: query_slice_bytes}` for every shard.
+#[pyfunction]
+#[pyo3(signature = (packed_query_bytes, num_shards, endianness="little"))]
+fn split_query_for_shards(
+    packed_query_bytes: &[u8],
+    num_shards: usize,
+    endianness: &str,
+) -> PyResult<HashMap<usize, Vec<u8>>> {
+    let endianness = parse_endianness(endianness)?;
+    let words = bytes_to_u64(packed_query_bytes, endianness)?;
+    let slices = split_query(&words, num_shards).map_err(shard_err)?;
+    Ok(slices
+        .into_iter()
+        .enumerate()
+        .map(|(shard, slice)| (shard, u64_to_bytes(slice, endianness)))
+        .collect())
+}
+
+/// Add the shards' partial answers up into the full response, which
+/// `extract` and friends decode as usual.
+#[pyfunction]
+#[pyo3(signature = (params, answers, endianness="little"))]
+fn combine_answers(
+    params: &PyYpirParams,
+    answers: Vec<Vec<u8>>,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let words = answers
+        .iter()
+        .map(|a| bytes_to_u64(a, endianness))
+        .collect::<PyResult<Vec<_>>>()?;
+    let slices = words.iter().map(|w| w.as_slice()).collect::<Vec<_>>();
+    let out = ypir_combine_answers(params.params, &slices).map_err(shard_err)?;
+    Ok(u64_to_bytes(&out, endianness))
+}
+
+fn shard_err(e: ShardError) -> PyErr {
+    YpirSizeError::new_err(e.to_string())
+}
+
+/// Builds a server from its transposed (column-major) database fed in blocks
+/// of whole columns, for databases too large to pass as one `bytes` object.
+///
+/// Each block must be a multiple of `layout_info("u8")["col_stride"]` bytes;
+/// blocks are concatenated in order, as `server_new(..., inp_transposed=True)`
+/// would read them.
+#[pyclass(unsendable, name = "ServerBuilder")]
+struct PyServerBuilder {
+    params: PyYpirParams,
+    builder: Option<YServerBuilder<'static, u8>>,
+    cache_size: usize,
+}
+
+#[pymethods]
+impl PyServerBuilder {
+    #[new]
+    #[pyo3(signature = (params, pad_rows=true, cache_size=0))]
+    fn new(params: &PyYpirParams, pad_rows: bool, cache_size: usize) -> Self {
+        Self {
+            params: params.clone(),
+            builder: Some(YServerBuilder::new(params.params, params.is_simplepir, pad_rows)),
+            cache_size,
+        }
+    }
+
+    /// Bytes fed so far and the total the database needs.
+    fn progress(&self) -> PyResult<(usize, usize)> {
+        let b = self.builder()?;
+        Ok((b.filled_elems(), b.total_elems()))
+    }
+
+    fn add_column_block(&mut self, block: &[u8]) -> PyResult<()> {
+        let b = self
+            .builder
+            .as_mut()
+            .ok_or_else(|| YpirError::new_err("ServerBuilder already finished"))?;
+        b.add_column_block(block)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))
+    }
+
+    /// Build the server; raises `YpirSizeError` if the database is incomplete.
+    /// The builder can still be fed more blocks after a failed `finish`.
+    fn finish(&mut self) -> PyResult<PyYpirServer> {
+        let b = self.builder()?;
+        if b.filled_elems() != b.total_elems() {
+            return Err(YpirSizeError::new_err(format!(
+                "only {} of {} database bytes provided",
+                b.filled_elems(),
+                b.total_elems()
+            )));
+        }
+        let s = self
+            .builder
+            .take()
+            .unwrap()
+            .finish()
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+        Ok(PyYpirServer::new(&self.params, s, self.cache_size))
+    }
+}
+
+impl PyServerBuilder {
+    fn builder(&self) -> PyResult<&YServerBuilder<'static, u8>> {
+        self.builder
+            .as_ref()
+            .ok_or_else(|| YpirError::new_err("ServerBuilder already finished"))
+    }
+}
+
+// ---------- constructors / API ----------
+
+#[pyfunction]
+fn params_db_dim_1(params: &PyYpirParams) -> usize {
+    params.params.db_dim_1
+}
+
+#[pyfunction]
+fn required_db_bytes(params: &PyYpirParams) -> usize {
+    db_num_bytes(params.params, params.is_simplepir)
+}
+
+/// Transpose a row-major database into column-major order, one unpadded
+/// column of `db_rows` bytes after another. It equals the server's stored
+/// buffer while `layout_info()["db_rows"]` equals the unpadded row count,
+/// as it does for every params today.
+#[pyfunction]
+fn transpose_db(params: &PyYpirParams, row_major_bytes: Vec<u8>) -> PyResult<Vec<u8>> {
+    let needed = db_num_bytes(params.params, params.is_simplepir);
+    if row_major_bytes.len() != needed {
+        return Err(YpirSizeError::new_err(format!(
+            "row_major_bytes is {} bytes, expected {}",
+            row_major_bytes.len(),
+            needed
+        )));
+    }
+    Ok(ypir_transpose_db(
+        params.params,
+        params.is_simplepir,
+        &row_major_bytes,
+    ))
+}
+
+/// `transpose_db` from the row-major file `in_path` to `out_path`, holding
+/// only `rows_per_block` rows (twice) in memory at a time. The output can
+/// be loaded with `server_from_path(..., inp_transposed=True)`. Releases
+/// the GIL while it runs.
+#[pyfunction]
+#[pyo3(signature = (params, in_path, out_path, rows_per_block=256))]
+fn transpose_db_to_file(
+    py: Python<'_>,
+    params: &PyYpirParams,
+    in_path: &str,
+    out_path: &str,
+    rows_per_block: usize,
+) -> PyResult<()> {
+    if rows_per_block == 0 {
+        return Err(PyValueError::new_err("rows_per_block must be positive"));
+    }
+    let needed = db_num_bytes(params.params, params.is_simplepir);
+    let input = std::fs::File::open(in_path)?;
+    let len = input.metadata()?.len() as usize;
+    if len != needed {
+        return Err(YpirSizeError::new_err(format!(
+            "{} is {} bytes, expected {}",
+            in_path, len, needed
+        )));
+    }
+    let output = std::fs::File::create(out_path)?;
+    let (p, is_simplepir) = (params.params, params.is_simplepir);
+    py.detach(|| transpose_db_stream(p, is_simplepir, input, output, rows_per_block))?;
+    Ok(())
+}
+
+/// Interleave a transposed database (as from `transpose_db`) four columns
+/// per little-endian u32 word, for `answer_repacked`. Worth it for databases
+/// with more than a few thousand rows, where the query no longer fits in L1.
+#[pyfunction]
+fn repack_db_u8_to_u32(params: &PyYpirParams, transposed_bytes: Vec<u8>) -> PyResult<Vec<u8>> {
+    let layout = db_layout(params.params, params.is_simplepir, true, 1);
+    if transposed_bytes.len() != layout.total_bytes() {
+        return Err(YpirSizeError::new_err(format!(
+            "transposed_bytes is {} bytes, expected {}",
+            transposed_bytes.len(),
+            layout.total_bytes()
+        )));
+    }
+    let words =
+        ypir_repack_db_u8_to_u32(&transposed_bytes, layout.db_rows_padded, layout.db_cols);
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+/// Answer a packed query against a database repacked by
+/// `repack_db_u8_to_u32`; returns the same response as `answer`.
+#[pyfunction]
+#[pyo3(signature = (params, repacked_bytes, packed_query_bytes, endianness="little"))]
+fn answer_repacked(
+    py: Python<'_>,
+    params: &PyYpirParams,
+    repacked_bytes: Vec<u8>,
+    packed_query_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let layout = db_layout(params.params, params.is_simplepir, true, 1);
+    let expected_bytes = layout.db_cols.div_ceil(4) * layout.db_rows_padded * 4;
+    if repacked_bytes.len() != expected_bytes {
+        return Err(YpirSizeError::new_err(format!(
+            "repacked_bytes is {} bytes, expected {}",
+            repacked_bytes.len(),
+            expected_bytes
+        )));
+    }
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    if packed_words.len() != layout.db_rows_padded {
+        return Err(query_err(QueryError::WrongLength {
+            len: packed_words.len(),
+            expected: layout.db_rows_padded,
+        }));
+    }
+    let b_packed = repacked_bytes
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .collect::<Vec<_>>();
+
+    let p = params.params;
+    let resp = py.detach(|| {
+        let mut resp = vec![0u64; layout.db_cols];
+        fast_batched_dot_product_repacked::<1>(
+            p,
+            &mut resp,
+            &packed_words,
+            layout.db_rows_padded,
+            &b_packed,
+            layout.db_rows_padded,
+            layout.db_cols,
+        );
+        resp
+    });
+    Ok(u64_to_bytes(&resp, endianness))
+}
+
+/// Run the dot-product kernel alone on synthetic inputs, for profiling it
+/// without the protocol: `a` holds `k` batches of `a_elems` packed u64 words
+/// and `b_t` a transposed u8 matrix of `b_rows` x `b_cols`. Returns the
+/// `k * b_cols` output words. Releases the GIL while the kernel runs.
+#[pyfunction]
+#[pyo3(signature = (params, a, a_elems, b_t, b_rows, b_cols, k=1, endianness="little"))]
+fn kernel_dot_product(
+    py: Python<'_>,
+    params: &PyYpirParams,
+    a: Vec<u8>,
+    a_elems: usize,
+    b_t: Vec<u8>,
+    b_rows: usize,
+    b_cols: usize,
+    k: usize,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let a_words = bytes_to_u64(&a, endianness)?;
+    let p = params.params;
+    let c = py
+        .detach(|| dot_product_checked(p, k, &a_words, a_elems, &b_t, b_rows, b_cols))
+        .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+    Ok(u64_to_bytes(&c, endianness))
+}
+
+/// Name of the kernel currently used to answer queries ("scalar" or "avx2").
+#[pyfunction]
+fn active_kernel() -> &'static str {
+    ypir_active_kernel().name()
+}
+
+/// Check that every compiled-in kernel variant agrees on a small fixed
+/// input; False (with the diverging variant logged) means a miscompiled
+/// build that must not serve queries.
+#[pyfunction]
+fn verify_kernel() -> bool {
+    ypir_verify_kernel()
+}
+
+/// Pin the kernel for this process, overriding the `YPIR_KERNEL` env var and
+/// the runtime detection. Unknown or unavailable names fall back to
+/// "scalar"; returns the name of the kernel actually selected.
+#[pyfunction]
+fn set_kernel(name: &str) -> &'static str {
+    ypir_set_kernel(KernelKind::from_name(name)).name()
+}
+
+fn query_err(e: QueryError) -> PyErr {
+    YpirSizeError::new_err(e.to_string())
+}
+
+fn build_db_err(e: BuildDbError) -> PyErr {
+    YpirSizeError::new_err(e.to_string())
+}
+
+/// Lay out `items` in order into a database blob for `server_new(...,
+/// inp_transposed=False, ...)`. Items shorter than the item size are
+/// zero-padded.
+///
+/// With `permutation_seed`, item `i` is stored in slot
+/// `params.permute_index(permutation_seed, i)` instead of slot `i`; query
+/// that slot (`logical=True`) to fetch it.
+///
+/// Raises `YpirSizeError` if there are more items than `params.capacity()`
+/// or an item is larger than the item size.
+#[pyfunction]
+#[pyo3(signature = (params, items, permutation_seed=None))]
+fn build_db(
+    params: &PyYpirParams,
+    items: Vec<Vec<u8>>,
+    permutation_seed: Option<u64>,
+) -> PyResult<Vec<u8>> {
+    let items = items.iter().map(|x| x.as_slice());
+    let (p, is_simplepir) = (params.params, params.is_simplepir);
+    let item_size = params.item_size_bytes();
+    match permutation_seed {
+        Some(seed) => build_db_permuted(p, is_simplepir, item_size, items, seed),
+        None => ypir::db::build_db(p, is_simplepir, item_size, items),
+    }
+    .map_err(build_db_err)
+}
+
+/// Quantize float vectors into PIR items, one item per vector: each value
+/// becomes the byte `round(x / scale) + zero_point`, clamped to 0..255, and
+/// short vectors are padded with `zero_point`. `dequantize_item` inverts it
+/// to within `scale / 2` for values in range; both sides must use the same
+/// `scale` and `zero_point`.
+///
+/// Returns the items concatenated (item `i` is bytes `i * item_size_bytes`
+/// onwards), or with `params` the `build_db` database of them, ready for
+/// `server_new(..., inp_transposed=False, ...)`.
+///
+/// Raises `ValueError` unless `scale` is positive and finite, and
+/// `YpirSizeError` if a vector is longer than `item_size_bytes` or there are
+/// more vectors than `params.capacity()`.
+#[pyfunction]
+#[pyo3(signature = (floats, scale, zero_point, item_size_bytes, params=None))]
+fn quantize_db(
+    floats: Vec<Vec<f32>>,
+    scale: f32,
+    zero_point: u8,
+    item_size_bytes: usize,
+    params: Option<&PyYpirParams>,
+) -> PyResult<Vec<u8>> {
+    if !(scale.is_finite() && scale > 0.) {
+        return Err(PyValueError::new_err(format!(
+            "scale must be positive and finite, not {}",
+            scale
+        )));
+    }
+    if item_size_bytes == 0 {
+        return Err(PyValueError::new_err("item_size_bytes must be positive"));
+    }
+    let items =
+        quantize_items(&floats, scale, zero_point, item_size_bytes).map_err(build_db_err)?;
+    let Some(params) = params else {
+        return Ok(items);
+    };
+    if params.item_size_bytes() != item_size_bytes {
+        return Err(PyValueError::new_err(format!(
+            "item_size_bytes {} does not match the params' item size {}",
+            item_size_bytes,
+            params.item_size_bytes()
+        )));
+    }
+    let (p, is_simplepir) = (params.params, params.is_simplepir);
+    let items = items.chunks(item_size_bytes);
+    ypir::db::build_db(p, is_simplepir, item_size_bytes, items).map_err(build_db_err)
+}
+
+/// The float values a `quantize_db` item stands for, one per byte; padding
+/// comes back as 0.0.
+#[pyfunction]
+fn dequantize_item(item_bytes: Vec<u8>, scale: f32, zero_point: u8) -> Vec<f32> {
+    item_bytes
+        .iter()
+        .map(|&q| dequantize(q, scale, zero_point))
+        .collect()
+}
+
+/// Tenant of each database row for a `build_db` database whose item `i`
+/// belongs to tenant `item_tenants[i]`, for `server.set_row_tenants`; `None`
+/// for rows holding no item. Raises `YpirSizeError` if a row would hold
+/// items of two tenants, so give each tenant whole rows.
+#[pyfunction]
+fn row_tenants(params: &PyYpirParams, item_tenants: Vec<u32>) -> PyResult<Vec<Option<u32>>> {
+    let (p, is_simplepir) = (params.params, params.is_simplepir);
+    ypir_row_tenants(p, is_simplepir, params.item_size_bytes(), &item_tenants).map_err(build_db_err)
+}
+
+/// Like `build_db`, but takes a dict of item index -> item bytes; missing
+/// indices are left zeroed.
+#[pyfunction]
+fn build_db_keyed(params: &PyYpirParams, items: HashMap<usize, Vec<u8>>) -> PyResult<Vec<u8>> {
+    ypir::db::build_db_keyed(
+        params.params,
+        params.is_simplepir,
+        params.item_size_bytes(),
+        items.iter().map(|(&i, x)| (i, x.as_slice())),
+    )
+    .map_err(build_db_err)
+}
+
+/// Like `build_db_keyed`, but consumes any iterable of `(index, item)` pairs
+/// (e.g. a generator) one at a time, so a mostly-empty database never has
+/// to be materialized as a dict in Python.
+#[pyfunction]
+fn build_sparse_db(params: &PyYpirParams, pairs: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    Ok(sparse_db_from_pairs(params, pairs)?.0)
+}
+
+fn sparse_db_from_pairs(
+    params: &PyYpirParams,
+    pairs: &Bound<'_, PyAny>,
+) -> PyResult<(Vec<u8>, WrittenItems)> {
+    let mut db = vec![0u8; db_num_bytes(params.params, params.is_simplepir)];
+    let mut written = WrittenItems::none();
+    for pair in pairs.try_iter()? {
+        let (index, item): (usize, Vec<u8>) = pair?.extract()?;
+        write_db_item(
+            params.params,
+            params.is_simplepir,
+            params.item_size_bytes(),
+            &mut db,
+            index,
+            &item,
+        )
+        .map_err(build_db_err)?;
+        written.mark(index);
+    }
+    Ok((db, written))
+}
+
+/// True lengths and positions of items laid out by `build_db_varlen`.
+#[pyclass(name = "VarlenManifest")]
+#[derive(Clone)]
+struct PyVarlenManifest {
+    inner: VarlenManifest,
+}
+
+#[pymethods]
+impl PyVarlenManifest {
+    /// Rebuild a manifest from `entries()` (e.g. after storing it).
+    #[staticmethod]
+    fn from_entries(db_cols: usize, entries: Vec<(usize, usize)>) -> Self {
+        Self {
+            inner: VarlenManifest { db_cols, entries },
+        }
+    }
+
+    /// `(byte offset, length)` of every item in the row-major database.
+    fn entries(&self) -> Vec<(usize, usize)> {
+        self.inner.entries.clone()
+    }
+
+    #[getter]
+    fn db_cols(&self) -> usize {
+        self.inner.db_cols
+    }
+
+    /// Row to pass to `query` for item `index`.
+    fn row(&self, index: usize) -> PyResult<usize> {
+        self.check_index(index)?;
+        Ok(self.inner.row(index))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+impl PyVarlenManifest {
+    fn check_index(&self, index: usize) -> PyResult<()> {
+        if index >= self.inner.len() {
+            return Err(YpirSizeError::new_err(format!(
+                "item index {} out of range for a manifest of {} items",
+                index,
+                self.inner.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Pack variable-length `items` contiguously (no per-item padding) into a
+/// database blob; returns it with the manifest `extract_varlen` needs.
+/// Items never straddle a row, so each must be at most one row long.
+#[pyfunction]
+fn build_db_varlen(
+    params: &PyYpirParams,
+    items: Vec<Vec<u8>>,
+) -> PyResult<(Vec<u8>, PyVarlenManifest)> {
+    let (db, inner) = ypir::db::build_db_varlen(
+        params.params,
+        params.is_simplepir,
+        items.iter().map(|x| x.as_slice()),
+    )
+    .map_err(build_db_err)?;
+    Ok((db, PyVarlenManifest { inner }))
+}
+
+
+/// Build spiral params from scenario helpers in ypir::params
+///
+/// `crt_limbs` (1 or 2) sets how many CRT factors the ciphertext modulus
+/// has, for comparing single- and multi-limb moduli; the default is 2.
+#[pyfunction]
+#[pyo3(signature = (num_items, item_size_bytes, is_simplepir, crt_limbs=2))]
+fn params_for(
+    num_items: usize,
+    item_size_bytes: usize,
+    is_simplepir: bool,
+    crt_limbs: usize,
+) -> PyResult<PyYpirParams> {
+    if num_items == 0 {
+        return Err(PyValueError::new_err("num_items must be > 0"));
+    }
+    if item_size_bytes == 0 {
+        return Err(PyValueError::new_err("item_size_bytes must be > 0"));
+    }
+    if !(1..=MAX_QUERY_LIMBS).contains(&crt_limbs) {
+        return Err(PyValueError::new_err(format!(
+            "crt_limbs must be 1 to {}",
+            MAX_QUERY_LIMBS
+        )));
+    }
+
+    let item_size_bits = item_size_bytes * 8;
+
+    let mut p: SpiralParams = if is_simplepir {
+        params_for_scenario_simplepir(num_items, item_size_bits)
+    } else {
+        params_for_scenario(num_items, item_size_bits)
+    };
+    if crt_limbs != p.crt_count {
+        p = params_with_crt_limbs(&p, crt_limbs);
+    }
+
+    let leaked: &'static SpiralParams = Box::leak(Box::new(p));
+
+    Ok(PyYpirParams {
+        params: leaked,
+        is_simplepir,
+        item_size_bits,
+        num_items,
+        public_seeds: PublicSeeds::default(),
+    })
+}
+
+#[pyfunction]
+fn client_new(params: &PyYpirParams) -> PyResult<PyYpirClient> {
+    let mut c = SpiralClient::init(params.params);
+    c.generate_secret_keys();
+    Ok(PyYpirClient {
+        params: params.params,
+        inner: c,
+        fingerprint: params.fingerprint_bytes(),
+        is_simplepir: params.is_simplepir,
+        item_size: params.item_size_bytes(),
+        seeds: params.public_seeds.clone(),
+        params_seeds: params.public_seeds.clone(),
+        keys_ready: true,
+    })
+}
+
+/// Create server using u8 DB elements (required by ToM512 bounds in server.rs).
+///
+/// `cache_size` > 0 enables an LRU of that many responses keyed by the
+/// `request_id` passed to `answer()`; it is disabled by default.
+///
+/// `lock_memory=True` pins the database in physical memory (see
+/// `server.lock_memory`), raising `YpirError` if the OS refuses.
+///
+/// The database is read from `length` bytes at `offset` of `db_bytes`
+/// (default: all of it), which must cover `required_db_bytes(params)`.
+///
+/// `db_bytes` can be any C-contiguous object exposing the buffer protocol
+/// (`bytes`, a numpy array, `mmap.mmap`, ...). If it is read-only and already
+/// holds the transposed layout (`inp_transposed=True`, see
+/// `params.layout_info`) at a 64-byte aligned address, as a page-aligned
+/// `mmap.mmap(..., access=mmap.ACCESS_READ)` does, the server reads it in
+/// place without copying and keeps the object alive; writes through the
+/// server (`update_item` and friends) copy the database first. Writable
+/// buffers are always copied, since Python code could change them under the
+/// server.
+///
+/// With `memory_limit_bytes`, construction raises `YpirSizeError` instead of
+/// allocating when the projected footprint (`params.server_memory_bytes()`,
+/// less the input copy when the database is read in place) exceeds it.
+///
+/// With `numa=True`, the server also keeps one copy of each run of database
+/// columns in the memory of every NUMA node (from
+/// `/sys/devices/system/node`), and `answer`/`answer_timed` compute each run
+/// on a thread pinned to its node. Answers are unchanged; memory grows by one
+/// database, and writes re-copy it. On a single-node host this only adds a
+/// thread hop.
+#[pyfunction]
+#[pyo3(signature = (
+    params, db_bytes, inp_transposed, pad_rows, cache_size=0, lock_memory=false, offset=0,
+    length=None, memory_limit_bytes=None, numa=false
+))]
+fn server_new(
+    params: &PyYpirParams,
+    db_bytes: PyBuffer<u8>,
+    inp_transposed: bool,
+    pad_rows: bool,
+    cache_size: usize,
+    lock_memory: bool,
+    offset: usize,
+    length: Option<usize>,
+    memory_limit_bytes: Option<usize>,
+    numa: bool,
+) -> PyResult<PyYpirServer> {
+    if !db_bytes.is_c_contiguous() {
+        return Err(PyValueError::new_err("db_bytes must be C-contiguous"));
+    }
+    // SAFETY: the buffer is contiguous, and the export stays alive as long
+    // as `db_bytes` (or the owner the server keeps below)
+    let data = unsafe {
+        std::slice::from_raw_parts(db_bytes.buf_ptr() as *const u8, db_bytes.len_bytes())
+    };
+    let range = db_subrange(params.params, params.is_simplepir, data.len(), offset, length)
+        .map_err(|e| PyValueError::new_err(format!("db_bytes: {}", e)))?;
+    let db = &data[range];
+
+    let layout = db_layout(params.params, params.is_simplepir, pad_rows, 1);
+    let in_place = inp_transposed
+        && db_bytes.readonly()
+        && db.as_ptr() as usize % DB_ALIGNMENT == 0
+        && db.len() == layout.total_bytes();
+    if let Some(limit) = memory_limit_bytes {
+        let mut memory = ServerMemory::new(params.params, params.is_simplepir, pad_rows, 1, 1);
+        if in_place {
+            memory.input_bytes = 0;
+        }
+        memory
+            .check_limit(limit)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+    }
+    let mut server = if !in_place {
+        build_server(params, db, inp_transposed, pad_rows, cache_size, lock_memory)?
+    } else {
+        // SAFETY: the server holds the buffer export (`owner`) for as long as
+        // it can read `db`, an exported buffer can't be freed or resized, and
+        // a read-only export can't be written through
+        let db: &'static [u8] = unsafe { std::mem::transmute::<&[u8], &'static [u8]>(db) };
+        let s = YServer::<u8>::from_shared(params.params, db, params.is_simplepir, pad_rows);
+        let mut server = PyYpirServer::with_owner(params, s, cache_size, Some(Arc::new(db_bytes)));
+        if lock_memory {
+            server.lock()?;
+        }
+        server
+    };
+    if numa {
+        server.numa = Some(NumaServer::new(params.params, &server.inner.0));
+    }
+    Ok(server)
+}
+
+/// `server_new(params, db_bytes, inp_transposed, pad_rows)`, also measuring
+/// the build: returns `(server, peak_bytes)`, where `peak_bytes` is how far
+/// the process's resident set (sampled every millisecond from
+/// `/proc/self/statm`) peaked above its size before the build, or `None`
+/// off Linux.
+///
+/// An input already in memory is not counted, so a build that copies it
+/// once peaks near `required_db_bytes(params)`; twice that means a second
+/// copy. Allocations by other threads count too.
+#[pyfunction]
+#[pyo3(signature = (params, db_bytes, inp_transposed=false, pad_rows=true))]
+fn build_server_profiled(
+    params: &PyYpirParams,
+    db_bytes: PyBuffer<u8>,
+    inp_transposed: bool,
+    pad_rows: bool,
+) -> PyResult<(PyYpirServer, Option<usize>)> {
+    let (server, peak_bytes) = peak_resident_growth(|| {
+        server_new(
+            params,
+            db_bytes,
+            inp_transposed,
+            pad_rows,
+            0,
+            false,
+            0,
+            None,
+            None,
+            false,
+        )
+    });
+    Ok((server?, peak_bytes))
+}
+
+/// Build a server straight from `(index, item)` pairs, as `build_sparse_db`
+/// would lay them out. Unlisted items are empty and decode to zeros;
+/// `server.is_written(index)` tells them apart from items that are zero.
+#[pyfunction]
+#[pyo3(signature = (params, pairs, pad_rows=true, cache_size=0, lock_memory=false))]
+fn server_new_sparse(
+    params: &PyYpirParams,
+    pairs: &Bound<'_, PyAny>,
+    pad_rows: bool,
+    cache_size: usize,
+    lock_memory: bool,
+) -> PyResult<PyYpirServer> {
+    let (db, written) = sparse_db_from_pairs(params, pairs)?;
+    let mut server = build_server(params, &db, false, pad_rows, cache_size, lock_memory)?;
+    server.written = written;
+    Ok(server)
+}
+
+/// Like `server_new`, but reads the database from the file at `path`;
+/// `offset` and `length` skip e.g. a header or trailing metadata. Only the
+/// database bytes are read.
+#[pyfunction]
+#[pyo3(signature = (
+    params, path, inp_transposed, pad_rows, cache_size=0, lock_memory=false, offset=0,
+    length=None
+))]
+fn server_from_path(
+    params: &PyYpirParams,
+    path: &str,
+    inp_transposed: bool,
+    pad_rows: bool,
+    cache_size: usize,
+    lock_memory: bool,
+    offset: usize,
+    length: Option<usize>,
+) -> PyResult<PyYpirServer> {
+    let mut file = std::fs::File::open(path)?;
+    let file_len = file.metadata()?.len() as usize;
+    let range = db_subrange(params.params, params.is_simplepir, file_len, offset, length)
+        .map_err(|e| YpirSizeError::new_err(format!("{}: {}", path, e)))?;
+    let mut db = vec![0u8; range.len()];
+    file.seek(SeekFrom::Start(range.start as u64))?;
+    file.read_exact(&mut db)?;
+    build_server(params, &db, inp_transposed, pad_rows, cache_size, lock_memory)
+}
+
+/// Adopt an existing mapping of `len` bytes at address `addr` as the server's
+/// database, without copying: e.g. an mmap made by a prefork parent, which
+/// forked children then share copy-on-write. The mapping must hold the
+/// transposed database as described by `params.layout_info("u8")` and be
+/// 64-byte aligned (page-aligned mmaps are).
+///
+/// Safety: the mapping must stay mapped and unmodified for the rest of the
+/// process, since the server (and any copy of it) reads it for as long as
+/// it lives; unmapping or writing it is undefined behavior. Writes through
+/// the server (`update_item` and friends) copy the database first and
+/// leave the mapping untouched.
+#[pyfunction]
+#[pyo3(signature = (params, addr, len, pad_rows=true, cache_size=0, lock_memory=false))]
+fn server_from_shared(
+    params: &PyYpirParams,
+    addr: usize,
+    len: usize,
+    pad_rows: bool,
+    cache_size: usize,
+    lock_memory: bool,
+) -> PyResult<PyYpirServer> {
+    let layout = db_layout(params.params, params.is_simplepir, pad_rows, 1);
+    if len != layout.total_bytes() {
+        return Err(YpirSizeError::new_err(format!(
+            "shared mapping is {} bytes, database needs {}",
+            len,
+            layout.total_bytes()
+        )));
+    }
+    if addr == 0 || addr % DB_ALIGNMENT != 0 {
+        return Err(PyValueError::new_err(format!(
+            "shared mapping at {:#x} is not {}-byte aligned",
+            addr, DB_ALIGNMENT
+        )));
+    }
+    // SAFETY: the caller guarantees the mapping outlives the process and is
+    // never written, as documented above
+    let db = unsafe { std::slice::from_raw_parts(addr as *const u8, len) };
+    let s = YServer::<u8>::from_shared(params.params, db, params.is_simplepir, pad_rows);
+
+    let mut server = PyYpirServer::new(params, s, cache_size);
+    if lock_memory {
+        server.lock()?;
+    }
+    Ok(server)
+}
+
+fn build_server(
+    params: &PyYpirParams,
+    db: &[u8],
+    inp_transposed: bool,
+    pad_rows: bool,
+    cache_size: usize,
+    lock_memory: bool,
+) -> PyResult<PyYpirServer> {
+    let iter = db.iter().copied();
+    let s = YServer::<u8>::new(params.params, iter, params.is_simplepir, inp_transposed, pad_rows);
+
+    let mut server = PyYpirServer::new(params, s, cache_size);
+    if lock_memory {
+        server.lock()?;
+    }
+    Ok(server)
+}
+
+/// Generate a query. If `pack=true`, return packed query bytes suitable for server.answer().
+///
+/// `endianness` ("little" or "big") selects the byte order of the returned
+/// words; `answer` and `extract` take the same argument and must agree.
+///
+/// With `logical=True`, `index_row` is a logical item index and is mapped to
+/// its database row (see `params.logical_to_physical`).
+///
+/// `packing` doesn't choose how many ciphertexts get packed: it pre-scales
+/// the encoded index by `1 / poly_len` so that YPIR's ring-packing step,
+/// which packs `params.packing_factor()` LWE ciphertexts per RLWE
+/// ciphertext, cancels it. `answer` returns the unpacked first-pass response
+/// (one word per database column), so its size is the same either way; see
+/// `response_packing` on `answer` for a smaller one.
+///
+/// `response_packing` (True or False) prefixes the packed query with a
+/// header word carrying that mode, so a remote `answer` packs its response
+/// (or doesn't) without being told separately; `extract` must then be given
+/// the same mode. Without it the query has no header and `answer` uses its
+/// own `response_packing` argument. Needs `pack=True`.
+#[pyfunction]
+#[pyo3(signature = (client, public_seed_idx, dim_log2, packing, index_row, pack, endianness="little", logical=false, response_packing=None))]
+fn query(
+    client: &mut PyYpirClient,
+    public_seed_idx: u8,
+    dim_log2: usize,
+    packing: bool,
+    index_row: usize,
+    pack: bool,
+    endianness: &str,
+    logical: bool,
+    response_packing: Option<bool>,
+) -> PyResult<Vec<u8>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    if response_packing.is_some() && !pack {
+        return Err(PyValueError::new_err(
+            "response_packing needs a packed query (pack=True)",
+        ));
+    }
+    let index_row = if logical {
+        logical_to_physical(client.params, client.is_simplepir, client.item_size, index_row)
+            .ok_or_else(|| {
+                YpirSizeError::new_err(format!("item index {} out of range", index_row))
+            })?
+    } else {
+        index_row
+    };
+    let words = client_query_words(
+        client.params,
+        &mut client.inner,
+        &client.seeds,
+        public_seed_idx,
+        dim_log2,
+        packing,
+        index_row,
+        pack,
+    );
+    let words = match response_packing {
+        Some(response_packing) => with_response_mode(&words, response_packing),
+        None => words,
+    };
+    Ok(u64_to_bytes(&words, endianness))
+}
+
+/// Queries for the `count` consecutive items from `start_index`, e.g. a
+/// record stored across several items: one query per database row the span
+/// touches, in row order, which `answer_batch` answers in one pass. Decode
+/// the responses with `extract_span`.
+#[pyfunction]
+#[pyo3(signature = (
+    client, public_seed_idx, dim_log2, packing, start_index, count, pack, endianness="little"
+))]
+fn query_span(
+    client: &mut PyYpirClient,
+    public_seed_idx: u8,
+    dim_log2: usize,
+    packing: bool,
+    start_index: usize,
+    count: usize,
+    pack: bool,
+    endianness: &str,
+) -> PyResult<Vec<Vec<u8>>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let spans = client_span(client, start_index, count)?;
+    Ok(spans
+        .into_iter()
+        .map(|(row, _)| {
+            client_query_bytes(
+                client.params,
+                &mut client.inner,
+                &client.seeds,
+                public_seed_idx,
+                dim_log2,
+                packing,
+                row,
+                pack,
+                endianness,
+            )
+        })
+        .collect())
+}
 
-// ---------- helpers: bytes <-> u64 words (little-endian) ----------
+fn client_span(
+    client: &PyYpirClient,
+    start_index: usize,
+    count: usize,
+) -> PyResult<Vec<(usize, std::ops::Range<usize>)>> {
+    span_row_ranges(client.params, client.is_simplepir, client.item_size, start_index, count)
+        .ok_or_else(|| {
+            YpirSizeError::new_err(format!(
+                "span of {} items from index {} out of range",
+                count, start_index
+            ))
+        })
+}
 
-unsafe fn shrink_client_lifetime<'a>(
-    c: &'a mut SpiralClient<'static>,
-) -> &'a mut SpiralClient<'a> {
-    std::mem::transmute::<&'a mut SpiralClient<'static>, &'a mut SpiralClient<'a>>(c)
+/// Like `query`, but returns the query as a list of u64 words instead of bytes.
+#[pyfunction]
+fn query_words(
+    client: &mut PyYpirClient,
+    public_seed_idx: u8,
+    dim_log2: usize,
+    packing: bool,
+    index_row: usize,
+    pack: bool,
+) -> PyResult<Vec<u64>> {
+    client.check_keys()?;
+    Ok(client_query_words(
+        client.params,
+        &mut client.inner,
+        &client.seeds,
+        public_seed_idx,
+        dim_log2,
+        packing,
+        index_row,
+        pack,
+    ))
 }
 
-unsafe fn shrink_params_lifetime<'a>(
-    p: &'static SpiralParams,
-) -> &'a SpiralParams {
-    std::mem::transmute::<&'static SpiralParams, &'a SpiralParams>(p)
+/// Answer a packed query given as u64 words, returning the response words;
+/// the word-level counterpart of `answer` (no cache or fingerprint check).
+#[pyfunction]
+fn answer_words(server: &PyYpirServer, packed_query_words: Vec<u64>) -> PyResult<Vec<u64>> {
+    server.check_untagged()?;
+    server
+        .inner
+        .check_query(&packed_query_words)
+        .map_err(query_err)?;
+    Ok(server
+        .inner
+        .answer_query(&packed_query_words)
+        .as_slice()
+        .to_vec())
 }
 
-fn bytes_to_u64_le(b: &[u8]) -> PyResult<Vec<u64>> {
-    if b.len() % 8 != 0 {
-        return Err(PyValueError::new_err("byte length must be multiple of 8"));
+/// Answer a packed query. If the server was built with a response cache and a
+/// `request_id` is given, a retried request returns the cached response
+/// instead of being recomputed.
+///
+/// If `fingerprint` is given (typically `client.fingerprint()`), the query is
+/// rejected unless it matches the server's params fingerprint. A query of the
+/// wrong size raises `YpirSizeError`; `server.check_query` runs the same
+/// checks without answering.
+///
+/// The query is read, and the response written, in `endianness` byte order.
+///
+/// With `numeric=True` the query is instead one plain integer coefficient per
+/// database row (not packed, not encrypted), and the response holds the exact
+/// integer dot product with each column, without modular reduction. That is
+/// only correct if no column's sum reaches 2^64, which the caller must ensure
+/// by keeping coefficients and values small. Numeric responses are not cached.
+///
+/// With `framed=True` the response is preceded by its byte length as a
+/// `frame_prefix_bytes`-byte (4 or 8) big-endian integer, for stream
+/// transports; `extract(..., framed=True)` checks and strips it.
+///
+/// With `response_packing=True` each response word is cut to the modulus'
+/// `modulus_log2` bits and the words are packed back to back, shrinking the
+/// response to `params.packed_response_size_bytes()` at the cost of a
+/// bit-level copy here and in `extract(..., response_packing=True)`, which
+/// must be told to expect it. Numeric responses can't be packed this way.
+/// A query from `query(..., response_packing=...)` carries the mode in its
+/// header, which is used when `response_packing` is not given; giving the
+/// other mode raises `ValueError`.
+///
+/// On a server with tenant-tagged rows (`set_row_tenants`), `token` is the
+/// tenant the caller has been authenticated as, and is required: the answer
+/// covers only that tenant's rows, and the response cache is not used.
+///
+/// With `top_bytes`, only the columns holding the first `top_bytes` bytes of
+/// each item in the queried row are computed, for a quick preview;
+/// `extract_preview` decodes it. Every item of the row is covered, so the
+/// item stays private, but items must tile the row (raises `YpirSizeError`
+/// otherwise). Previews can't be numeric or packed and are not cached.
+///
+/// With `cancel` (a `CancelToken`), the answer runs without the GIL and
+/// checks the token every few hundred columns; once `cancel.cancel()` is
+/// called it raises `YpirError("cancelled")` at the next check. It reads the
+/// main database copy, not the per-node ones of `server_new(..., numa=True)`.
+#[pyfunction]
+#[pyo3(signature = (
+    server, packed_query_bytes, request_id=None, fingerprint=None, endianness="little",
+    numeric=false, framed=false, frame_prefix_bytes=8, response_packing=None, token=None,
+    top_bytes=None, cancel=None,
+))]
+fn answer(
+    py: Python<'_>,
+    server: &mut PyYpirServer,
+    packed_query_bytes: Vec<u8>,
+    request_id: Option<String>,
+    fingerprint: Option<Vec<u8>>,
+    endianness: &str,
+    numeric: bool,
+    framed: bool,
+    frame_prefix_bytes: usize,
+    response_packing: Option<bool>,
+    token: Option<u32>,
+    top_bytes: Option<usize>,
+    cancel: Option<&PyCancelToken>,
+) -> PyResult<Vec<u8>> {
+    let (header_mode, packed_query_bytes) = split_response_mode(
+        &packed_query_bytes,
+        server.inner.db_rows_padded(),
+        parse_endianness(endianness)?,
+    )?;
+    let response_packing = match (header_mode, response_packing) {
+        (Some(header), Some(given)) if header != given => {
+            return Err(PyValueError::new_err(
+                "the query header and response_packing ask for different response modes",
+            ))
+        }
+        (Some(mode), _) | (None, Some(mode)) => mode,
+        (None, None) => false,
+    };
+    if numeric && response_packing {
+        return Err(PyValueError::new_err(
+            "numeric responses are not reduced mod the modulus and cannot be packed",
+        ));
     }
-    let mut out = Vec::with_capacity(b.len() / 8);
-    for chunk in b.chunks_exact(8) {
-        out.push(u64::from_le_bytes(chunk.try_into().unwrap()));
+    if top_bytes.is_some() && (numeric || response_packing) {
+        return Err(PyValueError::new_err(
+            "previews cannot be numeric or packed",
+        ));
     }
-    Ok(out)
-}
-
-fn u64_to_bytes_le(words: &[u64]) -> Vec<u8> {
-    let mut out = Vec::with_capacity(words.len() * 8);
-    for &w in words {
-        out.extend_from_slice(&w.to_le_bytes());
+    let mut resp = match top_bytes {
+        Some(top_bytes) => answer_preview_unframed(
+            server,
+            packed_query_bytes,
+            fingerprint.as_deref(),
+            endianness,
+            token,
+            top_bytes,
+        )?,
+        None => answer_unframed(
+            py,
+            server,
+            packed_query_bytes,
+            request_id.as_deref(),
+            fingerprint.as_deref(),
+            endianness,
+            numeric,
+            token,
+            cancel.map(|c| &c.inner),
+        )?,
+    };
+    if response_packing {
+        let words = bytes_to_u64(&resp, parse_endianness(endianness)?)?;
+        resp = pack_response(server.params, &words);
     }
-    out
+    if !framed {
+        return Ok(resp);
+    }
+    length_prefixed(&resp, frame_prefix_bytes).map_err(|e| PyValueError::new_err(e.to_string()))
 }
 
-fn aligned64_to_bytes_le(mem: &AlignedMemory64) -> Vec<u8> {
-    u64_to_bytes_le(mem.as_slice())
+fn answer_preview_unframed(
+    server: &PyYpirServer,
+    packed_query_bytes: &[u8],
+    fingerprint: Option<&[u8]>,
+    endianness: &str,
+    token: Option<u32>,
+    top_bytes: usize,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words =
+        server.checked_query_words(packed_query_bytes, fingerprint, endianness, token)?;
+    let resp = server
+        .inner
+        .answer_preview(&packed_words, server.item_size, top_bytes)
+        .map_err(|e| YpirSizeError::new_err(e.to_string()))?;
+    Ok(u64_to_bytes(&resp, endianness))
 }
 
-// ---------- Python-exposed wrapper types ----------
-// IMPORTANT: mark unsendable so PyO3 does NOT require Send/Sync.
+fn answer_unframed(
+    py: Python<'_>,
+    server: &mut PyYpirServer,
+    packed_query_bytes: &[u8],
+    request_id: Option<&str>,
+    fingerprint: Option<&[u8]>,
+    endianness: &str,
+    numeric: bool,
+    token: Option<u32>,
+    cancel: Option<&CancelToken>,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words =
+        server.checked_query_words(packed_query_bytes, fingerprint, endianness, token)?;
+    if numeric {
+        let resp = server.inner.answer_query_numeric(&packed_words);
+        return Ok(aligned64_to_bytes(&resp, endianness));
+    }
+    // request ids aren't per tenant
+    let request_id = request_id.filter(|_| token.is_none());
 
-#[pyclass(unsendable)]
-struct PyYpirParams {
-    params: &'static SpiralParams,
-    is_simplepir: bool,
-    item_size_bits: usize,
+    // cached responses are kept little-endian regardless of the caller's order
+    if let Some(id) = request_id {
+        if let Some(cached) = server.cache.get(id) {
+            let words = bytes_to_u64(cached, Endianness::Little)?;
+            return Ok(u64_to_bytes(&words, endianness));
+        }
+    }
+
+    let resp = match cancel {
+        // without the GIL, so another Python thread can cancel
+        Some(cancel) => {
+            let inner = &server.inner;
+            py.detach(|| {
+                inner
+                    .answer_query_cancellable(&packed_words, cancel)
+                    .map(|resp| resp.as_slice().to_vec())
+            })
+            .map_err(|e| YpirError::new_err(e.to_string()))?
+        }
+        None => server.answer_packed(&packed_words).as_slice().to_vec(),
+    };
+
+    if let Some(id) = request_id {
+        server
+            .cache
+            .insert(id, u64_to_bytes(&resp, Endianness::Little));
+    }
+    Ok(u64_to_bytes(&resp, endianness))
 }
 
-#[pymethods]
-impl PyYpirParams {
-    fn __repr__(&self) -> String {
-        format!(
-            "PyYpirParams(simplepir={}, item_size_bits={})",
-            self.is_simplepir, self.item_size_bits
-        )
+/// Answer several packed queries in one pass over the database, returning
+/// one response per query, in order, each equal to what `answer` returns
+/// (without the response cache). Each query is validated as in `answer`.
+#[pyfunction]
+#[pyo3(signature = (server, packed_queries, fingerprint=None, endianness="little"))]
+fn answer_batch(
+    server: &PyYpirServer,
+    packed_queries: Vec<Vec<u8>>,
+    fingerprint: Option<Vec<u8>>,
+    endianness: &str,
+) -> PyResult<Vec<Vec<u8>>> {
+    let endianness = parse_endianness(endianness)?;
+    let mut words = Vec::new();
+    for query in &packed_queries {
+        words.extend(server.checked_query_words(query, fingerprint.as_deref(), endianness, None)?);
     }
+    let resp = server
+        .inner
+        .answer_batch(&words, packed_queries.len())
+        .map_err(query_err)?;
+    let db_cols = server.inner.db_cols();
+    Ok(resp
+        .as_slice()
+        .chunks_exact(db_cols)
+        .map(|words| u64_to_bytes(words, endianness))
+        .collect())
 }
 
-#[pyclass(unsendable)]
-struct PyYpirClient {
-    params: &'static SpiralParams,
-    inner: SpiralClient<'static>,
+/// Split query (or any) bytes into fragments of at most `max_fragment` bytes
+/// each, including an 8-byte header with the fragment's index and the total.
+#[pyfunction]
+fn fragment_query(query_bytes: &[u8], max_fragment: usize) -> PyResult<Vec<Vec<u8>>> {
+    fragment(query_bytes, max_fragment).map_err(|e| PyValueError::new_err(e.to_string()))
 }
 
-#[pyclass(unsendable)]
-struct PyYpirServer {
-    params: &'static SpiralParams,
-    db_bytes: Vec<u8>,
-    inner: YServer<'static, u8>,
+/// Rebuild the bytes split by `fragment_query`, from fragments in any order.
+/// Raises `YpirError` if any are missing, repeated or malformed.
+#[pyfunction]
+fn reassemble_query(fragments: Vec<Vec<u8>>) -> PyResult<Vec<u8>> {
+    reassemble(&fragments).map_err(|e| YpirError::new_err(e.to_string()))
 }
 
-// ---------- constructors / API ----------
+/// Short, non-reversible digest of a packed query for audit logs. Queries are
+/// randomized, so it correlates a request without identifying the index.
+#[pyfunction]
+#[pyo3(signature = (packed_query_bytes, endianness="little"))]
+fn query_digest(packed_query_bytes: Vec<u8>, endianness: &str) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    Ok(ypir_query_digest(&packed_words).to_vec())
+}
 
+/// Append a tag tying a response to the packed query it answers, for
+/// `verify_response_for_query` on the client. The tag is the last 16 bytes.
 #[pyfunction]
-fn params_db_dim_1(params: &PyYpirParams) -> usize {
-    params.params.db_dim_1
+#[pyo3(signature = (packed_query_bytes, response_bytes, endianness="little"))]
+fn tag_response(
+    packed_query_bytes: Vec<u8>,
+    response_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    let response_words = bytes_to_u64(&response_bytes, endianness)?;
+    let tagged = ypir_tag_response(&packed_words, &response_words);
+    Ok(u64_to_bytes(&tagged, endianness))
 }
 
+/// Whether a tagged response (from `tag_response`) answers this packed query
+/// rather than another one, e.g. after passing through a relay. Not
+/// cryptographically binding: it catches crossed wires, not forgeries. Drop
+/// the last 16 bytes before decoding.
 #[pyfunction]
-fn required_db_bytes(params: &PyYpirParams) -> usize {
-    let p = params.params;
-    let db_rows = 1 << (p.db_dim_1 + p.poly_len_log2);
-    let db_cols = if params.is_simplepir {
-        p.instances * p.poly_len
-    } else {
-        1 << (p.db_dim_2 + p.poly_len_log2)
-    };
-    db_rows * db_cols
+#[pyo3(signature = (packed_query_bytes, tagged_response_bytes, endianness="little"))]
+fn verify_response_for_query(
+    packed_query_bytes: Vec<u8>,
+    tagged_response_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<bool> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    let tagged_words = bytes_to_u64(&tagged_response_bytes, endianness)?;
+    Ok(ypir_verify_response_for_query(&packed_words, &tagged_words))
+}
+
+/// Answer one packed query against several servers built with the same
+/// params (e.g. two versions of a database) in a single kernel pass, reusing
+/// each query word across the databases. Returns one response per server, in
+/// order, each equal to what `answer` would return for that server.
+#[pyfunction]
+#[pyo3(signature = (servers, packed_query_bytes, endianness="little"))]
+fn answer_multi_db(
+    servers: Vec<PyRef<'_, PyYpirServer>>,
+    packed_query_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<Vec<Vec<u8>>> {
+    let endianness = parse_endianness(endianness)?;
+    if let Some(first) = servers.first() {
+        if servers.iter().any(|s| s.fingerprint != first.fingerprint) {
+            return Err(PyValueError::new_err(
+                "params fingerprint mismatch: servers use different params",
+            ));
+        }
+    }
+    for server in &servers {
+        server.check_untagged()?;
+    }
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    let inners = servers.iter().map(|s| &s.inner.0).collect::<Vec<_>>();
+    let responses = YServer::answer_query_multi(&inners, &packed_words).map_err(query_err)?;
+    Ok(responses
+        .iter()
+        .map(|resp| aligned64_to_bytes(resp, endianness))
+        .collect())
 }
 
+/// Like `answer` (without the cache or fingerprint check), but also returns
+/// the server time in milliseconds and, if `digest` is set, the
+/// `query_digest` of the query: `(response, server_time_ms, digest or None)`.
+#[pyfunction]
+#[pyo3(signature = (server, packed_query_bytes, digest=false, endianness="little"))]
+fn answer_timed(
+    server: &PyYpirServer,
+    packed_query_bytes: Vec<u8>,
+    digest: bool,
+    endianness: &str,
+) -> PyResult<(Vec<u8>, f64, Option<Vec<u8>>)> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = server.checked_query_words(&packed_query_bytes, None, endianness, None)?;
+    let start = Instant::now();
+    let resp: AlignedMemory64 = server.answer_packed(&packed_words);
+    let server_time_ms = start.elapsed().as_secs_f64() * 1000.;
+    let digest = digest.then(|| ypir_query_digest(&packed_words).to_vec());
+    Ok((aligned64_to_bytes(&resp, endianness), server_time_ms, digest))
+}
 
-/// Build spiral params from scenario helpers in ypir::params
+/// Read one length-prefixed packed query from file descriptor `in_fd`,
+/// answer it and write the length-prefixed response to `out_fd`.
+///
+/// Frames are a little-endian u64 byte length followed by the payload. The
+/// descriptors are borrowed, not closed. Fingerprint checks and the response
+/// cache are not applied.
+#[cfg(unix)]
 #[pyfunction]
-fn params_for(num_items: usize, item_size_bytes: usize, is_simplepir: bool) -> PyResult<PyYpirParams> {
-    if num_items == 0 {
-        return Err(PyValueError::new_err("num_items must be > 0"));
+#[pyo3(signature = (server, in_fd, out_fd, endianness="little"))]
+fn answer_fd(server: &PyYpirServer, in_fd: i32, out_fd: i32, endianness: &str) -> PyResult<()> {
+    use std::fs::File;
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+
+    server.check_untagged()?;
+    let endianness = parse_endianness(endianness)?;
+    // SAFETY: the caller owns both descriptors; ManuallyDrop keeps us from
+    // closing them.
+    let mut input = ManuallyDrop::new(unsafe { File::from_raw_fd(in_fd) });
+    let mut output = ManuallyDrop::new(unsafe { File::from_raw_fd(out_fd) });
+    answer_stream(&server.inner.0, &mut *input, &mut *output, endianness)?;
+    Ok(())
+}
+
+/// Answer a packed query against a transposed database file (as written by
+/// `transpose_db`, or `dump_transposed`) without loading it into memory.
+///
+/// The file is read `cols_per_block` columns at a time on a background
+/// thread while the previous block is being computed.
+#[pyfunction]
+#[pyo3(signature = (params, db_path, packed_query_bytes, cols_per_block=64, endianness="little"))]
+fn answer_from_file(
+    py: Python<'_>,
+    params: &PyYpirParams,
+    db_path: &str,
+    packed_query_bytes: Vec<u8>,
+    cols_per_block: usize,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    if cols_per_block == 0 {
+        return Err(PyValueError::new_err("cols_per_block must be positive"));
     }
-    if item_size_bytes == 0 {
-        return Err(PyValueError::new_err("item_size_bytes must be > 0"));
+    // the transposed layout `answer_file` reads, padded rows included
+    let needed = db_layout(params.params, params.is_simplepir, true, 1).total_bytes();
+    let file = std::fs::File::open(db_path)?;
+    let len = file.metadata()?.len() as usize;
+    if len != needed {
+        return Err(YpirSizeError::new_err(format!(
+            "{} is {} bytes, expected {}",
+            db_path, len, needed
+        )));
+    }
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    if packed_words.len() != params.params.db_rows_padded() {
+        return Err(YpirSizeError::new_err(format!(
+            "packed query is {} words, expected {}",
+            packed_words.len(),
+            params.params.db_rows_padded()
+        )));
     }
 
-    let item_size_bits = item_size_bytes * 8;
+    let (p, is_simplepir) = (params.params, params.is_simplepir);
+    let resp =
+        py.detach(|| answer_file(p, is_simplepir, &packed_words, &file, cols_per_block))?;
+    Ok(aligned64_to_bytes(&resp, endianness))
+}
 
-    let p: SpiralParams = if is_simplepir {
-        params_for_scenario_simplepir(num_items, item_size_bits)
-    } else {
-        params_for_scenario(num_items, item_size_bits)
-    };
+/// Overwrite item `index` in place in a database file read by
+/// `answer_from_file`, so processes sharing the file see the update.
+///
+/// The write holds an exclusive advisory lock on the file, and
+/// `answer_from_file` a shared one for its whole pass, so an answer sees the
+/// item entirely before or entirely after the update. Readers that don't
+/// lock (an mmap passed to `server_new`, say) are not covered, and servers
+/// already built from the file keep the copy they loaded.
+#[pyfunction]
+fn update_item_in_file(
+    params: &PyYpirParams,
+    db_path: &str,
+    index: usize,
+    item: Vec<u8>,
+) -> PyResult<()> {
+    let file = std::fs::File::options().read(true).write(true).open(db_path)?;
+    let item_size = params.item_size_bytes();
+    ypir_update_item_in_file(params.params, params.is_simplepir, item_size, &file, index, &item)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::InvalidInput => YpirSizeError::new_err(e.to_string()),
+            _ => e.into(),
+        })
+}
 
-    let leaked: &'static SpiralParams = Box::leak(Box::new(p));
+/// Answer a packed query against a database that is never held in memory:
+/// `provider(col)` is called once per column, in order, and must return that
+/// column of the transposed, row-padded database (`layout_info("u8")
+/// ["col_stride"]` bytes). Exceptions raised by `provider` propagate.
+#[pyfunction]
+#[pyo3(signature = (params, packed_query_bytes, provider, endianness="little"))]
+fn answer_with_provider(
+    params: &PyYpirParams,
+    packed_query_bytes: Vec<u8>,
+    provider: &Bound<'_, PyAny>,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = bytes_to_u64(&packed_query_bytes, endianness)?;
+    if packed_words.len() != params.params.db_rows_padded() {
+        return Err(YpirSizeError::new_err(format!(
+            "packed query is {} words, expected {}",
+            packed_words.len(),
+            params.params.db_rows_padded()
+        )));
+    }
 
-    Ok(PyYpirParams {
-        params: leaked,
-        is_simplepir,
-        item_size_bits,
-    })
+    // keep the provider's own exception rather than a converted io::Error
+    let mut provider_err = None;
+    let resp = ypir_answer_with_provider(params.params, params.is_simplepir, &packed_words, |col| {
+        provider
+            .call1((col,))
+            .and_then(|column| column.extract::<Vec<u8>>())
+            .map_err(|e| {
+                provider_err = Some(e);
+                std::io::Error::other("provider failed")
+            })
+    });
+    match (resp, provider_err) {
+        (_, Some(e)) => Err(e),
+        (Ok(resp), None) => Ok(aligned64_to_bytes(&resp, endianness)),
+        (Err(e), None) => Err(YpirSizeError::new_err(e.to_string())),
+    }
 }
 
 #[pyfunction]
-fn client_new(params: &PyYpirParams) -> PyResult<PyYpirClient> {
-    let mut c = SpiralClient::init(params.params);
-    c.generate_secret_keys();
-    Ok(PyYpirClient {
-        params: params.params,
-        inner: c,
+fn set_future_result(fut: &Bound<'_, PyAny>, value: &Bound<'_, PyAny>) -> PyResult<()> {
+    // the awaiting task may have been cancelled in the meantime
+    if !fut.call_method0("done")?.is_truthy()? {
+        fut.call_method1("set_result", (value,))?;
+    }
+    Ok(())
+}
+
+#[pyfunction]
+fn set_future_exception(fut: &Bound<'_, PyAny>, exc: &Bound<'_, PyAny>) -> PyResult<()> {
+    if !fut.call_method0("done")?.is_truthy()? {
+        fut.call_method1("set_exception", (exc,))?;
+    }
+    Ok(())
+}
+
+/// Workers for `answer_async`, one per CPU, started on first use.
+fn answer_pool() -> &'static WorkerPool {
+    static POOL: OnceLock<WorkerPool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        WorkerPool::new(std::thread::available_parallelism().map_or(1, |n| n.get()))
     })
 }
 
-/// Create server using u8 DB elements (required by ToM512 bounds in server.rs).
+/// Awaitable version of `answer` for asyncio servers: the query is answered
+/// on a worker thread, without holding the GIL, and the returned future
+/// resolves to the response bytes on the running event loop.
+///
+/// The workers are a shared pool of one thread per CPU; once they are all
+/// busy, further calls queue rather than starting threads of their own.
+///
+/// Must be called from a coroutine running in an event loop. The response
+/// cache and fingerprint check are not applied.
 #[pyfunction]
-fn server_new(
-    params: &PyYpirParams,
-    db_bytes: Vec<u8>,
-    inp_transposed: bool,
-    pad_rows: bool,
-) -> PyResult<PyYpirServer> {
-    let p = params.params;
+#[pyo3(signature = (server, packed_query_bytes, endianness="little"))]
+fn answer_async<'py>(
+    py: Python<'py>,
+    server: &PyYpirServer,
+    packed_query_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<Bound<'py, PyAny>> {
+    let endianness = parse_endianness(endianness)?;
+    let packed_words = server.checked_query_words(&packed_query_bytes, None, endianness, None)?;
+
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    let fut = event_loop.call_method0("create_future")?;
+
+    let inner = Arc::clone(&server.inner);
+    let event_loop_handle = event_loop.clone().unbind();
+    let fut_handle = fut.clone().unbind();
+    answer_pool().execute(move || {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            aligned64_to_bytes(&inner.answer_query(&packed_words), endianness)
+        }));
+        Python::attach(|py| {
+            let event_loop = event_loop_handle.bind(py);
+            let fut = fut_handle.bind(py);
+            let scheduled = match result {
+                Ok(resp_bytes) => wrap_pyfunction!(set_future_result, py).and_then(|setter| {
+                    event_loop.call_method1("call_soon_threadsafe", (setter, fut, resp_bytes))
+                }),
+                Err(_) => wrap_pyfunction!(set_future_exception, py).and_then(|setter| {
+                    let exc = YpirError::new_err("answer_async: answering the query panicked");
+                    event_loop.call_method1(
+                        "call_soon_threadsafe",
+                        (setter, fut, exc.value(py).clone()),
+                    )
+                }),
+            };
+            // the loop may already be closed; nobody is left to tell
+            if let Err(e) = scheduled {
+                e.print(py);
+            }
+        });
+    });
+
+    Ok(fut)
+}
 
-    let db_rows = 1 << (p.db_dim_1 + p.poly_len_log2);
-    let db_cols = if params.is_simplepir {
-        p.instances * p.poly_len
+/// Decode a response into plaintext words, returned as little-endian u64s.
+/// `endianness` is the byte order of `response_bytes` only.
+///
+/// `fast=True` rounds in floating point and skips the noise measurement. Values
+/// very close to halfway between two encodings may round the other way, so the
+/// failure probability is slightly higher than the exact path; on parameters
+/// with a comfortable noise margin the two agree.
+///
+/// With `deadline_micros`, decoding raises `YpirError("deadline exceeded")`
+/// once that many microseconds have passed since the call, instead of running
+/// unbounded on a pathological response.
+///
+/// `framed=True` takes a response from `answer(..., framed=True)` and raises
+/// `YpirSizeError` if its length prefix doesn't match the payload.
+///
+/// `response_packing=True` takes a response from
+/// `answer(..., response_packing=True)`. The two modes give responses of
+/// different sizes, so a response in the other mode raises `YpirSizeError`.
+#[pyfunction]
+#[pyo3(signature = (
+    client, response_bytes, endianness="little", fast=false, deadline_micros=None,
+    framed=false, frame_prefix_bytes=8, response_packing=false,
+))]
+fn extract(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    endianness: &str,
+    fast: bool,
+    deadline_micros: Option<u64>,
+    framed: bool,
+    frame_prefix_bytes: usize,
+    response_packing: bool,
+) -> PyResult<Vec<u8>> {
+    // a deadline too far out to represent is no deadline
+    let deadline =
+        deadline_micros.and_then(|us| Instant::now().checked_add(Duration::from_micros(us)));
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let response_bytes = if framed {
+        strip_length_prefix(&response_bytes, frame_prefix_bytes)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?
     } else {
-        1 << (p.db_dim_2 + p.poly_len_log2)
+        &response_bytes
     };
-    let needed = db_rows * db_cols;
+    let resp_words = if response_packing {
+        unpack_response_words(client.params, client.is_simplepir, response_bytes)?
+    } else {
+        let expected = response_size_bytes(client.params, client.is_simplepir);
+        if response_bytes.len() != expected {
+            return Err(YpirSizeError::new_err(format!(
+                "response is {} bytes, expected {} (a bit-packed response needs \
+                 response_packing=True)",
+                response_bytes.len(),
+                expected
+            )));
+        }
+        bytes_to_u64(response_bytes, endianness)?
+    };
+    let out = client_extract_words_by(client.params, &mut client.inner, &resp_words, deadline, fast)
+        .map_err(|e| YpirError::new_err(e.to_string()))?;
+    Ok(decoded_to_bytes(&out))
+}
 
-    if db_bytes.len() < needed {
-        return Err(PyValueError::new_err(format!(
-            "db_bytes too small: got {} bytes, need at least {} (db_rows={} db_cols={})",
-            db_bytes.len(),
-            needed,
-            db_rows,
-            db_cols
-        )));
+/// Bundle a query and its response with `params`' fingerprint, for shipping
+/// a failed decode back to be diagnosed with `replay_transcript`. Contains
+/// no secret key; the replaying client must supply the one the query was
+/// made with.
+#[pyfunction]
+fn record_transcript(
+    params: &PyYpirParams,
+    query_bytes: Vec<u8>,
+    response_bytes: Vec<u8>,
+) -> Vec<u8> {
+    Transcript {
+        fingerprint: params.fingerprint_bytes(),
+        query: query_bytes,
+        response: response_bytes,
     }
+    .to_bytes()
+}
 
-    let db_for_server = db_bytes[..needed].to_vec();
-    let iter = db_for_server.iter().copied();
+/// Re-run `extract` on the response in a `record_transcript` blob; raises
+/// `ValueError` if it is malformed or was recorded under other params.
+#[pyfunction]
+#[pyo3(signature = (client, transcript, endianness="little"))]
+fn replay_transcript(
+    client: &mut PyYpirClient,
+    transcript: &[u8],
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let transcript = Transcript::from_bytes(transcript)
+        .map_err(|e| PyValueError::new_err(format!("transcript: {}", e)))?;
+    if transcript.fingerprint != client.fingerprint {
+        return Err(PyValueError::new_err(
+            "params fingerprint mismatch: transcript was recorded under different params",
+        ));
+    }
+    extract(client, transcript.response, endianness, false, None, false, 8, false)
+}
 
-    let s = YServer::<u8>::new(p, iter, params.is_simplepir, inp_transposed, pad_rows);
+/// Decode a response into the plaintext coefficients (each below
+/// `pt_modulus`), before they are packed into bytes; `extract` returns the
+/// same values as u64 words.
+#[pyfunction]
+#[pyo3(signature = (client, response_bytes, endianness="little"))]
+fn extract_coeffs(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<Vec<u64>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+    Ok(client_extract_words(client.params, &mut client.inner, &resp_words).0)
+}
 
-    Ok(PyYpirServer {
-        params: p,
-        db_bytes: db_for_server,
-        inner: s,
-    })
+/// Decode a response into item bytes (one byte per output coefficient).
+#[pyfunction]
+#[pyo3(signature = (client, response_bytes, endianness="little"))]
+fn extract_item(client: &mut PyYpirClient, response_bytes: Vec<u8>, endianness: &str) -> PyResult<Vec<u8>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+    let (out, _) = client_extract_words(client.params, &mut client.inner, &resp_words);
+    coeffs_to_item_bytes(client.params, &out)
 }
 
-/// Generate a query. If `pack=true`, return packed query bytes suitable for server.answer().
+/// Decode only the coefficients covering `byte_start..byte_start + byte_len`;
+/// equal to `extract_item(...)[byte_start:byte_start + byte_len]`.
 #[pyfunction]
-fn query(
+#[pyo3(signature = (client, response_bytes, byte_start, byte_len, endianness="little"))]
+fn extract_range(
     client: &mut PyYpirClient,
-    public_seed_idx: u8,
-    dim_log2: usize,
-    packing: bool,
-    index_row: usize,
-    pack: bool,
+    response_bytes: Vec<u8>,
+    byte_start: usize,
+    byte_len: usize,
+    endianness: &str,
 ) -> PyResult<Vec<u8>> {
-    let q_words: Vec<u64> = unsafe {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let p = client.params;
+    let db_cols = 1 << (p.db_dim_2 + p.poly_len_log2);
+    let end = byte_start.checked_add(byte_len).filter(|&end| end <= db_cols);
+    let Some(end) = end else {
+        return Err(YpirSizeError::new_err(format!(
+            "range {}+{} out of bounds for {} bytes",
+            byte_start, byte_len, db_cols
+        )));
+    };
+
+    let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+    let (out, _) = unsafe {
         let inner = shrink_client_lifetime(&mut client.inner);
-        let params = shrink_params_lifetime(client.params);
+        let params = shrink_params_lifetime(p);
         let y = YClient::new(inner, params);
-        y.generate_query(public_seed_idx, dim_log2, packing, index_row)
+        y.decode_response_range(&resp_words, byte_start..end)
     };
+    coeffs_to_item_bytes(p, &out)
+}
 
-    if pack {
-        let packed = pack_query(client.params, &q_words);
-        Ok(aligned64_to_bytes_le(&packed))
+/// The first `top_bytes` bytes of item `index` from the response to
+/// `answer(..., top_bytes=top_bytes)`; equal to
+/// `extract_item(...)[start:start + top_bytes]` on a full response, where
+/// `start` is the item's offset in its row.
+#[pyfunction]
+#[pyo3(signature = (
+    client, response_bytes, index, top_bytes, endianness="little", framed=false,
+    frame_prefix_bytes=8,
+))]
+fn extract_preview(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    index: usize,
+    top_bytes: usize,
+    endianness: &str,
+    framed: bool,
+    frame_prefix_bytes: usize,
+) -> PyResult<Vec<u8>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let response_bytes = if framed {
+        strip_length_prefix(&response_bytes, frame_prefix_bytes)
+            .map_err(|e| YpirSizeError::new_err(e.to_string()))?
     } else {
-        Ok(u64_to_bytes_le(&q_words))
+        &response_bytes
+    };
+    let p = client.params;
+    let (_, db_cols) = db_dims(p, client.is_simplepir);
+    let expected = preview_columns(db_cols, client.item_size, top_bytes)
+        .map_err(|e| YpirSizeError::new_err(e.to_string()))?
+        .len();
+    let resp_words = bytes_to_u64(response_bytes, endianness)?;
+    if resp_words.len() != expected {
+        return Err(YpirSizeError::new_err(format!(
+            "preview response is {} words, expected {}",
+            resp_words.len(),
+            expected
+        )));
+    }
+    let (is_simplepir, item_size) = (client.is_simplepir, client.item_size);
+    let out = unsafe {
+        let inner = shrink_client_lifetime(&mut client.inner);
+        let params = shrink_params_lifetime(p);
+        let y = YClient::new(inner, params);
+        y.decode_preview(&resp_words, is_simplepir, item_size, top_bytes, index)
+    };
+    coeffs_to_item_bytes(p, &out)
+}
+
+/// Decode item `index` of a `build_db_varlen` database from the response to a
+/// query for `manifest.row(index)`, trimmed to its true length.
+#[pyfunction]
+#[pyo3(signature = (client, response_bytes, manifest, index, endianness="little"))]
+fn extract_varlen(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    manifest: &PyVarlenManifest,
+    index: usize,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    manifest.check_index(index)?;
+    let cols = manifest.inner.cols(index);
+    extract_range(client, response_bytes, cols.start, cols.len(), endianness)
+}
+
+/// Decode the responses to `query_span(..., start_index, count, ...)`, in
+/// the same order, into the span's items concatenated and trimmed to
+/// `total_len` bytes.
+#[pyfunction]
+#[pyo3(signature = (client, responses, start_index, count, total_len, endianness="little"))]
+fn extract_span(
+    client: &mut PyYpirClient,
+    responses: Vec<Vec<u8>>,
+    start_index: usize,
+    count: usize,
+    total_len: usize,
+    endianness: &str,
+) -> PyResult<Vec<u8>> {
+    let spans = client_span(client, start_index, count)?;
+    if responses.len() != spans.len() {
+        return Err(YpirSizeError::new_err(format!(
+            "span covers {} rows, got {} responses",
+            spans.len(),
+            responses.len()
+        )));
+    }
+    if total_len > count * client.item_size {
+        return Err(YpirSizeError::new_err(format!(
+            "total_len {} exceeds the {} bytes of {} items",
+            total_len,
+            count * client.item_size,
+            count
+        )));
+    }
+    let mut out = Vec::with_capacity(count * client.item_size);
+    for (response, (_, cols)) in responses.into_iter().zip(spans) {
+        out.extend(extract_range(client, response, cols.start, cols.len(), endianness)?);
+    }
+    out.truncate(total_len);
+    Ok(out)
+}
+
+/// Fetch item `index` with `client` from `server` in the same process: the
+/// `query_span`, `answer`, `extract_span` path for that one item, passing
+/// queries and responses as words instead of serializing them to bytes.
+///
+/// Queries are generated as `query` would with the given seed and `packing`,
+/// for the database's first dimension, and checked against the server like
+/// any other.
+#[pyfunction]
+#[pyo3(signature = (client, server, index, public_seed_idx=0, packing=true))]
+fn local_fetch(
+    client: &mut PyYpirClient,
+    server: &PyYpirServer,
+    index: usize,
+    public_seed_idx: u8,
+    packing: bool,
+) -> PyResult<Vec<u8>> {
+    client.check_keys()?;
+    if client.fingerprint != server.fingerprint {
+        return Err(PyValueError::new_err(
+            "params fingerprint mismatch: client and server use different params",
+        ));
+    }
+    fetch_local_unchecked(client, server, index, public_seed_idx, packing)
+}
+
+/// `local_fetch` past its key and fingerprint checks.
+fn fetch_local_unchecked(
+    client: &mut PyYpirClient,
+    server: &PyYpirServer,
+    index: usize,
+    public_seed_idx: u8,
+    packing: bool,
+) -> PyResult<Vec<u8>> {
+    server.check_untagged()?;
+    let p = client.params;
+    let mut out = Vec::with_capacity(client.item_size);
+    for (row, cols) in client_span(client, index, 1)? {
+        let packed = client_query_words(
+            p,
+            &mut client.inner,
+            &client.seeds,
+            public_seed_idx,
+            p.db_dim_1,
+            packing,
+            row,
+            true,
+        );
+        server.inner.check_query(&packed).map_err(query_err)?;
+        let resp = server.inner.answer_query(&packed);
+        // SAFETY: `client.params` is the leaked `'static` params the client
+        // was built from, so shortening both lifetimes to this block only
+        // forgets how long they live; `y` borrows `client.inner` exclusively
+        // and is dropped before the next iteration touches it.
+        let (coeffs, _) = unsafe {
+            let inner = shrink_client_lifetime(&mut client.inner);
+            let params = shrink_params_lifetime(p);
+            let y = YClient::new(inner, params);
+            y.decode_response_range(resp.as_slice(), cols)
+        };
+        out.extend(coeffs_to_item_bytes(p, &coeffs)?);
     }
+    Ok(out)
+}
+
+/// Iterator returned by `client.scan(server)`.
+#[pyclass(unsendable, name = "Scan")]
+struct PyScan {
+    client: Py<PyYpirClient>,
+    server: Py<PyYpirServer>,
+    next: usize,
+    end: usize,
+    public_seed_idx: u8,
+    packing: bool,
+    trim: bool,
 }
 
+#[pymethods]
+impl PyScan {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Vec<u8>>> {
+        if self.next == self.end {
+            return Ok(None);
+        }
+        let mut client = self.client.borrow_mut(py);
+        // the keys may have been closed since the last item
+        client.check_keys()?;
+        let server = self.server.borrow(py);
+        let (seed, packing) = (self.public_seed_idx, self.packing);
+        let mut item = fetch_local_unchecked(&mut client, &server, self.next, seed, packing)?;
+        if self.trim {
+            let len = item.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            item.truncate(len);
+        }
+        self.next += 1;
+        Ok(Some(item))
+    }
+
+    /// Items left to yield.
+    fn __len__(&self) -> usize {
+        self.end - self.next
+    }
+}
 
+/// Like `extract`, but also returns the observed decode noise as a fraction of
+/// the decode threshold; values approaching 1.0 mean the params are marginal.
 #[pyfunction]
-fn answer(server: &PyYpirServer, packed_query_bytes: Vec<u8>) -> PyResult<Vec<u8>> {
-    let packed_words = bytes_to_u64_le(&packed_query_bytes)?;
-    let resp: AlignedMemory64 = server.inner.answer_query(&packed_words);
-    Ok(aligned64_to_bytes_le(&resp))
+#[pyo3(signature = (client, response_bytes, endianness="little"))]
+fn extract_with_noise(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    endianness: &str,
+) -> PyResult<(Vec<u8>, f64)> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+    let (out, noise) = client_extract_words(client.params, &mut client.inner, &resp_words);
+    Ok((decoded_to_bytes(&out), noise))
 }
 
+/// Like `extract`, but returns `None` instead of unreliable bytes when the
+/// decode noise (see `extract_with_noise`) exceeds `max_noise_ratio`.
 #[pyfunction]
-fn extract(client: &mut PyYpirClient, response_bytes: Vec<u8>) -> PyResult<Vec<u8>> {
-    let resp_words = bytes_to_u64_le(&response_bytes)?;
+#[pyo3(signature = (client, response_bytes, max_noise_ratio=DEFAULT_MAX_NOISE_RATIO, endianness="little"))]
+fn try_extract(
+    client: &mut PyYpirClient,
+    response_bytes: Vec<u8>,
+    max_noise_ratio: f64,
+    endianness: &str,
+) -> PyResult<Option<Vec<u8>>> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let resp_words = bytes_to_u64(&response_bytes, endianness)?;
+    let (out, noise) = client_extract_words(client.params, &mut client.inner, &resp_words);
+    if noise > max_noise_ratio {
+        return Ok(None);
+    }
+    Ok(Some(decoded_to_bytes(&out)))
+}
 
-    let out: Vec<u64> = unsafe {
+/// Whether two responses decode to the same plaintexts; meaningful equality
+/// for responses whose raw bytes differ only in the noise.
+#[pyfunction]
+#[pyo3(signature = (client, response_a, response_b, endianness="little"))]
+fn responses_equivalent(
+    client: &mut PyYpirClient,
+    response_a: Vec<u8>,
+    response_b: Vec<u8>,
+    endianness: &str,
+) -> PyResult<bool> {
+    client.check_keys()?;
+    let endianness = parse_endianness(endianness)?;
+    let words_a = bytes_to_u64(&response_a, endianness)?;
+    let words_b = bytes_to_u64(&response_b, endianness)?;
+    Ok(unsafe {
         let inner = shrink_client_lifetime(&mut client.inner);
         let params = shrink_params_lifetime(client.params);
-        let y = YClient::new(inner, params);
-        y.decode_response(&resp_words)
-    };
+        YClient::new(inner, params).responses_equivalent(&words_a, &words_b)
+    })
+}
+
+/// A ready-to-use `(params, server, client)` over a deterministic database
+/// of `num_items` items, item `i` being `testing.fixture_item(seed, i,
+/// item_size_bytes)`. Equal seeds give identical databases; client keys are
+/// always fresh.
+#[pyfunction]
+#[pyo3(signature = (num_items, item_size_bytes, is_simplepir=false, seed=0))]
+fn make_fixture(
+    num_items: usize,
+    item_size_bytes: usize,
+    is_simplepir: bool,
+    seed: u64,
+) -> PyResult<(PyYpirParams, PyYpirServer, PyYpirClient)> {
+    let params = params_for(num_items, item_size_bytes, is_simplepir, 2)?;
+    let db = fixture_db(params.params, is_simplepir, item_size_bytes, num_items, seed)
+        .map_err(build_db_err)?;
+    let server = build_server(&params, &db, false, true, 0, false)?;
+    let client = client_new(&params)?;
+    Ok((params, server, client))
+}
+
+/// The contents of item `index` of a `make_fixture` database: the
+/// little-endian bytes of `seed + index`, repeated to `item_size_bytes`.
+#[pyfunction]
+#[pyo3(signature = (seed, index, item_size_bytes))]
+fn fixture_item(seed: u64, index: usize, item_size_bytes: usize) -> Vec<u8> {
+    ypir_fixture_item(seed, index, item_size_bytes)
+}
+
+/// What item `index` of a seed-0 `make_fixture` database holds, so golden
+/// tests can assert `fetch(index) == expected_item(index, size)`.
+#[pyfunction]
+fn expected_item(index: usize, item_size_bytes: usize) -> Vec<u8> {
+    ypir_expected_item(index, item_size_bytes)
+}
+
+/// Time `num_iters` query/answer/extract round trips against a fixture
+/// database built for `params` (not counted in the timings), for latency
+/// regression checks. Returns `{"query": ..., "answer": ..., "extract": ...}`,
+/// each a dict of `p50_ms`, `p95_ms` and `p99_ms`.
+#[pyfunction]
+#[pyo3(signature = (params, num_iters=100))]
+fn benchmark_roundtrip<'py>(
+    py: Python<'py>,
+    params: &PyYpirParams,
+    num_iters: usize,
+) -> PyResult<Bound<'py, PyDict>> {
+    if num_iters == 0 {
+        return Err(PyValueError::new_err("num_iters must be > 0"));
+    }
+    let (p, is_simplepir, item_size) =
+        (params.params, params.is_simplepir, params.item_size_bytes());
+    let latency = py
+        .detach(|| ypir_benchmark_roundtrip(p, is_simplepir, item_size, num_iters))
+        .map_err(build_db_err)?;
+    let out = PyDict::new(py);
+    for (phase, pct) in [
+        ("query", latency.query),
+        ("answer", latency.answer),
+        ("extract", latency.extract),
+    ] {
+        let d = PyDict::new(py);
+        d.set_item("p50_ms", pct.p50_ms)?;
+        d.set_item("p95_ms", pct.p95_ms)?;
+        d.set_item("p99_ms", pct.p99_ms)?;
+        out.set_item(phase, d)?;
+    }
+    Ok(out)
+}
 
-    Ok(u64_to_bytes_le(&out))
+/// Item `index` of the row-major database `db`, computed by a slow,
+/// plainly-correct reference PIR (no packing or SIMD), for checking that the
+/// real protocol returns the same bytes. Untrimmed, unlike `get_item`.
+#[pyfunction]
+fn reference_fetch(params: &PyYpirParams, db: &[u8], index: usize) -> PyResult<Vec<u8>> {
+    let expected = db_num_bytes(params.params, params.is_simplepir);
+    if db.len() != expected {
+        return Err(YpirSizeError::new_err(format!(
+            "database is {} bytes, expected {}",
+            db.len(),
+            expected
+        )));
+    }
+    ypir_reference_fetch(
+        params.params,
+        params.is_simplepir,
+        params.item_size_bytes(),
+        db,
+        index,
+    )
+    .ok_or_else(|| YpirSizeError::new_err(format!("item index {} out of range", index)))
 }
 
 #[pymodule]
-fn ypir_rs(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+fn ypir_rs(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(params_for, m)?)?;
     m.add_function(wrap_pyfunction!(client_new, m)?)?;
     m.add_function(wrap_pyfunction!(server_new, m)?)?;
+    m.add_function(wrap_pyfunction!(build_server_profiled, m)?)?;
+    m.add_function(wrap_pyfunction!(server_from_path, m)?)?;
+    m.add_function(wrap_pyfunction!(server_from_shared, m)?)?;
+    m.add_function(wrap_pyfunction!(server_new_shard, m)?)?;
+    m.add_function(wrap_pyfunction!(shard_for_index, m)?)?;
+    m.add_function(wrap_pyfunction!(split_query_for_shards, m)?)?;
+    m.add_function(wrap_pyfunction!(combine_answers, m)?)?;
+    m.add_function(wrap_pyfunction!(server_new_multi, m)?)?;
+    m.add_function(wrap_pyfunction!(server_from_instance_files, m)?)?;
     m.add_function(wrap_pyfunction!(query, m)?)?;
     m.add_function(wrap_pyfunction!(answer, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_async, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_timed, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_multi_db, m)?)?;
+    m.add_function(wrap_pyfunction!(query_digest, m)?)?;
+    m.add_function(wrap_pyfunction!(tag_response, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_response_for_query, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark_roundtrip, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_with_provider, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(query_words, m)?)?;
+    m.add_function(wrap_pyfunction!(query_span, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_words, m)?)?;
+    #[cfg(unix)]
+    m.add_function(wrap_pyfunction!(answer_fd, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(update_item_in_file, m)?)?;
     m.add_function(wrap_pyfunction!(extract, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_with_noise, m)?)?;
+    m.add_function(wrap_pyfunction!(try_extract, m)?)?;
+    m.add_function(wrap_pyfunction!(responses_equivalent, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_item, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_coeffs, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_range, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_preview, m)?)?;
+    m.add_function(wrap_pyfunction!(quantize_db, m)?)?;
+    m.add_function(wrap_pyfunction!(dequantize_item, m)?)?;
 
     m.add_function(wrap_pyfunction!(params_db_dim_1, m)?)?;
     m.add_function(wrap_pyfunction!(required_db_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(build_db, m)?)?;
+    m.add_function(wrap_pyfunction!(row_tenants, m)?)?;
+    m.add_function(wrap_pyfunction!(build_db_keyed, m)?)?;
+    m.add_function(wrap_pyfunction!(build_db_varlen, m)?)?;
+    m.add_function(wrap_pyfunction!(build_sparse_db, m)?)?;
+    m.add_function(wrap_pyfunction!(server_new_sparse, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_varlen, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_span, m)?)?;
+    m.add_function(wrap_pyfunction!(local_fetch, m)?)?;
+    m.add_class::<PyScan>()?;
+    m.add_class::<PyServerStatus>()?;
+    m.add_class::<PyCancelToken>()?;
+    m.add_function(wrap_pyfunction!(record_transcript, m)?)?;
+    m.add_function(wrap_pyfunction!(replay_transcript, m)?)?;
+    m.add_function(wrap_pyfunction!(transpose_db, m)?)?;
+    m.add_function(wrap_pyfunction!(transpose_db_to_file, m)?)?;
+    m.add_function(wrap_pyfunction!(repack_db_u8_to_u32, m)?)?;
+    m.add_function(wrap_pyfunction!(answer_repacked, m)?)?;
+    m.add_function(wrap_pyfunction!(kernel_dot_product, m)?)?;
+    m.add_function(wrap_pyfunction!(fragment_query, m)?)?;
+    m.add_function(wrap_pyfunction!(reassemble_query, m)?)?;
+    m.add_function(wrap_pyfunction!(active_kernel, m)?)?;
+    m.add_function(wrap_pyfunction!(set_kernel, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_kernel, m)?)?;
+
+    m.add("YpirError", py.get_type::<YpirError>())?;
+    m.add("YpirSizeError", py.get_type::<YpirSizeError>())?;
+    m.add("YpirVersionError", py.get_type::<YpirVersionError>())?;
+    m.add("YpirParamsError", py.get_type::<YpirParamsError>())?;
 
     m.add_class::<PyYpirParams>()?;
     m.add_class::<PyYpirClient>()?;
     m.add_class::<PyYpirServer>()?;
+    m.add_class::<PyClientPool>()?;
+    m.add_class::<PyServerBuilder>()?;
+    m.add_class::<PyVarlenManifest>()?;
+    m.add_class::<PyMultiTenantServer>()?;
+    m.add_class::<PyInstanceFilesServer>()?;
+    m.add_class::<PyShardServer>()?;
+    m.add_class::<PyPipeline>()?;
+    m.add_class::<PyPipelineQuery>()?;
+    m.add_class::<PyPipelineResponse>()?;
+
+    let testing = PyModule::new(py, "testing")?;
+    testing.add_function(wrap_pyfunction!(make_fixture, &testing)?)?;
+    testing.add_function(wrap_pyfunction!(fixture_item, &testing)?)?;
+    testing.add_function(wrap_pyfunction!(expected_item, &testing)?)?;
+    testing.add_function(wrap_pyfunction!(reference_fetch, &testing)?)?;
+    m.add_submodule(&testing)?;
     Ok(())
 }
@@ -0,0 +1,61 @@
+"""
+Shared setup for the binding tests. Build the extension into the active
+environment first, then run from python/:
+
+    maturin develop && pytest tests
+"""
+from __future__ import annotations
+
+import pytest
+
+import ypir_rs
+
+NUM_ITEMS = 1000
+ITEM_SIZE = 64
+
+
+@pytest.fixture
+def deployment():
+    """(params, server, client) over a seed-0 fixture database."""
+    return ypir_rs.testing.make_fixture(NUM_ITEMS, ITEM_SIZE)
+
+
+def fixture_db_bytes(params, num_items: int = NUM_ITEMS, item_size: int = ITEM_SIZE) -> bytes:
+    """Row-major bytes of the database `make_fixture` builds."""
+    items = [ypir_rs.testing.expected_item(i, item_size) for i in range(num_items)]
+    return bytes(ypir_rs.build_db(params, items))
+
+
+def padded_transposed(params, row_major: bytes) -> bytes:
+    """`transpose_db` output with each column padded to the server's rows."""
+    layout = params.layout_info("u8")
+    t = bytes(ypir_rs.transpose_db(params, row_major))
+    rows = len(t) // layout["db_cols"]
+    pad = bytes(layout["db_rows"] - rows)
+    return b"".join(t[j * rows:(j + 1) * rows] + pad for j in range(layout["db_cols"]))
+
+
+def item_query(client, params, index: int, endianness: str = "little") -> bytes:
+    """The packed query for item `index`, which must not straddle rows."""
+    dim = ypir_rs.params_db_dim_1(params)
+    (q,) = ypir_rs.query_span(client, 0, dim, True, index, 1, True, endianness)
+    return q
+
+
+def decode_item(client, response: bytes, index: int, item_size: int = ITEM_SIZE,
+                endianness: str = "little") -> bytes:
+    """Item `index` from the response to `item_query(..., index)`."""
+    return bytes(ypir_rs.extract_span(client, [response], index, 1, item_size, endianness))
+
+
+def words_to_item(params, words: bytes, index: int, item_size: int = ITEM_SIZE) -> bytes:
+    """Item `index` from `extract` output (one little-endian u64 per column)."""
+    db_cols = params.layout_info("u8")["db_cols"]
+    start = index * item_size % db_cols
+    return bytes(words[8 * c] for c in range(start, start + item_size))
+
+
+def fetch(client, server, params, index: int, **answer_kwargs) -> bytes:
+    """Item `index` via query, `answer(**answer_kwargs)` and extract."""
+    response = ypir_rs.answer(server, item_query(client, params, index), **answer_kwargs)
+    return decode_item(client, response, index)
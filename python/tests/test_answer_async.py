@@ -0,0 +1,17 @@
+import asyncio
+
+import ypir_rs
+
+from conftest import item_query
+
+
+def test_answer_async_many_concurrent(deployment):
+    params, server, client = deployment
+    # far more calls than workers; the rest queue
+    queries = [item_query(client, params, i) for i in range(64)]
+
+    async def answer_all():
+        return await asyncio.gather(*(ypir_rs.answer_async(server, q) for q in queries))
+
+    responses = asyncio.run(answer_all())
+    assert responses == [ypir_rs.answer(server, q) for q in queries]
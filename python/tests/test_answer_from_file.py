@@ -0,0 +1,18 @@
+import pytest
+
+import ypir_rs
+
+from conftest import fixture_db_bytes, item_query, padded_transposed
+
+
+def test_answer_from_file_matches_answer(deployment, tmp_path):
+    params, server, client = deployment
+    path = tmp_path / "db.bin"
+    db = padded_transposed(params, fixture_db_bytes(params))
+    path.write_bytes(db)
+    q = item_query(client, params, 9)
+    assert ypir_rs.answer_from_file(params, str(path), q) == ypir_rs.answer(server, q)
+
+    path.write_bytes(db[:-1])
+    with pytest.raises(ypir_rs.YpirSizeError):
+        ypir_rs.answer_from_file(params, str(path), q)
@@ -0,0 +1,22 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, decode_item, fixture_db_bytes, item_query
+
+
+def test_update_invalidates_cached_response(deployment):
+    params, _, client = deployment
+    server = ypir_rs.server_new(params, fixture_db_bytes(params), False, True, cache_size=4)
+    index = 7
+    q = item_query(client, params, index)
+
+    first = ypir_rs.answer(server, q, request_id="r")
+    assert ypir_rs.answer(server, q, request_id="r") == first
+    assert server.cache_hits() == 1
+
+    new_item = bytes([0xAB]) * ITEM_SIZE
+    server.update_item(index, new_item)
+    after = ypir_rs.answer(server, q, request_id="r")
+    # recomputed against the new contents, not served from the cache
+    assert server.cache_hits() == 1
+    assert decode_item(client, after, index) == new_item
+    assert decode_item(client, first, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
@@ -0,0 +1,44 @@
+import asyncio
+
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, decode_item, fetch, item_query
+
+
+def test_update_item_cas(deployment):
+    params, server, client = deployment
+    index = 12
+    first, second = b"\x11" * ITEM_SIZE, b"\x22" * ITEM_SIZE
+    assert server.item_version(index) == 0
+
+    assert server.update_item_cas(index, first, 0) == 1
+    assert fetch(client, server, params, index) == first
+
+    # a writer still holding version 0 lost the race
+    with pytest.raises(ypir_rs.YpirVersionError, match="at version 1, expected 0"):
+        server.update_item_cas(index, second, 0)
+    assert server.item_version(index) == 1
+    assert fetch(client, server, params, index) == first
+
+
+def test_update_item_cas_while_answer_in_flight(deployment):
+    params, server, client = deployment
+    index = 30
+    new = b"\x33" * ITEM_SIZE
+
+    async def race():
+        # answer_async holds the current database until it resolves, so the
+        # update copies it rather than writing under the answer
+        pending = ypir_rs.answer_async(server, item_query(client, params, index))
+        version = server.update_item_cas(index, new, 0)
+        return version, await pending
+
+    version, response = asyncio.run(race())
+    assert version == 1
+    assert server.status().db_copies == 1
+    assert decode_item(client, response, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+    assert fetch(client, server, params, index) == new
+    with pytest.raises(ypir_rs.YpirVersionError):
+        server.update_item_cas(index, b"\x44" * ITEM_SIZE, 0)
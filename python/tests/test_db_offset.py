@@ -0,0 +1,33 @@
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, NUM_ITEMS, fetch, fixture_db_bytes
+
+HEADER = b"\xaa" * 4096
+TRAILER = b"\xbb" * 100
+
+
+def check_fetches(params, server, client):
+    for index in [0, 1, NUM_ITEMS - 1]:
+        expected = ypir_rs.testing.expected_item(index, ITEM_SIZE)
+        assert fetch(client, server, params, index) == expected
+
+
+def test_server_new_at_offset(deployment):
+    params, _, client = deployment
+    db = fixture_db_bytes(params)
+    buf = HEADER + db + TRAILER
+    server = ypir_rs.server_new(params, buf, False, True, offset=len(HEADER), length=len(db))
+    check_fetches(params, server, client)
+
+    with pytest.raises(ValueError):
+        ypir_rs.server_new(params, HEADER + db, False, True, offset=len(HEADER) + 1)
+
+
+def test_server_from_path_at_offset(deployment, tmp_path):
+    params, _, client = deployment
+    path = tmp_path / "db.bin"
+    path.write_bytes(HEADER + fixture_db_bytes(params) + TRAILER)
+    server = ypir_rs.server_from_path(params, str(path), False, True, offset=len(HEADER))
+    check_fetches(params, server, client)
@@ -0,0 +1,23 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, item_query, words_to_item
+
+
+def swap_words(data: bytes) -> bytes:
+    return b"".join(data[i:i + 8][::-1] for i in range(0, len(data), 8))
+
+
+def test_big_endian_extract_round_trip(deployment):
+    params, server, client = deployment
+    index = 42
+    response = ypir_rs.answer(server, item_query(client, params, index, "big"), endianness="big")
+
+    # the response is big-endian on the wire, but decoded words come back
+    # little-endian either way
+    out = ypir_rs.extract(client, response, "big")
+    assert out == ypir_rs.extract(client, swap_words(response), "little")
+    assert words_to_item(params, out, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+
+    noisy, _ = ypir_rs.extract_with_noise(client, response, "big")
+    assert noisy == out
+    assert ypir_rs.try_extract(client, response, endianness="big") == out
@@ -0,0 +1,13 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, item_query, words_to_item
+
+
+def test_extract_coeffs_packs_to_extract(deployment):
+    params, server, client = deployment
+    index = 42
+    response = ypir_rs.answer(server, item_query(client, params, index))
+    coeffs = ypir_rs.extract_coeffs(client, response)
+    packed = b"".join(c.to_bytes(8, "little") for c in coeffs)
+    assert packed == bytes(ypir_rs.extract(client, response))
+    assert words_to_item(params, packed, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
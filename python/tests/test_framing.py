@@ -0,0 +1,23 @@
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, item_query, words_to_item
+
+
+@pytest.mark.parametrize("width", [4, 8])
+def test_framed_round_trip(deployment, width):
+    params, server, client = deployment
+    index = 17
+    query = item_query(client, params, index)
+    plain = ypir_rs.answer(server, query)
+    framed = ypir_rs.answer(server, query, framed=True, frame_prefix_bytes=width)
+    assert int.from_bytes(framed[:width], "big") == len(plain)
+    assert framed[width:] == plain
+
+    out = ypir_rs.extract(client, framed, framed=True, frame_prefix_bytes=width)
+    assert out == ypir_rs.extract(client, plain)
+    assert words_to_item(params, out, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+
+    with pytest.raises(ypir_rs.YpirSizeError):
+        ypir_rs.extract(client, framed[:-1], framed=True, frame_prefix_bytes=width)
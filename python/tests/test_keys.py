@@ -0,0 +1,50 @@
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, fetch, item_query
+
+
+def test_keyless_client_refuses_query_and_extract(deployment):
+    params, server, client = deployment
+    dim = ypir_rs.params_db_dim_1(params)
+    response = ypir_rs.answer(server, item_query(client, params, 0))
+    client.close()
+
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        ypir_rs.query(client, 0, dim, True, 0, True)
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        ypir_rs.query_words(client, 0, dim, True, 0, True)
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        ypir_rs.extract(client, response)
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        ypir_rs.extract_item(client, response)
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        client.export_keys()
+
+
+def test_close_is_best_effort_and_idempotent(deployment):
+    params, server, client = deployment
+    other = ypir_rs.client_new(params)
+    assert any(client.export_keys())
+
+    client.close()
+    client.close()
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        client.public_material()
+    # other clients are untouched
+    assert fetch(other, server, params, 4) == ypir_rs.testing.expected_item(4, ITEM_SIZE)
+
+
+def test_reset_keeps_keys_and_round_trips(deployment):
+    params, server, client = deployment
+    keys = client.export_keys()
+    assert fetch(client, server, params, 4) == ypir_rs.testing.expected_item(4, ITEM_SIZE)
+
+    client.reset()
+    assert client.export_keys() == keys
+    assert fetch(client, server, params, 9) == ypir_rs.testing.expected_item(9, ITEM_SIZE)
+
+    client.close()
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        client.reset()
@@ -0,0 +1,12 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, NUM_ITEMS, fetch
+
+
+def test_local_fetch_matches_query_answer_extract(deployment):
+    params, server, client = deployment
+    for index in [0, 1, 500, NUM_ITEMS - 1]:
+        local = bytes(ypir_rs.local_fetch(client, server, index))
+        assert local == fetch(client, server, params, index)
+        assert local == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+
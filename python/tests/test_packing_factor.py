@@ -0,0 +1,18 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, words_to_item
+
+
+def test_packing_factor(deployment):
+    params, server, client = deployment
+    factor = params.packing_factor()
+    assert factor == 2048
+    dim = ypir_rs.params_db_dim_1(params)
+
+    index = 3
+    q = ypir_rs.query(client, 0, dim, True, index, True, logical=True)
+    response = ypir_rs.answer(server, q)
+    # the factor is the ring's, so the first-pass response stays one word per column
+    assert len(response) == params.response_size_bytes()
+    words = ypir_rs.extract(client, response)
+    assert words_to_item(params, words, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
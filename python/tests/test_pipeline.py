@@ -0,0 +1,25 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, words_to_item
+
+
+def test_pipeline_matches_manual_calls(deployment):
+    params, server, client = deployment
+    dim = ypir_rs.params_db_dim_1(params)
+    for index in [0, 7, 999]:
+        q = ypir_rs.query(client, 0, dim, True, index, True, logical=True)
+        words = ypir_rs.extract(client, ypir_rs.answer(server, q))
+        manual = words_to_item(params, words, index)
+
+        piped = bytes(ypir_rs.Pipeline(client).query(index).answer(server).extract())
+        assert piped == manual == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+
+
+def test_pipeline_remote_response(deployment):
+    params, server, client = deployment
+    for packing in [False, True]:
+        step = ypir_rs.Pipeline(client).query(5, response_packing=packing)
+        # the mode travels in the query header
+        response = ypir_rs.answer(server, step.query_bytes())
+        item = bytes(step.response(response).extract())
+        assert item == ypir_rs.testing.expected_item(5, ITEM_SIZE)
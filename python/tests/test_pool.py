@@ -0,0 +1,25 @@
+from concurrent.futures import ThreadPoolExecutor
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, words_to_item
+
+
+def test_pool_round_trip(deployment):
+    params, server, _ = deployment
+    pool = ypir_rs.ClientPool(params, 3)
+    dim = ypir_rs.params_db_dim_1(params)
+    indices = [0, 1, 5, 77, 500, 999]
+
+    def query(index):
+        return pool.query(0, dim, True, params.logical_to_physical(index), True)
+
+    with ThreadPoolExecutor(max_workers=4) as executor:
+        queries = list(executor.map(query, indices))
+        # the server is unsendable, so answers stay on this thread
+        answered = [(slot, ypir_rs.answer(server, q)) for slot, q in queries]
+        outs = list(executor.map(lambda sr: pool.extract(*sr), answered))
+
+    assert len({slot for slot, _ in queries}) == 3
+    for index, out in zip(indices, outs):
+        assert words_to_item(params, out, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
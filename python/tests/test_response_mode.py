@@ -0,0 +1,50 @@
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, words_to_item
+
+
+def mode_query(client, params, index, response_packing, endianness="little"):
+    dim = ypir_rs.params_db_dim_1(params)
+    return ypir_rs.query(client, 0, dim, True, index, True, endianness, logical=True,
+                         response_packing=response_packing)
+
+
+@pytest.mark.parametrize("endianness", ["little", "big"])
+@pytest.mark.parametrize("packing", [False, True])
+def test_mode_in_query_header(deployment, packing, endianness):
+    params, server, client = deployment
+    index = 7
+    q = mode_query(client, params, index, packing, endianness)
+    assert len(q) == 8 + len(ypir_rs.query(
+        client, 0, ypir_rs.params_db_dim_1(params), True, index, True, endianness))
+
+    # answer takes the mode from the header
+    response = ypir_rs.answer(server, q, endianness=endianness)
+    size = params.packed_response_size_bytes() if packing else params.response_size_bytes()
+    assert len(response) == size
+    assert response == ypir_rs.answer(server, q, endianness=endianness,
+                                      response_packing=packing)
+
+    words = ypir_rs.extract(client, response, endianness, response_packing=packing)
+    assert words_to_item(params, words, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
+
+
+@pytest.mark.parametrize("packing", [False, True])
+def test_mismatched_mode_rejected(deployment, packing):
+    params, server, client = deployment
+    q = mode_query(client, params, 3, packing)
+    with pytest.raises(ValueError, match="different response modes"):
+        ypir_rs.answer(server, q, response_packing=not packing)
+
+    response = ypir_rs.answer(server, q)
+    with pytest.raises(ypir_rs.YpirSizeError):
+        ypir_rs.extract(client, response, response_packing=not packing)
+
+
+def test_header_needs_packed_query(deployment):
+    params, _, client = deployment
+    dim = ypir_rs.params_db_dim_1(params)
+    with pytest.raises(ValueError, match="pack=True"):
+        ypir_rs.query(client, 0, dim, True, 0, False, response_packing=True)
@@ -0,0 +1,27 @@
+from itertools import islice
+
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE
+
+
+def test_scan_yields_items_in_order(deployment):
+    _, server, client = deployment
+    expected = [ypir_rs.testing.expected_item(i, ITEM_SIZE) for i in range(5)]
+
+    untrimmed = [bytes(x) for x in islice(client.scan(server, trim=False), 5)]
+    assert untrimmed == expected
+    trimmed = [bytes(x) for x in islice(client.scan(server), 5)]
+    assert trimmed == [x.rstrip(b"\0") for x in expected]
+    assert trimmed[0] == b""
+
+
+def test_scan_stops_once_keys_are_closed(deployment):
+    _, server, client = deployment
+    scan = client.scan(server, trim=False)
+    assert bytes(next(scan)) == ypir_rs.testing.expected_item(0, ITEM_SIZE)
+    client.close()
+    with pytest.raises(ypir_rs.YpirError, match="no secret keys"):
+        next(scan)
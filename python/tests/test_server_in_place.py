@@ -0,0 +1,32 @@
+import mmap
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, fetch, fixture_db_bytes, item_query, padded_transposed
+
+
+def test_read_only_mmap_served_in_place(deployment, tmp_path):
+    params, server, client = deployment
+    path = tmp_path / "db.t"
+    path.write_bytes(padded_transposed(params, fixture_db_bytes(params)))
+    with open(path, "rb") as f:
+        mapped = mmap.mmap(f.fileno(), 0, access=mmap.ACCESS_READ)
+    shared = ypir_rs.server_new(params, mapped, True, True)
+    for index in [0, 9, 999]:
+        expected = ypir_rs.testing.expected_item(index, ITEM_SIZE)
+        assert fetch(client, shared, params, index) == expected
+    q = item_query(client, params, 9)
+    assert ypir_rs.answer(shared, q) == ypir_rs.answer(server, q)
+
+
+def test_writable_mmap_is_copied(deployment):
+    params, server, client = deployment
+    db = padded_transposed(params, fixture_db_bytes(params))
+    # anonymous maps are page-aligned, so only writability keeps this from being shared
+    mapped = mmap.mmap(-1, len(db))
+    mapped[:] = db
+    copied = ypir_rs.server_new(params, mapped, True, True)
+    q = item_query(client, params, 9)
+    before = ypir_rs.answer(copied, q)
+    mapped[:] = bytes(len(db))
+    assert ypir_rs.answer(copied, q) == before == ypir_rs.answer(server, q)
@@ -0,0 +1,24 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE
+
+
+def test_span_round_trip_across_rows(deployment):
+    params, server, client = deployment
+    dim = ypir_rs.params_db_dim_1(params)
+    db_cols = params.layout_info("u8")["db_cols"]
+    # two items at the end of row 0 and two at the start of row 1
+    start, count = db_cols // ITEM_SIZE - 2, 4
+    expected = b"".join(ypir_rs.testing.expected_item(i, ITEM_SIZE)
+                        for i in range(start, start + count))
+
+    queries = ypir_rs.query_span(client, 0, dim, True, start, count, True)
+    assert len(queries) == 2
+    responses = ypir_rs.answer_batch(server, queries)
+    total_len = count * ITEM_SIZE - 10
+    out = ypir_rs.extract_span(client, responses, start, count, total_len)
+    assert bytes(out) == expected[:total_len]
+
+    # one answer per query gives the same responses
+    singles = [ypir_rs.answer(server, q) for q in queries]
+    assert bytes(ypir_rs.extract_span(client, singles, start, count, total_len)) == bytes(out)
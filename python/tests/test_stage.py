@@ -0,0 +1,42 @@
+import pytest
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, NUM_ITEMS, fetch
+
+
+def seeded_db(params, seed: int) -> bytes:
+    items = [ypir_rs.testing.fixture_item(seed, i, ITEM_SIZE) for i in range(NUM_ITEMS)]
+    return bytes(ypir_rs.build_db(params, items))
+
+
+def test_stage_then_commit_swaps_database(deployment):
+    params, server, client = deployment
+    with pytest.raises(ypir_rs.YpirError, match="no database staged"):
+        server.commit()
+
+    # the second stage replaces the first
+    server.stage(seeded_db(params, 1))
+    server.stage(seeded_db(params, 2))
+    # still answering from the old database until the commit
+    assert fetch(client, server, params, 3) == ypir_rs.testing.expected_item(3, ITEM_SIZE)
+    server.commit()
+    for index in [0, 3, NUM_ITEMS - 1]:
+        expected = ypir_rs.testing.fixture_item(2, index, ITEM_SIZE)
+        assert fetch(client, server, params, index) == expected
+
+    with pytest.raises(ypir_rs.YpirError, match="no database staged"):
+        server.commit()
+
+
+def test_write_during_stage_does_not_copy(deployment):
+    params, server, client = deployment
+    new = b"\x5a" * ITEM_SIZE
+    server.stage(seeded_db(params, 1))
+    server.update_item(3, new)
+    # the build holds only the server's settings, so the write is in place
+    assert server.status().db_copies == 0
+    assert fetch(client, server, params, 3) == new
+
+    server.commit()
+    assert fetch(client, server, params, 3) == ypir_rs.testing.fixture_item(1, 3, ITEM_SIZE)
@@ -0,0 +1,36 @@
+import ypir_rs
+
+from conftest import ITEM_SIZE, NUM_ITEMS, fixture_db_bytes
+
+
+def check_status(status, params, is_simplepir):
+    layout = params.layout_info("u8")
+    assert status.num_items == params.num_items == NUM_ITEMS
+    assert status.item_size_bytes == ITEM_SIZE
+    assert status.db_bytes == layout["db_rows"] * layout["db_cols"]
+    assert status.active_kernel == ypir_rs.active_kernel()
+    assert status.is_simplepir == is_simplepir
+    assert status.db_copies == 0
+
+
+def test_status_reports_server(deployment):
+    params, server, _ = deployment
+    status = server.status()
+    check_status(status, params, False)
+    assert status.num_threads == 1
+
+
+def test_status_reports_simplepir_server():
+    params, server, _ = ypir_rs.testing.make_fixture(NUM_ITEMS, ITEM_SIZE, is_simplepir=True)
+    status = server.status()
+    check_status(status, params, True)
+    assert status.num_threads == 1
+
+
+def test_status_counts_numa_threads(deployment):
+    params, _, _ = deployment
+    server = ypir_rs.server_new(params, fixture_db_bytes(params), False, True, numa=True)
+    status = server.status()
+    check_status(status, params, False)
+    # one thread per NUMA node the host reports, at least one
+    assert status.num_threads >= 1
@@ -0,0 +1,32 @@
+import struct
+
+import ypir_rs
+
+from conftest import ITEM_SIZE, decode_item, item_query
+
+
+def words_to_bytes(words) -> bytes:
+    return struct.pack(f"<{len(words)}Q", *words)
+
+
+def bytes_to_words(data: bytes) -> list:
+    return list(struct.unpack(f"<{len(data) // 8}Q", data))
+
+
+def test_answer_words_match_answer(deployment):
+    params, server, client = deployment
+    q = item_query(client, params, 3)
+    words = ypir_rs.answer_words(server, bytes_to_words(q))
+    assert words_to_bytes(words) == ypir_rs.answer(server, q)
+
+
+def test_query_words_round_trip(deployment):
+    params, server, client = deployment
+    index = 3
+    dim = ypir_rs.params_db_dim_1(params)
+    row = params.logical_to_physical(index)
+    q_words = ypir_rs.query_words(client, 0, dim, True, row, True)
+    assert len(q_words) == len(item_query(client, params, index)) // 8
+
+    response = words_to_bytes(ypir_rs.answer_words(server, q_words))
+    assert decode_item(client, response, index) == ypir_rs.testing.expected_item(index, ITEM_SIZE)
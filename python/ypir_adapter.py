@@ -92,6 +92,13 @@ def ypir_answer(ctx: YpirContext, query_bytes: bytes) -> bytes:
     return ypir_rs.answer(ctx.server, query_bytes)
 
 
+async def ypir_answer_async(ctx: YpirContext, query_bytes: bytes) -> bytes:
+    """
+    Like ypir_answer, but computed on a worker thread so the event loop keeps running.
+    """
+    return await ypir_rs.answer_async(ctx.server, query_bytes)
+
+
 def ypir_extract(ctx: YpirContext, response_bytes: bytes) -> bytes:
     """
     Returns decoded response words as bytes (little-endian u64s).
@@ -99,3 +106,12 @@ def ypir_extract(ctx: YpirContext, response_bytes: bytes) -> bytes:
     """
     out = ypir_rs.extract(ctx.client, response_bytes)
     return out
+
+
+def ypir_extract_with_noise(ctx: YpirContext, response_bytes: bytes) -> tuple[bytes, float]:
+    """
+    Like ypir_extract, but also returns the decode noise relative to the decode
+    threshold (< 1.0 decodes correctly; values near 1.0 mean params are marginal).
+    """
+    out, noise = ypir_rs.extract_with_noise(ctx.client, response_bytes)
+    return out, noise
@@ -0,0 +1,89 @@
+//! Reference TCP client for `server_tcp`: sends one packed query for a row
+//! and prints the decoded response.
+
+use clap::Parser;
+use spiral_rs::client::Client;
+use tokio::net::TcpStream;
+
+use ypir::bits::{bytes_to_u64s, u64s_to_bytes, Endianness};
+use ypir::client::{pack_query, YClient};
+use ypir::net::{read_frame_async, write_frame_async};
+use ypir::params::params_for_scenario;
+use ypir::scheme::SEED_0;
+use ypir::stream::write_frame;
+
+/// Query a YPIR server over TCP
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Number of items in the database (must match the server)
+    num_items: usize,
+    /// Size of each item in bits (optional, default 1; must match the server)
+    item_size_bits: Option<usize>,
+    /// Server address
+    #[clap(long, default_value = "127.0.0.1:7878")]
+    addr: String,
+    /// Database row to query
+    #[clap(long, default_value_t = 0)]
+    row: usize,
+    /// Also write the query frame followed by the response frame to this file
+    #[clap(long)]
+    exchange_out: Option<String>,
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> std::io::Result<()> {
+    env_logger::init();
+    let Args {
+        num_items,
+        item_size_bits,
+        addr,
+        row,
+        exchange_out,
+    } = Args::parse();
+
+    let params = params_for_scenario(num_items, item_size_bits.unwrap_or(1));
+    let mut client = Client::init(&params);
+    client.generate_secret_keys();
+    let y_client = YClient::new(&mut client, &params);
+
+    // the packed form the server answers: one word per database row
+    let query = y_client.generate_query(SEED_0, params.db_dim_1, true, row);
+    let packed = pack_query(&params, &query);
+    let query_bytes = u64s_to_bytes(packed.as_slice(), Endianness::Little);
+
+    let mut sock = TcpStream::connect(&addr).await?;
+    write_frame_async(&mut sock, &query_bytes).await?;
+    let response_bytes = read_frame_async(&mut sock).await?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "server closed the connection without answering",
+        )
+    })?;
+    drop(sock);
+
+    if let Some(path) = exchange_out {
+        let mut out = Vec::new();
+        write_frame(&mut out, &query_bytes)?;
+        write_frame(&mut out, &response_bytes)?;
+        std::fs::write(path, out)?;
+    }
+
+    let response = bytes_to_u64s(&response_bytes, Endianness::Little).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "response length must be a multiple of 8",
+        )
+    })?;
+    let decoded = y_client.decode_response(&response);
+    println!("received {} words", response.len());
+    println!(
+        "row {}: {}",
+        row,
+        decoded
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect::<String>()
+    );
+    Ok(())
+}
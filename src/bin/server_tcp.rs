@@ -0,0 +1,120 @@
+//! Reference TCP server: answers length-prefixed packed queries (see
+//! `ypir::stream`) over a socket. Not meant for production use; it serves
+//! one connection at a time on a single thread. A connection that sends a
+//! malformed query is closed, and the server goes on accepting.
+
+use std::io::{self, Write};
+
+use clap::Parser;
+use log::{debug, info, warn};
+use tokio::net::{TcpListener, TcpStream};
+
+use ypir::bits::{bytes_to_u64s, u64s_to_bytes, Endianness};
+use ypir::db::db_num_bytes;
+use ypir::net::{read_frame_async, write_frame_async};
+use ypir::params::params_for_scenario;
+use ypir::server::YServer;
+
+/// Serve YPIR queries over TCP
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Number of items in the database
+    num_items: usize,
+    /// Size of each item in bits (optional, default 1)
+    item_size_bits: Option<usize>,
+    /// Row-major database file (optional, defaults to a random database);
+    /// see `db::build_db` for the layout
+    #[clap(long)]
+    db: Option<String>,
+    /// Address to listen on; use port 0 to pick a free port
+    #[clap(long, default_value = "127.0.0.1:7878")]
+    addr: String,
+    /// Exit after the first connection served without error
+    #[clap(long, action)]
+    once: bool,
+}
+
+/// Answers the queries on `sock` until the peer hangs up. An error,
+/// including a malformed query, ends only this connection.
+async fn serve_connection(server: &YServer<'_, u8>, sock: &mut TcpStream) -> io::Result<()> {
+    while let Some(query_bytes) = read_frame_async(sock).await? {
+        let query = bytes_to_u64s(&query_bytes, Endianness::Little).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "query length must be a multiple of 8",
+            )
+        })?;
+        server
+            .check_query(&query)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        // blocks the (single-threaded) runtime; fine for a reference server
+        let response = server.answer_query(&query);
+        debug!("answered query of {} words", query.len());
+        let response_bytes = u64s_to_bytes(response.as_slice(), Endianness::Little);
+        write_frame_async(sock, &response_bytes).await?;
+    }
+    Ok(())
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> io::Result<()> {
+    env_logger::init();
+    let Args {
+        num_items,
+        item_size_bits,
+        db,
+        addr,
+        once,
+    } = Args::parse();
+
+    let params = Box::leak(Box::new(params_for_scenario(
+        num_items,
+        item_size_bits.unwrap_or(1),
+    )));
+    let needed = db_num_bytes(params, false);
+    let db_bytes = match db {
+        Some(path) => {
+            let bytes = std::fs::read(&path)?;
+            if bytes.len() != needed {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} is {} bytes, but the database needs {}",
+                        path,
+                        bytes.len(),
+                        needed
+                    ),
+                ));
+            }
+            bytes
+        }
+        None => (0..needed).map(|_| fastrand::u8(..)).collect(),
+    };
+    let server = YServer::<u8>::new(params, db_bytes.into_iter(), false, false, true);
+
+    let listener = TcpListener::bind(&addr).await?;
+    // printed on stdout so callers binding port 0 can find us
+    println!("listening on {}", listener.local_addr()?);
+    io::stdout().flush()?;
+
+    loop {
+        let (mut sock, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("accept failed: {}", e);
+                continue;
+            }
+        };
+        info!("connection from {}", peer);
+        match serve_connection(&server, &mut sock).await {
+            Ok(()) => {
+                info!("{} disconnected", peer);
+                if once {
+                    return Ok(());
+                }
+            }
+            Err(e) => warn!("closing connection from {}: {}", peer, e),
+        }
+    }
+}
@@ -102,11 +102,82 @@ pub fn contiguous_bytes_to_u64s(data: &[u8], out_mod_bits: usize) -> Vec<u64> {
     out
 }
 
+/// Byte order used when serializing u64 words for the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// Parses "little"/"le" or "big"/"be" (case-insensitive).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "little" | "le" => Some(Endianness::Little),
+            "big" | "be" => Some(Endianness::Big),
+            _ => None,
+        }
+    }
+}
+
+pub fn u64s_to_bytes(words: &[u64], endianness: Endianness) -> Vec<u8> {
+    let mut out = Vec::with_capacity(words.len() * 8);
+    for &w in words {
+        match endianness {
+            Endianness::Little => out.extend_from_slice(&w.to_le_bytes()),
+            Endianness::Big => out.extend_from_slice(&w.to_be_bytes()),
+        }
+    }
+    out
+}
+
+/// Returns `None` if `data` is not a whole number of words.
+pub fn bytes_to_u64s(data: &[u8], endianness: Endianness) -> Option<Vec<u64>> {
+    if data.len() % 8 != 0 {
+        return None;
+    }
+    let words = data
+        .chunks_exact(8)
+        .map(|chunk| {
+            let chunk: [u8; 8] = chunk.try_into().unwrap();
+            match endianness {
+                Endianness::Little => u64::from_le_bytes(chunk),
+                Endianness::Big => u64::from_be_bytes(chunk),
+            }
+        })
+        .collect();
+    Some(words)
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
 
+    #[test]
+    fn test_u64s_bytes_endianness() {
+        let words = (0..17).map(|_| fastrand::u64(..)).collect::<Vec<_>>();
+        for endianness in [Endianness::Little, Endianness::Big] {
+            let bytes = u64s_to_bytes(&words, endianness);
+            assert_eq!(bytes.len(), words.len() * 8);
+            assert_eq!(bytes_to_u64s(&bytes, endianness).unwrap(), words);
+        }
+
+        let be = u64s_to_bytes(&[0x0102030405060708], Endianness::Big);
+        assert_eq!(be, [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            bytes_to_u64s(&be, Endianness::Little).unwrap(),
+            [0x0807060504030201]
+        );
+
+        assert_eq!(bytes_to_u64s(&[0u8; 7], Endianness::Big), None);
+        assert_eq!(Endianness::parse("BE"), Some(Endianness::Big));
+        assert_eq!(Endianness::parse("little"), Some(Endianness::Little));
+        assert_eq!(Endianness::parse("middle"), None);
+        assert_eq!(Endianness::default(), Endianness::Little);
+    }
+
     #[test]
     fn test_write_and_read_bits() {
         let mut buffer = [0u8; 4];
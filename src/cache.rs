@@ -0,0 +1,102 @@
+use std::collections::VecDeque;
+
+/// A small LRU of responses, keyed by a client-supplied request id.
+///
+/// Lookups are linear, which is fine for the handful of entries this is meant
+/// to hold. A capacity of 0 disables caching entirely.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    capacity: usize,
+    entries: VecDeque<(String, Vec<u8>)>,
+    hits: usize,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            hits: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of lookups that were answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Returns the cached response for `request_id`, marking it most recently used.
+    pub fn get(&mut self, request_id: &str) -> Option<&[u8]> {
+        let pos = self.entries.iter().position(|(id, _)| id == request_id)?;
+        let entry = self.entries.remove(pos).unwrap();
+        self.entries.push_back(entry);
+        self.hits += 1;
+        self.entries.back().map(|(_, resp)| resp.as_slice())
+    }
+
+    pub fn insert(&mut self, request_id: &str, response: Vec<u8>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Some(pos) = self.entries.iter().position(|(id, _)| id == request_id) {
+            self.entries.remove(pos);
+        }
+        self.entries.push_back((request_id.to_owned(), response));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Drops every cached response; must be called whenever the database changes.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_response_cache_lru() {
+        let mut cache = ResponseCache::new(2);
+        cache.insert("a", vec![1]);
+        cache.insert("b", vec![2]);
+        assert_eq!(cache.get("a"), Some(&[1u8][..]));
+
+        // "b" is now least recently used and gets evicted
+        cache.insert("c", vec![3]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&[1u8][..]));
+        assert_eq!(cache.get("c"), Some(&[3u8][..]));
+        assert_eq!(cache.hits(), 3);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("a"), None);
+    }
+
+    #[test]
+    fn test_response_cache_disabled() {
+        let mut cache = ResponseCache::new(0);
+        cache.insert("a", vec![1]);
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("a"), None);
+    }
+}
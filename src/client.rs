@@ -1,3 +1,5 @@
+use std::time::Instant;
+
 use log::debug;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
@@ -7,7 +9,11 @@ use spiral_rs::{
     arith::*, client::*, discrete_gaussian::*, gadget::*, number_theory::*, params::*, poly::*,
 };
 
+use sha2::{Digest, Sha256};
+
+use super::bits::{u64s_to_bytes, u64s_to_contiguous_bytes, Endianness};
 use super::convolution::negacyclic_matrix_u32;
+use super::params::query_limb_crt_indices;
 use super::{lwe::*, noise_analysis::measure_noise_width_squared, scheme::*, util::*};
 
 pub fn rlwe_to_lwe<'a>(params: &'a Params, ct: &PolyMatrixRaw<'a>) -> Vec<u64> {
@@ -23,14 +29,213 @@ pub fn rlwe_to_lwe_last_row<'a>(_params: &'a Params, ct: &PolyMatrixRaw<'a>) ->
     ct.get_poly(1, 0).to_vec()
 }
 
+/// How far `val` is from the nearest plaintext encoding, as a fraction of the
+/// decode threshold (half the gap between encodings). Values of 1.0 or more
+/// decode to the wrong plaintext.
+pub fn decode_noise_ratio(val: u64, modulus: u64, pt_modulus: u64) -> f64 {
+    let delta = modulus as f64 / pt_modulus as f64;
+    let pt = rescale(val, modulus, pt_modulus);
+    let mut diff = val as f64 - pt as f64 * delta;
+    if diff > modulus as f64 / 2. {
+        diff -= modulus as f64;
+    } else if diff < -(modulus as f64) / 2. {
+        diff += modulus as f64;
+    }
+    diff.abs() / (delta / 2.)
+}
+
+/// Smallest value the decode (`rescale`) rounds to plaintext 1 rather than
+/// 0: `ceil(ceil(modulus / 2) / pt_modulus)`, the first value at least half
+/// an encoding gap above 0.
+///
+/// The decode rounds `val * pt_modulus / modulus` to the nearest integer mod
+/// `pt_modulus`, so the boundary above plaintext `m` is `m * modulus /
+/// pt_modulus` further up, give or take the rounding of that.
+pub fn decode_threshold(modulus: u64, pt_modulus: u64) -> u64 {
+    modulus.div_ceil(2).div_ceil(pt_modulus)
+}
+
+/// `rescale` with one f64 multiply instead of exact integer rounding. The
+/// modulus is wider than f64's 53-bit mantissa, so values within about
+/// 2^-44 of halfway between two encodings (relative to the modulus) may round
+/// the other way, a slightly higher failure rate than `rescale`. Values that
+/// close to the boundary are near-failures anyway.
+pub fn rescale_fast(val: u64, modulus: u64, pt_modulus: u64) -> u64 {
+    let scaled = (val as f64 * (pt_modulus as f64 / modulus as f64)).round() as u64;
+    scaled % pt_modulus
+}
+
+/// Estimated client-side operation counts for one `generate_query` (first
+/// dimension) and one `decode_response`, for judging whether a low-power
+/// device can keep up; symmetric to `KernelCost` on the server.
+///
+/// With `packing` the query is `2^db_dim_1` RLWE encryptions. Without it the
+/// query is the `SEED_0` path: one LWE encryption per database row, with no
+/// NTTs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClientCost {
+    /// Polynomial NTTs, forward or inverse (each covers every CRT limb).
+    pub query_ntts: u64,
+    /// Coefficient-wise modular multiplies outside the NTTs.
+    pub query_multiplies: u64,
+    /// Gaussian error samples.
+    pub query_samples: u64,
+    /// Multiplies to decrypt a full-format (LWE matrix) response; the short
+    /// format `answer_query` returns needs none.
+    pub decode_multiplies: u64,
+    /// Values rounded down to the plaintext modulus.
+    pub decode_rescales: u64,
+}
+
+impl ClientCost {
+    pub fn new(params: &Params, packing: bool) -> Self {
+        let cts = 1u64 << params.db_dim_1;
+        let coeffs = (params.poly_len * params.crt_count) as u64;
+        let db_rows = 1u64 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1u64 << (params.db_dim_2 + params.poly_len_log2);
+        let decode_multiplies = db_cols * params.poly_len as u64;
+
+        if !packing {
+            // per row: `a . s` over the n-dimensional secret, plus adding the
+            // error and the scaled plaintext into `b`
+            let n = LWEParams::default().n as u64;
+            return Self {
+                query_ntts: 0,
+                query_multiplies: (n + 1) * db_rows,
+                query_samples: db_rows,
+                decode_multiplies,
+                decode_rescales: db_cols,
+            };
+        }
+
+        // per ciphertext: the plaintext's NTT, the error's NTT, and the
+        // inverse NTTs of both ciphertext rows, plus a round trip of the
+        // plaintext through the NTT to pre-multiply it by 1 / poly_len;
+        // `a * s`, the scaling and the pre-multiply are one multiply per
+        // coefficient each
+        Self {
+            query_ntts: cts * 7,
+            query_multiplies: cts * 3 * coeffs,
+            query_samples: cts * params.poly_len as u64,
+            decode_multiplies,
+            decode_rescales: db_cols,
+        }
+    }
+}
+
+/// Length in bytes of `export_secret_key`.
+pub fn secret_key_len(params: &Params) -> usize {
+    params.poly_len * std::mem::size_of::<u64>()
+}
+
+/// The client's regular secret key (the one `decode_response` uses), one
+/// little-endian u64 per coefficient.
+pub fn export_secret_key(client: &Client) -> Vec<u8> {
+    u64s_to_bytes(client.get_sk_reg().as_slice(), Endianness::Little)
+}
+
+/// Overwrites the client's regular secret key with zeros. The writes are
+/// volatile so they aren't dropped as dead stores; spiral's other key forms
+/// are left as they are.
+pub fn scrub_secret_key(client: &mut Client) {
+    for x in client.get_sk_reg_mut().data.as_mut_slice() {
+        // SAFETY: `x` is a valid, aligned, exclusive reference
+        unsafe { std::ptr::write_volatile(x, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// The packing key switching parameters for the client's keys, with the
+/// encryption noise drawn from a generator seeded by a hash of the secret
+/// key instead of fresh entropy.
+fn deterministic_pack_pub_params<'a>(
+    params: &'a Params,
+    client: &Client<'a>,
+) -> Vec<PolyMatrixNTT<'a>> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ypir public material v1");
+    hasher.update(export_secret_key(client));
+    let seed: [u8; 32] = hasher.finalize().into();
+
+    raw_generate_expansion_params(
+        params,
+        &client.get_sk_reg(),
+        params.poly_len_log2,
+        params.t_exp_left,
+        &mut ChaCha20Rng::from_seed(seed),
+        &mut ChaCha20Rng::from_seed(STATIC_SEED_2),
+    )
+}
+
+/// The public material the server needs for the packing step: the second
+/// rows of the client's packing parameters, `modulus_log2` bits per
+/// coefficient, `pack_pub_params_size_bytes(params)` bytes in all. The server
+/// reads it back with `pack_pub_params_from_material`.
+///
+/// The same keys always give the same bytes, so the material can be
+/// regenerated and re-sent (to a restarted server, say) without new keys, and
+/// a server that receives it twice learns nothing from the second copy.
+pub fn public_material<'a>(params: &'a Params, client: &Client<'a>) -> Vec<u8> {
+    let coeffs = deterministic_pack_pub_params(params, client)
+        .iter()
+        .flat_map(|p| p.submatrix(1, 0, 1, p.cols).raw().as_slice().to_vec())
+        .collect::<Vec<_>>();
+    u64s_to_contiguous_bytes(&coeffs, params.modulus_log2 as usize)
+}
+
+/// Default limit on `decode_noise_ratio` for `YClient::try_decode_response`.
+///
+/// A single value past 1.0 decodes wrongly but looks like a clean decode of
+/// its neighbour, so failure is detected statistically: with adequate params
+/// every ratio stays well below this, while noise that overwhelms the
+/// encoding spreads ratios over [0, 1).
+pub const DEFAULT_MAX_NOISE_RATIO: f64 = 0.5;
+
+/// A response whose noise makes the decoded values unreliable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub noise_ratio: f64,
+    pub max_noise_ratio: f64,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "decode noise ratio {:.3} exceeds limit {:.3}",
+            self.noise_ratio, self.max_noise_ratio
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A deadline-bounded decode (`YClient::decode_response_by`) ran out of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadlineExceeded;
+
+impl std::fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "deadline exceeded")
+    }
+}
+
+impl std::error::Error for DeadlineExceeded {}
+
+/// Columns decoded between deadline checks; reading the clock per column
+/// would cost about as much as the short-format decode itself.
+const DEADLINE_CHECK_COLS: usize = 64;
+
 pub fn pack_query(params: &Params, query: &[u64]) -> AlignedMemory64 {
+    let limbs = query_limb_crt_indices(params);
     let query_packed = query
         .iter()
-        .enumerate()
-        .map(|(_i, x)| {
-            let crt0 = (*x) % params.moduli[0];
-            let crt1 = (*x) % params.moduli[1];
-            crt0 | (crt1 << 32)
+        .map(|x| {
+            limbs
+                .iter()
+                .enumerate()
+                .map(|(limb, &crt)| (x % params.moduli[crt]) << (32 * limb))
+                .fold(0, |word, residue| word | residue)
         })
         .collect::<Vec<_>>();
     let mut aligned_query_packed = AlignedMemory64::new(query_packed.len());
@@ -40,6 +245,25 @@ pub fn pack_query(params: &Params, query: &[u64]) -> AlignedMemory64 {
     aligned_query_packed
 }
 
+/// The query `pack_query` packed into `packed`, each word mod
+/// `params.modulus`.
+pub fn unpack_query(params: &Params, packed: &[u64]) -> Vec<u64> {
+    let limbs = query_limb_crt_indices(params);
+    packed
+        .iter()
+        .map(|&word| {
+            let mut residues = [0u64; 2];
+            for (limb, &crt) in limbs.iter().enumerate() {
+                residues[crt] = (word >> (32 * limb)) & 0xFFFF_FFFF;
+            }
+            match limbs.len() {
+                1 => residues[0],
+                _ => params.crt_compose_2(residues[0], residues[1]) % params.modulus,
+            }
+        })
+        .collect()
+}
+
 pub fn get_reg_sample<'a>(
     params: &'a Params,
     sk_reg: &PolyMatrixRaw<'a>,
@@ -128,6 +352,7 @@ pub struct YClient<'a> {
     inner: &'a mut Client<'a>,
     params: &'a Params,
     lwe_client: LWEClient,
+    seeds: PublicSeeds,
 }
 
 pub fn get_seed(public_seed_idx: u8) -> [u8; 32] {
@@ -136,6 +361,37 @@ pub fn get_seed(public_seed_idx: u8) -> [u8; 32] {
     seed
 }
 
+/// Replacements for the public seeds `get_seed` derives, so a compromised
+/// seed can be rotated without changing params. Client and server must use
+/// the same table.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicSeeds {
+    overrides: Vec<(u8, [u8; 32])>,
+}
+
+impl PublicSeeds {
+    /// Replaces the seed for `public_seed_idx`.
+    pub fn with_seed(mut self, public_seed_idx: u8, seed: [u8; 32]) -> Self {
+        self.overrides.retain(|&(idx, _)| idx != public_seed_idx);
+        self.overrides.push((public_seed_idx, seed));
+        self.overrides.sort_unstable();
+        self
+    }
+
+    pub fn get(&self, public_seed_idx: u8) -> [u8; 32] {
+        self.overrides
+            .iter()
+            .find(|&&(idx, _)| idx == public_seed_idx)
+            .map(|&(_, seed)| seed)
+            .unwrap_or_else(|| get_seed(public_seed_idx))
+    }
+
+    /// The replaced seeds, sorted by index.
+    pub fn overrides(&self) -> &[(u8, [u8; 32])] {
+        &self.overrides
+    }
+}
+
 pub fn generate_matrix_ring(
     rng_pub: &mut ChaCha20Rng,
     n: usize,
@@ -174,9 +430,16 @@ impl<'a> YClient<'a> {
             inner,
             params,
             lwe_client: LWEClient::new(LWEParams::default()),
+            seeds: PublicSeeds::default(),
         }
     }
 
+    /// Uses `seeds` instead of the default public seeds for queries.
+    pub fn with_public_seeds(mut self, seeds: PublicSeeds) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
     pub fn lwe_client(&self) -> &LWEClient {
         &self.lwe_client
     }
@@ -202,7 +465,7 @@ impl<'a> YClient<'a> {
 
         let multiply_ct = true;
 
-        let mut rng_pub = ChaCha20Rng::from_seed(get_seed(public_seed_idx));
+        let mut rng_pub = ChaCha20Rng::from_seed(self.seeds.get(public_seed_idx));
 
         // Generate dim1_bits LWE samples under public randomness
         let mut out = Vec::new();
@@ -317,7 +580,7 @@ impl<'a> YClient<'a> {
             let mut vals_to_encrypt = vec![0u32; dim];
             vals_to_encrypt[index_row] = scale_k;
 
-            let mut rng_pub = ChaCha20Rng::from_seed(get_seed(public_seed_idx));
+            let mut rng_pub = ChaCha20Rng::from_seed(self.seeds.get(public_seed_idx));
 
             for i in (0..dim).step_by(lwe_params.n) {
                 let out = self
@@ -342,22 +605,189 @@ impl<'a> YClient<'a> {
         }
     }
 
+    /// Debug check that `query_words`, a packed row query as the server
+    /// receives it (`pack_query` of `generate_query(SEED_0, db_dim_1, true,
+    /// expected_index)`), decrypts to a selection vector: the scaled
+    /// plaintext rounds to 1 at row `expected_index` and to 0 at every other
+    /// row. False for a query of the wrong length.
+    ///
+    /// The public halves of the ciphertexts are regenerated from the seed
+    /// (by encrypting a throwaway query), so this costs about as much as
+    /// generating a query. Not meant for production paths.
+    pub fn debug_decrypt_query(&self, query_words: &[u64], expected_index: usize) -> bool {
+        let poly_len = self.params.poly_len;
+        let num_cts = 1 << self.params.db_dim_1;
+        if query_words.len() != num_cts * poly_len {
+            return false;
+        }
+        let bs = unpack_query(self.params, query_words);
+        let public_cts = self.generate_query_impl(SEED_0, self.params.db_dim_1, true, 0);
+
+        // queries for packing carry plaintext and noise times 1 / poly_len;
+        // multiplying back leaves the plaintext scaled by `modulus / pt_modulus`
+        let n = poly_len as u64 % self.params.modulus;
+        for (i, public_ct) in public_cts.iter().enumerate() {
+            let mut ct = PolyMatrixRaw::zero(self.params, 2, 1);
+            ct.get_poly_mut(0, 0)
+                .copy_from_slice(public_ct.get_poly(0, 0));
+            ct.get_poly_mut(1, 0)
+                .copy_from_slice(&bs[i * poly_len..(i + 1) * poly_len]);
+            let dec = self.inner.decrypt_matrix_reg(&ct.ntt()).raw();
+            for (j, &x) in dec.get_poly(0, 0).iter().enumerate() {
+                let x = multiply_uint_mod(x, n, self.params.modulus);
+                let pt = rescale(x, self.params.modulus, self.params.pt_modulus);
+                if pt != (i * poly_len + j == expected_index) as u64 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     pub fn decode_response(&self, response: &[u64]) -> Vec<u64> {
+        self.decode_response_with_noise(response).0
+    }
+
+    /// The first `top_bytes` plaintexts of item `index` from an
+    /// `answer_preview(query, item_size, top_bytes)` response, equal to the
+    /// start of the item a full response decodes to.
+    pub fn decode_preview(
+        &self,
+        response: &[u64],
+        is_simplepir: bool,
+        item_size: usize,
+        top_bytes: usize,
+        index: usize,
+    ) -> Vec<u64> {
+        let (_, db_cols) = crate::db::db_dims(self.params, is_simplepir);
+        // the response holds `top_bytes` words per item, item by item
+        let slot = index * item_size % db_cols / item_size;
+        let cols = slot * top_bytes..(slot + 1) * top_bytes;
+        self.decode_response_range(response, cols).0
+    }
+
+    /// Like `decode_response`, but also returns the largest `decode_noise_ratio`
+    /// seen across the decoded values.
+    pub fn decode_response_with_noise(&self, response: &[u64]) -> (Vec<u64>, f64) {
+        self.decode_response_range(response, 0..self.response_cols(response))
+    }
+
+    /// Decodes only the output coefficients in `cols`; equal to slicing the
+    /// output of `decode_response_with_noise` (the noise covers `cols` only).
+    pub fn decode_response_range(
+        &self,
+        response: &[u64],
+        cols: std::ops::Range<usize>,
+    ) -> (Vec<u64>, f64) {
+        let mut noise = 0f64;
+        let out = self
+            .decrypted_values(response, cols, None)
+            .unwrap_or_else(|_| unreachable!("no deadline"))
+            .into_iter()
+            .map(|result| {
+                noise = noise.max(decode_noise_ratio(
+                    result,
+                    self.params.modulus,
+                    self.params.pt_modulus,
+                ));
+                rescale(result, self.params.modulus, self.params.pt_modulus)
+            })
+            .collect();
+        (out, noise)
+    }
+
+    /// `decode_response` with `rescale_fast` and no noise measurement, for
+    /// callers that detect the occasional wrong value themselves (e.g. with
+    /// checksums). Agrees with `decode_response` unless a value is within
+    /// rounding error of a decode boundary.
+    pub fn decode_response_fast(&self, response: &[u64]) -> Vec<u64> {
+        self.decode_response_impl(response, None, rescale_fast)
+            .unwrap_or_else(|_| unreachable!("no deadline"))
+    }
+
+    /// `decode_response`, giving up with `DeadlineExceeded` once `deadline`
+    /// has passed; bounds the time spent on a pathological (e.g. oversized
+    /// full-format) response.
+    pub fn decode_response_by(
+        &self,
+        response: &[u64],
+        deadline: Instant,
+    ) -> Result<Vec<u64>, DeadlineExceeded> {
+        self.decode_response_impl(response, Some(deadline), rescale)
+    }
+
+    /// `decode_response_fast` with a deadline, as in `decode_response_by`.
+    pub fn decode_response_fast_by(
+        &self,
+        response: &[u64],
+        deadline: Instant,
+    ) -> Result<Vec<u64>, DeadlineExceeded> {
+        self.decode_response_impl(response, Some(deadline), rescale_fast)
+    }
+
+    fn decode_response_impl(
+        &self,
+        response: &[u64],
+        deadline: Option<Instant>,
+        round: fn(u64, u64, u64) -> u64,
+    ) -> Result<Vec<u64>, DeadlineExceeded> {
+        Ok(self
+            .decrypted_values(response, 0..self.response_cols(response), deadline)?
+            .into_iter()
+            .map(|result| round(result, self.params.modulus, self.params.pt_modulus))
+            .collect())
+    }
+
+    /// Columns `response` holds. The short format has one word per database
+    /// column, which is `2^(db_dim_2 + poly_len_log2)` for YPIR but
+    /// `instances * poly_len` for SimplePIR, so it is taken from the length;
+    /// anything as long as the full LWE format is read as that.
+    fn response_cols(&self, response: &[u64]) -> usize {
+        let db_cols = 1 << (self.params.db_dim_2 + self.params.poly_len_log2);
+        if response.len() < (self.params.poly_len + 1) * db_cols {
+            response.len()
+        } else {
+            db_cols
+        }
+    }
+
+    /// The scaled plaintexts (mod `modulus`, before rounding) in `cols`,
+    /// checking `deadline` every `DEADLINE_CHECK_COLS` columns.
+    fn decrypted_values(
+        &self,
+        response: &[u64],
+        cols: std::ops::Range<usize>,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<u64>, DeadlineExceeded> {
+        let check_deadline = |i: usize| match deadline {
+            Some(deadline) if i % DEADLINE_CHECK_COLS == 0 && Instant::now() >= deadline => {
+                Err(DeadlineExceeded)
+            }
+            _ => Ok(()),
+        };
+
         debug!("Decoding response: {:?}", &response[..response.len().min(16)]);
         let db_cols = 1 << (self.params.db_dim_2 + self.params.poly_len_log2);
+        let response_cols = self.response_cols(response);
+        assert!(
+            cols.end <= response_cols,
+            "decode_response: range {:?} out of bounds for {} columns",
+            cols,
+            response_cols
+        );
 
         // ------------------------------------------------------------
         // NEW: Handle "short" response format produced by current server:
-        // server.answer_query() returns exactly db_cols u64 words.
+        // server.answer_query() returns one u64 word per database column.
         // ------------------------------------------------------------
-        if response.len() == db_cols {
-            let mut out = Vec::with_capacity(db_cols);
-            for col in 0..db_cols {
-                let result = (response[col] % self.params.modulus) as u64;
-                let result_rescaled = rescale(result, self.params.modulus, self.params.pt_modulus);
-                out.push(result_rescaled);
-            }
-            return out;
+        if response.len() == response_cols {
+            return cols
+                .enumerate()
+                .map(|(i, col)| {
+                    check_deadline(i)?;
+                    Ok(response[col] % self.params.modulus)
+                })
+                .collect();
         }
 
         // ------------------------------------------------------------
@@ -373,8 +803,9 @@ impl<'a> YClient<'a> {
 
         let sk = self.inner.get_sk_reg().as_slice().to_vec();
 
-        let mut out = Vec::with_capacity(db_cols);
-        for col in 0..db_cols {
+        let mut out = Vec::with_capacity(cols.len());
+        for (i, col) in cols.enumerate() {
+            check_deadline(i)?;
             let mut sum = 0u128;
             for i in 0..self.params.poly_len {
                 let v1 = response[i * db_cols + col];
@@ -384,14 +815,34 @@ impl<'a> YClient<'a> {
 
             sum += response[self.params.poly_len * db_cols + col] as u128;
 
-            let result = (sum % self.params.modulus as u128) as u64;
-            let result_rescaled = rescale(result, self.params.modulus, self.params.pt_modulus);
-            out.push(result_rescaled);
+            out.push((sum % self.params.modulus as u128) as u64);
         }
 
-        out
+        Ok(out)
+    }
+
+    /// Like `decode_response`, but fails if any value's `decode_noise_ratio`
+    /// exceeds `max_noise_ratio` (see `DEFAULT_MAX_NOISE_RATIO`).
+    pub fn try_decode_response(
+        &self,
+        response: &[u64],
+        max_noise_ratio: f64,
+    ) -> Result<Vec<u64>, DecodeError> {
+        let (out, noise_ratio) = self.decode_response_with_noise(response);
+        if noise_ratio > max_noise_ratio {
+            return Err(DecodeError {
+                noise_ratio,
+                max_noise_ratio,
+            });
+        }
+        Ok(out)
     }
 
+    /// Whether two responses decode to the same plaintexts, even though their
+    /// raw words differ in the noise.
+    pub fn responses_equivalent(&self, response_a: &[u64], response_b: &[u64]) -> bool {
+        self.decode_response(response_a) == self.decode_response(response_b)
+    }
 
     pub fn client(&self) -> &Client<'a> {
         self.inner
@@ -413,4 +864,383 @@ mod test {
         let result = rescale(pt_dec as u64, lwe_params.modulus, lwe_params.pt_modulus) as u32;
         assert_eq!(result, pt);
     }
+
+    #[test]
+    fn test_debug_decrypt_query() {
+        let params = test_params();
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+
+        let target_row = 77;
+        let query = y_client.generate_query(SEED_0, params.db_dim_1, true, target_row);
+        let packed = pack_query(&params, &query);
+        assert_eq!(unpack_query(&params, packed.as_slice()), query);
+
+        assert!(y_client.debug_decrypt_query(packed.as_slice(), target_row));
+        assert!(!y_client.debug_decrypt_query(packed.as_slice(), target_row + 1));
+        let other = pack_query(
+            &params,
+            &y_client.generate_query(SEED_0, params.db_dim_1, true, 5),
+        );
+        assert!(!y_client.debug_decrypt_query(other.as_slice(), target_row));
+        assert!(!y_client.debug_decrypt_query(&packed.as_slice()[1..], target_row));
+    }
+
+    #[test]
+    fn test_decode_response_range() {
+        let params = test_params();
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+
+        for len in [db_cols, (params.poly_len + 1) * db_cols] {
+            let response = (0..len)
+                .map(|_| fastrand::u64(0..params.modulus))
+                .collect::<Vec<_>>();
+            let (full, _) = y_client.decode_response_with_noise(&response);
+            assert_eq!(full.len(), db_cols);
+            for range in [0..1, 17..90, db_cols - 5..db_cols] {
+                let (part, _) = y_client.decode_response_range(&response, range.clone());
+                assert_eq!(part, &full[range]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scrub_secret_key() {
+        let params = test_params();
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        assert!(export_secret_key(&client).iter().any(|&b| b != 0));
+        scrub_secret_key(&mut client);
+        assert!(export_secret_key(&client).iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_key_and_public_material_sizes() {
+        use crate::measurement::{get_vec_pm_size_bytes, pack_pub_params_size_bytes};
+        use crate::packing::condense_matrix;
+
+        for params in [test_params(), crate::params::params_for_scenario(1 << 30, 1)] {
+            let mut client = Client::init(&params);
+            client.generate_secret_keys();
+            assert_eq!(export_secret_key(&client).len(), secret_key_len(&params));
+
+            let sk_reg = client.get_sk_reg();
+            let pack_pub_params = raw_generate_expansion_params(
+                &params,
+                &sk_reg,
+                params.poly_len_log2,
+                params.t_exp_left,
+                &mut ChaCha20Rng::from_entropy(),
+                &mut ChaCha20Rng::from_seed(STATIC_SEED_2),
+            );
+            let row_1s = pack_pub_params
+                .iter()
+                .map(|p| condense_matrix(&params, &p.submatrix(1, 0, 1, p.cols)))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                get_vec_pm_size_bytes(&row_1s),
+                pack_pub_params_size_bytes(&params)
+            );
+        }
+    }
+
+    #[test]
+    fn test_public_material_deterministic() {
+        use crate::measurement::pack_pub_params_size_bytes;
+        use crate::packing::condense_matrix;
+        use crate::server::pack_pub_params_from_material;
+
+        let params = test_params();
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+
+        let material = public_material(&params, &client);
+        assert_eq!(material.len(), pack_pub_params_size_bytes(&params));
+        assert_eq!(public_material(&params, &client), material);
+
+        let row_1s = pack_pub_params_from_material(&params, &material).unwrap();
+        let expected = deterministic_pack_pub_params(&params, &client);
+        assert_eq!(row_1s.len(), expected.len());
+        for (row_1, p) in row_1s.iter().zip(&expected) {
+            let condensed = condense_matrix(&params, &p.submatrix(1, 0, 1, p.cols));
+            assert_eq!(row_1.as_slice(), condensed.as_slice());
+        }
+        assert!(pack_pub_params_from_material(&params, &material[1..]).is_none());
+
+        let mut other = Client::init(&params);
+        other.generate_secret_keys();
+        assert_ne!(public_material(&params, &other), material);
+    }
+
+    #[test]
+    fn test_decode_threshold() {
+        let mut small_pt = test_params();
+        small_pt.pt_modulus = 1 << 10;
+        let scenario = crate::params::params_for_scenario(1 << 30, 1);
+        for params in [test_params(), small_pt, scenario] {
+            let (modulus, pt_modulus) = (params.modulus, params.pt_modulus);
+            let threshold = decode_threshold(modulus, pt_modulus);
+            assert_eq!(rescale(threshold - 1, modulus, pt_modulus), 0);
+            assert_eq!(rescale(threshold, modulus, pt_modulus), 1);
+
+            // the same decision through the client's decode
+            let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+            let mut response = vec![0u64; db_cols];
+            response[..2].copy_from_slice(&[threshold - 1, threshold]);
+            let mut client = Client::init(&params);
+            client.generate_secret_keys();
+            let y_client = YClient::new(&mut client, &params);
+            let (decoded, _) = y_client.decode_response_range(&response, 0..2);
+            assert_eq!(decoded, [0, 1]);
+        }
+    }
+
+    #[test]
+    fn test_decode_noise_ratio() {
+        let lwe_params = LWEParams::default();
+        let client = LWEClient::new(lwe_params.clone());
+        let pt = fastrand::u32(0..lwe_params.pt_modulus as u32);
+        let scaled_pt = pt.wrapping_mul(lwe_params.scale_k() as u32);
+        let ct = client.encrypt(&mut ChaCha20Rng::from_entropy(), scaled_pt);
+        let pt_dec = client.decrypt(&ct) as u64;
+        let ratio = decode_noise_ratio(pt_dec, lwe_params.modulus, lwe_params.pt_modulus);
+        assert!(ratio < 1.0, "ratio: {}", ratio);
+
+        // the same absolute error gets closer to the threshold as the modulus shrinks
+        let pt_modulus = 256u64;
+        let err = 1000u64;
+        let mut modulus = 1u64 << 40;
+        let mut last_ratio = 0.;
+        while modulus / pt_modulus > 2 * err {
+            let delta = modulus / pt_modulus;
+            let val = (pt as u64 % pt_modulus) * delta + err;
+            let ratio = decode_noise_ratio(val, modulus, pt_modulus);
+            assert!(ratio > last_ratio && ratio < 1.0, "ratio: {}", ratio);
+            last_ratio = ratio;
+            modulus >>= 1;
+        }
+        assert!(last_ratio > 0.5, "ratio: {}", last_ratio);
+    }
+
+    #[test]
+    fn test_client_cost_formulas() {
+        let params = test_params();
+        let cts = 1u64 << params.db_dim_1;
+        let coeffs = (params.poly_len * params.crt_count) as u64;
+        let db_rows = 1u64 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1u64 << (params.db_dim_2 + params.poly_len_log2);
+        let n = LWEParams::default().n as u64;
+
+        let packed = ClientCost::new(&params, true);
+        assert_eq!(packed.query_ntts, 7 * cts);
+        assert_eq!(packed.query_multiplies, 3 * cts * coeffs);
+        assert_eq!(packed.query_samples, cts * params.poly_len as u64);
+
+        let lwe = ClientCost::new(&params, false);
+        assert_eq!(lwe.query_ntts, 0);
+        assert_eq!(lwe.query_multiplies, (n + 1) * db_rows);
+        assert_eq!(lwe.query_samples, db_rows);
+
+        // decoding doesn't depend on how the query was made
+        for cost in [packed, lwe] {
+            assert_eq!(cost.decode_multiplies, db_cols * params.poly_len as u64);
+            assert_eq!(cost.decode_rescales, db_cols);
+        }
+    }
+
+    #[test]
+    fn test_client_cost_scaling() {
+        let params = test_params();
+        let mut more_rows = params.clone();
+        more_rows.db_dim_1 += 1;
+        let mut more_cols = params.clone();
+        more_cols.db_dim_2 += 1;
+
+        // queries scale with the row dimension, decoding with the column one
+        for packing in [true, false] {
+            let base = ClientCost::new(&params, packing);
+            let rows_x2 = ClientCost::new(&more_rows, packing);
+            assert_eq!(rows_x2.query_ntts, 2 * base.query_ntts);
+            assert_eq!(rows_x2.query_multiplies, 2 * base.query_multiplies);
+            assert_eq!(rows_x2.query_samples, 2 * base.query_samples);
+            assert_eq!(rows_x2.decode_rescales, base.decode_rescales);
+
+            let cols_x2 = ClientCost::new(&more_cols, packing);
+            assert_eq!(cols_x2.query_ntts, base.query_ntts);
+            assert_eq!(cols_x2.query_multiplies, base.query_multiplies);
+            assert_eq!(cols_x2.decode_multiplies, 2 * base.decode_multiplies);
+            assert_eq!(cols_x2.decode_rescales, 2 * base.decode_rescales);
+        }
+    }
+
+    #[test]
+    fn test_decode_response_fast_agrees() {
+        let params = test_params();
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+
+        // encodings with noise well inside the decode threshold
+        let delta = params.modulus / params.pt_modulus;
+        let response = (0..db_cols)
+            .map(|_| {
+                let pt = fastrand::u64(0..params.pt_modulus);
+                let e = fastrand::u64(0..delta / 2);
+                (pt * delta + params.modulus - delta / 4 + e) % params.modulus
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            y_client.decode_response_fast(&response),
+            y_client.decode_response(&response)
+        );
+    }
+
+    #[test]
+    fn test_decode_response_deadline() {
+        let params = test_params();
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+
+        // full LWE format, the slow path
+        let response = (0..(params.poly_len + 1) * db_cols)
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            y_client.decode_response_by(&response, Instant::now()),
+            Err(DeadlineExceeded)
+        );
+        assert_eq!(
+            y_client.decode_response_fast_by(&response, Instant::now()),
+            Err(DeadlineExceeded)
+        );
+
+        let generous = Instant::now() + std::time::Duration::from_secs(600);
+        assert_eq!(
+            y_client.decode_response_by(&response, generous),
+            Ok(y_client.decode_response(&response))
+        );
+    }
+
+    #[test]
+    fn test_rotated_public_seed() {
+        let params = test_params();
+        let lwe_params = LWEParams::default();
+        let dim = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let seeds = PublicSeeds::default().with_seed(SEED_0, [7u8; 32]);
+        assert_ne!(seeds.get(SEED_0), get_seed(SEED_0));
+        assert_eq!(seeds.get(SEED_1), get_seed(SEED_1));
+
+        let mut client_a = Client::init(&params);
+        client_a.generate_secret_keys();
+        let y_client_a = YClient::new(&mut client_a, &params).with_public_seeds(seeds.clone());
+        let mut client_b = Client::init(&params);
+        client_b.generate_secret_keys();
+        let y_client_b = YClient::new(&mut client_b, &params);
+
+        let target_row = 77;
+        let query = y_client_a.generate_query(SEED_0, params.db_dim_1, false, target_row);
+        let query_default = y_client_b.generate_query(SEED_0, params.db_dim_1, false, target_row);
+        let a_rows = lwe_params.n * dim;
+        // the public part comes from the rotated seed, not the default one
+        assert_ne!(&query[..a_rows], &query_default[..a_rows]);
+
+        // and still decrypts to a unit vector at the target row
+        for row in 0..dim {
+            let ct = (0..lwe_params.n + 1)
+                .map(|r| query[r * dim + row] as u32)
+                .collect::<Vec<_>>();
+            let dec = y_client_a.lwe_client().decrypt(&ct);
+            let pt = rescale(dec as u64, lwe_params.modulus, lwe_params.pt_modulus);
+            assert_eq!(pt, (row == target_row) as u64, "row {}", row);
+        }
+    }
+
+    #[test]
+    fn test_responses_equivalent() {
+        use crate::db::{db_capacity, db_dims};
+        use crate::testing::{fixture_client, fixture_db, fixture_server};
+
+        let params = test_params();
+        let (db_rows, _) = db_dims(&params, false);
+        let num_items = db_capacity(&params, false, 64);
+        let db = fixture_db(&params, false, 64, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        // independently noised selection vectors
+        let delta = params.modulus / params.pt_modulus;
+        let answer_for = |target_row: usize| {
+            let query = (0..db_rows)
+                .map(|i| {
+                    let e = fastrand::i64(-(1 << 20)..=1 << 20);
+                    let e = e.rem_euclid(params.modulus as i64) as u64;
+                    let v = if i == target_row { delta } else { 0 };
+                    (v + e) % params.modulus
+                })
+                .collect::<Vec<_>>();
+            server.answer_query(pack_query(&params, &query).as_slice())
+        };
+
+        let resp_a = answer_for(9);
+        let resp_b = answer_for(9);
+        assert_ne!(resp_a.as_slice(), resp_b.as_slice());
+        assert!(y_client.responses_equivalent(resp_a.as_slice(), resp_b.as_slice()));
+
+        let resp_other = answer_for(10);
+        assert!(!y_client.responses_equivalent(resp_a.as_slice(), resp_other.as_slice()));
+    }
+
+    #[test]
+    fn test_try_decode_response_detects_under_provisioned_params() {
+        use crate::db::{db_capacity, db_dims};
+        use crate::testing::{fixture_client, fixture_db, fixture_server};
+
+        // p = 2^16 leaves the same query noise larger than half a plaintext step
+        let mut under_provisioned = test_params();
+        under_provisioned.pt_modulus = 1 << 16;
+
+        for (params, should_decode) in [(test_params(), true), (under_provisioned, false)] {
+            let (db_rows, db_cols) = db_dims(&params, false);
+            let num_items = db_capacity(&params, false, 64);
+            let db = fixture_db(&params, false, 64, num_items, 0).unwrap();
+            let server = fixture_server(&params, false, &db);
+            let mut client = fixture_client(&params);
+            let y_client = YClient::new(&mut client, &params);
+
+            // a noisy plaintext selection vector stands in for an encrypted query
+            let target_row = 42;
+            let delta = params.modulus / params.pt_modulus;
+            let noise_bound = 1i64 << 30;
+            let query = (0..db_rows)
+                .map(|i| {
+                    let e = fastrand::i64(-noise_bound..=noise_bound);
+                    let e = e.rem_euclid(params.modulus as i64) as u64;
+                    let v = if i == target_row { delta } else { 0 };
+                    (v + e) % params.modulus
+                })
+                .collect::<Vec<_>>();
+            let response = server.answer_query(pack_query(&params, &query).as_slice());
+
+            let decoded =
+                y_client.try_decode_response(response.as_slice(), DEFAULT_MAX_NOISE_RATIO);
+            if should_decode {
+                let decoded = decoded.unwrap();
+                for col in 0..db_cols {
+                    assert_eq!(decoded[col], db[target_row * db_cols + col] as u64);
+                }
+            } else {
+                let err = decoded.unwrap_err();
+                assert!(err.noise_ratio > DEFAULT_MAX_NOISE_RATIO, "{}", err);
+            }
+        }
+    }
 }
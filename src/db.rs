@@ -0,0 +1,1000 @@
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+
+use sha2::{Digest, Sha256};
+use spiral_rs::params::Params;
+
+use crate::transpose::transpose;
+
+/// Rows and columns of the database for `params`.
+pub fn db_dims(params: &Params, is_simplepir: bool) -> (usize, usize) {
+    let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+    let db_cols = if is_simplepir {
+        params.instances * params.poly_len
+    } else {
+        1 << (params.db_dim_2 + params.poly_len_log2)
+    };
+    (db_rows, db_cols)
+}
+
+/// Size in bytes of the (u8-element, row-major) database for `params`.
+pub fn db_num_bytes(params: &Params, is_simplepir: bool) -> usize {
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    db_rows * db_cols
+}
+
+/// Converts a row-major u8 database into column-major form: `db_cols`
+/// columns of `db_rows` bytes each, with no padding added. That is the
+/// buffer the server stores (and the kernel reads) whenever
+/// `db_rows_padded() == db_rows`, which `pad_rows` currently always gives,
+/// since its padding adds no rows.
+pub fn transpose_db(params: &Params, is_simplepir: bool, row_major: &[u8]) -> Vec<u8> {
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    assert_eq!(row_major.len(), db_rows * db_cols);
+    transpose(row_major, db_rows, db_cols, 1)
+}
+
+/// `transpose_db` from `input` to `output` with bounded memory: reads
+/// `rows_per_block` rows at a time and writes each column's piece of them
+/// at its place in the column-major output, so only about two blocks are
+/// ever in memory.
+pub fn transpose_db_stream<R: Read, W: Write + Seek>(
+    params: &Params,
+    is_simplepir: bool,
+    mut input: R,
+    mut output: W,
+    rows_per_block: usize,
+) -> io::Result<()> {
+    assert!(rows_per_block > 0);
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    let mut block = vec![0u8; rows_per_block.min(db_rows) * db_cols];
+    let mut row = 0;
+    while row < db_rows {
+        let rows = rows_per_block.min(db_rows - row);
+        let block = &mut block[..rows * db_cols];
+        input.read_exact(block)?;
+        let block_t = transpose(block, rows, db_cols, 1);
+        for (col, piece) in block_t.chunks_exact(rows).enumerate() {
+            output.seek(SeekFrom::Start((col * db_rows + row) as u64))?;
+            output.write_all(piece)?;
+        }
+        row += rows;
+    }
+    output.flush()
+}
+
+/// Number of `item_size`-byte items the database for `params` can hold.
+///
+/// Item `i` occupies bytes `[i * item_size, (i + 1) * item_size)` of the
+/// row-major database.
+pub fn db_capacity(params: &Params, is_simplepir: bool, item_size: usize) -> usize {
+    assert!(item_size > 0);
+    db_num_bytes(params, is_simplepir) / item_size
+}
+
+/// Physical row holding the start of item `index`, or `None` past the last
+/// item.
+///
+/// Padding rows (`pad_rows`) are only ever appended after the last logical
+/// row, so an item's row doesn't depend on whether padding is enabled.
+pub fn logical_to_physical(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    index: usize,
+) -> Option<usize> {
+    if index >= db_capacity(params, is_simplepir, item_size) {
+        return None;
+    }
+    let (_, db_cols) = db_dims(params, is_simplepir);
+    Some(index * item_size / db_cols)
+}
+
+/// First item starting in physical row `row`, or `None` for padding rows
+/// and rows no item starts in.
+pub fn physical_to_logical(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    row: usize,
+) -> Option<usize> {
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    if row >= db_rows {
+        return None;
+    }
+    let index = (row * db_cols).div_ceil(item_size);
+    (logical_to_physical(params, is_simplepir, item_size, index)? == row).then_some(index)
+}
+
+/// Where the `count` consecutive items from `start_index` lie: each row they
+/// touch, in order, with the columns of that row they cover.
+///
+/// A span costs one query per row rather than one per item, and the decoded
+/// columns, concatenated, are the items' bytes. `None` if `count` is 0 or
+/// the span runs past the last item.
+pub fn span_row_ranges(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    start_index: usize,
+    count: usize,
+) -> Option<Vec<(usize, Range<usize>)>> {
+    let last = start_index.checked_add(count)?.checked_sub(1)?;
+    logical_to_physical(params, is_simplepir, item_size, last)?;
+    let (_, db_cols) = db_dims(params, is_simplepir);
+    let bytes = start_index * item_size..(last + 1) * item_size;
+    let rows = bytes.start / db_cols..(bytes.end - 1) / db_cols + 1;
+    Some(
+        rows.map(|row| {
+            let start = bytes.start.max(row * db_cols) - row * db_cols;
+            let end = bytes.end.min((row + 1) * db_cols) - row * db_cols;
+            (row, start..end)
+        })
+        .collect(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildDbError {
+    /// More items were provided than the database has slots for.
+    TooManyItems { provided: usize, capacity: usize },
+    /// An item index (for keyed input) is past the last slot.
+    IndexOutOfRange { index: usize, capacity: usize },
+    /// An item is longer than the item size.
+    ItemTooLarge {
+        index: usize,
+        len: usize,
+        item_size: usize,
+    },
+    /// The database filled up before item `index` could be placed.
+    OutOfSpace { index: usize },
+    /// Row `row` would hold items of two different tenants.
+    MixedTenantRow { row: usize },
+}
+
+impl fmt::Display for BuildDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildDbError::TooManyItems { provided, capacity } => write!(
+                f,
+                "{} items provided, but the database only holds {}",
+                provided, capacity
+            ),
+            BuildDbError::IndexOutOfRange { index, capacity } => write!(
+                f,
+                "item index {} out of range for a database of {} items",
+                index, capacity
+            ),
+            BuildDbError::ItemTooLarge {
+                index,
+                len,
+                item_size,
+            } => write!(
+                f,
+                "item {} is {} bytes, larger than the item size of {} bytes",
+                index, len, item_size
+            ),
+            BuildDbError::OutOfSpace { index } => {
+                write!(f, "database is full; item {} does not fit", index)
+            }
+            BuildDbError::MixedTenantRow { row } => {
+                write!(f, "row {} holds items of more than one tenant", row)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildDbError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbRangeError {
+    /// The range runs past the end of the buffer or file.
+    OutOfBounds {
+        offset: usize,
+        length: usize,
+        buf_len: usize,
+    },
+    /// The range is shorter than the database.
+    TooShort { length: usize, required: usize },
+}
+
+impl fmt::Display for DbRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbRangeError::OutOfBounds {
+                offset,
+                length,
+                buf_len,
+            } => write!(
+                f,
+                "range of {} bytes at offset {} runs past the end of {} bytes",
+                length, offset, buf_len
+            ),
+            DbRangeError::TooShort { length, required } => write!(
+                f,
+                "range is {} bytes, but the database needs {}",
+                length, required
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DbRangeError {}
+
+/// Locates a database embedded in a larger buffer or file of `buf_len`
+/// bytes, e.g. after a header: `length` bytes from `offset` (default: to the
+/// end). Returns the `db_num_bytes` the server reads, from the start of the
+/// range; anything after them is ignored.
+pub fn db_subrange(
+    params: &Params,
+    is_simplepir: bool,
+    buf_len: usize,
+    offset: usize,
+    length: Option<usize>,
+) -> Result<Range<usize>, DbRangeError> {
+    let length = length.unwrap_or(buf_len.saturating_sub(offset));
+    if !matches!(offset.checked_add(length), Some(end) if end <= buf_len) {
+        return Err(DbRangeError::OutOfBounds {
+            offset,
+            length,
+            buf_len,
+        });
+    }
+    let required = db_num_bytes(params, is_simplepir);
+    if length < required {
+        return Err(DbRangeError::TooShort { length, required });
+    }
+    Ok(offset..offset + required)
+}
+
+/// Lays out `items` (in order, zero-padded to `item_size`) into a row-major
+/// database blob suitable for `YServer::new(.., inp_transposed = false, ..)`.
+pub fn build_db<'b>(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    items: impl ExactSizeIterator<Item = &'b [u8]>,
+) -> Result<Vec<u8>, BuildDbError> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if items.len() > capacity {
+        return Err(BuildDbError::TooManyItems {
+            provided: items.len(),
+            capacity,
+        });
+    }
+    build_db_keyed(params, is_simplepir, item_size, items.enumerate())
+}
+
+/// Like `build_db`, but stores item `i` in slot `permute_index(.., seed, i)`,
+/// so the stored order of the items doesn't follow their indices.
+pub fn build_db_permuted<'b>(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    items: impl ExactSizeIterator<Item = &'b [u8]>,
+    seed: u64,
+) -> Result<Vec<u8>, BuildDbError> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if items.len() > capacity {
+        return Err(BuildDbError::TooManyItems {
+            provided: items.len(),
+            capacity,
+        });
+    }
+    let items = items.enumerate().map(|(index, item)| {
+        let slot = permute_index(params, is_simplepir, item_size, seed, index).unwrap();
+        (slot, item)
+    });
+    build_db_keyed(params, is_simplepir, item_size, items)
+}
+
+/// `x` quantized to a byte: `round(x / scale) + zero_point`, clamped to
+/// 0..=255. For `x` in `-zero_point * scale..=(255 - zero_point) * scale`,
+/// `dequantize` gets back to within `scale / 2`; outside it saturates.
+/// `scale` must be positive and finite.
+pub fn quantize(x: f32, scale: f32, zero_point: u8) -> u8 {
+    let q = (x / scale).round() + zero_point as f32;
+    q.clamp(0., 255.) as u8
+}
+
+/// The value byte `q` stands for under `quantize(.., scale, zero_point)`.
+pub fn dequantize(q: u8, scale: f32, zero_point: u8) -> f32 {
+    (q as f32 - zero_point as f32) * scale
+}
+
+/// Quantizes each vector into one `item_size`-byte item (`quantize` per
+/// element), concatenated in order: item `i` is bytes `i * item_size..(i +
+/// 1) * item_size`, ready for `build_db`. Short vectors are padded with
+/// `zero_point`, which dequantizes to 0.0.
+pub fn quantize_items(
+    vectors: &[Vec<f32>],
+    scale: f32,
+    zero_point: u8,
+    item_size: usize,
+) -> Result<Vec<u8>, BuildDbError> {
+    let mut out = vec![zero_point; vectors.len() * item_size];
+    for (index, (vector, item)) in vectors.iter().zip(out.chunks_mut(item_size)).enumerate() {
+        if vector.len() > item_size {
+            return Err(BuildDbError::ItemTooLarge {
+                index,
+                len: vector.len(),
+                item_size,
+            });
+        }
+        for (byte, &x) in item.iter_mut().zip(vector) {
+            *byte = quantize(x, scale, zero_point);
+        }
+    }
+    Ok(out)
+}
+
+/// Tenant tag of each physical row of the `build_db` database whose item
+/// `i` belongs to tenant `item_tenants[i]`; `None` for rows holding no item.
+///
+/// Access is enforced per row, so every row must belong to one tenant: give
+/// each tenant whole rows (e.g. pad its run of items to a row boundary),
+/// otherwise this fails with `MixedTenantRow`.
+pub fn row_tenants(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    item_tenants: &[u32],
+) -> Result<Vec<Option<u32>>, BuildDbError> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if item_tenants.len() > capacity {
+        return Err(BuildDbError::TooManyItems {
+            provided: item_tenants.len(),
+            capacity,
+        });
+    }
+    let (db_rows, _) = db_dims(params, is_simplepir);
+    let mut tags = vec![None; db_rows];
+    for (index, &tenant) in item_tenants.iter().enumerate() {
+        for (row, _) in span_row_ranges(params, is_simplepir, item_size, index, 1).unwrap() {
+            match tags[row] {
+                Some(other) if other != tenant => {
+                    return Err(BuildDbError::MixedTenantRow { row });
+                }
+                _ => tags[row] = Some(tenant),
+            }
+        }
+    }
+    Ok(tags)
+}
+
+const PERMUTE_ROUNDS: u8 = 4;
+
+/// Slot holding item `logical` in a database built by `build_db_permuted`
+/// with `seed`, or `None` past the last item; query that slot (e.g. via
+/// `logical_to_physical`) to fetch the item.
+///
+/// A keyed pseudorandom permutation of `0..db_capacity`: a SHA-256 Feistel
+/// network on the smallest even-width power of two covering the capacity,
+/// cycle-walked back into range. Anyone with the seed can undo it.
+pub fn permute_index(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    seed: u64,
+    logical: usize,
+) -> Option<usize> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if logical >= capacity {
+        return None;
+    }
+    let bits = usize::BITS - (capacity - 1).leading_zeros();
+    let half_bits = bits.div_ceil(2).max(1);
+    let mut x = logical as u64;
+    loop {
+        x = feistel(seed, half_bits, x);
+        if x < capacity as u64 {
+            return Some(x as usize);
+        }
+    }
+}
+
+fn feistel(seed: u64, half_bits: u32, x: u64) -> u64 {
+    let mask = (1u64 << half_bits) - 1;
+    let (mut l, mut r) = (x >> half_bits, x & mask);
+    for round in 0..PERMUTE_ROUNDS {
+        let mut hasher = Sha256::new();
+        hasher.update(b"ypir-permute-v1");
+        hasher.update(seed.to_le_bytes());
+        hasher.update([round]);
+        hasher.update(r.to_le_bytes());
+        let f = u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap());
+        (l, r) = (r, l ^ (f & mask));
+    }
+    (l << half_bits) | r
+}
+
+/// Like `build_db`, but places each item at its given index; missing
+/// indices are left zeroed.
+pub fn build_db_keyed<'b>(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    items: impl ExactSizeIterator<Item = (usize, &'b [u8])>,
+) -> Result<Vec<u8>, BuildDbError> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if items.len() > capacity {
+        return Err(BuildDbError::TooManyItems {
+            provided: items.len(),
+            capacity,
+        });
+    }
+
+    let mut db = vec![0u8; db_num_bytes(params, is_simplepir)];
+    for (index, item) in items {
+        write_db_item(params, is_simplepir, item_size, &mut db, index, item)?;
+    }
+    Ok(db)
+}
+
+/// Writes one item into a row-major database blob at its slot; for building
+/// sparse databases item by item from a zeroed buffer of `db_num_bytes`.
+pub fn write_db_item(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    db: &mut [u8],
+    index: usize,
+    item: &[u8],
+) -> Result<(), BuildDbError> {
+    assert_eq!(db.len(), db_num_bytes(params, is_simplepir));
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if index >= capacity {
+        return Err(BuildDbError::IndexOutOfRange { index, capacity });
+    }
+    if item.len() > item_size {
+        return Err(BuildDbError::ItemTooLarge {
+            index,
+            len: item.len(),
+            item_size,
+        });
+    }
+    let start = index * item_size;
+    db[start..start + item.len()].copy_from_slice(item);
+    Ok(())
+}
+
+/// Where `build_db_varlen` placed each item: item `i` is `entries[i].1` bytes
+/// starting at row-major byte offset `entries[i].0`.
+///
+/// Items never straddle a row, so each one is recovered from the single
+/// response for its row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarlenManifest {
+    pub db_cols: usize,
+    pub entries: Vec<(usize, usize)>,
+}
+
+impl VarlenManifest {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Row to query for item `index`.
+    pub fn row(&self, index: usize) -> usize {
+        self.entries[index].0 / self.db_cols
+    }
+
+    /// Columns of that row's response holding item `index`.
+    pub fn cols(&self, index: usize) -> std::ops::Range<usize> {
+        let (offset, len) = self.entries[index];
+        let start = offset % self.db_cols;
+        start..start + len
+    }
+}
+
+/// Packs variable-length `items` contiguously into a row-major database,
+/// moving to the next row whenever an item wouldn't fit in the current one.
+/// Items can be at most one row (`db_cols` bytes) long.
+pub fn build_db_varlen<'b>(
+    params: &Params,
+    is_simplepir: bool,
+    items: impl Iterator<Item = &'b [u8]>,
+) -> Result<(Vec<u8>, VarlenManifest), BuildDbError> {
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    let mut db = vec![0u8; db_rows * db_cols];
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    for (index, item) in items.enumerate() {
+        if item.len() > db_cols {
+            return Err(BuildDbError::ItemTooLarge {
+                index,
+                len: item.len(),
+                item_size: db_cols,
+            });
+        }
+        if offset % db_cols + item.len() > db_cols {
+            offset = offset.next_multiple_of(db_cols);
+        }
+        if offset + item.len() > db.len() {
+            return Err(BuildDbError::OutOfSpace { index });
+        }
+        db[offset..offset + item.len()].copy_from_slice(item);
+        entries.push((offset, item.len()));
+        offset += item.len();
+    }
+    Ok((db, VarlenManifest { db_cols, entries }))
+}
+
+/// Per-item version counters for optimistic concurrency on a mutable
+/// database. Versions are kept outside the PIR payload; every item starts
+/// at version 0.
+#[derive(Debug, Clone, Default)]
+pub struct ItemVersions {
+    versions: std::collections::HashMap<usize, u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub index: usize,
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for VersionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "item {} is at version {}, expected {}",
+            self.index, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for VersionMismatch {}
+
+impl ItemVersions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, index: usize) -> u32 {
+        self.versions.get(&index).copied().unwrap_or(0)
+    }
+
+    /// Unconditionally bumps the version of `index`, returning the new one.
+    pub fn bump(&mut self, index: usize) -> u32 {
+        let v = self.versions.entry(index).or_insert(0);
+        *v = v.wrapping_add(1);
+        *v
+    }
+
+    /// Checks that `index` is currently at version `expected`.
+    pub fn check(&self, index: usize, expected: u32) -> Result<(), VersionMismatch> {
+        let actual = self.get(index);
+        if actual != expected {
+            return Err(VersionMismatch {
+                index,
+                expected,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.versions.clear();
+    }
+}
+
+/// Which items of a database were ever written, so an operator can tell an
+/// empty slot from an item that happens to be all zeros; both decode to zero
+/// bytes. Like `ItemVersions`, this is plaintext bookkeeping outside the PIR
+/// payload.
+#[derive(Debug, Clone)]
+pub struct WrittenItems {
+    /// `None` counts every item as written.
+    indices: Option<std::collections::HashSet<usize>>,
+}
+
+impl WrittenItems {
+    /// For a database loaded whole, where every slot holds data.
+    pub fn all() -> Self {
+        Self { indices: None }
+    }
+
+    /// For a sparse database built from an all-zero buffer.
+    pub fn none() -> Self {
+        Self {
+            indices: Some(Default::default()),
+        }
+    }
+
+    pub fn mark(&mut self, index: usize) {
+        if let Some(indices) = &mut self.indices {
+            indices.insert(index);
+        }
+    }
+
+    pub fn is_written(&self, index: usize) -> bool {
+        self.indices.as_ref().map_or(true, |x| x.contains(&index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_params;
+
+    #[test]
+    fn test_quantize_fetch_dequantize() {
+        use crate::client::YClient;
+        use crate::testing::{fetch_item, fixture_client, fixture_server};
+
+        let params = test_params();
+        let item_size = 64;
+        let (scale, zero_point) = (1. / 127., 128);
+        // fixed values spread over [-1, 1), some vectors shorter than an item
+        let vectors = (0..40)
+            .map(|i| {
+                (0..item_size - i % 3)
+                    .map(|j| ((i * item_size + j) * 37 % 256) as f32 / 128. - 1.)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let items = quantize_items(&vectors, scale, zero_point, item_size).unwrap();
+        let db = build_db(&params, false, item_size, items.chunks(item_size)).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        for (index, vector) in vectors.iter().enumerate() {
+            // noiseless query, so the fetch itself is exact
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            let values = item
+                .iter()
+                .map(|&b| dequantize(b, scale, zero_point))
+                .collect::<Vec<_>>();
+            for (&x, &y) in vector.iter().zip(&values) {
+                assert!((x - y).abs() <= scale / 2. + 1e-6, "{} vs {}", x, y);
+            }
+            assert!(values[vector.len()..].iter().all(|&y| y == 0.));
+        }
+
+        assert_eq!(
+            quantize_items(&[vec![0.; item_size + 1]], scale, zero_point, item_size),
+            Err(BuildDbError::ItemTooLarge {
+                index: 0,
+                len: item_size + 1,
+                item_size
+            })
+        );
+        assert_eq!(quantize(10., scale, zero_point), 255);
+        assert_eq!(quantize(-10., scale, zero_point), 0);
+    }
+
+    #[test]
+    fn test_transpose_db_stream() {
+        use crate::client::pack_query;
+        use crate::server::{DbRowsPadded, YServer};
+
+        let params = test_params();
+        let row_major = (0..db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let dir = std::env::temp_dir();
+        let in_path = dir.join(format!("ypir_rowmajor_{}", std::process::id()));
+        let out_path = dir.join(format!("ypir_colmajor_{}", std::process::id()));
+        std::fs::write(&in_path, &row_major).unwrap();
+
+        // 300 doesn't divide the 2048 rows, so the last block is short
+        let input = std::fs::File::open(&in_path).unwrap();
+        let output = std::fs::File::create(&out_path).unwrap();
+        transpose_db_stream(&params, false, input, output, 300).unwrap();
+        let transposed = std::fs::read(&out_path).unwrap();
+        std::fs::remove_file(&in_path).unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+        assert_eq!(transposed, transpose_db(&params, false, &row_major));
+
+        let from_file = YServer::<u8>::new(&params, transposed.iter().copied(), false, true, true);
+        let reference = YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        assert_eq!(
+            from_file.answer_query(packed.as_slice()).as_slice(),
+            reference.answer_query(packed.as_slice()).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_db_subrange_in_file() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        use crate::client::YClient;
+        use crate::testing::{
+            expected_item, fetch_item, fixture_client, fixture_db, fixture_server,
+        };
+
+        let params = test_params();
+        let required = db_num_bytes(&params, false);
+        let item_size = 64;
+        let num_items = db_capacity(&params, false, item_size);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let (header, trailer) = (vec![0xaa; 4096], vec![0xbb; 100]);
+        let path = std::env::temp_dir().join(format!("ypir_subrange_{}", std::process::id()));
+        std::fs::write(&path, [&header[..], &db, &trailer].concat()).unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let file_len = file.metadata().unwrap().len() as usize;
+        let range = db_subrange(&params, false, file_len, header.len(), None).unwrap();
+        let mut read = vec![0u8; range.len()];
+        file.seek(SeekFrom::Start(range.start as u64)).unwrap();
+        file.read_exact(&mut read).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read, db);
+
+        // the window serves queries like the bare database
+        let server = fixture_server(&params, false, &read);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        for index in [0, 1, num_items - 1] {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            assert_eq!(item, expected_item(index, item_size));
+        }
+
+        assert_eq!(
+            db_subrange(&params, false, file_len, header.len(), Some(required)),
+            Ok(range)
+        );
+        assert_eq!(
+            db_subrange(&params, false, file_len, header.len() + 200, None),
+            Err(DbRangeError::TooShort {
+                length: required - 100,
+                required
+            })
+        );
+        assert_eq!(
+            db_subrange(&params, false, file_len, 1, Some(file_len)),
+            Err(DbRangeError::OutOfBounds {
+                offset: 1,
+                length: file_len,
+                buf_len: file_len
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_db_capacity() {
+        let params = test_params();
+        let item_size = 1024;
+        let capacity = db_capacity(&params, false, item_size);
+        assert_eq!(capacity * item_size, db_num_bytes(&params, false));
+
+        let items = (0..capacity)
+            .map(|i| vec![(i % 251) as u8; item_size])
+            .collect::<Vec<_>>();
+        let db = build_db(&params, false, item_size, items.iter().map(|x| x.as_slice())).unwrap();
+        assert_eq!(db.len(), db_num_bytes(&params, false));
+        for &i in &[0, 1, capacity / 2, capacity - 1] {
+            assert_eq!(&db[i * item_size..(i + 1) * item_size], items[i].as_slice());
+        }
+
+        let mut too_many = items.clone();
+        too_many.push(vec![0u8; item_size]);
+        assert_eq!(
+            build_db(&params, false, item_size, too_many.iter().map(|x| x.as_slice())),
+            Err(BuildDbError::TooManyItems {
+                provided: capacity + 1,
+                capacity
+            })
+        );
+
+        let oversized = vec![0u8; item_size + 1];
+        assert_eq!(
+            build_db(&params, false, item_size, std::iter::once(oversized.as_slice())),
+            Err(BuildDbError::ItemTooLarge {
+                index: 0,
+                len: item_size + 1,
+                item_size
+            })
+        );
+    }
+
+    #[test]
+    fn test_item_versions_stale_update() {
+        let mut versions = ItemVersions::new();
+        assert_eq!(versions.get(3), 0);
+
+        // two writers read version 0; the first one wins
+        versions.check(3, 0).unwrap();
+        assert_eq!(versions.bump(3), 1);
+        assert_eq!(
+            versions.check(3, 0),
+            Err(VersionMismatch {
+                index: 3,
+                expected: 0,
+                actual: 1
+            })
+        );
+        versions.check(3, 1).unwrap();
+        assert_eq!(versions.get(4), 0);
+    }
+
+    #[test]
+    fn test_logical_physical_roundtrip() {
+        let params = test_params();
+        let (db_rows, db_cols) = db_dims(&params, false);
+        for item_size in [1, 16, 3000] {
+            let capacity = db_capacity(&params, false, item_size);
+            for index in [0, 1, capacity / 3, capacity - 1] {
+                let row = logical_to_physical(&params, false, item_size, index).unwrap();
+                assert_eq!(row, index * item_size / db_cols);
+                let first = physical_to_logical(&params, false, item_size, row).unwrap();
+                assert!(first <= index);
+                assert_eq!(logical_to_physical(&params, false, item_size, first), Some(row));
+            }
+            assert_eq!(logical_to_physical(&params, false, item_size, capacity), None);
+        }
+        assert_eq!(physical_to_logical(&params, false, 16, db_rows), None);
+        // 3000-byte items start at bytes 6000 and 9000, skipping row 3
+        assert_eq!(physical_to_logical(&params, false, 3000, 3), None);
+    }
+
+    #[test]
+    fn test_span_reassembles_record() {
+        let params = test_params();
+        let (_, db_cols) = db_dims(&params, false);
+        // a 5000-byte record in 3000-byte items 1..3, across rows 1 to 4
+        let item_size = 3000;
+        let record = (0..5000).map(|_| fastrand::u8(..)).collect::<Vec<_>>();
+        let mut items = vec![vec![0u8; item_size]; 4];
+        for (item, chunk) in items[1..3].iter_mut().zip(record.chunks(item_size)) {
+            item[..chunk.len()].copy_from_slice(chunk);
+        }
+        let db = build_db(&params, false, item_size, items.iter().map(|i| &i[..])).unwrap();
+
+        let spans = span_row_ranges(&params, false, item_size, 1, 2).unwrap();
+        let rows = spans.iter().map(|(row, _)| *row).collect::<Vec<_>>();
+        assert_eq!(rows, [1, 2, 3, 4]);
+        let mut got = spans
+            .iter()
+            .flat_map(|(row, cols)| &db[row * db_cols..][cols.clone()])
+            .copied()
+            .collect::<Vec<_>>();
+        got.truncate(record.len());
+        assert_eq!(got, record);
+
+        let capacity = db_capacity(&params, false, item_size);
+        assert!(span_row_ranges(&params, false, item_size, capacity - 1, 1).is_some());
+        assert!(span_row_ranges(&params, false, item_size, capacity - 1, 2).is_none());
+        assert!(span_row_ranges(&params, false, item_size, 0, 0).is_none());
+    }
+
+    #[test]
+    fn test_permuted_db_fetches_logical_item() {
+        use std::collections::HashSet;
+
+        use crate::client::YClient;
+        use crate::testing::{
+            expected_item, fetch_item, fixture_client, fixture_item, fixture_server,
+        };
+
+        let params = test_params();
+        let item_size = 64;
+        let capacity = db_capacity(&params, false, item_size);
+        let seed = 7;
+
+        let slots = (0..capacity)
+            .map(|i| permute_index(&params, false, item_size, seed, i).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(slots.iter().collect::<HashSet<_>>().len(), capacity);
+        assert!(slots.iter().all(|&slot| slot < capacity));
+        assert_ne!(slots, (0..capacity).collect::<Vec<_>>());
+        assert_ne!(
+            slots[..16],
+            (0..16)
+                .map(|i| permute_index(&params, false, item_size, seed + 1, i).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert!(permute_index(&params, false, item_size, seed, capacity).is_none());
+
+        let items = (0..capacity)
+            .map(|i| fixture_item(0, i, item_size))
+            .collect::<Vec<_>>();
+        let item_refs = || items.iter().map(|x| &x[..]);
+        let db = build_db_permuted(&params, false, item_size, item_refs(), seed).unwrap();
+        let plain = build_db(&params, false, item_size, item_refs()).unwrap();
+        assert_ne!(db, plain);
+
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        for index in [0, 1, capacity / 2, capacity - 1] {
+            let slot = permute_index(&params, false, item_size, seed, index).unwrap();
+            let item = fetch_item(&params, false, &server, &y_client, item_size, slot);
+            assert_eq!(item, expected_item(index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_build_db_varlen_errors() {
+        let params = test_params();
+        let (db_rows, db_cols) = db_dims(&params, false);
+
+        let too_long = vec![1u8; db_cols + 1];
+        assert_eq!(
+            build_db_varlen(&params, false, std::iter::once(too_long.as_slice())),
+            Err(BuildDbError::ItemTooLarge {
+                index: 0,
+                len: db_cols + 1,
+                item_size: db_cols
+            })
+        );
+
+        // two items per row don't fit, so each takes a row of its own
+        let half_plus_one = vec![1u8; db_cols / 2 + 1];
+        let items = vec![half_plus_one.as_slice(); db_rows + 1];
+        assert_eq!(
+            build_db_varlen(&params, false, items.into_iter()),
+            Err(BuildDbError::OutOfSpace { index: db_rows })
+        );
+    }
+
+    #[test]
+    fn test_build_db_keyed() {
+        let params = test_params();
+        let item_size = 1024;
+        let capacity = db_capacity(&params, false, item_size);
+
+        let item = [7u8, 8, 9];
+        let db = build_db_keyed(&params, false, item_size, [(5, &item[..])].into_iter()).unwrap();
+        assert_eq!(&db[5 * item_size..5 * item_size + 3], &item);
+        assert!(db[..5 * item_size].iter().all(|&x| x == 0));
+
+        assert_eq!(
+            build_db_keyed(&params, false, item_size, [(capacity, &item[..])].into_iter()),
+            Err(BuildDbError::IndexOutOfRange {
+                index: capacity,
+                capacity
+            })
+        );
+    }
+
+    #[test]
+    fn test_written_items_distinguish_empty_from_zero() {
+        use crate::client::YClient;
+        use crate::testing::{
+            expected_item, fetch_item, fixture_client, fixture_item, fixture_server,
+        };
+
+        let params = test_params();
+        let item_size = 64;
+        let (data, zero, never) = (7, 8, 9);
+
+        let mut db = vec![0u8; db_num_bytes(&params, false)];
+        let mut written = WrittenItems::none();
+        for (index, item) in [
+            (data, fixture_item(0, data, item_size)),
+            (zero, vec![0; item_size]),
+        ] {
+            write_db_item(&params, false, item_size, &mut db, index, &item).unwrap();
+            written.mark(index);
+        }
+        assert!(written.is_written(data) && written.is_written(zero));
+        assert!(!written.is_written(never));
+        assert!(WrittenItems::all().is_written(never));
+
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        for (index, want) in [
+            (data, expected_item(data, item_size)),
+            (zero, vec![0; item_size]),
+            (never, vec![0; item_size]),
+        ] {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            assert_eq!(item, want, "item {}", index);
+        }
+    }
+}
@@ -1,7 +1,406 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
 use spiral_rs::{arith::*, params::*};
 
+use super::params::{query_limb_crt_indices, MAX_QUERY_LIMBS};
 use super::server::ToM512;
 
+/// Environment variable that pins the kernel ("scalar" or "avx2").
+pub const KERNEL_ENV_VAR: &str = "YPIR_KERNEL";
+
+/// Which reduction path `fast_batched_dot_product_avx512` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KernelKind {
+    Scalar,
+    /// Reduces `REDUCE_LANES` columns at a time with AVX2.
+    Avx2,
+}
+
+impl KernelKind {
+    pub const ALL: [KernelKind; 2] = [KernelKind::Scalar, KernelKind::Avx2];
+
+    /// The kernels this build can run on this CPU.
+    pub fn available() -> impl Iterator<Item = KernelKind> {
+        KernelKind::ALL.into_iter().filter(|k| k.is_available())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KernelKind::Scalar => "scalar",
+            KernelKind::Avx2 => "avx2",
+        }
+    }
+
+    /// Parses a kernel name; unknown names, and kernels this build or CPU
+    /// can't run, fall back to `Scalar`.
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "avx2" if KernelKind::Avx2.is_available() => KernelKind::Avx2,
+            _ => KernelKind::Scalar,
+        }
+    }
+
+    pub fn is_available(self) -> bool {
+        match self {
+            KernelKind::Scalar => true,
+            #[cfg(target_feature = "avx2")]
+            KernelKind::Avx2 => is_x86_feature_detected!("avx2"),
+            #[cfg(not(target_feature = "avx2"))]
+            KernelKind::Avx2 => false,
+        }
+    }
+
+    fn detect() -> Self {
+        match std::env::var(KERNEL_ENV_VAR) {
+            Ok(name) => KernelKind::from_name(&name),
+            Err(_) if KernelKind::Avx2.is_available() => KernelKind::Avx2,
+            Err(_) => KernelKind::Scalar,
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            KernelKind::Scalar => 1,
+            KernelKind::Avx2 => 2,
+        }
+    }
+}
+
+// 0 = not chosen yet
+static ACTIVE_KERNEL: AtomicU8 = AtomicU8::new(0);
+
+/// The kernel in use: the last `set_kernel`, else `YPIR_KERNEL`, else the
+/// best one available.
+pub fn active_kernel() -> KernelKind {
+    match ACTIVE_KERNEL.load(Ordering::Relaxed) {
+        1 => KernelKind::Scalar,
+        2 => KernelKind::Avx2,
+        _ => {
+            let kind = KernelKind::detect();
+            ACTIVE_KERNEL.store(kind.to_u8(), Ordering::Relaxed);
+            kind
+        }
+    }
+}
+
+/// Overrides the kernel for the whole process. Falls back to `Scalar` if
+/// `kind` isn't available; returns the kernel actually selected.
+pub fn set_kernel(kind: KernelKind) -> KernelKind {
+    let kind = if kind.is_available() {
+        kind
+    } else {
+        KernelKind::Scalar
+    };
+    ACTIVE_KERNEL.store(kind.to_u8(), Ordering::Relaxed);
+    kind
+}
+
+/// Reads element `idx` of `b_t`, widened to u64.
+///
+/// For `T = u8` this is a plain widening load; other element types go through
+/// `ToM512`, which is the scalar fallback on non-avx512f targets.
+#[inline(always)]
+fn load_db_elem<T: Copy>(b_t: &[T], idx: usize) -> u64
+where
+    *const T: ToM512,
+{
+    debug_assert!(idx < b_t.len());
+    if std::mem::size_of::<T>() == 1 {
+        unsafe { *(b_t.as_ptr().add(idx) as *const u8) as u64 }
+    } else {
+        unsafe { b_t.as_ptr().add(idx).to_m512() }
+    }
+}
+
+fn pow_mod(mut base: u64, mut exp: u64, modulus: u64) -> u64 {
+    let mut result = 1u64;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = ((result as u128 * base as u128) % modulus as u128) as u64;
+        }
+        base = ((base as u128 * base as u128) % modulus as u128) as u64;
+        exp >>= 1;
+    }
+    result
+}
+
+/// Per-limb column accumulator. By default the sums wrap at 2^64, which is
+/// only exact while `a_elems * 2^32 * max_db_elem` stays below that; the
+/// `wide_accum` feature accumulates in u128 instead, which cannot overflow for
+/// any database that fits in memory.
+#[cfg(feature = "wide_accum")]
+type LimbSum = u128;
+#[cfg(not(feature = "wide_accum"))]
+type LimbSum = u64;
+
+/// `sum + a * b` for the limb accumulators. Release builds wrap, as the
+/// SIMD kernels do; debug builds check instead and panic, so tests catch an
+/// input that overflows the accumulator rather than returning a corrupted
+/// answer.
+#[inline(always)]
+fn mul_acc(sum: LimbSum, a: LimbSum, b: LimbSum) -> LimbSum {
+    #[cfg(debug_assertions)]
+    {
+        a.checked_mul(b)
+            .and_then(|product| sum.checked_add(product))
+            .expect("kernel limb sum overflow")
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        sum.wrapping_add(a.wrapping_mul(b))
+    }
+}
+
+/// Brings the sum of packed-query limb `limb` back to u64 for `CrtReducer`,
+/// preserving it mod that limb's CRT factor.
+#[inline(always)]
+fn narrow_limb_sum(params: &Params, sum: LimbSum, limb: usize) -> u64 {
+    #[cfg(feature = "wide_accum")]
+    {
+        // a limb with no CRT factor multiplies zeros, so its sum is 0
+        let Some(&crt_index) = query_limb_crt_indices(params).get(limb) else {
+            return 0;
+        };
+        (sum % params.moduli[crt_index] as u128) as u64
+    }
+    #[cfg(not(feature = "wide_accum"))]
+    {
+        let _ = (params, limb);
+        sum
+    }
+}
+
+/// Number of columns whose limb sums are reduced together.
+pub const REDUCE_LANES: usize = 4;
+
+/// Constants for reducing CRT limb sums to a value mod `params.modulus`.
+///
+/// `reduce` computes exactly what the scalar
+/// `barrett_coeff_u64` / `crt_compose_2` sequence does (every step is a full
+/// reduction, so any correct reduction gives the same result), but uses
+/// Garner's formula for the composition so that all products fit in 64 bits.
+///
+/// With a single CRT factor (see `params_with_crt_limbs`) only the low limb
+/// carries a residue and there is nothing to compose; `reduce` then takes
+/// the scalar path.
+///
+/// Only one and two limbs are supported: a packed query word has room for
+/// two 32-bit residues, and the composition is the two-factor Garner step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrtReducer {
+    limbs: usize,
+    moduli: [u64; 2],
+    barrett_cr: [u64; 2],
+    mod0_inv_mod1: u64,
+}
+
+// the reducer, and the packed query word, stop at two limbs
+const _: () = assert!(MAX_QUERY_LIMBS == 2);
+
+impl CrtReducer {
+    pub fn new(params: &Params) -> Self {
+        // indexed by limb; Garner's formula below works in either order
+        let limb_crt = query_limb_crt_indices(params);
+        let limbs = limb_crt.len();
+        let moduli: [u64; 2] = std::array::from_fn(|l| params.moduli[limb_crt[l.min(limbs - 1)]]);
+        assert!(moduli.iter().all(|&m| m < 1 << 30));
+        let mod0_inv_mod1 = if limbs == 2 {
+            // moduli[1] is prime, so a^(p-2) is the inverse
+            pow_mod(moduli[0] % moduli[1], moduli[1] - 2, moduli[1])
+        } else {
+            0
+        };
+        Self {
+            limbs,
+            moduli,
+            barrett_cr: moduli.map(|m| u64::MAX / m),
+            mod0_inv_mod1,
+        }
+    }
+
+    /// The scalar reduction, as in the original kernel.
+    #[inline(always)]
+    pub fn reduce_scalar(params: &Params, sum_lo: u64, sum_hi: u64) -> u64 {
+        let limb_crt = query_limb_crt_indices(params);
+        let sums = [sum_lo, sum_hi];
+        let mut residues = [0u64; 2];
+        for (&sum, &crt) in sums.iter().zip(limb_crt) {
+            residues[crt] = barrett_coeff_u64(params, sum, crt);
+        }
+        match limb_crt.len() {
+            // the one residue is already the value mod `params.modulus`
+            1 => residues[0],
+            _ => params.crt_compose_2(residues[0], residues[1]),
+        }
+    }
+
+    /// Reduces `REDUCE_LANES` columns at once.
+    #[cfg(target_feature = "avx2")]
+    #[inline(always)]
+    pub fn reduce(
+        &self,
+        params: &Params,
+        sum_lo: &[u64; REDUCE_LANES],
+        sum_hi: &[u64; REDUCE_LANES],
+    ) -> [u64; REDUCE_LANES] {
+        if self.limbs == 1 {
+            return std::array::from_fn(|i| Self::reduce_scalar(params, sum_lo[i], sum_hi[i]));
+        }
+        unsafe { self.reduce_avx2(sum_lo, sum_hi) }
+    }
+
+    #[cfg(not(target_feature = "avx2"))]
+    #[inline(always)]
+    pub fn reduce(
+        &self,
+        params: &Params,
+        sum_lo: &[u64; REDUCE_LANES],
+        sum_hi: &[u64; REDUCE_LANES],
+    ) -> [u64; REDUCE_LANES] {
+        std::array::from_fn(|i| Self::reduce_scalar(params, sum_lo[i], sum_hi[i]))
+    }
+
+    #[cfg(target_feature = "avx2")]
+    #[inline(always)]
+    unsafe fn reduce_avx2(
+        &self,
+        sum_lo: &[u64; REDUCE_LANES],
+        sum_hi: &[u64; REDUCE_LANES],
+    ) -> [u64; REDUCE_LANES] {
+        use std::arch::x86_64::*;
+
+        // high 64 bits of the 64x64 product, per lane
+        #[inline(always)]
+        unsafe fn mulhi_epu64(a: __m256i, b: __m256i) -> __m256i {
+            let mask = _mm256_set1_epi64x(0xFFFF_FFFF);
+            let a_hi = _mm256_srli_epi64(a, 32);
+            let b_hi = _mm256_srli_epi64(b, 32);
+            let lolo = _mm256_mul_epu32(a, b);
+            let lohi = _mm256_mul_epu32(a, b_hi);
+            let hilo = _mm256_mul_epu32(a_hi, b);
+            let hihi = _mm256_mul_epu32(a_hi, b_hi);
+            let mid = _mm256_add_epi64(
+                _mm256_srli_epi64(lolo, 32),
+                _mm256_add_epi64(_mm256_and_si256(lohi, mask), _mm256_and_si256(hilo, mask)),
+            );
+            _mm256_add_epi64(
+                _mm256_add_epi64(hihi, _mm256_srli_epi64(mid, 32)),
+                _mm256_add_epi64(_mm256_srli_epi64(lohi, 32), _mm256_srli_epi64(hilo, 32)),
+            )
+        }
+
+        // subtract m where x >= m; lanes here are all < 2^31, so a signed compare is fine
+        #[inline(always)]
+        unsafe fn cond_sub(x: __m256i, m: __m256i, m_minus_1: __m256i) -> __m256i {
+            _mm256_sub_epi64(x, _mm256_and_si256(m, _mm256_cmpgt_epi64(x, m_minus_1)))
+        }
+
+        // x mod m for arbitrary u64 lanes (m < 2^30)
+        #[inline(always)]
+        unsafe fn barrett(x: __m256i, cr: __m256i, m: __m256i, m_minus_1: __m256i) -> __m256i {
+            let q = mulhi_epu64(x, cr);
+            let q_hi = _mm256_srli_epi64(q, 32);
+            let qm = _mm256_add_epi64(
+                _mm256_mul_epu32(q, m),
+                _mm256_slli_epi64(_mm256_mul_epu32(q_hi, m), 32),
+            );
+            cond_sub(_mm256_sub_epi64(x, qm), m, m_minus_1)
+        }
+
+        let m0 = _mm256_set1_epi64x(self.moduli[0] as i64);
+        let m1 = _mm256_set1_epi64x(self.moduli[1] as i64);
+        let m0_minus_1 = _mm256_set1_epi64x(self.moduli[0] as i64 - 1);
+        let m1_minus_1 = _mm256_set1_epi64x(self.moduli[1] as i64 - 1);
+        let cr0 = _mm256_set1_epi64x(self.barrett_cr[0] as i64);
+        let cr1 = _mm256_set1_epi64x(self.barrett_cr[1] as i64);
+        let inv = _mm256_set1_epi64x(self.mod0_inv_mod1 as i64);
+
+        let lo = _mm256_loadu_si256(sum_lo.as_ptr() as *const __m256i);
+        let hi = _mm256_loadu_si256(sum_hi.as_ptr() as *const __m256i);
+        let x = barrett(lo, cr0, m0, m0_minus_1);
+        let y = barrett(hi, cr1, m1, m1_minus_1);
+
+        // Garner: v = x + m0 * (((y - x) * m0^-1) mod m1), with v < m0 * m1
+        let x_mod_m1 = cond_sub(x, m1, m1_minus_1);
+        let t = _mm256_sub_epi64(_mm256_add_epi64(y, m1), x_mod_m1);
+        let u = barrett(_mm256_mul_epu32(t, inv), cr1, m1, m1_minus_1);
+        let v = _mm256_add_epi64(x, _mm256_mul_epu32(u, m0));
+
+        let mut out = [0u64; REDUCE_LANES];
+        _mm256_storeu_si256(out.as_mut_ptr() as *mut __m256i, v);
+        out
+    }
+}
+
+/// Operation counts for one `fast_batched_dot_product_avx512` call, for
+/// projecting throughput; every kernel performs the same counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KernelCost {
+    /// 64-bit multiplies: one per CRT limb per (batch, row, column).
+    pub multiplies: u64,
+    /// 64-bit adds: one per multiply, plus accumulating each output into `c`.
+    pub adds: u64,
+    /// Database bytes streamed; the database is read once per batch.
+    pub db_bytes_read: u64,
+    /// Modular reductions per output: two limb Barretts, the CRT
+    /// composition and the final Barrett into `c`.
+    pub reductions: u64,
+}
+
+impl KernelCost {
+    pub fn new(k: usize, b_rows: usize, b_cols: usize, elem_bytes: usize) -> Self {
+        let (k, b_rows, b_cols) = (k as u64, b_rows as u64, b_cols as u64);
+        let inner = k * b_rows * b_cols;
+        Self {
+            multiplies: 2 * inner,
+            adds: 2 * inner + k * b_cols,
+            db_bytes_read: inner * elem_bytes as u64,
+            reductions: 4 * k * b_cols,
+        }
+    }
+
+    /// Cost of answering `k` batched queries against the database for
+    /// `params` (`instances` is accounted for in the SimplePIR column count).
+    pub fn for_params(params: &Params, is_simplepir: bool, k: usize, elem_bytes: usize) -> Self {
+        let (b_rows, b_cols) = crate::db::db_dims(params, is_simplepir);
+        Self::new(k, b_rows, b_cols, elem_bytes)
+    }
+}
+
+/// A database operand in transposed layout: row `k` of column `j` is at
+/// `b_t[j * b_rows + k]`.
+#[derive(Debug, Clone, Copy)]
+pub struct TransposedDb<'a, T> {
+    b_t: &'a [T],
+    b_rows: usize,
+    b_cols: usize,
+}
+
+impl<'a, T> TransposedDb<'a, T> {
+    pub fn new(b_t: &'a [T], b_rows: usize, b_cols: usize) -> Self {
+        assert_eq!(b_t.len(), b_rows * b_cols);
+        Self {
+            b_t,
+            b_rows,
+            b_cols,
+        }
+    }
+}
+
+/// A kernel and the reduction constants it uses, which must come from
+/// `CrtReducer::new` for the params it is run with.
+#[derive(Debug, Clone, Copy)]
+pub struct PreparedKernel<'a> {
+    pub kind: KernelKind,
+    pub reducer: &'a CrtReducer,
+}
+
+impl<'a> PreparedKernel<'a> {
+    pub fn new(kind: KernelKind, reducer: &'a CrtReducer) -> Self {
+        Self { kind, reducer }
+    }
+}
+
 /// Portable implementation (no AVX2/AVX-512).
 ///
 /// Keeps the same signature/name so the rest of the codebase doesn’t change.
@@ -22,10 +421,63 @@ pub fn fast_batched_dot_product_avx512<const K: usize, T: Copy>(
 ) where
     *const T: ToM512,
 {
+    fast_batched_dot_product_with_kernel::<K, T>(
+        active_kernel(),
+        params,
+        c,
+        a,
+        a_elems,
+        TransposedDb::new(b_t, b_rows, b_cols),
+    )
+}
+
+/// `fast_batched_dot_product_avx512` on an explicit kernel rather than the
+/// active one. Every kernel gives bit-identical output.
+pub fn fast_batched_dot_product_with_kernel<const K: usize, T: Copy>(
+    kernel: KernelKind,
+    params: &Params,
+    c: &mut [u64],
+    a: &[u64],
+    a_elems: usize,
+    db: TransposedDb<'_, T>,
+) where
+    *const T: ToM512,
+{
+    fast_batched_dot_product_with_reducer::<K, T>(
+        PreparedKernel::new(kernel, &CrtReducer::new(params)),
+        params,
+        c,
+        a,
+        a_elems,
+        db,
+    )
+}
+
+/// `fast_batched_dot_product_with_kernel` with the reduction constants
+/// precomputed, e.g. once per server rather than once per answer.
+pub fn fast_batched_dot_product_with_reducer<const K: usize, T: Copy>(
+    kernel: PreparedKernel<'_>,
+    params: &Params,
+    c: &mut [u64],
+    a: &[u64],
+    a_elems: usize,
+    db: TransposedDb<'_, T>,
+) where
+    *const T: ToM512,
+{
+    let PreparedKernel {
+        kind: kernel,
+        reducer,
+    } = kernel;
+    let TransposedDb {
+        b_t,
+        b_rows,
+        b_cols,
+    } = db;
+    assert!(kernel.is_available(), "kernel {} unavailable", kernel.name());
     assert_eq!(a_elems, b_rows);
     assert_eq!(c.len(), K * b_cols);
     assert_eq!(a.len(), K * a_elems);
-    assert_eq!(b_t.len(), b_cols * b_rows);
 
     // Split output into K batches (same as old code).
     let c_batches = c.chunks_exact_mut(b_cols);
@@ -35,44 +487,545 @@ pub fn fast_batched_dot_product_avx512<const K: usize, T: Copy>(
 
     // For each output column j, compute dot-products for all K batches.
     // We keep the same “wrap then Barrett reduce” behavior as the AVX-512 version:
-    // - accumulate in u64 with wrapping arithmetic
+    // - accumulate in u64 with wrapping arithmetic (checked in debug builds, see `mul_acc`)
     // - reduce low/high limbs with barrett_coeff_u64
     // - crt_compose_2 and barrett_u64 for final accumulation into c
+    // The limb reduction and CRT composition are done REDUCE_LANES columns at a time.
     for (batch_idx, (c_batch, a_batch)) in c_batches.zip(a_batches).enumerate() {
         debug_assert!(batch_idx < K);
 
-        for j in 0..b_cols {
-            let mut sum_lo: u64 = 0;
-            let mut sum_hi: u64 = 0;
+        let col_sums = |j: usize| column_limb_sums(params, a_batch, b_t, j * b_rows);
+
+        let full_cols = match kernel {
+            KernelKind::Avx2 => b_cols - b_cols % REDUCE_LANES,
+            KernelKind::Scalar => 0,
+        };
+        for j0 in (0..full_cols).step_by(REDUCE_LANES) {
+            let mut sum_lo = [0u64; REDUCE_LANES];
+            let mut sum_hi = [0u64; REDUCE_LANES];
+            for l in 0..REDUCE_LANES {
+                (sum_lo[l], sum_hi[l]) = col_sums(j0 + l);
+            }
+
+            let res = reducer.reduce(params, &sum_lo, &sum_hi);
+            for l in 0..REDUCE_LANES {
+                c_batch[j0 + l] = barrett_u64(params, c_batch[j0 + l].wrapping_add(res[l]));
+            }
+        }
+
+        for j in full_cols..b_cols {
+            let (sum_lo, sum_hi) = col_sums(j);
+            let res = CrtReducer::reduce_scalar(params, sum_lo, sum_hi);
+            c_batch[j] = barrett_u64(params, c_batch[j].wrapping_add(res));
+        }
+    }
+}
+
+/// Limb sums of the database column starting at `b_t[base]` against one
+/// query batch, narrowed for `CrtReducer`.
+#[inline(always)]
+fn column_limb_sums<T: Copy>(params: &Params, a_batch: &[u64], b_t: &[T], base: usize) -> (u64, u64)
+where
+    *const T: ToM512,
+{
+    let mut sum_lo: LimbSum = 0;
+    let mut sum_hi: LimbSum = 0;
+
+    for (k, &a_val) in a_batch.iter().enumerate() {
+        let b_val = load_db_elem(b_t, base + k) as LimbSum;
+
+        let a_lo = (a_val & 0xFFFF_FFFF) as LimbSum;
+        let a_hi = (a_val >> 32) as LimbSum;
+
+        // Match old behavior: multiply 32-bit limbs by db word, accumulate with wrapping.
+        sum_lo = mul_acc(sum_lo, a_lo, b_val);
+        sum_hi = mul_acc(sum_hi, a_hi, b_val);
+    }
+    (
+        narrow_limb_sum(params, sum_lo, 0),
+        narrow_limb_sum(params, sum_hi, 1),
+    )
+}
+
+/// The answer restricted to the database columns in `cols`, in that order:
+/// `c[i]` gets what `fast_batched_dot_product_with_reducer::<1, T>` puts in
+/// `c[cols[i]]`, and no other column is read.
+///
+/// On the AVX2 kernel a u8 database is read `REDUCE_LANES` selected columns
+/// at a time, gathering each row of the group into one vector (see
+/// `gather_columns_u8`); other element types, the scalar kernel and the
+/// `wide_accum` build read one column at a time.
+pub fn fast_dot_product_columns<T: Copy>(
+    kernel: PreparedKernel<'_>,
+    params: &Params,
+    c: &mut [u64],
+    a: &[u64],
+    db: TransposedDb<'_, T>,
+    cols: &[usize],
+) where
+    *const T: ToM512,
+{
+    let PreparedKernel {
+        kind: kernel,
+        reducer,
+    } = kernel;
+    let TransposedDb {
+        b_t,
+        b_rows,
+        b_cols,
+    } = db;
+    assert!(
+        kernel.is_available(),
+        "kernel {} unavailable",
+        kernel.name()
+    );
+    assert_eq!(a.len(), b_rows);
+    assert_eq!(c.len(), cols.len());
+    assert!(cols.iter().all(|&j| j < b_cols), "column out of range");
 
-            let base = j * b_rows;
+    let gathered = if kernel == KernelKind::Avx2 && std::mem::size_of::<T>() == 1 {
+        // SAFETY: T is one byte wide, so b_t can be read as the bytes it is
+        let b_u8 = unsafe { std::slice::from_raw_parts(b_t.as_ptr() as *const u8, b_t.len()) };
+        gather_columns_u8(reducer, params, c, a, b_u8, cols)
+    } else {
+        0
+    };
+    for (out, &j) in c.iter_mut().zip(cols).skip(gathered) {
+        let (sum_lo, sum_hi) = column_limb_sums(params, a, b_t, j * b_rows);
+        let res = CrtReducer::reduce_scalar(params, sum_lo, sum_hi);
+        *out = barrett_u64(params, out.wrapping_add(res));
+    }
+}
 
-            for k in 0..a_elems {
-                // Read db value (u8/u16/u32) through ToM512 fallback (scalar on non-avx512f).
-                let b_val_u64: u64 = unsafe { b_t.as_ptr().add(base + k).to_m512() };
+/// `fast_dot_product_columns` for the leading whole groups of
+/// `REDUCE_LANES` columns, with a 32-bit AVX2 gather loading row `k` of every
+/// column in the group; returns how many columns it did.
+///
+/// The limb sums wrap at 2^64 like the release build of the dense kernel.
+/// Debug builds recompute each group's sums with `column_limb_sums`, which
+/// checks them (see `mul_acc`), so an input that overflows panics here too.
+#[cfg(all(target_feature = "avx2", not(feature = "wide_accum")))]
+fn gather_columns_u8(
+    reducer: &CrtReducer,
+    params: &Params,
+    c: &mut [u64],
+    a: &[u64],
+    b_t: &[u8],
+    cols: &[usize],
+) -> usize {
+    use std::arch::x86_64::*;
 
-                let a_val: u64 = a_batch[k];
-                let a_lo: u64 = a_val & 0xFFFF_FFFF;
-                let a_hi: u64 = a_val >> 32;
+    let b_rows = a.len();
+    let groups = cols.len() / REDUCE_LANES;
+    for g in 0..groups {
+        let bases: [usize; REDUCE_LANES] =
+            std::array::from_fn(|l| cols[g * REDUCE_LANES + l] * b_rows);
+        // each gathered lane reads 4 bytes from its index, so rows whose
+        // read would run past the end of b_t are added one byte at a time
+        let max_base = *bases.iter().max().unwrap();
+        let gathered_rows = b_rows.min((b_t.len() - max_base).saturating_sub(3));
 
-                // Match old behavior: multiply 32-bit limbs by db word, accumulate with wrapping.
-                sum_lo = sum_lo.wrapping_add(a_lo.wrapping_mul(b_val_u64));
-                sum_hi = sum_hi.wrapping_add(a_hi.wrapping_mul(b_val_u64));
+        let mut sum_lo = [0u64; REDUCE_LANES];
+        let mut sum_hi = [0u64; REDUCE_LANES];
+        // SAFETY: the caller checked that AVX2 is available, and every
+        // gathered read lies within b_t as arranged above
+        unsafe {
+            let mut idx = _mm256_set_epi64x(
+                bases[3] as i64,
+                bases[2] as i64,
+                bases[1] as i64,
+                bases[0] as i64,
+            );
+            let one = _mm256_set1_epi64x(1);
+            let byte_mask = _mm_set1_epi32(0xFF);
+            let mut lo = _mm256_setzero_si256();
+            let mut hi = _mm256_setzero_si256();
+            for &a_val in &a[..gathered_rows] {
+                let words = _mm256_i64gather_epi32::<1>(b_t.as_ptr() as *const i32, idx);
+                let b = _mm256_cvtepu32_epi64(_mm_and_si128(words, byte_mask));
+                let a_lo = _mm256_set1_epi64x((a_val & 0xFFFF_FFFF) as i64);
+                let a_hi = _mm256_set1_epi64x((a_val >> 32) as i64);
+                lo = _mm256_add_epi64(lo, _mm256_mul_epu32(a_lo, b));
+                hi = _mm256_add_epi64(hi, _mm256_mul_epu32(a_hi, b));
+                idx = _mm256_add_epi64(idx, one);
             }
+            _mm256_storeu_si256(sum_lo.as_mut_ptr() as *mut __m256i, lo);
+            _mm256_storeu_si256(sum_hi.as_mut_ptr() as *mut __m256i, hi);
+        }
+        for (k, &a_val) in a.iter().enumerate().skip(gathered_rows) {
+            for l in 0..REDUCE_LANES {
+                let b = b_t[bases[l] + k] as u64;
+                sum_lo[l] = sum_lo[l].wrapping_add((a_val & 0xFFFF_FFFF).wrapping_mul(b));
+                sum_hi[l] = sum_hi[l].wrapping_add((a_val >> 32).wrapping_mul(b));
+            }
+        }
+        #[cfg(debug_assertions)]
+        for ((&base, &lo), &hi) in bases.iter().zip(&sum_lo).zip(&sum_hi) {
+            debug_assert_eq!(column_limb_sums(params, a, b_t, base), (lo, hi));
+        }
 
-            // Reduce both limbs, compose CRT, and accumulate into output (same as old kernel).
-            let lo = barrett_coeff_u64(params, sum_lo, 0);
-            let hi = barrett_coeff_u64(params, sum_hi, 1);
-            let res = params.crt_compose_2(lo, hi);
+        let res = reducer.reduce(params, &sum_lo, &sum_hi);
+        for l in 0..REDUCE_LANES {
+            let out = &mut c[g * REDUCE_LANES + l];
+            *out = barrett_u64(params, out.wrapping_add(res[l]));
+        }
+    }
+    groups * REDUCE_LANES
+}
 
-            c_batch[j] = barrett_u64(params, c_batch[j].wrapping_add(res));
+#[cfg(not(all(target_feature = "avx2", not(feature = "wide_accum"))))]
+fn gather_columns_u8(
+    _reducer: &CrtReducer,
+    _params: &Params,
+    _c: &mut [u64],
+    _a: &[u64],
+    _b_t: &[u8],
+    _cols: &[usize],
+) -> usize {
+    0
+}
+
+/// Plain integer dot products `c[j] = sum_k a[k] * b_t[j * b_rows + k]`,
+/// with no Barrett or CRT reduction; `a` holds ordinary integers rather than
+/// packed CRT limbs.
+///
+/// The sums are exact only if none of them reaches 2^64, e.g. when
+/// `b_rows * max(a) * max(b)` is below it; the caller must guarantee that,
+/// as larger sums silently wrap.
+pub fn numeric_dot_product<T: Copy>(
+    c: &mut [u64],
+    a: &[u64],
+    b_t: &[T], // transposed
+    b_rows: usize,
+    b_cols: usize,
+) where
+    *const T: ToM512,
+{
+    assert_eq!(a.len(), b_rows);
+    assert_eq!(c.len(), b_cols);
+    assert_eq!(b_t.len(), b_rows * b_cols);
+
+    for (j, c_j) in c.iter_mut().enumerate() {
+        let base = j * b_rows;
+        *c_j = a.iter().enumerate().fold(0u64, |sum, (k, &a_val)| {
+            sum.wrapping_add(a_val.wrapping_mul(load_db_elem(b_t, base + k)))
+        });
+    }
+}
+
+/// One query against several same-shaped databases in a single pass:
+/// `cs[d]` accumulates `a` times `dbs[d]`, exactly as
+/// `fast_batched_dot_product_avx512::<1, _>` would for each database alone,
+/// but each query word is loaded once per row rather than once per database.
+pub fn fast_dot_product_multi_db<T: Copy>(
+    params: &Params,
+    cs: &mut [&mut [u64]],
+    a: &[u64],
+    a_elems: usize,
+    dbs: &[&[T]], // each transposed
+    b_rows: usize,
+    b_cols: usize,
+) where
+    *const T: ToM512,
+{
+    assert_eq!(a_elems, b_rows);
+    assert_eq!(a.len(), a_elems);
+    assert_eq!(cs.len(), dbs.len());
+    assert!(cs.iter().all(|c| c.len() == b_cols));
+    assert!(dbs.iter().all(|b_t| b_t.len() == b_cols * b_rows));
+
+    let mut sum_lo: Vec<LimbSum> = vec![0; dbs.len()];
+    let mut sum_hi: Vec<LimbSum> = vec![0; dbs.len()];
+    for j in 0..b_cols {
+        sum_lo.fill(0);
+        sum_hi.fill(0);
+        let base = j * b_rows;
+        for (k, &a_val) in a.iter().enumerate() {
+            let a_lo = (a_val & 0xFFFF_FFFF) as LimbSum;
+            let a_hi = (a_val >> 32) as LimbSum;
+            for (d, b_t) in dbs.iter().enumerate() {
+                let b_val = load_db_elem(b_t, base + k) as LimbSum;
+                sum_lo[d] = mul_acc(sum_lo[d], a_lo, b_val);
+                sum_hi[d] = mul_acc(sum_hi[d], a_hi, b_val);
+            }
+        }
+        for (d, c) in cs.iter_mut().enumerate() {
+            let res = CrtReducer::reduce_scalar(
+                params,
+                narrow_limb_sum(params, sum_lo[d], 0),
+                narrow_limb_sum(params, sum_hi[d], 1),
+            );
+            c[j] = barrett_u64(params, c[j].wrapping_add(res));
         }
     }
 }
 
+/// Largest batch `dot_product_checked` dispatches to.
+pub const MAX_CHECKED_BATCH: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KernelShapeError {
+    /// `k` is 0 or above `MAX_CHECKED_BATCH`.
+    UnsupportedBatch { k: usize },
+    /// Each batch of `a` must have one element per row of `b_t`.
+    RowsMismatch { a_elems: usize, b_rows: usize },
+    /// `a` must hold `k * a_elems` elements.
+    ALength { len: usize, expected: usize },
+    /// `b_t` must hold `b_rows * b_cols` elements.
+    BLength { len: usize, expected: usize },
+}
+
+impl std::fmt::Display for KernelShapeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KernelShapeError::UnsupportedBatch { k } => write!(
+                f,
+                "batch size {} unsupported (expected 1 to {})",
+                k, MAX_CHECKED_BATCH
+            ),
+            KernelShapeError::RowsMismatch { a_elems, b_rows } => write!(
+                f,
+                "a_elems is {} but b_t has {} rows",
+                a_elems, b_rows
+            ),
+            KernelShapeError::ALength { len, expected } => {
+                write!(f, "a has {} elements, expected {}", len, expected)
+            }
+            KernelShapeError::BLength { len, expected } => {
+                write!(f, "b_t has {} elements, expected {}", len, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for KernelShapeError {}
+
+/// `fast_batched_dot_product_avx512` on a u8 `b_t` with the batch size chosen
+/// at runtime and the shape assertions turned into errors, for driving the
+/// kernel on synthetic inputs. Returns the `k * b_cols` outputs.
+pub fn dot_product_checked(
+    params: &Params,
+    k: usize,
+    a: &[u64],
+    a_elems: usize,
+    b_t: &[u8],
+    b_rows: usize,
+    b_cols: usize,
+) -> Result<Vec<u64>, KernelShapeError> {
+    if k == 0 || k > MAX_CHECKED_BATCH {
+        return Err(KernelShapeError::UnsupportedBatch { k });
+    }
+    if a_elems != b_rows {
+        return Err(KernelShapeError::RowsMismatch { a_elems, b_rows });
+    }
+    if a.len() != k * a_elems {
+        return Err(KernelShapeError::ALength {
+            len: a.len(),
+            expected: k * a_elems,
+        });
+    }
+    if b_t.len() != b_rows * b_cols {
+        return Err(KernelShapeError::BLength {
+            len: b_t.len(),
+            expected: b_rows * b_cols,
+        });
+    }
+
+    let mut c = vec![0u64; k * b_cols];
+    macro_rules! call {
+        ($k:literal) => {
+            fast_batched_dot_product_avx512::<$k, u8>(
+                params, &mut c, a, a_elems, b_t, b_rows, b_cols,
+            )
+        };
+    }
+    match k {
+        1 => call!(1),
+        2 => call!(2),
+        3 => call!(3),
+        4 => call!(4),
+        5 => call!(5),
+        6 => call!(6),
+        7 => call!(7),
+        8 => call!(8),
+        _ => unreachable!(),
+    }
+    Ok(c)
+}
+
+/// Interleaves groups of `REDUCE_LANES` (4) columns of a transposed u8
+/// database into u32 words: byte `l` of word `g * b_rows + k` is row `k` of
+/// column `4 * g + l`. A trailing partial group is zero-padded.
+///
+/// One load then feeds all four lanes of `fast_batched_dot_product_repacked`,
+/// and each query word is read once per group instead of once per column.
+/// This pays off once the query (8 bytes per row) no longer fits in L1,
+/// i.e. for databases with more than a few thousand rows; the repack itself
+/// is a one-time pass over the database and takes the same memory.
+pub fn repack_db_u8_to_u32(b_t: &[u8], b_rows: usize, b_cols: usize) -> Vec<u32> {
+    assert_eq!(b_t.len(), b_rows * b_cols);
+    let groups = b_cols.div_ceil(REDUCE_LANES);
+    let mut out = vec![0u32; groups * b_rows];
+    for j in 0..b_cols {
+        let (g, l) = (j / REDUCE_LANES, j % REDUCE_LANES);
+        let col = &b_t[j * b_rows..(j + 1) * b_rows];
+        for (word, &b) in out[g * b_rows..(g + 1) * b_rows].iter_mut().zip(col) {
+            *word |= (b as u32) << (8 * l);
+        }
+    }
+    out
+}
+
+/// `fast_batched_dot_product_avx512` for a u8 database repacked by
+/// `repack_db_u8_to_u32`; gives bit-identical output.
+pub fn fast_batched_dot_product_repacked<const K: usize>(
+    params: &Params,
+    c: &mut [u64],
+    a: &[u64],
+    a_elems: usize,
+    b_packed: &[u32],
+    b_rows: usize,
+    b_cols: usize,
+) {
+    assert_eq!(a_elems, b_rows);
+    assert_eq!(c.len(), K * b_cols);
+    assert_eq!(a.len(), K * a_elems);
+    assert_eq!(b_packed.len(), b_cols.div_ceil(REDUCE_LANES) * b_rows);
+
+    let reducer = CrtReducer::new(params);
+
+    for (c_batch, a_batch) in c.chunks_exact_mut(b_cols).zip(a.chunks_exact(a_elems)) {
+        for (g, words) in b_packed.chunks_exact(b_rows).enumerate() {
+            let mut sum_lo: [LimbSum; REDUCE_LANES] = [0; REDUCE_LANES];
+            let mut sum_hi: [LimbSum; REDUCE_LANES] = [0; REDUCE_LANES];
+            for (&word, &a_val) in words.iter().zip(a_batch) {
+                let a_lo = (a_val & 0xFFFF_FFFF) as LimbSum;
+                let a_hi = (a_val >> 32) as LimbSum;
+                for l in 0..REDUCE_LANES {
+                    let b_val = ((word >> (8 * l)) & 0xFF) as LimbSum;
+                    sum_lo[l] = mul_acc(sum_lo[l], a_lo, b_val);
+                    sum_hi[l] = mul_acc(sum_hi[l], a_hi, b_val);
+                }
+            }
+
+            let sum_lo = sum_lo.map(|s| narrow_limb_sum(params, s, 0));
+            let sum_hi = sum_hi.map(|s| narrow_limb_sum(params, s, 1));
+            let res = reducer.reduce(params, &sum_lo, &sum_hi);
+            let j0 = g * REDUCE_LANES;
+            for l in 0..REDUCE_LANES.min(b_cols - j0) {
+                c_batch[j0 + l] = barrett_u64(params, c_batch[j0 + l].wrapping_add(res[l]));
+            }
+        }
+    }
+}
+
+/// The params `verify_kernel` runs with: the smallest geometry, with both
+/// CRT factors spelled out so the check doesn't move with the defaults.
+fn verify_kernel_params() -> Params {
+    crate::params::ext_params_from_json(
+        r#"{
+        "n": 1,
+        "nu_1": 0,
+        "nu_2": 0,
+        "p": 256,
+        "q2_bits": 22,
+        "t_gsw": 3,
+        "t_conv": 2,
+        "t_exp_left": 2,
+        "t_exp_right": 2,
+        "instances": 1,
+        "db_item_size": 0,
+        "version": 2,
+        "moduli": ["268369921", "249561089"]
+    }"#,
+    )
+}
+
+/// Runs a small fixed input through every compiled-in kernel variant (each
+/// available `KernelKind`, plus the repacked u8 path) and checks that they
+/// agree with the scalar kernel. Logs the first variant that diverges and
+/// returns false; meant as a startup check against a miscompiled SIMD build.
+pub fn verify_kernel() -> bool {
+    let params = verify_kernel_params();
+    // not a lane multiple, so the tail path is covered too
+    let (b_rows, b_cols) = (64, 4 * REDUCE_LANES + 3);
+
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut next = move || {
+        // xorshift64, plenty for a fixed input
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let a = (0..2 * b_rows)
+        .map(|_| (next() % params.moduli[0]) | (next() % params.moduli[1]) << 32)
+        .collect::<Vec<_>>();
+    let b_u8 = (0..b_rows * b_cols)
+        .map(|_| next() as u8)
+        .collect::<Vec<_>>();
+    let b_u16 = (0..b_rows * b_cols)
+        .map(|_| next() as u16)
+        .collect::<Vec<_>>();
+
+    fn run<T: Copy>(
+        kernel: KernelKind,
+        params: &Params,
+        a: &[u64],
+        b: &[T],
+        b_cols: usize,
+    ) -> Vec<u64>
+    where
+        *const T: ToM512,
+    {
+        let b_rows = b.len() / b_cols;
+        let mut c = vec![0u64; 2 * b_cols];
+        fast_batched_dot_product_with_kernel::<2, T>(
+            kernel,
+            params,
+            &mut c,
+            a,
+            b_rows,
+            TransposedDb::new(b, b_rows, b_cols),
+        );
+        c
+    }
+
+    let expected_u8 = run(KernelKind::Scalar, &params, &a, &b_u8, b_cols);
+    let expected_u16 = run(KernelKind::Scalar, &params, &a, &b_u16, b_cols);
+    for kernel in KernelKind::available() {
+        if run(kernel, &params, &a, &b_u8, b_cols) != expected_u8
+            || run(kernel, &params, &a, &b_u16, b_cols) != expected_u16
+        {
+            log::error!("kernel {} diverges from scalar", kernel.name());
+            return false;
+        }
+    }
+
+    let mut c = vec![0u64; 2 * b_cols];
+    let b_packed = repack_db_u8_to_u32(&b_u8, b_rows, b_cols);
+    fast_batched_dot_product_repacked::<2>(&params, &mut c, &a, b_rows, &b_packed, b_rows, b_cols);
+    if c != expected_u8 {
+        log::error!("repacked kernel diverges from scalar");
+        return false;
+    }
+    true
+}
+
 #[cfg(test)]
 mod test {
+    use std::time::Instant;
+
+    use log::debug;
+    use test_log::test;
+
     use super::*;
+    use crate::client::pack_query;
+    use crate::params::{
+        params_for_scenario, params_for_scenario_simplepir, params_with_crt_limbs,
+    };
+    use crate::server::ToU64;
+    use crate::util::test_params;
     use spiral_rs::aligned_memory::AlignedMemory64;
     use spiral_rs::poly::*;
 
@@ -102,4 +1055,720 @@ mod test {
 
         fast_batched_dot_product_avx512::<K, _>(&params, &mut c, &a, a_elems, b_u16, b_rows, b_cols);
     }
+
+    /// Straightforward (u128) reference for one batch of the kernel.
+    fn reference_dot_product<T: Copy + ToU64>(
+        params: &Params,
+        a: &[u64],
+        b_t: &[T],
+        b_rows: usize,
+        b_cols: usize,
+    ) -> Vec<u64> {
+        (0..b_cols)
+            .map(|j| {
+                let mut sum = 0u128;
+                for k in 0..b_rows {
+                    sum += a[k] as u128 * b_t[j * b_rows + k].to_u64() as u128;
+                }
+                (sum % params.modulus as u128) as u64
+            })
+            .collect()
+    }
+
+    fn random_query(params: &Params, len: usize) -> Vec<u64> {
+        (0..len).map(|_| fastrand::u64(0..params.modulus)).collect()
+    }
+
+    #[test]
+    fn test_fast_batched_dot_product_u8_correct() {
+        let params = test_params();
+
+        let b_rows = 2048;
+        let b_cols = 64;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+
+        let mut c = vec![0u64; b_cols];
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+
+        assert_eq!(c, reference_dot_product(&params, &a, &b_t, b_rows, b_cols));
+    }
+
+    #[test]
+    fn test_fast_batched_dot_product_u16_correct() {
+        let params = test_params();
+
+        let b_rows = 2048;
+        let b_cols = 16;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u16(..))
+            .collect::<Vec<_>>();
+
+        let mut c = vec![0u64; b_cols];
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+
+        assert_eq!(c, reference_dot_product(&params, &a, &b_t, b_rows, b_cols));
+    }
+
+    #[cfg(feature = "wide_accum")]
+    #[test]
+    fn test_wide_accum_large_a_elems() {
+        let params = test_params();
+
+        // u32 elements near the max, so each limb term is about 2^60 and the
+        // limb sums of 2^18 rows would wrap a u64 accumulator many times over
+        let b_rows = 1 << 18;
+        let b_cols = 5;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| u32::MAX - fastrand::u32(..256))
+            .collect::<Vec<_>>();
+        let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+
+        for j in 0..b_cols {
+            let column = &b_t[j * b_rows..(j + 1) * b_rows];
+            for limb in 0..query_limb_crt_indices(&params).len() {
+                let limb_sum = a_packed
+                    .as_slice()
+                    .iter()
+                    .zip(column)
+                    .map(|(&a, &b)| (a >> (32 * limb) & 0xFFFF_FFFF) as u128 * b as u128)
+                    .sum::<u128>();
+                assert!(limb_sum > u64::MAX as u128, "column {} limb {}", j, limb);
+            }
+        }
+
+        for kernel in KernelKind::available() {
+            let mut c = vec![0u64; b_cols];
+            fast_batched_dot_product_with_kernel::<1, _>(
+                kernel,
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                TransposedDb::new(&b_t, b_rows, b_cols),
+            );
+            assert_eq!(c, expected, "kernel {}", kernel.name());
+        }
+    }
+
+    #[cfg(all(debug_assertions, not(feature = "wide_accum")))]
+    #[test]
+    #[should_panic(expected = "kernel limb sum overflow")]
+    fn test_limb_sum_overflow_panics_in_debug() {
+        let params = test_params();
+
+        // each term is about 2^48, so 2^17 rows overflow the u64 sum
+        let b_rows = 1 << 17;
+        let a_packed = vec![u64::MAX; b_rows];
+        let b_t = vec![u16::MAX; b_rows];
+        let mut c = vec![0u64; 1];
+        fast_batched_dot_product_with_kernel::<1, _>(
+            KernelKind::Scalar,
+            &params,
+            &mut c,
+            &a_packed,
+            b_rows,
+            TransposedDb::new(&b_t, b_rows, 1),
+        );
+    }
+
+    // the same overflow through the active kernel, with a whole group of
+    // columns so the AVX2 lanes are used if `detect()` picked them
+    #[cfg(all(debug_assertions, not(feature = "wide_accum")))]
+    #[test]
+    #[should_panic(expected = "kernel limb sum overflow")]
+    fn test_limb_sum_overflow_panics_in_debug_active_kernel() {
+        let params = test_params();
+
+        let b_rows = 1 << 17;
+        let b_cols = REDUCE_LANES;
+        let a_packed = vec![u64::MAX; b_rows];
+        let b_t = vec![u16::MAX; b_rows * b_cols];
+        let mut c = vec![0u64; b_cols];
+        fast_batched_dot_product_avx512::<1, _>(
+            &params, &mut c, &a_packed, b_rows, &b_t, b_rows, b_cols,
+        );
+    }
+
+    #[test]
+    fn test_crt_reducer_matches_scalar() {
+        let params = test_params();
+        let reducer = CrtReducer::new(&params);
+
+        let edge = [
+            0,
+            1,
+            params.moduli[0] - 1,
+            params.moduli[0],
+            params.moduli[1],
+            u64::MAX,
+            u64::MAX - 1,
+            1 << 63,
+        ];
+        let mut inputs = edge.to_vec();
+        inputs.extend((0..4000).map(|_| fastrand::u64(..)));
+        for lo in inputs.chunks_exact(REDUCE_LANES) {
+            for hi in [lo, &edge[..REDUCE_LANES], &edge[REDUCE_LANES..]] {
+                let sum_lo: [u64; REDUCE_LANES] = lo.try_into().unwrap();
+                let sum_hi: [u64; REDUCE_LANES] = hi.try_into().unwrap();
+                let got = reducer.reduce(&params, &sum_lo, &sum_hi);
+                for l in 0..REDUCE_LANES {
+                    assert_eq!(
+                        got[l],
+                        CrtReducer::reduce_scalar(&params, sum_lo[l], sum_hi[l]),
+                        "sum_lo: {}, sum_hi: {}",
+                        sum_lo[l],
+                        sum_hi[l]
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_precomputed_reducer_matches() {
+        let params = test_params();
+        let reducer = CrtReducer::new(&params);
+        assert_eq!(reducer, CrtReducer::new(&params));
+
+        let b_rows = 64;
+        let b_cols = 2 * REDUCE_LANES + 3;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+        for kernel in KernelKind::available() {
+            let mut c = vec![0u64; b_cols];
+            fast_batched_dot_product_with_reducer::<1, _>(
+                PreparedKernel::new(kernel, &reducer),
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                TransposedDb::new(&b_t, b_rows, b_cols),
+            );
+            assert_eq!(c, expected, "kernel {}", kernel.name());
+        }
+    }
+
+    #[test]
+    fn test_reduction_with_reordered_moduli() {
+        let params = crate::util::test_params_reordered_moduli();
+        assert_eq!(params.modulus, test_params().modulus);
+        assert_eq!(params.moduli[0], test_params().moduli[1]);
+        // limb i holds the residue mod moduli[1 - i] here
+        assert_eq!(query_limb_crt_indices(&params), &[1, 0]);
+        assert_eq!(query_limb_crt_indices(&test_params()), &[0, 1]);
+
+        let b_rows = 2048;
+        let b_cols = 64 + 3;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        assert_eq!(
+            a_packed.as_slice(),
+            pack_query(&test_params(), &a).as_slice()
+        );
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+
+        for kernel in KernelKind::available() {
+            let mut c = vec![0u64; b_cols];
+            fast_batched_dot_product_with_kernel::<1, _>(
+                kernel,
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                TransposedDb::new(&b_t, b_rows, b_cols),
+            );
+            assert_eq!(c, expected, "kernel {}", kernel.name());
+        }
+    }
+
+    #[test]
+    fn test_reduction_by_crt_limbs() {
+        for limbs in 1..=MAX_QUERY_LIMBS {
+            let params = params_with_crt_limbs(&test_params(), limbs);
+            assert_eq!(params.crt_count, limbs);
+            let reducer = CrtReducer::new(&params);
+
+            let b_rows = 2048;
+            let b_cols = 64 + 3;
+            let a = random_query(&params, b_rows);
+            let a_packed = pack_query(&params, &a);
+            let b_t = (0..b_rows * b_cols)
+                .map(|_| fastrand::u8(..))
+                .collect::<Vec<_>>();
+            let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+
+            for kernel in KernelKind::available() {
+                let mut c = vec![0u64; b_cols];
+                fast_batched_dot_product_with_reducer::<1, _>(
+                    PreparedKernel::new(kernel, &reducer),
+                    &params,
+                    &mut c,
+                    a_packed.as_slice(),
+                    b_rows,
+                    TransposedDb::new(&b_t, b_rows, b_cols),
+                );
+                assert_eq!(c, expected, "{} limbs, kernel {}", limbs, kernel.name());
+            }
+        }
+    }
+
+    fn run_all_kernels<const K: usize, T: Copy>(
+        params: &Params,
+        b_rows: usize,
+        b_cols: usize,
+        b_t: &[T],
+    ) where
+        *const T: ToM512,
+    {
+        let a = random_query(params, K * b_rows);
+        let a_packed = (0..K)
+            .flat_map(|i| {
+                pack_query(params, &a[i * b_rows..(i + 1) * b_rows])
+                    .as_slice()
+                    .to_vec()
+            })
+            .collect::<Vec<_>>();
+        // start from a non-zero accumulator, as batched callers may
+        let c_init = (0..K * b_cols)
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+
+        let outputs = KernelKind::available()
+            .map(|kernel| {
+                let mut c = c_init.clone();
+                fast_batched_dot_product_with_kernel::<K, T>(
+                    kernel,
+                    params,
+                    &mut c,
+                    &a_packed,
+                    b_rows,
+                    TransposedDb::new(b_t, b_rows, b_cols),
+                );
+                (kernel, c)
+            })
+            .collect::<Vec<_>>();
+
+        let (first_kernel, first) = &outputs[0];
+        for (kernel, c) in &outputs[1..] {
+            assert!(
+                c == first,
+                "kernel {} diverges from {} (b_rows={}, b_cols={}, K={})",
+                kernel.name(),
+                first_kernel.name(),
+                b_rows,
+                b_cols,
+                K
+            );
+        }
+    }
+
+    #[test]
+    fn kernel_variants_agree() {
+        let all_params = [
+            test_params(),
+            params_for_scenario(1 << 30, 1),
+            params_for_scenario_simplepir(1 << 14, 16384 * 8),
+        ];
+        debug!(
+            "kernels: {:?}",
+            KernelKind::available().map(|k| k.name()).collect::<Vec<_>>()
+        );
+
+        for params in &all_params {
+            for &(b_rows, b_cols) in &[(16, 1 << 12), (1024, 37), (2048, 64), (1, 9)] {
+                let b_u8 = (0..b_rows * b_cols)
+                    .map(|_| fastrand::u8(..))
+                    .collect::<Vec<_>>();
+                run_all_kernels::<1, u8>(params, b_rows, b_cols, &b_u8);
+                run_all_kernels::<2, u8>(params, b_rows, b_cols, &b_u8);
+
+                let b_u16 = (0..b_rows * b_cols)
+                    .map(|_| fastrand::u16(..))
+                    .collect::<Vec<_>>();
+                run_all_kernels::<1, u16>(params, b_rows, b_cols, &b_u16);
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_kernel() {
+        assert!(verify_kernel());
+    }
+
+    #[test]
+    fn test_set_kernel_scalar() {
+        let params = test_params();
+        let previous = active_kernel();
+
+        assert_eq!(set_kernel(KernelKind::Scalar), KernelKind::Scalar);
+        assert_eq!(active_kernel(), KernelKind::Scalar);
+        assert_eq!(active_kernel().name(), "scalar");
+        assert_eq!(KernelKind::from_name("no-such-kernel"), KernelKind::Scalar);
+
+        let b_rows = 256;
+        let b_cols = 4 * REDUCE_LANES + 1;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let mut c = vec![0u64; b_cols];
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+        assert_eq!(c, reference_dot_product(&params, &a, &b_t, b_rows, b_cols));
+
+        set_kernel(previous);
+    }
+
+    #[test]
+    fn test_repacked_matches_u8() {
+        let params = test_params();
+
+        for (b_rows, b_cols) in [(64, 4 * REDUCE_LANES + 3), (2048, 2048)] {
+            let a = (0..2)
+                .flat_map(|_| random_query(&params, b_rows))
+                .collect::<Vec<_>>();
+            let a_packed = pack_query(&params, &a);
+            let b_t = (0..b_rows * b_cols)
+                .map(|_| fastrand::u8(..))
+                .collect::<Vec<_>>();
+
+            let mut expected = vec![0u64; 2 * b_cols];
+            fast_batched_dot_product_avx512::<2, _>(
+                &params,
+                &mut expected,
+                a_packed.as_slice(),
+                b_rows,
+                &b_t,
+                b_rows,
+                b_cols,
+            );
+
+            let b_packed = repack_db_u8_to_u32(&b_t, b_rows, b_cols);
+            let mut c = vec![0u64; 2 * b_cols];
+            fast_batched_dot_product_repacked::<2>(
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                &b_packed,
+                b_rows,
+                b_cols,
+            );
+            assert_eq!(c, expected);
+        }
+    }
+
+    #[test]
+    fn test_dot_product_checked_column_sums() {
+        let params = test_params();
+        let (k, b_rows, b_cols) = (3, 512, 37);
+        let ones = pack_query(&params, &vec![1u64; b_rows]);
+        let a = ones.as_slice().repeat(k);
+        let b_t = vec![1u8; b_rows * b_cols];
+
+        let c = dot_product_checked(&params, k, &a, b_rows, &b_t, b_rows, b_cols).unwrap();
+        assert_eq!(c, vec![b_rows as u64; k * b_cols]);
+
+        assert_eq!(
+            dot_product_checked(&params, 0, &a, b_rows, &b_t, b_rows, b_cols),
+            Err(KernelShapeError::UnsupportedBatch { k: 0 })
+        );
+        assert_eq!(
+            dot_product_checked(&params, k, &a, b_rows, &b_t[1..], b_rows, b_cols),
+            Err(KernelShapeError::BLength {
+                len: b_rows * b_cols - 1,
+                expected: b_rows * b_cols
+            })
+        );
+        assert_eq!(
+            dot_product_checked(&params, 2, &a, b_rows, &b_t, b_rows, b_cols),
+            Err(KernelShapeError::ALength {
+                len: k * b_rows,
+                expected: 2 * b_rows
+            })
+        );
+    }
+
+    #[test]
+    fn test_kernel_cost_scaling() {
+        let base = KernelCost::new(1, 2048, 2048, 1);
+        assert_eq!(base.multiplies, 2 * 2048 * 2048);
+        assert_eq!(base.db_bytes_read, 2048 * 2048);
+
+        // the inner loop is linear in rows and columns; reductions only in columns
+        let rows_x2 = KernelCost::new(1, 4096, 2048, 1);
+        assert_eq!(rows_x2.multiplies, 2 * base.multiplies);
+        assert_eq!(rows_x2.db_bytes_read, 2 * base.db_bytes_read);
+        assert_eq!(rows_x2.adds - 2048, 2 * (base.adds - 2048));
+        assert_eq!(rows_x2.reductions, base.reductions);
+
+        let cols_x2 = KernelCost::new(1, 2048, 4096, 1);
+        assert_eq!(cols_x2.multiplies, 2 * base.multiplies);
+        assert_eq!(cols_x2.adds, 2 * base.adds);
+        assert_eq!(cols_x2.reductions, 2 * base.reductions);
+
+        // batches and wider elements multiply the traffic
+        assert_eq!(KernelCost::new(4, 2048, 2048, 2).db_bytes_read, 8 * base.db_bytes_read);
+        assert_eq!(KernelCost::new(4, 2048, 2048, 1).reductions, 4 * base.reductions);
+
+        let params = test_params();
+        assert_eq!(KernelCost::for_params(&params, false, 1, 1), base);
+    }
+
+    #[test]
+    fn test_fast_batched_dot_product_odd_cols_correct() {
+        let params = test_params();
+
+        // exercises both the lane-wise and the tail reduction
+        let b_rows = 64;
+        let b_cols = 4 * REDUCE_LANES + 3;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+
+        let mut c = vec![0u64; b_cols];
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+
+        assert_eq!(c, reference_dot_product(&params, &a, &b_t, b_rows, b_cols));
+    }
+
+    #[test]
+    fn test_tail_columns_match_scalar() {
+        let params = test_params();
+
+        // b_cols on either side of a lane multiple: the last 1 to 3 columns
+        // go through the scalar tail
+        let b_rows = 128;
+        for b_cols in [63, 64, 65] {
+            let a = random_query(&params, b_rows);
+            let a_packed = pack_query(&params, &a);
+            let b_t = (0..b_rows * b_cols)
+                .map(|_| fastrand::u8(..))
+                .collect::<Vec<_>>();
+            let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+
+            for kernel in KernelKind::available() {
+                let mut c = vec![0u64; b_cols];
+                fast_batched_dot_product_with_kernel::<1, _>(
+                    kernel,
+                    &params,
+                    &mut c,
+                    a_packed.as_slice(),
+                    b_rows,
+                    TransposedDb::new(&b_t, b_rows, b_cols),
+                );
+                assert_eq!(c, expected, "kernel {}, b_cols {}", kernel.name(), b_cols);
+            }
+
+            let b_packed = repack_db_u8_to_u32(&b_t, b_rows, b_cols);
+            let mut c = vec![0u64; b_cols];
+            fast_batched_dot_product_repacked::<1>(
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                &b_packed,
+                b_rows,
+                b_cols,
+            );
+            assert_eq!(c, expected, "repacked, b_cols {}", b_cols);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_fast_batched_dot_product_wide_bench() {
+        let params = test_params();
+
+        // short rows, many columns: the per-column reduction dominates
+        let b_rows = 16;
+        let b_cols = 1 << 20;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let mut c = vec![0u64; b_cols];
+
+        let now = Instant::now();
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+        debug!("wide u8 kernel: {} us", now.elapsed().as_micros());
+
+        assert_eq!(c, reference_dot_product(&params, &a, &b_t, b_rows, b_cols));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_fast_batched_dot_product_u8_bench() {
+        let params = test_params();
+
+        let b_rows = 1 << 14;
+        let b_cols = 1 << 12;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let mut c = vec![0u64; b_cols];
+
+        let now = Instant::now();
+        fast_batched_dot_product_avx512::<1, _>(
+            &params,
+            &mut c,
+            a_packed.as_slice(),
+            b_rows,
+            &b_t,
+            b_rows,
+            b_cols,
+        );
+        debug!("u8 kernel: {} us", now.elapsed().as_micros());
+
+        let now = Instant::now();
+        let expected = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+        debug!("u128 reference: {} us", now.elapsed().as_micros());
+
+        assert_eq!(c, expected);
+    }
+
+    #[test]
+    fn test_columns_match_dense() {
+        let params = test_params();
+        let reducer = CrtReducer::new(&params);
+
+        let b_rows = 300;
+        let b_cols = 64;
+        let a = random_query(&params, b_rows);
+        let a_packed = pack_query(&params, &a);
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let dense = reference_dot_product(&params, &a, &b_t, b_rows, b_cols);
+
+        // unordered, with a repeat, the last column (whose final rows can't
+        // be gathered) and a partial group at the end
+        let cols = [63, 0, 5, 6, 40, 17, 17, 2, 62, 33, 11];
+        for kernel in KernelKind::available() {
+            let mut c = vec![0u64; cols.len()];
+            fast_dot_product_columns(
+                PreparedKernel::new(kernel, &reducer),
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                TransposedDb::new(&b_t, b_rows, b_cols),
+                &cols,
+            );
+            let expected = cols.iter().map(|&j| dense[j]).collect::<Vec<_>>();
+            assert_eq!(c, expected, "kernel {}", kernel.name());
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn test_precomputed_reducer_bench() {
+        let params = test_params();
+
+        // a tiny database, so the per-call setup is what's measured
+        let b_rows = 4;
+        let b_cols = REDUCE_LANES;
+        let calls = 100_000;
+        let a_packed = pack_query(&params, &random_query(&params, b_rows));
+        let b_t = (0..b_rows * b_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let kernel = active_kernel();
+        let mut c = vec![0u64; b_cols];
+
+        let now = Instant::now();
+        for _ in 0..calls {
+            c.fill(0);
+            fast_batched_dot_product_with_kernel::<1, _>(
+                kernel,
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                TransposedDb::new(&b_t, b_rows, b_cols),
+            );
+        }
+        let per_call = now.elapsed().as_nanos() / calls;
+        let expected = c.clone();
+        debug!("reducer built per call: {} ns/call", per_call);
+
+        let reducer = CrtReducer::new(&params);
+        let now = Instant::now();
+        for _ in 0..calls {
+            c.fill(0);
+            fast_batched_dot_product_with_reducer::<1, _>(
+                PreparedKernel::new(kernel, &reducer),
+                &params,
+                &mut c,
+                a_packed.as_slice(),
+                b_rows,
+                TransposedDb::new(&b_t, b_rows, b_cols),
+            );
+        }
+        debug!(
+            "precomputed reducer: {} ns/call",
+            now.elapsed().as_nanos() / calls
+        );
+
+        assert_eq!(c, expected);
+    }
 }
@@ -1,17 +1,27 @@
 #![feature(stdarch_x86_avx512)]
 
 pub mod bits;
+pub mod cache;
 pub mod client;
 pub mod convolution;
+pub mod db;
 pub mod kernel;
 pub mod lwe;
 pub mod matmul;
 pub mod measurement;
 pub mod modulus_switch;
+#[cfg(feature = "net")]
+pub mod net;
 pub mod noise_analysis;
+pub mod numa;
 pub mod packing;
 pub mod params;
+pub mod pool;
+pub mod reference;
 pub mod scheme;
 pub mod server;
+pub mod shard;
+pub mod stream;
+pub mod testing;
 pub mod transpose;
 pub mod util;
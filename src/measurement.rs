@@ -1,5 +1,9 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
 use serde::{Deserialize, Serialize};
 
+use spiral_rs::params::Params;
 use spiral_rs::poly::PolyMatrixNTT;
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -42,6 +46,38 @@ pub struct Online {
     pub std_dev_server_time_ms: f64,
 }
 
+/// Nearest-rank latency percentiles over a set of samples.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Percentiles {
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl Percentiles {
+    pub fn from_samples_ms(samples_ms: &[f64]) -> Self {
+        assert!(!samples_ms.is_empty(), "no samples");
+        let mut sorted = samples_ms.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let rank = |p: usize| sorted[(p * sorted.len()).div_ceil(100).max(1) - 1];
+        Self {
+            p50_ms: rank(50),
+            p95_ms: rank(95),
+            p99_ms: rank(99),
+        }
+    }
+}
+
+/// Per-phase latencies of `testing::benchmark_roundtrip`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RoundtripLatency {
+    pub query: Percentiles,
+    pub answer: Percentiles,
+    pub extract: Percentiles,
+}
+
 pub fn get_vec_pm_size_bytes(v_p: &[PolyMatrixNTT]) -> usize {
     v_p.len()
         * v_p[0].rows
@@ -51,6 +87,17 @@ pub fn get_vec_pm_size_bytes(v_p: &[PolyMatrixNTT]) -> usize {
         / 8
 }
 
+/// Size of the packing public parameters a client uploads (the condensed
+/// second rows of `raw_generate_expansion_params`), as counted by
+/// `get_vec_pm_size_bytes`, computed without generating them.
+pub fn pack_pub_params_size_bytes(params: &Params) -> usize {
+    params.poly_len_log2
+        * params.t_exp_left
+        * params.poly_len
+        * params.modulus_log2 as usize
+        / 8
+}
+
 pub fn get_size_bytes(response: &[Vec<Vec<u8>>]) -> usize {
     let mut size_bytes = 0;
     for i in 0..response.len() {
@@ -60,3 +107,44 @@ pub fn get_size_bytes(response: &[Vec<Vec<u8>>]) -> usize {
     }
     size_bytes
 }
+
+/// Resident set size of this process, in bytes, from `/proc/self/statm`;
+/// `None` off Linux or if it can't be read.
+pub fn resident_bytes() -> Option<usize> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * usize::try_from(page_size).ok()?)
+}
+
+/// Runs `f` while a background thread samples `resident_bytes` every
+/// millisecond, returning `f`'s result and the peak growth of the resident
+/// set over its size before the call (`None` without `resident_bytes`).
+///
+/// The resident set is per process, so allocations by other threads count
+/// too, and a peak shorter than the sampling interval can be missed.
+pub fn peak_resident_growth<R>(f: impl FnOnce() -> R) -> (R, Option<usize>) {
+    let Some(before) = resident_bytes() else {
+        return (f(), None);
+    };
+    let done = AtomicBool::new(false);
+    let (result, peak) = std::thread::scope(|s| {
+        let sampler = s.spawn(|| {
+            let mut peak = before;
+            while !done.load(Ordering::Relaxed) {
+                peak = peak.max(resident_bytes().unwrap_or(0));
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            peak
+        });
+        let result = f();
+        let after = resident_bytes().unwrap_or(0);
+        done.store(true, Ordering::Relaxed);
+        (result, sampler.join().unwrap().max(after))
+    });
+    (result, Some(peak - before))
+}
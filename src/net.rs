@@ -0,0 +1,40 @@
+//! Async framing for the reference TCP server and client (`net` feature).
+//!
+//! Frames are the same as in `stream`: a little-endian u64 byte length
+//! followed by that many bytes.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::stream::MAX_FRAME_BYTES;
+
+/// Reads one frame, or returns `None` if the peer closed the connection
+/// cleanly before sending another one.
+pub async fn read_frame_async<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len_bytes = [0u8; 8];
+    match r.read_exact(&mut len_bytes).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u64::from_le_bytes(len_bytes);
+    if len > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds limit of {}",
+                len, MAX_FRAME_BYTES
+            ),
+        ));
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf).await?;
+    Ok(Some(buf))
+}
+
+pub async fn write_frame_async<W: AsyncWrite + Unpin>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u64).to_le_bytes()).await?;
+    w.write_all(data).await?;
+    w.flush().await
+}
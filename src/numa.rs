@@ -0,0 +1,256 @@
+use std::ops::Range;
+
+use spiral_rs::aligned_memory::AlignedMemory64;
+use spiral_rs::params::Params;
+
+use crate::kernel::CrtReducer;
+use crate::server::{answer_col_range, YServer};
+
+/// Parses a sysfs CPU list such as `0-3,8,10-11`; `None` if malformed.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((lo, hi)) => {
+                let (lo, hi) = (lo.parse::<usize>().ok()?, hi.parse::<usize>().ok()?);
+                if lo > hi {
+                    return None;
+                }
+                cpus.extend(lo..=hi);
+            }
+            None => cpus.push(part.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// The CPUs of each NUMA node with any, in node order, from
+/// `/sys/devices/system/node`. A single node with no CPU list (threads are
+/// left unpinned) where that isn't available, e.g. off Linux.
+pub fn numa_nodes() -> Vec<Vec<usize>> {
+    let mut nodes = std::fs::read_dir("/sys/devices/system/node")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let id = name.strip_prefix("node")?.parse::<usize>().ok()?;
+            let list = std::fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            Some((id, parse_cpu_list(&list)?))
+        })
+        .filter(|(_, cpus)| !cpus.is_empty())
+        .collect::<Vec<_>>();
+    if nodes.is_empty() {
+        return vec![Vec::new()];
+    }
+    nodes.sort();
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+/// Pins the calling thread to `cpus`. Does nothing for an empty list, off
+/// Linux, or if the kernel refuses (the thread then runs wherever it is
+/// scheduled, which only costs locality). CPU ids a `cpu_set_t` can't hold
+/// are skipped.
+fn pin_to(cpus: &[usize]) {
+    #[cfg(target_os = "linux")]
+    {
+        let cpus = cpus
+            .iter()
+            .copied()
+            .filter(|&cpu| cpu < libc::CPU_SETSIZE as usize)
+            .collect::<Vec<_>>();
+        if !cpus.is_empty() {
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                for cpu in cpus {
+                    libc::CPU_SET(cpu, &mut set);
+                }
+                // pinning is only an optimization: a refusal (offline CPUs,
+                // a cgroup cpuset without them) leaves the thread unpinned,
+                // where it still computes the same answer
+                let _ = libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set);
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = cpus;
+}
+
+struct NodeShard {
+    cpus: Vec<usize>,
+    cols: Range<usize>,
+    /// Columns `cols` of the transposed database.
+    db_t: Vec<u8>,
+}
+
+/// A u8 server's database split by column across NUMA nodes, answering
+/// with one thread per node.
+///
+/// Node `i` keeps its own copy of a contiguous run of the columns, copied by
+/// a thread pinned to the node's CPUs, so under Linux's default first-touch
+/// policy its pages live in that node's memory. `answer_query` computes each
+/// run on a thread pinned to the same node, so every thread streams only
+/// local memory, and concatenates the runs into the answer
+/// `YServer::answer_query` gives.
+///
+/// The copies are taken at construction; rebuild after writing to the
+/// server.
+pub struct NumaServer<'a> {
+    params: &'a Params,
+    reducer: CrtReducer,
+    db_rows_padded: usize,
+    db_cols: usize,
+    nodes: Vec<NodeShard>,
+}
+
+impl<'a> NumaServer<'a> {
+    /// Splits `server`'s database over the nodes `numa_nodes()` reports.
+    pub fn new(params: &'a Params, server: &YServer<'a, u8>) -> Self {
+        Self::with_nodes(params, server, numa_nodes())
+    }
+
+    /// Splits `server`'s database over `nodes`, each given as the CPUs its
+    /// thread is pinned to (an empty list leaves it unpinned). At most one
+    /// node per column is used.
+    pub fn with_nodes(
+        params: &'a Params,
+        server: &YServer<'a, u8>,
+        nodes: Vec<Vec<usize>>,
+    ) -> Self {
+        assert!(!nodes.is_empty());
+        let db_rows_padded = server.db_rows_padded();
+        let db_cols = server.db_cols();
+        let num_nodes = nodes.len().min(db_cols);
+        let db = server.db();
+
+        let nodes = std::thread::scope(|s| {
+            let handles = nodes
+                .into_iter()
+                .take(num_nodes)
+                .enumerate()
+                .map(|(i, cpus)| {
+                    let cols = db_cols * i / num_nodes..db_cols * (i + 1) / num_nodes;
+                    s.spawn(move || {
+                        pin_to(&cpus);
+                        // column-major, so the run is one contiguous slice
+                        let db_t =
+                            db[cols.start * db_rows_padded..cols.end * db_rows_padded].to_vec();
+                        NodeShard { cpus, cols, db_t }
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        Self {
+            params,
+            reducer: CrtReducer::new(params),
+            db_rows_padded,
+            db_cols,
+            nodes,
+        }
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Same as `YServer::answer_query` on the server this was built from.
+    pub fn answer_query(&self, aligned_query_packed: &[u64]) -> AlignedMemory64 {
+        let rows = self.db_rows_padded;
+        assert_eq!(aligned_query_packed.len(), rows);
+
+        let mut result = AlignedMemory64::new(self.db_cols);
+        std::thread::scope(|s| {
+            let mut rest = result.as_mut_slice();
+            for node in &self.nodes {
+                let (out, tail) = rest.split_at_mut(node.cols.len());
+                rest = tail;
+                s.spawn(move || {
+                    pin_to(&node.cpus);
+                    // the node's copy starts at its first column
+                    answer_col_range(
+                        &self.reducer,
+                        self.params,
+                        out,
+                        aligned_query_packed,
+                        &node.db_t,
+                        rows,
+                        0..node.cols.len(),
+                    );
+                });
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::pack_query;
+    use crate::db::db_num_bytes;
+    use crate::server::DbRowsPadded;
+    use crate::util::test_params;
+
+    fn random_query(params: &Params) -> Vec<u64> {
+        (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list("5"), Some(vec![5]));
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+    }
+
+    #[test]
+    fn test_numa_answer_matches_server() {
+        let params = test_params();
+        let db = (0..db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let server = YServer::<u8>::new(&params, db.iter().copied(), false, false, true);
+
+        // more nodes than the host likely has, unpinned, with uneven runs
+        for nodes in [1, 2, 3] {
+            let numa = NumaServer::with_nodes(&params, &server, vec![Vec::new(); nodes]);
+            assert_eq!(numa.num_nodes(), nodes);
+            let packed = pack_query(&params, &random_query(&params));
+            assert_eq!(
+                numa.answer_query(packed.as_slice()).as_slice(),
+                server.answer_query(packed.as_slice()).as_slice()
+            );
+        }
+
+        let numa = NumaServer::new(&params, &server);
+        let packed = pack_query(&params, &random_query(&params));
+        assert_eq!(
+            numa.answer_query(packed.as_slice()).as_slice(),
+            server.answer_query(packed.as_slice()).as_slice()
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_pin_to_skips_out_of_range_cpus() {
+        // CPU_SET panics past CPU_SETSIZE; on a thread of its own so the test
+        // thread stays unpinned
+        std::thread::spawn(|| {
+            pin_to(&[usize::MAX]);
+            pin_to(&[0, libc::CPU_SETSIZE as usize, 1 << 20]);
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -1,14 +1,16 @@
 use log::debug;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 
 use spiral_rs::{arith::*, params::*};
 
+use super::client::PublicSeeds;
 use super::lwe::LWEParams;
 
 static DEFAULT_MODULI: [u64; 2] = [268369921u64, 249561089u64];
 const DEF_MOD_STR: &str = "[\"268369921\", \"249561089\"]";
 
-fn ext_params_from_json(json_str: &str) -> Params {
+pub(crate) fn ext_params_from_json(json_str: &str) -> Params {
     let v: Value = serde_json::from_str(json_str).unwrap();
 
     let n = v["n"].as_u64().unwrap() as usize;
@@ -96,6 +98,9 @@ fn internal_params_for(
     ))
 }
 
+/// Params for `num_items` items of `item_size_bits` bits. However few the
+/// items, the database is at least one `poly_len` x `poly_len` block
+/// (`nu_1 = nu_2 = 0`).
 pub fn params_for_scenario(num_items: usize, item_size_bits: usize) -> Params {
     let total_db_bytes = num_items * item_size_bits / 8;
     let lwe_pt_word_bytes = 1;
@@ -119,6 +124,9 @@ pub fn params_for_scenario(num_items: usize, item_size_bits: usize) -> Params {
     internal_params_for(nu_1, nu_2, p, q2_bits, t_exp_left, DEF_MOD_STR)
 }
 
+/// SimplePIR params with one row per item and enough instances of
+/// `poly_len` columns for `item_size_bits`; at least `poly_len` rows and one
+/// instance.
 pub fn params_for_scenario_simplepir(num_items: usize, item_size_bits: usize) -> Params {
     let db_rows = num_items;
     let db_cols = (item_size_bits as f64 / (2048.0 * 14.0)).ceil() as usize;
@@ -177,3 +185,410 @@ impl GetQPrime for LWEParams {
 pub struct YPIRParams {
     pub is_simplepir: bool,
 }
+
+/// The CRT factors of `params.modulus`.
+pub fn crt_moduli(params: &Params) -> &[u64] {
+    &params.moduli[..params.crt_count]
+}
+
+/// Most CRT limbs a modulus can have: a packed query word holds one 32-bit
+/// residue per limb.
+pub const MAX_QUERY_LIMBS: usize = 2;
+
+static QUERY_LIMB_CRT_INDICES: [usize; MAX_QUERY_LIMBS] = [0, 1];
+static QUERY_LIMB_CRT_INDICES_SWAPPED: [usize; MAX_QUERY_LIMBS] = [1, 0];
+
+/// CRT index of the residue held in each 32-bit limb of a packed query word,
+/// low limb first; one limb per CRT factor, and any unused limb is zero.
+/// `pack_query` writes the residues in this order, and the kernel reduces
+/// each limb's sum modulo the same factor, so the two can't disagree about
+/// which limb belongs to which modulus.
+///
+/// The limbs hold the residues by decreasing CRT factor, whatever order
+/// `params.moduli` lists the factors in, so params that differ only in that
+/// order pack a query to the same words. For the default moduli this is
+/// limb `i` -> `moduli[i]`.
+pub fn query_limb_crt_indices(params: &Params) -> &'static [usize] {
+    assert!(
+        (1..=MAX_QUERY_LIMBS).contains(&params.crt_count),
+        "packed queries hold 1 to {} CRT residues, not {}",
+        MAX_QUERY_LIMBS,
+        params.crt_count
+    );
+    if params.crt_count == 2 && params.moduli[0] < params.moduli[1] {
+        return &QUERY_LIMB_CRT_INDICES_SWAPPED;
+    }
+    &QUERY_LIMB_CRT_INDICES[..params.crt_count]
+}
+
+/// `params` over a modulus of `crt_limbs` CRT factors (1 or 2, see
+/// `MAX_QUERY_LIMBS`), the first `crt_limbs` of the default moduli, with
+/// every other field unchanged. One limb means a smaller modulus, so a
+/// plaintext modulus chosen for two may leave little noise budget; check
+/// with `validate_params` and the noise estimates.
+pub fn params_with_crt_limbs(params: &Params, crt_limbs: usize) -> Params {
+    assert!(
+        (1..=MAX_QUERY_LIMBS).contains(&crt_limbs),
+        "crt_limbs must be 1 to {}, not {}",
+        MAX_QUERY_LIMBS,
+        crt_limbs
+    );
+    Params::init(
+        params.poly_len,
+        &DEFAULT_MODULI[..crt_limbs],
+        params.noise_width,
+        params.n,
+        params.pt_modulus,
+        params.q2_bits,
+        params.t_conv,
+        params.t_exp_left,
+        params.t_exp_right,
+        params.t_gsw,
+        params.expand_queries,
+        params.db_dim_1,
+        params.db_dim_2,
+        params.instances,
+        params.db_item_size,
+        params.version,
+    )
+}
+
+/// The first inconsistency `validate_params` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamsError {
+    /// `poly_len` is not `2^poly_len_log2`.
+    PolyLen {
+        poly_len: usize,
+        poly_len_log2: usize,
+    },
+    /// Packed queries hold one or two CRT residues.
+    CrtCount { crt_count: usize },
+    /// The CRT moduli don't multiply to `modulus`.
+    CrtProduct,
+    /// `pt_modulus` must be at least 2 and below the reduced modulus.
+    PtModulus { pt_modulus: u64, limit: u64 },
+    /// `q2_bits` has no reduced modulus.
+    Q2Bits { q2_bits: u64 },
+    /// The mode and the instance count disagree: YPIR uses one instance,
+    /// SimplePIR at least one.
+    Instances {
+        is_simplepir: bool,
+        instances: usize,
+    },
+    /// SimplePIR has no second dimension; `db_dim_2` above 1 means YPIR
+    /// params were meant.
+    SimplePirDim2 { db_dim_2: usize },
+}
+
+impl std::fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParamsError::PolyLen {
+                poly_len,
+                poly_len_log2,
+            } => write!(
+                f,
+                "poly_len {} is not 2^poly_len_log2 (2^{})",
+                poly_len, poly_len_log2
+            ),
+            ParamsError::CrtCount { crt_count } => {
+                write!(
+                    f,
+                    "{} CRT moduli, expected 1 to {}",
+                    crt_count, MAX_QUERY_LIMBS
+                )
+            }
+            ParamsError::CrtProduct => write!(f, "CRT moduli don't multiply to the modulus"),
+            ParamsError::PtModulus { pt_modulus, limit } => write!(
+                f,
+                "pt_modulus {} must be at least 2 and below {}",
+                pt_modulus, limit
+            ),
+            ParamsError::Q2Bits { q2_bits } => {
+                write!(f, "no reduced modulus for q2_bits {}", q2_bits)
+            }
+            ParamsError::Instances {
+                is_simplepir: true,
+                instances,
+            } => write!(f, "SimplePIR needs at least 1 instance, not {}", instances),
+            ParamsError::Instances {
+                is_simplepir: false,
+                instances,
+            } => write!(f, "YPIR uses 1 instance, not {}", instances),
+            ParamsError::SimplePirDim2 { db_dim_2 } => write!(
+                f,
+                "db_dim_2 is {}, but SimplePIR has no second dimension",
+                db_dim_2
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+/// Checks that `params` make sense for the mode: ring, CRT and plaintext
+/// moduli, reduced modulus, and the dimensions and instances the mode uses.
+/// The scenario helpers always produce params that pass.
+pub fn validate_params(params: &Params, is_simplepir: bool) -> Result<(), ParamsError> {
+    if params.poly_len != 1 << params.poly_len_log2 {
+        return Err(ParamsError::PolyLen {
+            poly_len: params.poly_len,
+            poly_len_log2: params.poly_len_log2,
+        });
+    }
+    if !(1..=MAX_QUERY_LIMBS).contains(&params.crt_count) {
+        return Err(ParamsError::CrtCount {
+            crt_count: params.crt_count,
+        });
+    }
+    let product = crt_moduli(params)
+        .iter()
+        .map(|&m| m as u128)
+        .product::<u128>();
+    if product != params.modulus as u128 {
+        return Err(ParamsError::CrtProduct);
+    }
+    if params.q2_bits != params.modulus_log2
+        && !(MIN_Q2_BITS..Q2_VALUES.len() as u64).contains(&params.q2_bits)
+    {
+        return Err(ParamsError::Q2Bits {
+            q2_bits: params.q2_bits,
+        });
+    }
+    // responses are rounded to the smaller reduced modulus
+    let limit = params.get_q_prime_1();
+    if params.pt_modulus < 2 || params.pt_modulus >= limit {
+        return Err(ParamsError::PtModulus {
+            pt_modulus: params.pt_modulus,
+            limit,
+        });
+    }
+    let instances_ok = if is_simplepir {
+        params.instances >= 1
+    } else {
+        params.instances == 1
+    };
+    if !instances_ok {
+        return Err(ParamsError::Instances {
+            is_simplepir,
+            instances: params.instances,
+        });
+    }
+    if is_simplepir && params.db_dim_2 > 1 {
+        return Err(ParamsError::SimplePirDim2 {
+            db_dim_2: params.db_dim_2,
+        });
+    }
+    Ok(())
+}
+
+/// Stable hash of every scheme-relevant params field, plus the mode and item
+/// size, so that two parties can cheaply confirm they are using identical params.
+pub fn params_fingerprint(params: &Params, is_simplepir: bool, item_size_bits: usize) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ypir-params-v1");
+
+    let fields: [u64; 16] = [
+        params.poly_len as u64,
+        params.crt_count as u64,
+        params.modulus,
+        params.noise_width.to_bits(),
+        params.n as u64,
+        params.pt_modulus,
+        params.q2_bits as u64,
+        params.t_conv as u64,
+        params.t_exp_left as u64,
+        params.t_exp_right as u64,
+        params.t_gsw as u64,
+        params.expand_queries as u64,
+        params.db_dim_1 as u64,
+        params.db_dim_2 as u64,
+        params.instances as u64,
+        params.db_item_size as u64,
+    ];
+    for field in fields {
+        hasher.update(field.to_le_bytes());
+    }
+    for modulus in crt_moduli(params) {
+        hasher.update(modulus.to_le_bytes());
+    }
+    hasher.update((params.version as u64).to_le_bytes());
+    hasher.update([is_simplepir as u8]);
+    hasher.update((item_size_bits as u64).to_le_bytes());
+
+    hasher.finalize().into()
+}
+
+/// A field that differs between two params, with each side's value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParamsDifference {
+    pub field: &'static str,
+    pub left: String,
+    pub right: String,
+}
+
+/// Every field `params_fingerprint` covers, by name, in the order it hashes
+/// them.
+fn fingerprint_fields(
+    params: &Params,
+    is_simplepir: bool,
+    item_size_bits: usize,
+) -> [(&'static str, String); 20] {
+    [
+        ("simplepir", is_simplepir.to_string()),
+        ("poly_len", params.poly_len.to_string()),
+        ("crt_count", params.crt_count.to_string()),
+        ("modulus", params.modulus.to_string()),
+        ("noise_width", params.noise_width.to_string()),
+        ("n", params.n.to_string()),
+        ("pt_modulus", params.pt_modulus.to_string()),
+        ("q2_bits", params.q2_bits.to_string()),
+        ("t_conv", params.t_conv.to_string()),
+        ("t_exp_left", params.t_exp_left.to_string()),
+        ("t_exp_right", params.t_exp_right.to_string()),
+        ("t_gsw", params.t_gsw.to_string()),
+        ("expand_queries", params.expand_queries.to_string()),
+        ("db_dim_1", params.db_dim_1.to_string()),
+        ("db_dim_2", params.db_dim_2.to_string()),
+        ("instances", params.instances.to_string()),
+        ("db_item_size", params.db_item_size.to_string()),
+        ("crt_moduli", format!("{:?}", crt_moduli(params))),
+        ("version", params.version.to_string()),
+        ("item_size_bits", item_size_bits.to_string()),
+    ]
+}
+
+/// The fields on which two params differ, e.g. to explain a
+/// `params_fingerprint` mismatch between a client and a server; empty
+/// exactly when the fingerprints agree.
+pub fn params_diff(
+    left: &Params,
+    left_simplepir: bool,
+    left_item_size_bits: usize,
+    right: &Params,
+    right_simplepir: bool,
+    right_item_size_bits: usize,
+) -> Vec<ParamsDifference> {
+    let left = fingerprint_fields(left, left_simplepir, left_item_size_bits);
+    let right = fingerprint_fields(right, right_simplepir, right_item_size_bits);
+    left.into_iter()
+        .zip(right)
+        .filter(|((_, l), (_, r))| l != r)
+        .map(|((field, left), (_, right))| ParamsDifference { field, left, right })
+        .collect()
+}
+
+/// `params_fingerprint`, also covering any rotated public seeds; equal to it
+/// when `seeds` replaces none.
+pub fn params_fingerprint_with_seeds(
+    params: &Params,
+    is_simplepir: bool,
+    item_size_bits: usize,
+    seeds: &PublicSeeds,
+) -> [u8; 32] {
+    let fingerprint = params_fingerprint(params, is_simplepir, item_size_bits);
+    if seeds.overrides().is_empty() {
+        return fingerprint;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(b"ypir-seeds-v1");
+    hasher.update(fingerprint);
+    for (idx, seed) in seeds.overrides() {
+        hasher.update([*idx]);
+        hasher.update(seed);
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_params_fingerprint() {
+        let fp_a = params_fingerprint(&params_for_scenario(1 << 30, 1), false, 1);
+        let fp_b = params_fingerprint(&params_for_scenario(1 << 30, 1), false, 1);
+        assert_eq!(fp_a, fp_b);
+
+        let fp_bigger = params_fingerprint(&params_for_scenario(1 << 32, 1), false, 1);
+        assert_ne!(fp_a, fp_bigger);
+
+        let fp_item_size = params_fingerprint(&params_for_scenario(1 << 30, 1), false, 8);
+        assert_ne!(fp_a, fp_item_size);
+
+        let fp_mode = params_fingerprint(&params_for_scenario(1 << 30, 1), true, 1);
+        assert_ne!(fp_a, fp_mode);
+    }
+
+    #[test]
+    fn test_params_diff() {
+        let ypir = params_for_scenario(1 << 30, 1);
+        assert_eq!(params_diff(&ypir, false, 8, &ypir, false, 8), vec![]);
+
+        let mut other = params_for_scenario(1 << 30, 1);
+        other.pt_modulus = 1 << 10;
+        other.db_dim_2 += 1;
+        let fields = params_diff(&ypir, false, 8, &other, true, 8)
+            .iter()
+            .map(|d| d.field)
+            .collect::<Vec<_>>();
+        assert_eq!(fields, ["simplepir", "pt_modulus", "db_dim_2"]);
+
+        let diff = params_diff(&ypir, false, 8, &ypir, false, 16);
+        assert_eq!(
+            diff,
+            vec![ParamsDifference {
+                field: "item_size_bits",
+                left: "8".to_string(),
+                right: "16".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_params() {
+        let ypir = params_for_scenario(1 << 30, 1);
+        let simplepir = params_for_scenario_simplepir(1 << 14, 16384 * 8);
+        assert_eq!(validate_params(&ypir, false), Ok(()));
+        assert_eq!(validate_params(&simplepir, true), Ok(()));
+        assert_eq!(validate_params(&crate::util::test_params(), false), Ok(()));
+
+        let mut dim_2 = params_for_scenario_simplepir(1 << 14, 16384 * 8);
+        dim_2.db_dim_2 = 3;
+        assert_eq!(
+            validate_params(&dim_2, true),
+            Err(ParamsError::SimplePirDim2 { db_dim_2: 3 })
+        );
+        assert_eq!(
+            validate_params(&simplepir, false),
+            Err(ParamsError::Instances {
+                is_simplepir: false,
+                instances: simplepir.instances
+            })
+        );
+        let mut pt = params_for_scenario(1 << 30, 1);
+        pt.pt_modulus = 1;
+        assert!(matches!(
+            validate_params(&pt, false),
+            Err(ParamsError::PtModulus { pt_modulus: 1, .. })
+        ));
+        let mut modulus = params_for_scenario(1 << 30, 1);
+        modulus.modulus += 2;
+        let err = validate_params(&modulus, false);
+        assert_eq!(err, Err(ParamsError::CrtProduct));
+    }
+
+    #[test]
+    fn test_crt_moduli_multiply_to_modulus() {
+        for params in [
+            crate::util::test_params(),
+            params_for_scenario(1 << 30, 1),
+            params_for_scenario_simplepir(1 << 14, 16384 * 8),
+        ] {
+            let moduli = crt_moduli(&params);
+            assert_eq!(moduli.len(), params.crt_count);
+            let product = moduli.iter().map(|&m| m as u128).product::<u128>();
+            assert_eq!(product, params.modulus as u128);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+
+/// A fixed set of values handed out round-robin, each behind its own lock.
+///
+/// Used to spread work over several independent clients: `acquire` picks the
+/// next slot and blocks only if that particular slot is still in use. The slot
+/// index is returned so follow-up work (e.g. decoding a response) can go back
+/// to the same value with `get`.
+#[derive(Debug)]
+pub struct RoundRobinPool<T> {
+    slots: Vec<Mutex<T>>,
+    next: AtomicUsize,
+}
+
+impl<T> RoundRobinPool<T> {
+    pub fn new(items: Vec<T>) -> Self {
+        assert!(!items.is_empty(), "pool must have at least one slot");
+        Self {
+            slots: items.into_iter().map(Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Locks the next slot in round-robin order.
+    pub fn acquire(&self) -> (usize, MutexGuard<'_, T>) {
+        let slot = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        (slot, self.get(slot))
+    }
+
+    /// Locks a specific slot. Panics if `slot` is out of range.
+    pub fn get(&self, slot: usize) -> MutexGuard<'_, T> {
+        self.slots[slot]
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads running queued jobs, so a burst of work
+/// waits for a free worker instead of spawning a thread per job.
+///
+/// A job that panics doesn't take its worker down. Dropping the pool lets the
+/// workers finish the queued jobs and exit.
+#[derive(Debug)]
+pub struct WorkerPool {
+    sender: mpsc::Sender<Job>,
+    workers: usize,
+}
+
+impl WorkerPool {
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0, "pool must have at least one worker");
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            std::thread::spawn(move || loop {
+                // the lock is held only while waiting for the next job
+                let job = receiver
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .recv();
+                match job {
+                    Ok(job) => {
+                        let _ = std::panic::catch_unwind(AssertUnwindSafe(job));
+                    }
+                    Err(_) => break,
+                }
+            });
+        }
+        Self { sender, workers }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Queues `job` to run on the next free worker.
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        // a worker only exits once the sender is dropped
+        self.sender
+            .send(Box::new(job))
+            .expect("worker pool has no workers left");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_round_robin_pool_concurrent() {
+        let pool = RoundRobinPool::new(vec![Vec::new(); 4]);
+
+        std::thread::scope(|s| {
+            for t in 0..8 {
+                let pool = &pool;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        let (slot, mut log) = pool.acquire();
+                        log.push((t, i));
+                        drop(log);
+
+                        // the same slot is still reachable afterwards
+                        assert!(pool.get(slot).contains(&(t, i)));
+                    }
+                });
+            }
+        });
+
+        let counts = (0..pool.len())
+            .map(|slot| pool.get(slot).len())
+            .collect::<Vec<_>>();
+        assert_eq!(counts.iter().sum::<usize>(), 800);
+        assert!(counts.iter().all(|&c| c == 200), "counts: {:?}", counts);
+    }
+
+    #[test]
+    fn test_worker_pool_bounds_concurrency() {
+        let pool = WorkerPool::new(3);
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (done_tx, done_rx) = mpsc::channel();
+        for i in 0..30 {
+            let (active, peak, done_tx) = (active.clone(), peak.clone(), done_tx.clone());
+            pool.execute(move || {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(2));
+                active.fetch_sub(1, Ordering::SeqCst);
+                done_tx.send(i).unwrap();
+            });
+        }
+        let mut done = done_rx.iter().take(30).collect::<Vec<_>>();
+        done.sort();
+        assert_eq!(done, (0..30).collect::<Vec<_>>());
+        assert!(peak.load(Ordering::SeqCst) <= pool.workers());
+
+        // panicking jobs don't kill their workers: it takes all of them at
+        // once to get past the barrier
+        for _ in 0..pool.workers() {
+            pool.execute(|| panic!("job panicked"));
+        }
+        let barrier = Arc::new(Barrier::new(pool.workers()));
+        for i in 0..pool.workers() {
+            let (barrier, done_tx) = (barrier.clone(), done_tx.clone());
+            pool.execute(move || {
+                barrier.wait();
+                done_tx.send(i).unwrap();
+            });
+        }
+        for _ in 0..pool.workers() {
+            done_rx
+                .recv_timeout(Duration::from_secs(10))
+                .expect("a worker died");
+        }
+    }
+}
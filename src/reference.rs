@@ -0,0 +1,122 @@
+use spiral_rs::params::Params;
+
+use crate::db::{db_capacity, db_dims, db_num_bytes};
+
+/// Item `index` of the row-major database `db`, fetched the slowest and
+/// plainest way the scheme allows, as an oracle for the optimized path.
+///
+/// For every byte of the item, the answer to a selection vector for its row
+/// (scaled by `modulus / pt_modulus`) is computed at its column in u128
+/// arithmetic mod `params.modulus` and rounded back to plaintext.
+/// No packing, CRT limbs, transposition or SIMD is involved. `None` past the
+/// last item.
+pub fn reference_fetch(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    db: &[u8],
+    index: usize,
+) -> Option<Vec<u8>> {
+    assert_eq!(db.len(), db_num_bytes(params, is_simplepir));
+    if index >= db_capacity(params, is_simplepir, item_size) {
+        return None;
+    }
+    let (db_rows, db_cols) = db_dims(params, is_simplepir);
+    let (modulus, pt_modulus) = (params.modulus as u128, params.pt_modulus as u128);
+    let delta = modulus / pt_modulus;
+
+    // column `col` of the answer to a query selecting `row`
+    let answer = |row: usize, col: usize| {
+        let sum = (0..db_rows)
+            .map(|r| {
+                let selector = if r == row { delta } else { 0 };
+                selector * db[r * db_cols + col] as u128
+            })
+            .sum::<u128>();
+        sum % modulus
+    };
+
+    let item = (index * item_size..(index + 1) * item_size)
+        .map(|offset| {
+            let x = answer(offset / db_cols, offset % db_cols);
+            // round(x * p / q) mod p
+            (((x * pt_modulus + modulus / 2) / modulus) % pt_modulus) as u8
+        })
+        .collect();
+    Some(item)
+}
+
+#[cfg(test)]
+mod test {
+    use spiral_rs::client::Client;
+
+    use super::*;
+    use crate::client::{pack_query, YClient};
+    use crate::db::logical_to_physical;
+    use crate::params::params_with_crt_limbs;
+    use crate::server::{DbRowsPadded, YServer};
+    use crate::util::{test_params, test_params_reordered_moduli};
+
+    /// The same item through pack_query, the server kernel and the client
+    /// decoder, one noiseless query per row the item touches.
+    fn protocol_fetch(
+        params: &Params,
+        server: &YServer<u8>,
+        y_client: &YClient,
+        item_size: usize,
+        index: usize,
+    ) -> Vec<u8> {
+        let (_, db_cols) = db_dims(params, false);
+        let delta = params.modulus / params.pt_modulus;
+        let first_row = logical_to_physical(params, false, item_size, index).unwrap();
+        let last_row = ((index + 1) * item_size - 1) / db_cols;
+
+        let mut item = Vec::new();
+        for row in first_row..=last_row {
+            let mut query = vec![0u64; server.db_rows_padded()];
+            query[row] = delta;
+            let response = server.answer_query(pack_query(params, &query).as_slice());
+            let start = (index * item_size).max(row * db_cols) - row * db_cols;
+            let end = ((index + 1) * item_size).min((row + 1) * db_cols) - row * db_cols;
+            let (coeffs, _) = y_client.decode_response_range(response.as_slice(), start..end);
+            item.extend(coeffs.iter().map(|&x| x as u8));
+        }
+        item
+    }
+
+    #[test]
+    fn test_reference_matches_protocol() {
+        let mut small_pt = test_params();
+        small_pt.pt_modulus = 1 << 10;
+        let reordered = test_params_reordered_moduli();
+        let one_limb = params_with_crt_limbs(&test_params(), 1);
+        for params in [test_params(), small_pt, reordered, one_limb] {
+            let db = (0..db_num_bytes(&params, false))
+                .map(|_| fastrand::u8(..))
+                .collect::<Vec<_>>();
+            let server = YServer::<u8>::new(&params, db.iter().copied(), false, false, true);
+            let mut client = Client::init(&params);
+            let y_client = YClient::new(&mut client, &params);
+
+            // 100-byte items straddle rows
+            for item_size in [64, 100] {
+                let capacity = db_capacity(&params, false, item_size);
+                for index in [0, 1, 20, capacity / 2, capacity - 1] {
+                    let expected = reference_fetch(&params, false, item_size, &db, index).unwrap();
+                    assert_eq!(expected, &db[index * item_size..(index + 1) * item_size]);
+                    assert_eq!(
+                        protocol_fetch(&params, &server, &y_client, item_size, index),
+                        expected,
+                        "item {} of size {}",
+                        index,
+                        item_size
+                    );
+                }
+                assert_eq!(
+                    reference_fetch(&params, false, item_size, &db, capacity),
+                    None
+                );
+            }
+        }
+    }
+}
@@ -6,7 +6,7 @@ use rand_chacha::ChaCha20Rng;
 
 use spiral_rs::aligned_memory::AlignedMemory64;
 use spiral_rs::arith::rescale;
-use spiral_rs::poly::{PolyMatrix, PolyMatrixRaw};
+use spiral_rs::poly::{PolyMatrix, PolyMatrixNTT, PolyMatrixRaw};
 use spiral_rs::{client::*, params::*};
 
 use crate::bits::{read_bits, u64s_to_contiguous_bytes};
@@ -529,68 +529,7 @@ pub fn run_ypir_on_params<const K: usize>(
             debug!("log2_expected_outer_noise: {}", log2_expected_outer_noise);
 
             let start_decode = Instant::now();
-
-            debug!("rescaling response...");
-            let mut response = Vec::new();
-            for ct_bytes in response_switched.iter() {
-                let ct = PolyMatrixRaw::recover(&params, rlwe_q_prime_1, rlwe_q_prime_2, ct_bytes);
-                response.push(ct);
-            }
-
-            debug!("decrypting outer cts...");
-            let outer_ct = response
-                .iter()
-                .flat_map(|ct| {
-                    decrypt_ct_reg_measured(y_client.client(), &params, &ct.ntt(), params.poly_len)
-                        .as_slice()
-                        .to_vec()
-                })
-                .collect::<Vec<_>>();
-            assert_eq!(outer_ct.len(), out_rows);
-            // debug!("outer_ct: {:?}", &outer_ct[..]);
-            let outer_ct_t_u8 = u64s_to_contiguous_bytes(&outer_ct, pt_bits);
-
-            let mut inner_ct = PolyMatrixRaw::zero(&params, 2, 1);
-            let mut bit_offs = 0;
-            let lwe_q_prime = lwe_params.get_q_prime_2();
-            let special_offs =
-                ((lwe_params.n * lwe_q_prime_bits) as f64 / pt_bits as f64).ceil() as usize;
-            for z in 0..lwe_params.n {
-                let val = read_bits(&outer_ct_t_u8, bit_offs, lwe_q_prime_bits);
-                bit_offs += lwe_q_prime_bits;
-                assert!(
-                    val < lwe_q_prime,
-                    "val: {}, lwe_q_prime: {}",
-                    val,
-                    lwe_q_prime
-                );
-                inner_ct.data[z] = rescale(val, lwe_q_prime, lwe_params.modulus);
-            }
-
-            let mut val = 0;
-            for i in 0..blowup_factor.ceil() as usize {
-                val |= outer_ct[special_offs + i] << (i * pt_bits);
-            }
-            assert!(
-                val < lwe_q_prime,
-                "val: {}, lwe_q_prime: {}",
-                val,
-                lwe_q_prime
-            );
-            debug!("got b_val of: {}", val);
-            inner_ct.data[lwe_params.n] = rescale(val, lwe_q_prime, lwe_params.modulus);
-
-            debug!("decrypting inner ct...");
-            // let plaintext = decrypt_ct_reg_measured(y_client.client(), &params, &inner_ct.ntt(), 1);
-            // let final_result = plaintext.data[0];
-            let inner_ct_as_u32 = inner_ct
-                .as_slice()
-                .iter()
-                .take(lwe_params.n + 1)
-                .map(|x| *x as u32)
-                .collect::<Vec<_>>();
-            let decrypted = y_client.lwe_client().decrypt(&inner_ct_as_u32);
-            let final_result = rescale(decrypted as u64, lwe_params.modulus, lwe_params.pt_modulus);
+            let final_result = decode_doublepir_response(&params, y_client, response_switched);
 
             measurement.online.client_decode_time_ms = start_decode.elapsed().as_millis() as usize;
 
@@ -630,6 +569,179 @@ pub fn run_ypir_on_params<const K: usize>(
     final_measurement
 }
 
+/// Decodes a single DoublePIR response (one client's entry of the online
+/// computation output) back into the plaintext database element.
+pub fn decode_doublepir_response<'a>(
+    params: &'a Params,
+    y_client: &YClient<'a>,
+    response_switched: &[Vec<u8>],
+) -> u64 {
+    let lwe_params = LWEParams::default();
+    let rlwe_q_prime_1 = params.get_q_prime_1();
+    let rlwe_q_prime_2 = params.get_q_prime_2();
+    let lwe_q_prime_bits = lwe_params.q2_bits as usize;
+    let pt_bits = (params.pt_modulus as f64).log2().floor() as usize;
+    let blowup_factor = lwe_q_prime_bits as f64 / pt_bits as f64;
+    let out_rows_log2 = ((blowup_factor * (lwe_params.n + 1) as f64) / params.poly_len as f64)
+        .log2()
+        .ceil() as usize;
+    let out_rows = 1 << (out_rows_log2 + params.poly_len_log2);
+
+    debug!("rescaling response...");
+    let mut response = Vec::new();
+    for ct_bytes in response_switched.iter() {
+        let ct = PolyMatrixRaw::recover(params, rlwe_q_prime_1, rlwe_q_prime_2, ct_bytes);
+        response.push(ct);
+    }
+
+    debug!("decrypting outer cts...");
+    let outer_ct = response
+        .iter()
+        .flat_map(|ct| {
+            decrypt_ct_reg_measured(y_client.client(), params, &ct.ntt(), params.poly_len)
+                .as_slice()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(outer_ct.len(), out_rows);
+    // debug!("outer_ct: {:?}", &outer_ct[..]);
+    let outer_ct_t_u8 = u64s_to_contiguous_bytes(&outer_ct, pt_bits);
+
+    let mut inner_ct = PolyMatrixRaw::zero(params, 2, 1);
+    let mut bit_offs = 0;
+    let lwe_q_prime = lwe_params.get_q_prime_2();
+    let special_offs =
+        ((lwe_params.n * lwe_q_prime_bits) as f64 / pt_bits as f64).ceil() as usize;
+    for z in 0..lwe_params.n {
+        let val = read_bits(&outer_ct_t_u8, bit_offs, lwe_q_prime_bits);
+        bit_offs += lwe_q_prime_bits;
+        assert!(
+            val < lwe_q_prime,
+            "val: {}, lwe_q_prime: {}",
+            val,
+            lwe_q_prime
+        );
+        inner_ct.data[z] = rescale(val, lwe_q_prime, lwe_params.modulus);
+    }
+
+    let mut val = 0;
+    for i in 0..blowup_factor.ceil() as usize {
+        val |= outer_ct[special_offs + i] << (i * pt_bits);
+    }
+    assert!(
+        val < lwe_q_prime,
+        "val: {}, lwe_q_prime: {}",
+        val,
+        lwe_q_prime
+    );
+    debug!("got b_val of: {}", val);
+    inner_ct.data[lwe_params.n] = rescale(val, lwe_q_prime, lwe_params.modulus);
+
+    debug!("decrypting inner ct...");
+    // let plaintext = decrypt_ct_reg_measured(y_client.client(), params, &inner_ct.ntt(), 1);
+    // let final_result = plaintext.data[0];
+    let inner_ct_as_u32 = inner_ct
+        .as_slice()
+        .iter()
+        .take(lwe_params.n + 1)
+        .map(|x| *x as u32)
+        .collect::<Vec<_>>();
+    let decrypted = y_client.lwe_client().decrypt(&inner_ct_as_u32);
+    rescale(decrypted as u64, lwe_params.modulus, lwe_params.pt_modulus)
+}
+
+/// Client and server state for fetching several columns of a single row.
+///
+/// The first-dimension (row) query is generated and answered once, in
+/// `QueryContext::new`; each `query_column` call then only generates, answers
+/// and decodes a second-dimension query.
+///
+/// The context uses the client's existing secret keys and holds no borrow of
+/// the client, so one client can keep several contexts; each must be queried
+/// with the `YClient` it was built with.
+pub struct QueryContext<'a> {
+    params: &'a Params,
+    target_row: usize,
+    pack_pub_params_row_1s: Vec<PolyMatrixNTT<'a>>,
+    intermediate: Vec<u32>,
+}
+
+impl<'a> QueryContext<'a> {
+    /// `y_client`'s client must already hold secret keys.
+    pub fn new(
+        params: &'a Params,
+        y_server: &YServer<'a, u8>,
+        y_client: &YClient<'a>,
+        target_row: usize,
+    ) -> Self {
+        let lwe_params = LWEParams::default();
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        assert!(target_row < db_rows, "row {} out of range", target_row);
+
+        let sk_reg = &y_client.client().get_sk_reg();
+        let pack_pub_params = raw_generate_expansion_params(
+            params,
+            sk_reg,
+            params.poly_len_log2,
+            params.t_exp_left,
+            &mut ChaCha20Rng::from_entropy(),
+            &mut ChaCha20Rng::from_seed(STATIC_SEED_2),
+        );
+        let pack_pub_params_row_1s = pack_pub_params
+            .iter()
+            .map(|pp| condense_matrix(params, &pp.submatrix(1, 0, 1, pp.cols)))
+            .collect::<Vec<_>>();
+
+        let query_row = y_client.generate_query(SEED_0, params.db_dim_1, false, target_row);
+        let mut packed_query_row = vec![0u32; params.db_rows_padded()];
+        for (out, x) in packed_query_row
+            .iter_mut()
+            .zip(query_row[lwe_params.n * db_rows..].iter())
+        {
+            *out = *x as u32;
+        }
+
+        let intermediate = y_server.perform_online_computation_first_pass::<1>(&packed_query_row, None);
+
+        Self {
+            params,
+            target_row,
+            pack_pub_params_row_1s,
+            intermediate,
+        }
+    }
+
+    pub fn target_row(&self) -> usize {
+        self.target_row
+    }
+
+    /// Fetches column `target_col` of the context's row, reusing the cached
+    /// first-dimension answer. `y_client` must be the one the context was
+    /// built with.
+    pub fn query_column(
+        &self,
+        y_server: &YServer<'a, u8>,
+        y_client: &YClient<'a>,
+        offline_vals: &mut OfflinePrecomputedValues<'a>,
+        target_col: usize,
+    ) -> u64 {
+        let params = self.params;
+        let query_col = y_client.generate_query(SEED_1, params.db_dim_2, true, target_col);
+        let packed_query_col = pack_query(params, &query_col);
+
+        let responses = y_server.perform_online_computation_second_pass(
+            offline_vals,
+            &self.intermediate,
+            &[(
+                packed_query_col.as_slice(),
+                self.pack_pub_params_row_1s.as_slice(),
+            )],
+            None,
+        );
+        decode_doublepir_response(params, y_client, &responses[0])
+    }
+}
+
 fn mean(xs: &[usize]) -> f64 {
     xs.iter().map(|x| *x as f64).sum::<f64>() / xs.len() as f64
 }
@@ -668,6 +780,110 @@ mod test {
         run_ypir_batched(1 << 17, 65536 * 8, 1, true, 1);
     }
 
+    #[test]
+    fn test_ypir_query_context_same_row() {
+        let params = params_for_scenario(1 << 30, 1);
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+
+        let pt_iter = std::iter::repeat_with(|| u8::sample());
+        let y_server = YServer::<u8>::new(&params, pt_iter, false, false, true);
+        let mut offline_values = y_server.perform_offline_precomputation(None);
+
+        let mut rng = thread_rng();
+        let target_row = rng.gen::<usize>() % db_rows;
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+        let ctx = QueryContext::new(&params, &y_server, &y_client, target_row);
+        assert_eq!(ctx.target_row(), target_row);
+
+        for _ in 0..3 {
+            let target_col = rng.gen::<usize>() % db_cols;
+            let result = ctx.query_column(&y_server, &y_client, &mut offline_values, target_col);
+            let corr_result = y_server.get_elem(target_row, target_col).to_u64();
+            assert_eq!(result, corr_result);
+        }
+    }
+
+    #[test]
+    fn test_ypir_query_contexts_share_keys() {
+        let params = params_for_scenario(1 << 30, 1);
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+
+        let pt_iter = std::iter::repeat_with(|| u8::sample());
+        let y_server = YServer::<u8>::new(&params, pt_iter, false, false, true);
+        let mut offline_values = y_server.perform_offline_precomputation(None);
+
+        let mut rng = thread_rng();
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let sk = client.get_sk_reg().as_slice().to_vec();
+        let y_client = YClient::new(&mut client, &params);
+
+        let rows = [rng.gen::<usize>() % db_rows, rng.gen::<usize>() % db_rows];
+        let contexts = rows.map(|row| QueryContext::new(&params, &y_server, &y_client, row));
+        // building a context leaves the keys alone
+        assert_eq!(y_client.client().get_sk_reg().as_slice(), sk.as_slice());
+
+        // interleaved, so each context must still decode under the same keys
+        for _ in 0..2 {
+            for (ctx, &row) in contexts.iter().zip(&rows) {
+                let col = rng.gen::<usize>() % db_cols;
+                let result = ctx.query_column(&y_server, &y_client, &mut offline_values, col);
+                assert_eq!(result, y_server.get_elem(row, col).to_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn test_ypir_with_public_material() {
+        let params = params_for_scenario(1 << 30, 1);
+        let lwe_params = LWEParams::default();
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+
+        let pt_iter = std::iter::repeat_with(|| u8::sample());
+        let y_server = YServer::<u8>::new(&params, pt_iter, false, false, true);
+        let mut offline_values = y_server.perform_offline_precomputation(None);
+
+        let mut rng = thread_rng();
+        let (target_row, target_col) = (rng.gen::<usize>() % db_rows, rng.gen::<usize>() % db_cols);
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        // what the server is left with after the material is sent a second time
+        let material = public_material(&params, &client);
+        assert_eq!(public_material(&params, &client), material);
+        let pack_pub_params_row_1s = pack_pub_params_from_material(&params, &material).unwrap();
+
+        let y_client = YClient::new(&mut client, &params);
+        let query_row = y_client.generate_query(SEED_0, params.db_dim_1, false, target_row);
+        let mut packed_query_row = vec![0u32; params.db_rows_padded()];
+        for (out, x) in packed_query_row
+            .iter_mut()
+            .zip(query_row[lwe_params.n * db_rows..].iter())
+        {
+            *out = *x as u32;
+        }
+        let query_col = y_client.generate_query(SEED_1, params.db_dim_2, true, target_col);
+        let packed_query_col = pack_query(&params, &query_col);
+
+        let intermediate =
+            y_server.perform_online_computation_first_pass::<1>(&packed_query_row, None);
+        let responses = y_server.perform_online_computation_second_pass(
+            &mut offline_values,
+            &intermediate,
+            &[(
+                packed_query_col.as_slice(),
+                pack_pub_params_row_1s.as_slice(),
+            )],
+            None,
+        );
+        let result = decode_doublepir_response(&params, &y_client, &responses[0]);
+        assert_eq!(result, y_server.get_elem(target_row, target_col).to_u64());
+    }
+
     #[test]
     fn test_ypir_many_clients() {
         run_ypir_batched(1 << 30, 1, 2, false, 1);
@@ -1,5 +1,7 @@
 #[cfg(target_feature = "avx2")]
 use std::arch::x86_64::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::{marker::PhantomData, ops::Range, time::Instant};
 
 use log::debug;
@@ -10,7 +12,7 @@ use spiral_rs::aligned_memory::AlignedMemory64;
 use spiral_rs::{arith::*, client::*, params::*, poly::*};
 
 use crate::convolution::naive_multiply_matrices;
-use crate::measurement::Measurement;
+use crate::measurement::{pack_pub_params_size_bytes, Measurement};
 
 use super::{
     bits::*,
@@ -141,6 +143,30 @@ pub fn generate_fake_pack_pub_params<'a>(params: &'a Params) -> Vec<PolyMatrixNT
     pack_pub_params
 }
 
+/// The condensed packing parameters (the `pack_pub_params_row_1s` the online
+/// computation takes) held in a client's `public_material`, or `None` if it
+/// isn't `pack_pub_params_size_bytes(params)` bytes long.
+pub fn pack_pub_params_from_material<'a>(
+    params: &'a Params,
+    material: &[u8],
+) -> Option<Vec<PolyMatrixNTT<'a>>> {
+    if material.len() != pack_pub_params_size_bytes(params) {
+        return None;
+    }
+    let coeffs = contiguous_bytes_to_u64s(material, params.modulus_log2 as usize);
+    let poly_count = params.t_exp_left * params.poly_len;
+    let row_1s = (0..params.poly_len_log2)
+        .map(|i| {
+            let mut row_1 = PolyMatrixRaw::zero(params, 1, params.t_exp_left);
+            row_1
+                .as_mut_slice()
+                .copy_from_slice(&coeffs[i * poly_count..(i + 1) * poly_count]);
+            condense_matrix(params, &row_1.ntt())
+        })
+        .collect();
+    Some(row_1s)
+}
+
 pub type Precomp<'a> = Vec<(PolyMatrixNTT<'a>, Vec<PolyMatrixNTT<'a>>, Vec<Vec<usize>>)>;
 
 #[derive(Clone)]
@@ -159,10 +185,51 @@ pub struct OfflinePrecomputedValues<'a> {
 pub struct YServer<'a, T> {
     params: &'a Params,
     smaller_params: Params,
-    db_buf_aligned: AlignedMemory64, // db_buf: Vec<u8>, // stored transposed
+    db_buf_aligned: DbBuf, // db_buf: Vec<u8>, // stored transposed
     phantom: PhantomData<T>,
     pad_rows: bool,
     ypir_params: YPIRParams,
+    seeds: PublicSeeds,
+    // derived from `params` once, rather than on every answer
+    reducer: CrtReducer,
+}
+
+/// Memory holding a `YServer`'s transposed database.
+#[derive(Clone)]
+enum DbBuf {
+    Owned(AlignedMemory64),
+    /// Memory the server doesn't own (see `YServer::from_shared`); copied
+    /// into an owned buffer on the first write.
+    Shared(&'static [u64]),
+}
+
+impl DbBuf {
+    fn as_slice(&self) -> &[u64] {
+        match self {
+            DbBuf::Owned(mem) => mem.as_slice(),
+            DbBuf::Shared(words) => words,
+        }
+    }
+
+    fn as_ptr(&self) -> *const u64 {
+        self.as_slice().as_ptr()
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn to_mut(&mut self) -> &mut AlignedMemory64 {
+        if let DbBuf::Shared(words) = *self {
+            let mut owned = AlignedMemory64::new(words.len());
+            owned.as_mut_slice().copy_from_slice(words);
+            *self = DbBuf::Owned(owned);
+        }
+        match self {
+            DbBuf::Owned(mem) => mem,
+            DbBuf::Shared(_) => unreachable!(),
+        }
+    }
 }
 
 pub trait DbRowsPadded {
@@ -178,6 +245,729 @@ impl DbRowsPadded for Params {
     }
 }
 
+/// Alignment, in bytes, of the database buffer allocated by `YServer::new`.
+pub const DB_ALIGNMENT: usize = 64;
+
+/// Describes the transposed database layout that `YServer::new` produces
+/// (and that a caller passing `inp_transposed = true` must supply).
+///
+/// Element `(row, col)` lives at byte offset `col * col_stride + row * row_stride`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbLayout {
+    pub db_rows: usize,
+    pub db_rows_padded: usize,
+    pub db_cols: usize,
+    pub row_stride: usize,
+    pub col_stride: usize,
+    pub alignment: usize,
+    pub element_bytes: usize,
+}
+
+impl DbLayout {
+    pub fn total_bytes(&self) -> usize {
+        self.db_cols * self.col_stride
+    }
+
+    pub fn offset(&self, row: usize, col: usize) -> usize {
+        col * self.col_stride + row * self.row_stride
+    }
+}
+
+pub fn db_layout(
+    params: &Params,
+    is_simplepir: bool,
+    pad_rows: bool,
+    element_bytes: usize,
+) -> DbLayout {
+    let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+    let db_rows_padded = if pad_rows {
+        params.db_rows_padded()
+    } else {
+        db_rows
+    };
+    let db_cols = if is_simplepir {
+        params.instances * params.poly_len
+    } else {
+        1 << (params.db_dim_2 + params.poly_len_log2)
+    };
+
+    DbLayout {
+        db_rows,
+        db_rows_padded,
+        db_cols,
+        row_stride: element_bytes,
+        col_stride: db_rows_padded * element_bytes,
+        alignment: DB_ALIGNMENT,
+        element_bytes,
+    }
+}
+
+/// Bytes of one `YServer::answer_query` response: a u64 word per column.
+pub fn response_size_bytes(params: &Params, is_simplepir: bool) -> usize {
+    db_layout(params, is_simplepir, true, 1).db_cols * std::mem::size_of::<u64>()
+}
+
+/// Response bytes downloaded per item byte retrieved, the bandwidth overhead
+/// of `item_size`-byte items. The response covers a whole row, so the ratio
+/// falls as items fill more of it.
+pub fn expansion_ratio(params: &Params, is_simplepir: bool, item_size: usize) -> f64 {
+    response_size_bytes(params, is_simplepir) as f64 / item_size as f64
+}
+
+/// Bytes of a response packed by `pack_response`.
+pub fn packed_response_size_bytes(params: &Params, is_simplepir: bool) -> usize {
+    let db_cols = db_layout(params, is_simplepir, true, 1).db_cols;
+    (db_cols * params.modulus_log2 as usize).div_ceil(8)
+}
+
+/// A response with each word cut to its `modulus_log2` significant bits,
+/// for transports that prefer a smaller download. Words are reduced mod
+/// `params.modulus`, so nothing is lost; the cost is a bit-level copy on
+/// both ends instead of reading words in place.
+pub fn pack_response(params: &Params, response: &[u64]) -> Vec<u8> {
+    debug_assert!(response.iter().all(|&w| w < params.modulus));
+    u64s_to_contiguous_bytes(response, params.modulus_log2 as usize)
+}
+
+/// The response words back from `pack_response`, or `None` if `packed` is
+/// not `packed_response_size_bytes` long.
+pub fn unpack_response(params: &Params, is_simplepir: bool, packed: &[u8]) -> Option<Vec<u64>> {
+    if packed.len() != packed_response_size_bytes(params, is_simplepir) {
+        return None;
+    }
+    let mut words = contiguous_bytes_to_u64s(packed, params.modulus_log2 as usize);
+    // the padding bits of the last byte can read as one more word
+    words.truncate(db_layout(params, is_simplepir, true, 1).db_cols);
+    Some(words)
+}
+
+/// Estimated memory, in bytes, of building a server with `YServer::new` and
+/// answering queries on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ServerMemory {
+    /// The transposed database buffer the server keeps.
+    pub db_bytes: usize,
+    /// The untransposed input, held only while the server is being built.
+    pub input_bytes: usize,
+    /// Query and response buffers of every concurrently running answer.
+    pub scratch_bytes: usize,
+}
+
+impl ServerMemory {
+    pub fn new(
+        params: &Params,
+        is_simplepir: bool,
+        pad_rows: bool,
+        element_bytes: usize,
+        num_threads: usize,
+    ) -> Self {
+        let layout = db_layout(params, is_simplepir, pad_rows, element_bytes);
+        let query_bytes = layout.db_rows_padded * 8;
+        let response_bytes = layout.db_cols * 8;
+        Self {
+            db_bytes: layout.total_bytes() + std::mem::size_of::<Params>(),
+            input_bytes: layout.db_rows * layout.db_cols * element_bytes,
+            scratch_bytes: num_threads * (query_bytes + response_bytes),
+        }
+    }
+
+    /// Peak footprint: the input and the server coexist while building.
+    pub fn total(&self) -> usize {
+        self.db_bytes + self.input_bytes + self.scratch_bytes
+    }
+
+    /// Refuses a build whose peak footprint would exceed `limit_bytes`.
+    pub fn check_limit(&self, limit_bytes: usize) -> Result<(), ServerBuildError> {
+        let needed = self.total();
+        if needed > limit_bytes {
+            return Err(ServerBuildError::MemoryLimit {
+                needed,
+                limit: limit_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn lock_pages(ptr: *const u8, len: usize) -> std::io::Result<()> {
+    match unsafe { libc::mlock(ptr as *const libc::c_void, len) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(unix)]
+fn unlock_pages(ptr: *const u8, len: usize) -> std::io::Result<()> {
+    match unsafe { libc::munlock(ptr as *const libc::c_void, len) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(windows)]
+fn lock_pages(ptr: *const u8, len: usize) -> std::io::Result<()> {
+    use windows_sys::Win32::System::Memory::VirtualLock;
+    match unsafe { VirtualLock(ptr as *const std::ffi::c_void, len) } {
+        0 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(windows)]
+fn unlock_pages(ptr: *const u8, len: usize) -> std::io::Result<()> {
+    use windows_sys::Win32::System::Memory::VirtualUnlock;
+    match unsafe { VirtualUnlock(ptr as *const std::ffi::c_void, len) } {
+        0 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn lock_pages(_ptr: *const u8, _len: usize) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn unlock_pages(_ptr: *const u8, _len: usize) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerBuildError {
+    /// A block was not a whole number of (padded) columns.
+    PartialColumns { block_len: usize, col_len: usize },
+    /// More data was fed than the database holds.
+    Overflow { provided: usize, expected: usize },
+    /// `finish` was called before the database was complete.
+    Incomplete { provided: usize, expected: usize },
+    /// The projected footprint (`ServerMemory::total`) exceeds the cap.
+    MemoryLimit { needed: usize, limit: usize },
+}
+
+impl std::fmt::Display for ServerBuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerBuildError::PartialColumns { block_len, col_len } => write!(
+                f,
+                "block of {} elements is not a whole number of {}-element columns",
+                block_len, col_len
+            ),
+            ServerBuildError::Overflow { provided, expected } => write!(
+                f,
+                "{} elements provided, but the database holds {}",
+                provided, expected
+            ),
+            ServerBuildError::Incomplete { provided, expected } => write!(
+                f,
+                "only {} of {} database elements provided",
+                provided, expected
+            ),
+            ServerBuildError::MemoryLimit { needed, limit } => write!(
+                f,
+                "server needs an estimated {} bytes, over the {}-byte limit",
+                needed, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ServerBuildError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// A packed query must have one word per (padded) database row.
+    WrongLength { len: usize, expected: usize },
+    /// `answer_query_multi` needs every server's layout to match the first's.
+    LayoutMismatch { server: usize },
+    /// `answer_batch` needs at least one query.
+    EmptyBatch,
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryError::WrongLength { len, expected } => write!(
+                f,
+                "packed query is {} words, expected {}",
+                len, expected
+            ),
+            QueryError::LayoutMismatch { server } => write!(
+                f,
+                "server {} has a different database layout than server 0",
+                server
+            ),
+            QueryError::EmptyBatch => write!(f, "batch has no queries"),
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+/// Asks an answer in progress (`YServer::answer_query_cancellable`) to stop,
+/// e.g. when the client has disconnected. Clones share one flag, so one can
+/// be kept by whoever cancels while the answer runs elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// An answer stopped early because its `CancelToken` was set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Columns `answer_query_cancellable` computes between checks of its token;
+/// a multiple of `REDUCE_LANES`, so blocks keep the vectorized reduction.
+pub const CANCEL_CHECK_COLS: usize = 256;
+
+/// The answer words for columns `cols` of `db_t`, a column-major database
+/// of `rows` elements per column, into `out` (one word per column). Only
+/// those columns' contiguous slice of `db_t` is read, so an answer split by
+/// column computes each part with this.
+pub fn answer_col_range<T: Copy>(
+    reducer: &CrtReducer,
+    params: &Params,
+    out: &mut [u64],
+    aligned_query_packed: &[u64],
+    db_t: &[T],
+    rows: usize,
+    cols: Range<usize>,
+) where
+    *const T: ToM512,
+{
+    fast_batched_dot_product_with_reducer::<1, T>(
+        PreparedKernel::new(active_kernel(), reducer),
+        params,
+        out,
+        aligned_query_packed,
+        rows,
+        TransposedDb::new(&db_t[cols.start * rows..cols.end * rows], rows, cols.len()),
+    );
+}
+
+/// Builds a `YServer` from its transposed database fed in consecutive blocks
+/// of whole columns, so the database is never held in memory twice.
+pub struct YServerBuilder<'a, T> {
+    params: &'a Params,
+    is_simplepir: bool,
+    pad_rows: bool,
+    layout: DbLayout,
+    db_buf_aligned: AlignedMemory64,
+    filled: usize, // in elements
+    phantom: PhantomData<T>,
+}
+
+impl<'a, T> YServerBuilder<'a, T>
+where
+    T: Sized + Copy + ToU64 + Default,
+    *const T: ToM512,
+{
+    pub fn new(params: &'a Params, is_simplepir: bool, pad_rows: bool) -> Self {
+        let layout = db_layout(params, is_simplepir, pad_rows, std::mem::size_of::<T>());
+        Self {
+            params,
+            is_simplepir,
+            pad_rows,
+            layout,
+            db_buf_aligned: AlignedMemory64::new(layout.total_bytes() / 8),
+            filled: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    pub fn layout(&self) -> DbLayout {
+        self.layout
+    }
+
+    pub fn total_elems(&self) -> usize {
+        self.layout.db_rows_padded * self.layout.db_cols
+    }
+
+    pub fn filled_elems(&self) -> usize {
+        self.filled
+    }
+
+    /// Appends the next columns (each `db_rows_padded` elements) of the
+    /// transposed database.
+    pub fn add_column_block(&mut self, block: &[T]) -> Result<(), ServerBuildError> {
+        let col_len = self.layout.db_rows_padded;
+        if block.len() % col_len != 0 {
+            return Err(ServerBuildError::PartialColumns {
+                block_len: block.len(),
+                col_len,
+            });
+        }
+        if self.filled + block.len() > self.total_elems() {
+            return Err(ServerBuildError::Overflow {
+                provided: self.filled + block.len(),
+                expected: self.total_elems(),
+            });
+        }
+
+        let db = unsafe {
+            std::slice::from_raw_parts_mut(
+                self.db_buf_aligned.as_mut_slice().as_mut_ptr() as *mut T,
+                self.total_elems(),
+            )
+        };
+        db[self.filled..self.filled + block.len()].copy_from_slice(block);
+        self.filled += block.len();
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<YServer<'a, T>, ServerBuildError> {
+        if self.filled != self.total_elems() {
+            return Err(ServerBuildError::Incomplete {
+                provided: self.filled,
+                expected: self.total_elems(),
+            });
+        }
+        Ok(YServer::from_db_buf(
+            self.params,
+            self.db_buf_aligned,
+            self.is_simplepir,
+            self.pad_rows,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TenantError {
+    NoTenants,
+    /// A tenant database is not `db_num_bytes` long.
+    WrongSize {
+        tenant: usize,
+        len: usize,
+        expected: usize,
+    },
+    UnknownTenant { tenant: usize, tenants: usize },
+    /// Row tenant tags are not one per database row.
+    WrongTagCount { len: usize, expected: usize },
+    Query(QueryError),
+}
+
+impl std::fmt::Display for TenantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TenantError::NoTenants => write!(f, "at least one tenant database is required"),
+            TenantError::WrongSize {
+                tenant,
+                len,
+                expected,
+            } => write!(
+                f,
+                "tenant {} database is {} bytes, expected {}",
+                tenant, len, expected
+            ),
+            TenantError::UnknownTenant { tenant, tenants } => write!(
+                f,
+                "tenant {} out of range for {} tenants",
+                tenant, tenants
+            ),
+            TenantError::WrongTagCount { len, expected } => {
+                write!(f, "{} row tenant tags, expected {}", len, expected)
+            }
+            TenantError::Query(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TenantError {}
+
+/// Zeroes the words of a packed query for every row not tagged `tenant` in
+/// `row_tenants` (see `row_tenants`), and for the padding rows past them, so
+/// those rows contribute nothing to the answer.
+///
+/// Each query word only ever multiplies its own row, so the masked answer is
+/// the answer over the tenant's rows alone, and the server learns no more
+/// about which of them is selected than before.
+pub fn restrict_query_to_tenant(
+    aligned_query_packed: &mut [u64],
+    row_tenants: &[Option<u32>],
+    tenant: u32,
+) {
+    for (row, word) in aligned_query_packed.iter_mut().enumerate() {
+        if row_tenants.get(row).copied().flatten() != Some(tenant) {
+            *word = 0;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreviewError {
+    /// Items don't tile the row, so which columns hold their first bytes
+    /// depends on the queried row.
+    ItemsStraddleRows {
+        item_size: usize,
+        db_cols: usize,
+    },
+    /// `top_bytes` must be 1 to `item_size`.
+    TopBytes {
+        top_bytes: usize,
+        item_size: usize,
+    },
+    Query(QueryError),
+}
+
+impl std::fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreviewError::ItemsStraddleRows { item_size, db_cols } => write!(
+                f,
+                "{}-byte items don't tile {}-column rows, so they can't be previewed",
+                item_size, db_cols
+            ),
+            PreviewError::TopBytes {
+                top_bytes,
+                item_size,
+            } => write!(
+                f,
+                "cannot preview {} bytes of a {}-byte item",
+                top_bytes, item_size
+            ),
+            PreviewError::Query(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PreviewError {}
+
+/// Columns of a row holding the first `top_bytes` bytes of every
+/// `item_size`-byte item in it, item by item; `answer_preview` computes only
+/// these.
+///
+/// Every item in the row is covered, since the server must not learn which
+/// one is wanted, so this needs items that tile the row exactly.
+pub fn preview_columns(
+    db_cols: usize,
+    item_size: usize,
+    top_bytes: usize,
+) -> Result<Vec<usize>, PreviewError> {
+    if item_size == 0 || db_cols % item_size != 0 {
+        return Err(PreviewError::ItemsStraddleRows { item_size, db_cols });
+    }
+    if top_bytes == 0 || top_bytes > item_size {
+        return Err(PreviewError::TopBytes {
+            top_bytes,
+            item_size,
+        });
+    }
+    Ok((0..db_cols)
+        .step_by(item_size)
+        .flat_map(|start| start..start + top_bytes)
+        .collect())
+}
+
+/// Several same-shaped u8 databases in one buffer, answered one tenant at a
+/// time. Tenant `t`'s transposed database is the `t`-th run of `db_cols`
+/// columns, so answering it is `YServer::answer_query` on a column slice.
+/// Which tenant is queried is public; only the item stays private.
+pub struct MultiTenantServer<'a> {
+    params: &'a Params,
+    layout: DbLayout,
+    tenants: usize,
+    db_buf_aligned: AlignedMemory64,
+}
+
+impl<'a> MultiTenantServer<'a> {
+    /// Builds from each tenant's row-major database (see `db_num_bytes`).
+    pub fn new(
+        params: &'a Params,
+        is_simplepir: bool,
+        tenant_dbs: &[&[u8]],
+    ) -> Result<Self, TenantError> {
+        if tenant_dbs.is_empty() {
+            return Err(TenantError::NoTenants);
+        }
+        let layout = db_layout(params, is_simplepir, true, 1);
+        let expected = layout.db_rows * layout.db_cols;
+        let per_tenant = layout.total_bytes();
+        let mut db_buf_aligned = AlignedMemory64::new(tenant_dbs.len() * per_tenant / 8);
+        let buf = as_bytes_mut(&mut db_buf_aligned);
+        for (tenant, db) in tenant_dbs.iter().enumerate() {
+            if db.len() != expected {
+                return Err(TenantError::WrongSize {
+                    tenant,
+                    len: db.len(),
+                    expected,
+                });
+            }
+            let transposed = crate::db::transpose_db(params, is_simplepir, db);
+            buf[tenant * per_tenant..(tenant + 1) * per_tenant].copy_from_slice(&transposed);
+        }
+        Ok(Self {
+            params,
+            layout,
+            tenants: tenant_dbs.len(),
+            db_buf_aligned,
+        })
+    }
+
+    pub fn tenants(&self) -> usize {
+        self.tenants
+    }
+
+    /// Tenant `tenant`'s transposed database.
+    pub fn tenant_db(&self, tenant: usize) -> Option<&[u8]> {
+        if tenant >= self.tenants {
+            return None;
+        }
+        let start = tenant * self.layout.total_bytes();
+        Some(&as_bytes(&self.db_buf_aligned)[start..start + self.layout.total_bytes()])
+    }
+
+    pub fn answer_query(
+        &self,
+        tenant: usize,
+        aligned_query_packed: &[u64],
+    ) -> Result<AlignedMemory64, TenantError> {
+        let db = self.tenant_db(tenant).ok_or(TenantError::UnknownTenant {
+            tenant,
+            tenants: self.tenants,
+        })?;
+        let (db_rows_padded, db_cols) = (self.layout.db_rows_padded, self.layout.db_cols);
+        if aligned_query_packed.len() != db_rows_padded {
+            return Err(TenantError::Query(QueryError::WrongLength {
+                len: aligned_query_packed.len(),
+                expected: db_rows_padded,
+            }));
+        }
+        let mut result = AlignedMemory64::new(db_cols);
+        fast_batched_dot_product_avx512::<1, u8>(
+            self.params,
+            result.as_mut_slice(),
+            aligned_query_packed,
+            db_rows_padded,
+            db,
+            db_rows_padded,
+            db_cols,
+        );
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstanceError {
+    /// Not one database per SimplePIR instance.
+    Count {
+        found: usize,
+        expected: usize,
+    },
+    /// An instance database is not `instance_db_bytes` long.
+    WrongSize {
+        instance: usize,
+        len: usize,
+        expected: usize,
+    },
+    Query(QueryError),
+}
+
+impl std::fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceError::Count { found, expected } => write!(
+                f,
+                "{} instance databases given, params have {} instances",
+                found, expected
+            ),
+            InstanceError::WrongSize {
+                instance,
+                len,
+                expected,
+            } => write!(
+                f,
+                "instance {} database is {} bytes, expected {}",
+                instance, len, expected
+            ),
+            InstanceError::Query(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+/// Bytes of one SimplePIR instance's database: its `poly_len` columns of the
+/// transposed, row-padded layout (see `db_layout`).
+pub fn instance_db_bytes(params: &Params) -> usize {
+    params.poly_len * params.db_rows_padded()
+}
+
+/// Answers a packed query against a SimplePIR database held as one buffer
+/// per instance, so one instance can be replaced without touching the
+/// others. Instance `i`'s buffer is columns `i * poly_len..(i + 1) *
+/// poly_len` of the transposed database; the answers for each are
+/// concatenated in instance order, equal to `YServer::answer_query` on the
+/// whole database.
+pub fn answer_by_instance(
+    params: &Params,
+    aligned_query_packed: &[u64],
+    instance_dbs: &[&[u8]],
+) -> Result<AlignedMemory64, InstanceError> {
+    if instance_dbs.len() != params.instances {
+        return Err(InstanceError::Count {
+            found: instance_dbs.len(),
+            expected: params.instances,
+        });
+    }
+    let (rows, cols) = (params.db_rows_padded(), params.poly_len);
+    if aligned_query_packed.len() != rows {
+        return Err(InstanceError::Query(QueryError::WrongLength {
+            len: aligned_query_packed.len(),
+            expected: rows,
+        }));
+    }
+    let expected = instance_db_bytes(params);
+    if let Some((instance, db)) = instance_dbs
+        .iter()
+        .enumerate()
+        .find(|(_, db)| db.len() != expected)
+    {
+        return Err(InstanceError::WrongSize {
+            instance,
+            len: db.len(),
+            expected,
+        });
+    }
+
+    let mut result = AlignedMemory64::new(instance_dbs.len() * cols);
+    for (out, db) in result
+        .as_mut_slice()
+        .chunks_exact_mut(cols)
+        .zip(instance_dbs)
+    {
+        fast_batched_dot_product_avx512::<1, u8>(
+            params,
+            out,
+            aligned_query_packed,
+            rows,
+            db,
+            rows,
+            cols,
+        );
+    }
+    Ok(result)
+}
+
 impl<'a, T> YServer<'a, T>
 where
     T: Sized + Copy + ToU64 + Default,
@@ -195,8 +985,6 @@ where
     {
         // TODO: hack
         // let lwe_params = LWEParams::default();
-        let mut ypir_params = YPIRParams::default();
-        ypir_params.is_simplepir = is_simplepir;
         let bytes_per_pt_el = std::mem::size_of::<T>(); //1; //((lwe_params.pt_modulus as f64).log2() / 8.).ceil() as usize;
 
         let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
@@ -236,6 +1024,68 @@ where
             }
         }
 
+        Self::from_db_buf(params, db_buf_aligned, is_simplepir, pad_rows)
+    }
+
+    /// Builds a server around an already transposed database buffer, laid out
+    /// as described by `db_layout`.
+    pub fn from_db_buf(
+        params: &'a Params,
+        db_buf_aligned: AlignedMemory64,
+        is_simplepir: bool,
+        pad_rows: bool,
+    ) -> Self {
+        Self::from_storage(params, DbBuf::Owned(db_buf_aligned), is_simplepir, pad_rows)
+    }
+
+    /// Builds a server over a transposed database it doesn't own, laid out as
+    /// described by `db_layout`, without copying it: e.g. an mmap loaded once
+    /// by a prefork parent and shared copy-on-write with its children.
+    ///
+    /// `db` must be `DB_ALIGNMENT`-aligned. Writes through the server
+    /// (`set_elem`, `update_item`, ...) first copy the database into a buffer
+    /// of its own, leaving `db` untouched.
+    pub fn from_shared(
+        params: &'a Params,
+        db: &'static [u8],
+        is_simplepir: bool,
+        pad_rows: bool,
+    ) -> Self {
+        assert_eq!(
+            db.as_ptr() as usize % DB_ALIGNMENT,
+            0,
+            "shared database must be {}-byte aligned",
+            DB_ALIGNMENT
+        );
+        assert_eq!(db.len() % std::mem::size_of::<u64>(), 0);
+        // SAFETY: aligned and sized for u64 as checked above, and u64 has no
+        // invalid bit patterns
+        let words = unsafe {
+            std::slice::from_raw_parts(
+                db.as_ptr() as *const u64,
+                db.len() / std::mem::size_of::<u64>(),
+            )
+        };
+        Self::from_storage(params, DbBuf::Shared(words), is_simplepir, pad_rows)
+    }
+
+    /// Whether the database is still the memory passed to `from_shared`,
+    /// i.e. no write has copied it yet.
+    pub fn db_is_shared(&self) -> bool {
+        matches!(self.db_buf_aligned, DbBuf::Shared(_))
+    }
+
+    fn from_storage(
+        params: &'a Params,
+        db_buf_aligned: DbBuf,
+        is_simplepir: bool,
+        pad_rows: bool,
+    ) -> Self {
+        let mut ypir_params = YPIRParams::default();
+        ypir_params.is_simplepir = is_simplepir;
+        let layout = db_layout(params, is_simplepir, pad_rows, std::mem::size_of::<T>());
+        assert_eq!(db_buf_aligned.len() * 8, layout.total_bytes());
+
         // Parameters for the second round (the "DoublePIR" round)
         let smaller_params = if is_simplepir {
             params.clone()
@@ -263,9 +1113,38 @@ where
             phantom: PhantomData,
             pad_rows,
             ypir_params,
+            seeds: PublicSeeds::default(),
+            reducer: CrtReducer::new(params),
         }
     }
 
+    /// Uses `seeds` for the public randomness of hints (must match the
+    /// clients' table, see `YClient::with_public_seeds`).
+    pub fn set_public_seeds(&mut self, seeds: PublicSeeds) {
+        self.seeds = seeds;
+    }
+
+    pub fn public_seeds(&self) -> &PublicSeeds {
+        &self.seeds
+    }
+
+    pub fn pad_rows(&self) -> bool {
+        self.pad_rows
+    }
+
+    /// A server over `db` (transposed if `inp_transposed`, as for `new`) with
+    /// this one's params, scheme, row padding and public seeds, so it can
+    /// replace this one without clients noticing anything but the data.
+    pub fn rebuilt<I>(&self, db: I, inp_transposed: bool) -> Self
+    where
+        I: Iterator<Item = T>,
+    {
+        let is_simplepir = self.ypir_params.is_simplepir;
+        let mut s = Self::new(self.params, db, is_simplepir, inp_transposed, self.pad_rows);
+        s.set_public_seeds(self.seeds.clone());
+        s
+    }
+
     pub fn db_rows_padded(&self) -> usize {
         if self.pad_rows {
             self.params.db_rows_padded()
@@ -282,6 +1161,43 @@ where
         }
     }
 
+    pub fn layout(&self) -> DbLayout {
+        db_layout(
+            self.params,
+            self.ypir_params.is_simplepir,
+            self.pad_rows,
+            std::mem::size_of::<T>(),
+        )
+    }
+
+    /// Reads the whole (transposed) database once, one word per cache line,
+    /// to fault in its pages before the first query. Returns the number of
+    /// bytes covered.
+    pub fn warmup(&self) -> usize {
+        let words = self.db_buf_aligned.as_slice();
+        let step = DB_ALIGNMENT / std::mem::size_of::<u64>();
+        let mut acc = 0u64;
+        for i in (0..words.len()).step_by(step) {
+            acc ^= unsafe { std::ptr::read_volatile(words.as_ptr().add(i)) };
+        }
+        std::hint::black_box(acc);
+        words.len() * std::mem::size_of::<u64>()
+    }
+
+    /// Pins the database buffer in physical memory (`mlock`/`VirtualLock`) so
+    /// it is never paged out. Fails if the OS refuses, e.g. when the buffer
+    /// exceeds `RLIMIT_MEMLOCK`. The lock is released when the buffer is
+    /// freed; clones of the server are not locked.
+    pub fn lock_memory(&self) -> std::io::Result<()> {
+        let words = self.db_buf_aligned.as_slice();
+        lock_pages(words.as_ptr() as *const u8, std::mem::size_of_val(words))
+    }
+
+    pub fn unlock_memory(&self) -> std::io::Result<()> {
+        let words = self.db_buf_aligned.as_slice();
+        unlock_pages(words.as_ptr() as *const u8, std::mem::size_of_val(words))
+    }
+
     pub fn multiply_batched_with_db_packed<const K: usize>(
         &self,
         aligned_query_packed: &[u64],
@@ -295,14 +1211,13 @@ where
 
         let now = Instant::now();
         let mut result = AlignedMemory64::new(K * db_cols);
-        fast_batched_dot_product_avx512::<K, _>(
+        fast_batched_dot_product_with_reducer::<K, _>(
+            PreparedKernel::new(active_kernel(), &self.reducer),
             self.params,
             result.as_mut_slice(),
             aligned_query_packed,
             db_rows_padded,
-            &self.db(),
-            db_rows_padded,
-            db_cols,
+            TransposedDb::new(&self.db(), db_rows_padded, db_cols),
         );
         debug!("Fast dot product in {} us", now.elapsed().as_micros());
 
@@ -425,7 +1340,8 @@ where
     pub fn generate_pseudorandom_query(&self, public_seed_idx: u8) -> Vec<PolyMatrixNTT<'a>> {
         let mut client = Client::init(&self.params);
         client.generate_secret_keys();
-        let y_client = YClient::new(&mut client, &self.params);
+        let y_client =
+            YClient::new(&mut client, &self.params).with_public_seeds(self.seeds.clone());
         let query = y_client.generate_query_impl(public_seed_idx, self.params.db_dim_1, true, 0);
         let query_mapped = query
             .iter()
@@ -465,7 +1381,7 @@ where
         let _db_rows = 1 << (self.params.db_dim_1 + self.params.poly_len_log2);
         let db_cols = self.db_cols();
 
-        let mut rng_pub = ChaCha20Rng::from_seed(get_seed(SEED_0));
+        let mut rng_pub = ChaCha20Rng::from_seed(self.seeds.get(SEED_0));
         let lwe_params = LWEParams::default();
 
         // pseudorandom LWE query is n x db_rows
@@ -498,7 +1414,7 @@ where
 
         let convd_len = conv.params().crt_count * conv.params().poly_len;
 
-        let mut rng_pub = ChaCha20Rng::from_seed(get_seed(SEED_0));
+        let mut rng_pub = ChaCha20Rng::from_seed(self.seeds.get(SEED_0));
 
         let mut v_nega_perm_a = Vec::new();
         for _ in 0..db_rows / n {
@@ -562,10 +1478,202 @@ where
         hint_0
     }
 
+    /// Checks that `answer_query` would accept `aligned_query_packed`, without
+    /// computing anything.
+    pub fn check_query(&self, aligned_query_packed: &[u64]) -> Result<(), QueryError> {
+        let expected = self.db_rows_padded();
+        if aligned_query_packed.len() != expected {
+            return Err(QueryError::WrongLength {
+                len: aligned_query_packed.len(),
+                expected,
+            });
+        }
+        Ok(())
+    }
+
     pub fn answer_query(&self, aligned_query_packed: &[u64]) -> AlignedMemory64 {
         self.multiply_batched_with_db_packed::<1>(aligned_query_packed, 1)
     }
 
+    /// `answer_query`, computed `CANCEL_CHECK_COLS` columns at a time,
+    /// stopping with `Cancelled` at the first block boundary after `cancel`
+    /// is set. The database is column-major, so each block reads its own
+    /// contiguous slice and the blocks add up to the same answer.
+    pub fn answer_query_cancellable(
+        &self,
+        aligned_query_packed: &[u64],
+        cancel: &CancelToken,
+    ) -> Result<AlignedMemory64, Cancelled> {
+        self.answer_blocks_until_cancelled(aligned_query_packed, cancel, |_| {})
+    }
+
+    /// `answer_query_cancellable`, calling `after_block` with the index of
+    /// each block once it is computed.
+    fn answer_blocks_until_cancelled(
+        &self,
+        aligned_query_packed: &[u64],
+        cancel: &CancelToken,
+        mut after_block: impl FnMut(usize),
+    ) -> Result<AlignedMemory64, Cancelled> {
+        let db_rows_padded = self.db_rows_padded();
+        assert_eq!(aligned_query_packed.len(), db_rows_padded);
+
+        let db = self.db();
+        let mut result = AlignedMemory64::new(self.db_cols());
+        let blocks = result.as_mut_slice().chunks_mut(CANCEL_CHECK_COLS);
+        for (block, out) in blocks.enumerate() {
+            if cancel.is_cancelled() {
+                return Err(Cancelled);
+            }
+            let cols = block * CANCEL_CHECK_COLS..block * CANCEL_CHECK_COLS + out.len();
+            answer_col_range(
+                &self.reducer,
+                self.params,
+                out,
+                aligned_query_packed,
+                db,
+                db_rows_padded,
+                cols,
+            );
+            after_block(block);
+        }
+        Ok(result)
+    }
+
+    /// The words of `answer_query(aligned_query_packed)` for the database
+    /// columns in `cols` only, in that order, computed without reading the
+    /// other columns; for a client that only decodes part of the row.
+    pub fn answer_columns(&self, aligned_query_packed: &[u64], cols: &[usize]) -> Vec<u64> {
+        let db_rows_padded = self.db_rows_padded();
+        assert_eq!(aligned_query_packed.len(), db_rows_padded);
+
+        let mut result = vec![0u64; cols.len()];
+        let contiguous = cols.windows(2).all(|w| w[1] == w[0] + 1);
+        match cols.first() {
+            // a run of columns is one slice of the database, read densely
+            Some(&start) if contiguous => answer_col_range(
+                &self.reducer,
+                self.params,
+                &mut result,
+                aligned_query_packed,
+                self.db(),
+                db_rows_padded,
+                start..start + cols.len(),
+            ),
+            _ => fast_dot_product_columns(
+                PreparedKernel::new(active_kernel(), &self.reducer),
+                self.params,
+                &mut result,
+                aligned_query_packed,
+                TransposedDb::new(self.db(), db_rows_padded, self.db_cols()),
+                cols,
+            ),
+        }
+        result
+    }
+
+    /// The answer for a preview of the first `top_bytes` bytes of every
+    /// `item_size`-byte item in the queried row: `answer_columns` over
+    /// `preview_columns`, about `top_bytes / item_size` of the full answer's
+    /// work. `YClient::decode_preview` reads an item's bytes back out.
+    pub fn answer_preview(
+        &self,
+        aligned_query_packed: &[u64],
+        item_size: usize,
+        top_bytes: usize,
+    ) -> Result<Vec<u64>, PreviewError> {
+        self.check_query(aligned_query_packed)
+            .map_err(PreviewError::Query)?;
+        let cols = preview_columns(self.db_cols(), item_size, top_bytes)?;
+        Ok(self.answer_columns(aligned_query_packed, &cols))
+    }
+
+    /// `answer_query` over only the rows of `tenant`, given one tag per
+    /// database row (`row_tenants`); see `restrict_query_to_tenant`.
+    ///
+    /// The caller is trusted to have authenticated the client as `tenant`.
+    /// A query for another tenant's row decodes to zeros.
+    pub fn answer_query_for_tenant(
+        &self,
+        aligned_query_packed: &[u64],
+        row_tenants: &[Option<u32>],
+        tenant: u32,
+    ) -> Result<AlignedMemory64, TenantError> {
+        self.check_query(aligned_query_packed)
+            .map_err(TenantError::Query)?;
+        let (db_rows, _) = crate::db::db_dims(self.params, self.ypir_params.is_simplepir);
+        if row_tenants.len() != db_rows {
+            return Err(TenantError::WrongTagCount {
+                len: row_tenants.len(),
+                expected: db_rows,
+            });
+        }
+        let mut masked = aligned_query_packed.to_vec();
+        restrict_query_to_tenant(&mut masked, row_tenants, tenant);
+        Ok(self.answer_query(&masked))
+    }
+
+    /// Numeric mode: answers with the exact integer dot product of `query`
+    /// (one unpacked coefficient per padded row, not a `pack_query` output)
+    /// and each database column, skipping the modular reduction, so the
+    /// server acts as a plain matrix-vector product.
+    ///
+    /// Exact only while every column's sum stays below 2^64 (see
+    /// `numeric_dot_product`); it is the caller's job to keep coefficients
+    /// and database values small enough.
+    pub fn answer_query_numeric(&self, query: &[u64]) -> AlignedMemory64 {
+        let db_rows_padded = self.db_rows_padded();
+        let db_cols = self.db_cols();
+        assert_eq!(query.len(), db_rows_padded);
+
+        let mut result = AlignedMemory64::new(db_cols);
+        numeric_dot_product(
+            result.as_mut_slice(),
+            query,
+            self.db(),
+            db_rows_padded,
+            db_cols,
+        );
+        result
+    }
+
+    /// Answers one packed query against several servers with the same params
+    /// and layout (e.g. two versions of a database) in a single kernel pass;
+    /// each response equals that server's `answer_query`.
+    pub fn answer_query_multi(
+        servers: &[&Self],
+        aligned_query_packed: &[u64],
+    ) -> Result<Vec<AlignedMemory64>, QueryError> {
+        let Some(first) = servers.first() else {
+            return Ok(Vec::new());
+        };
+        first.check_query(aligned_query_packed)?;
+        if let Some(server) = servers.iter().position(|s| s.layout() != first.layout()) {
+            return Err(QueryError::LayoutMismatch { server });
+        }
+
+        let (db_rows_padded, db_cols) = (first.db_rows_padded(), first.db_cols());
+        let mut results = servers
+            .iter()
+            .map(|_| AlignedMemory64::new(db_cols))
+            .collect::<Vec<_>>();
+        let mut cs = results
+            .iter_mut()
+            .map(|r| r.as_mut_slice())
+            .collect::<Vec<_>>();
+        let dbs = servers.iter().map(|s| s.db()).collect::<Vec<_>>();
+        fast_dot_product_multi_db(
+            first.params,
+            &mut cs,
+            aligned_query_packed,
+            db_rows_padded,
+            &dbs,
+            db_rows_padded,
+            db_cols,
+        );
+        Ok(results)
+    }
+
     pub fn answer_batched_queries<const K: usize>(
         &self,
         aligned_queries_packed: &[u64],
@@ -573,6 +1681,45 @@ where
         self.multiply_batched_with_db_packed::<K>(aligned_queries_packed, 1)
     }
 
+    /// `answer_batched_queries` for `k` concatenated packed queries, with `k`
+    /// known only at runtime. Only K = 1, 2, 4 and 8 are instantiated, each
+    /// fully unrolled; other batch sizes are split into those (7 runs as
+    /// 4 + 2 + 1). Response `i` is words `i * db_cols..(i + 1) * db_cols`.
+    pub fn answer_batch(
+        &self,
+        aligned_queries_packed: &[u64],
+        k: usize,
+    ) -> Result<AlignedMemory64, QueryError> {
+        if k == 0 {
+            return Err(QueryError::EmptyBatch);
+        }
+        let rows = self.db_rows_padded();
+        if aligned_queries_packed.len() != k * rows {
+            return Err(QueryError::WrongLength {
+                len: aligned_queries_packed.len(),
+                expected: k * rows,
+            });
+        }
+
+        let db_cols = self.db_cols();
+        let mut result = AlignedMemory64::new(k * db_cols);
+        let mut done = 0;
+        while done < k {
+            let chunk = [8, 4, 2, 1].into_iter().find(|&c| c <= k - done).unwrap();
+            let queries = &aligned_queries_packed[done * rows..(done + chunk) * rows];
+            let answers = match chunk {
+                8 => self.answer_batched_queries::<8>(queries),
+                4 => self.answer_batched_queries::<4>(queries),
+                2 => self.answer_batched_queries::<2>(queries),
+                _ => self.answer_batched_queries::<1>(queries),
+            };
+            result.as_mut_slice()[done * db_cols..(done + chunk) * db_cols]
+                .copy_from_slice(answers.as_slice());
+            done += chunk;
+        }
+        Ok(result)
+    }
+
     pub fn perform_offline_precomputation_simplepir(
         &self,
         measurement: Option<&mut Measurement>,
@@ -725,13 +1872,14 @@ where
         debug!("Done splitting intermediate cts.");
 
         // This is the 'intermediate' db after the first pass of PIR and expansion
-        let smaller_server: YServer<u16> = YServer::<u16>::new(
+        let mut smaller_server: YServer<u16> = YServer::<u16>::new(
             &self.smaller_params,
             smaller_db.into_iter(),
             false,
             true,
             false,
         );
+        smaller_server.set_public_seeds(self.seeds.clone());
         debug!("gen'd smaller server.");
 
         let hint_1 = smaller_server.answer_hint_ring(
@@ -809,14 +1957,13 @@ where
         let first_pass = Instant::now();
         debug!("Performing mul...");
         let mut intermediate = AlignedMemory64::new(db_cols);
-        fast_batched_dot_product_avx512::<1, T>(
+        fast_batched_dot_product_with_reducer::<1, T>(
+            PreparedKernel::new(active_kernel(), &self.reducer),
             &params,
             intermediate.as_mut_slice(),
             first_dim_queries_packed,
             db_rows,
-            self.db(),
-            db_rows,
-            db_cols,
+            TransposedDb::new(self.db(), db_rows, db_cols),
         );
         debug!("Done w mul...");
         let first_pass_time_ms = first_pass.elapsed().as_millis();
@@ -856,6 +2003,61 @@ where
         first_dim_queries_packed: &[u32],
         second_dim_queries: &[(&[u64], &[PolyMatrixNTT<'a>])],
         mut measurement: Option<&mut Measurement>,
+    ) -> Vec<Vec<Vec<u8>>> {
+        let online_phase = Instant::now();
+        let intermediate =
+            self.perform_online_computation_first_pass::<K>(first_dim_queries_packed, measurement.as_deref_mut());
+        let responses = self.perform_online_computation_second_pass(
+            offline_vals,
+            &intermediate,
+            second_dim_queries,
+            measurement,
+        );
+        debug!(
+            "Total online time: {} us",
+            online_phase.elapsed().as_micros()
+        );
+        debug!("");
+
+        responses
+    }
+
+    /// The first (SimplePIR) pass of the online phase: returns the K
+    /// intermediate LWE ciphertext rows, concatenated.
+    ///
+    /// The result depends only on the first-dimension queries, so it can be
+    /// reused across second passes for different columns of the same row.
+    pub fn perform_online_computation_first_pass<const K: usize>(
+        &self,
+        first_dim_queries_packed: &[u32],
+        measurement: Option<&mut Measurement>,
+    ) -> Vec<u32> {
+        let lwe_params = LWEParams::default();
+        let lwe_q_prime_bits = lwe_params.q2_bits as usize;
+
+        let first_pass = Instant::now();
+        let intermediate = self.lwe_multiply_batched_with_db_packed::<K>(first_dim_queries_packed);
+        let simplepir_resp_bytes = intermediate.len() / K * (lwe_q_prime_bits as usize) / 8;
+        debug!("simplepir_resp_bytes {} bytes", simplepir_resp_bytes);
+        let first_pass_time_ms = first_pass.elapsed().as_millis();
+        debug!("First pass took {} us", first_pass.elapsed().as_micros());
+
+        if let Some(m) = measurement {
+            m.online.first_pass_time_ms = first_pass_time_ms as usize;
+            m.online.simplepir_resp_bytes = simplepir_resp_bytes;
+        }
+
+        intermediate
+    }
+
+    /// The second (DoublePIR) pass of the online phase, over the output of
+    /// `perform_online_computation_first_pass`.
+    pub fn perform_online_computation_second_pass(
+        &self,
+        offline_vals: &mut OfflinePrecomputedValues<'a>,
+        intermediate: &[u32],
+        second_dim_queries: &[(&[u64], &[PolyMatrixNTT<'a>])],
+        mut measurement: Option<&mut Measurement>,
     ) -> Vec<Vec<Vec<u8>>> {
         // Set up some parameters
 
@@ -910,25 +2112,11 @@ where
 
         // Begin online computation
 
-        let online_phase = Instant::now();
-        let first_pass = Instant::now();
-        let intermediate = self.lwe_multiply_batched_with_db_packed::<K>(first_dim_queries_packed);
-        let simplepir_resp_bytes = intermediate.len() / K * (lwe_q_prime_bits as usize) / 8;
-        debug!("simplepir_resp_bytes {} bytes", simplepir_resp_bytes);
-        let first_pass_time_ms = first_pass.elapsed().as_millis();
-        debug!("First pass took {} us", first_pass.elapsed().as_micros());
-
-        if let Some(ref mut m) = measurement {
-            m.online.first_pass_time_ms = first_pass_time_ms as usize;
-            m.online.simplepir_resp_bytes = simplepir_resp_bytes;
-        }
-
         debug!("intermediate.len(): {}", intermediate.len());
         let mut second_pass_time_ms = 0;
         let mut ring_packing_time_ms = 0;
         let mut responses = Vec::new();
         for (intermediate_chunk, (packed_query_col, pack_pub_params_row_1s)) in intermediate
-            .as_slice()
             .chunks(db_cols)
             .zip(second_dim_queries.iter())
         {
@@ -1086,11 +2274,6 @@ where
 
             responses.push(packed_mod_switched);
         }
-        debug!(
-            "Total online time: {} us",
-            online_phase.elapsed().as_micros()
-        );
-        debug!("");
 
         if let Some(ref mut m) = measurement {
             m.online.second_pass_time_ms = second_pass_time_ms as usize;
@@ -1111,10 +2294,11 @@ where
     }
 
     pub fn db_mut(&mut self) -> &mut [T] {
+        let words = self.db_buf_aligned.to_mut();
         unsafe {
             std::slice::from_raw_parts_mut(
-                self.db_buf_aligned.as_ptr() as *mut T,
-                self.db_buf_aligned.len() * 8 / std::mem::size_of::<T>(),
+                words.as_ptr() as *mut T,
+                words.len() * 8 / std::mem::size_of::<T>(),
             )
         }
     }
@@ -1155,6 +2339,77 @@ where
         // }
         // res_u8
     }
+
+    /// Reads item `index` back out of the transposed layout (the inverse of
+    /// `update_item`). A plaintext read for operators, not a private one.
+    pub fn get_item(&self, index: usize, item_size: usize) -> Vec<T> {
+        let db_rows = 1 << (self.params.db_dim_1 + self.params.poly_len_log2);
+        let db_cols = self.db_cols();
+        assert!(
+            (index + 1) * item_size <= db_rows * db_cols,
+            "item {} out of range",
+            index
+        );
+
+        (index * item_size..(index + 1) * item_size)
+            .map(|offset| self.get_elem(offset / db_cols, offset % db_cols))
+            .collect()
+    }
+
+    pub fn set_elem(&mut self, row: usize, col: usize, val: T) {
+        let db_rows_padded = self.db_rows_padded();
+        self.db_mut()[col * db_rows_padded + row] = val; // stored transposed
+    }
+
+    /// Overwrites item `index` (elements `[index * item_size, (index + 1) *
+    /// item_size)` of the row-major database), zero-padding `item`.
+    ///
+    /// Offline precomputed values derived from the old contents are stale
+    /// afterwards and must be recomputed.
+    pub fn update_item(&mut self, index: usize, item_size: usize, item: &[T]) {
+        let db_rows = 1 << (self.params.db_dim_1 + self.params.poly_len_log2);
+        let db_cols = self.db_cols();
+        assert!(item.len() <= item_size, "item larger than item_size");
+        assert!(
+            (index + 1) * item_size <= db_rows * db_cols,
+            "item {} out of range",
+            index
+        );
+
+        for k in 0..item_size {
+            let offset = index * item_size + k;
+            let val = item.get(k).copied().unwrap_or_default();
+            self.set_elem(offset / db_cols, offset % db_cols, val);
+        }
+    }
+
+    /// Overwrites the contiguous items `start_index..start_index +
+    /// items.len()`, zero-padding each, in one pass over the database; the
+    /// bulk form of `update_item`, with the same caveat about offline values.
+    pub fn update_range(&mut self, start_index: usize, item_size: usize, items: &[&[T]]) {
+        let db_rows = 1 << (self.params.db_dim_1 + self.params.poly_len_log2);
+        let db_cols = self.db_cols();
+        let db_rows_padded = self.db_rows_padded();
+        assert!(
+            items.iter().all(|item| item.len() <= item_size),
+            "item larger than item_size"
+        );
+        assert!(
+            (start_index + items.len()) * item_size <= db_rows * db_cols,
+            "items {}..{} out of range",
+            start_index,
+            start_index + items.len()
+        );
+
+        let db = self.db_mut();
+        for (i, item) in items.iter().enumerate() {
+            for k in 0..item_size {
+                let offset = (start_index + i) * item_size + k;
+                let (row, col) = (offset / db_cols, offset % db_cols);
+                db[col * db_rows_padded + row] = item.get(k).copied().unwrap_or_default();
+            }
+        }
+    }
 }
 
 #[cfg(not(target_feature = "avx2"))]
@@ -1198,21 +2453,21 @@ mod m512_impl {
     impl ToM512 for *const u8 {
         #[inline(always)]
         fn to_m512(self) -> __m512i {
-            self as __m512i
+            unsafe { *self as __m512i }
         }
     }
 
     impl ToM512 for *const u16 {
         #[inline(always)]
         fn to_m512(self) -> __m512i {
-            self as __m512i
+            unsafe { *self as __m512i }
         }
     }
 
     impl ToM512 for *const u32 {
         #[inline(always)]
         fn to_m512(self) -> __m512i {
-            self as __m512i
+            unsafe { *self as __m512i }
         }
     }
 }
@@ -1244,3 +2499,997 @@ impl ToU64 for u64 {
         self
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn layout_pattern(row: usize, col: usize) -> u8 {
+        ((row * 7 + col * 13) % 256) as u8
+    }
+
+    #[test]
+    fn test_db_layout_blob_accepted() {
+        let params = test_params();
+        let layout = db_layout(&params, false, true, std::mem::size_of::<u8>());
+
+        // build a blob externally, following only the layout description
+        let mut blob = vec![0u8; layout.total_bytes()];
+        for col in 0..layout.db_cols {
+            for row in 0..layout.db_rows {
+                blob[layout.offset(row, col)] = layout_pattern(row, col);
+            }
+        }
+
+        let server = YServer::<u8>::new(&params, blob.iter().copied(), false, true, true);
+        assert_eq!(server.layout(), layout);
+        assert_eq!(server.db().len(), layout.total_bytes());
+        assert_eq!(server.db().as_ptr() as usize % layout.alignment, 0);
+
+        for &(row, col) in &[(0, 0), (1, 0), (0, 1), (17, 1023), (2047, 2047)] {
+            assert_eq!(server.get_elem(row, col), layout_pattern(row, col));
+        }
+    }
+    #[test]
+    fn test_server_builder_column_blocks() {
+        let params = test_params();
+        let layout = db_layout(&params, false, true, 1);
+        let blob = (0..layout.total_bytes())
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let expected = YServer::<u8>::new(&params, blob.iter().copied(), false, true, true);
+
+        let mut builder = YServerBuilder::<u8>::new(&params, false, true);
+        let col_len = layout.db_rows_padded;
+        assert_eq!(
+            builder.add_column_block(&blob[..col_len + 1]),
+            Err(ServerBuildError::PartialColumns {
+                block_len: col_len + 1,
+                col_len
+            })
+        );
+        // uneven blocks of whole columns
+        let mut start = 0;
+        for cols in [1, 100, 3].into_iter().cycle() {
+            let end = (start + cols * col_len).min(blob.len());
+            builder.add_column_block(&blob[start..end]).unwrap();
+            start = end;
+            if start == blob.len() {
+                break;
+            }
+        }
+        assert_eq!(
+            builder.add_column_block(&blob[..col_len]),
+            Err(ServerBuildError::Overflow {
+                provided: blob.len() + col_len,
+                expected: blob.len()
+            })
+        );
+        let server = builder.finish().unwrap();
+        assert_eq!(server.db(), expected.db());
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        assert_eq!(
+            server.answer_query(packed.as_slice()).as_slice(),
+            expected.answer_query(packed.as_slice()).as_slice()
+        );
+
+        let incomplete = YServerBuilder::<u8>::new(&params, false, true);
+        assert!(matches!(
+            incomplete.finish(),
+            Err(ServerBuildError::Incomplete { provided: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_expansion_ratio() {
+        let params = test_params();
+        let server = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, false)).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let response = server.answer_query(pack_query(&params, &query).as_slice());
+        let response_bytes = response.as_slice().len() * 8;
+        assert_eq!(response_size_bytes(&params, false), response_bytes);
+
+        let mut last = f64::INFINITY;
+        for item_size in [1, 64, 1000, 2048] {
+            let ratio = expansion_ratio(&params, false, item_size);
+            assert_eq!(ratio, response_bytes as f64 / item_size as f64);
+            assert!(ratio < last);
+            last = ratio;
+        }
+    }
+
+    #[test]
+    fn test_answer_columns() {
+        let params = test_params();
+        let server = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, false)).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let full = server.answer_query(packed.as_slice());
+
+        let cols = [7, 3, 1000, 1001, 1002, 1003, 2047];
+        let expected = cols.iter().map(|&j| full[j]).collect::<Vec<_>>();
+        assert_eq!(server.answer_columns(packed.as_slice(), &cols), expected);
+
+        // a contiguous run takes the dense path
+        let run = (1000..1300).collect::<Vec<_>>();
+        assert_eq!(
+            server.answer_columns(packed.as_slice(), &run),
+            &full.as_slice()[1000..1300]
+        );
+    }
+
+    #[test]
+    fn test_answer_query_cancellable() {
+        let params = test_params();
+        let server = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, false)).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+
+        let full = server.answer_query(packed.as_slice());
+        let cancel = CancelToken::new();
+        assert_eq!(
+            server
+                .answer_query_cancellable(packed.as_slice(), &cancel)
+                .unwrap()
+                .as_slice(),
+            full.as_slice()
+        );
+
+        // set during block `k` (here by the same thread, as another would),
+        // the token is seen at the next block boundary
+        let num_blocks = server.db_cols().div_ceil(CANCEL_CHECK_COLS);
+        assert!(num_blocks > 2);
+        for k in [0, num_blocks / 2, num_blocks - 2] {
+            let cancel = CancelToken::new();
+            let mut blocks_done = 0;
+            let result =
+                server.answer_blocks_until_cancelled(packed.as_slice(), &cancel, |block| {
+                    blocks_done = block + 1;
+                    if block == k {
+                        cancel.clone().cancel();
+                    }
+                });
+            assert_eq!(result.err(), Some(Cancelled));
+            assert_eq!(blocks_done, k + 1);
+        }
+        assert_eq!(Cancelled.to_string(), "cancelled");
+
+        // a token set beforehand stops the answer before any work
+        cancel.cancel();
+        assert_eq!(
+            server
+                .answer_query_cancellable(packed.as_slice(), &cancel)
+                .err(),
+            Some(Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_answer_preview_matches_item_prefix() {
+        use crate::db::{db_capacity, db_dims, logical_to_physical};
+        use crate::testing::{
+            expected_item, fixture_client, fixture_db, fixture_server, plaintext_query,
+        };
+
+        let item_size = 64;
+        let top_bytes = 5;
+        for is_simplepir in [false, true] {
+            let params = if is_simplepir {
+                // several instances, so a row is wider than one poly_len block
+                params_for_scenario_simplepir(1 << 11, 3 * 2048 * 14)
+            } else {
+                test_params()
+            };
+            let (_, db_cols) = db_dims(&params, is_simplepir);
+            let num_items = db_capacity(&params, is_simplepir, item_size);
+            let db = fixture_db(&params, is_simplepir, item_size, num_items, 0).unwrap();
+            let server = fixture_server(&params, is_simplepir, &db);
+            let mut client = fixture_client(&params);
+            let y_client = YClient::new(&mut client, &params);
+
+            for index in [0, 1, 31, 32, 1000] {
+                let row = logical_to_physical(&params, is_simplepir, item_size, index).unwrap();
+                let packed = plaintext_query(&params, server.db_rows_padded(), row);
+
+                let preview = server
+                    .answer_preview(packed.as_slice(), item_size, top_bytes)
+                    .unwrap();
+                assert_eq!(preview.len(), db_cols / item_size * top_bytes);
+                let full = server.answer_query(packed.as_slice());
+                let cols = preview_columns(db_cols, item_size, top_bytes).unwrap();
+                assert_eq!(preview, cols.iter().map(|&j| full[j]).collect::<Vec<_>>());
+
+                let item =
+                    y_client.decode_preview(&preview, is_simplepir, item_size, top_bytes, index);
+                let start = index * item_size % db_cols;
+                let (full_item, _) =
+                    y_client.decode_response_range(full.as_slice(), start..start + item_size);
+                assert_eq!(item, &full_item[..top_bytes]);
+                let item = item.iter().map(|&x| x as u8).collect::<Vec<_>>();
+                assert_eq!(item, &expected_item(index, item_size)[..top_bytes]);
+            }
+        }
+
+        let (_, db_cols) = db_dims(&test_params(), false);
+        assert_eq!(
+            preview_columns(db_cols, 100, 5),
+            Err(PreviewError::ItemsStraddleRows {
+                item_size: 100,
+                db_cols
+            })
+        );
+        assert_eq!(
+            preview_columns(db_cols, item_size, item_size + 1),
+            Err(PreviewError::TopBytes {
+                top_bytes: item_size + 1,
+                item_size
+            })
+        );
+    }
+
+    #[test]
+    fn test_packed_response_roundtrip() {
+        use crate::testing::{
+            expected_item, fetch_item_with, fixture_client, fixture_db, fixture_server,
+        };
+
+        let params = test_params();
+        let (item_size, num_items) = (64, 1000);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        let rows = server.db_rows_padded();
+        for index in [0, 100, num_items - 1] {
+            let item = fetch_item_with(&params, false, &y_client, rows, item_size, index, |q| {
+                let response = server.answer_query(q);
+                let packed = pack_response(&params, response.as_slice());
+                assert_eq!(packed.len(), packed_response_size_bytes(&params, false));
+                assert!(packed.len() < response_size_bytes(&params, false));
+                assert_eq!(unpack_response(&params, false, &packed[1..]), None);
+                let unpacked = unpack_response(&params, false, &packed).unwrap();
+                assert_eq!(unpacked, response.as_slice());
+                unpacked
+            });
+            assert_eq!(item, expected_item(index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let params = test_params();
+        let memory = ServerMemory::new(&params, false, true, 1, 1);
+        let needed = memory.total();
+        assert_eq!(
+            memory.check_limit(needed - 1),
+            Err(ServerBuildError::MemoryLimit {
+                needed,
+                limit: needed - 1
+            })
+        );
+        assert_eq!(memory.check_limit(needed), Ok(()));
+
+        // the estimate covers what the server actually keeps
+        let db = vec![0u8; crate::db::db_num_bytes(&params, false)];
+        let server = YServer::<u8>::new(&params, db.into_iter(), false, false, true);
+        assert!(server.db().len() <= memory.db_bytes);
+    }
+
+    #[test]
+    #[ignore] // the resident set is shared with concurrently running tests
+    fn test_build_peak_resident() {
+        use crate::measurement::peak_resident_growth;
+
+        let params = params_for_scenario(1 << 30, 1);
+        let num_bytes = crate::db::db_num_bytes(&params, false);
+        let db = (0..num_bytes).map(|_| fastrand::u8(..)).collect::<Vec<_>>();
+        let (server, peak) = peak_resident_growth(|| {
+            YServer::<u8>::new(&params, db.iter().copied(), false, false, true)
+        });
+        let Some(peak) = peak else {
+            return;
+        };
+        // the input is resident before the build, so only the server's copy counts
+        assert!(peak >= server.db().len() / 2, "peak {} bytes", peak);
+        assert!(peak < 2 * num_bytes, "peak {} bytes", peak);
+    }
+
+    #[test]
+    fn test_single_row_database() {
+        use crate::db::db_dims;
+        use crate::testing::{
+            expected_item, fixture_client, fixture_db, fixture_server, plaintext_query,
+        };
+
+        let item_size = 64;
+        for is_simplepir in [false, true] {
+            // the smallest geometry either mode has: one poly_len x poly_len block
+            let params = if is_simplepir {
+                params_for_scenario_simplepir(1, item_size * 8)
+            } else {
+                params_for_scenario(1, item_size * 8)
+            };
+            validate_params(&params, is_simplepir).unwrap();
+            let (db_rows, db_cols) = db_dims(&params, is_simplepir);
+            assert_eq!((db_rows, db_cols), (params.poly_len, params.poly_len));
+
+            // every item in the first row, the rest of the database empty
+            let num_items = db_cols / item_size;
+            let db = fixture_db(&params, is_simplepir, item_size, num_items, 0).unwrap();
+            let server = fixture_server(&params, is_simplepir, &db);
+            assert_eq!(server.db_rows_padded(), params.poly_len);
+
+            let mut client = fixture_client(&params);
+            let y_client = YClient::new(&mut client, &params);
+            // noiseless query, so the decode is exact
+            let query = plaintext_query(&params, server.db_rows_padded(), 0);
+            let response = server.answer_query(query.as_slice());
+            let decoded = y_client.decode_response(response.as_slice());
+            assert_eq!(decoded.len(), db_cols);
+            let expected = (0..num_items)
+                .flat_map(|i| expected_item(i, item_size))
+                .collect::<Vec<_>>();
+            assert!(decoded.iter().zip(&expected).all(|(&x, &e)| x == e as u64));
+        }
+    }
+
+    #[test]
+    fn test_get_item() {
+        use crate::db::{build_db, db_capacity};
+        use crate::testing::{fixture_item, fixture_server};
+
+        let params = test_params();
+        let item_size = 1000;
+        let capacity = db_capacity(&params, false, item_size);
+        // fixture items cut short, so get_item has padding to return
+        let items = (0..capacity)
+            .map(|i| fixture_item(1, i, fastrand::usize(1..=item_size)))
+            .collect::<Vec<_>>();
+        let db = build_db(
+            &params,
+            false,
+            item_size,
+            items.iter().map(|x| x.as_slice()),
+        )
+        .unwrap();
+        let server = fixture_server(&params, false, &db);
+
+        for index in [0, 1, 2, capacity / 2, capacity - 1] {
+            let stored = server.get_item(index, item_size);
+            assert_eq!(&stored[..items[index].len()], items[index].as_slice());
+            assert!(stored[items[index].len()..].iter().all(|&x| x == 0));
+        }
+    }
+
+    #[test]
+    fn test_update_item() {
+        let params = test_params();
+        let item_size = 1000; // items straddle rows
+        let row_major = (0..crate::db::db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let mut server =
+            YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+
+        let item = [1u8, 2, 3, 4];
+        server.update_item(2, item_size, &item);
+
+        let mut expected = row_major.clone();
+        expected[2 * item_size..3 * item_size].fill(0);
+        expected[2 * item_size..2 * item_size + item.len()].copy_from_slice(&item);
+        assert_eq!(
+            server.db(),
+            crate::db::transpose_db(&params, false, &expected).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_answer_batch_sizes() {
+        use crate::testing::{fixture_db, fixture_server};
+
+        let params = test_params();
+        let row_major = fixture_db(&params, false, 64, 1000, 0).unwrap();
+        let server = fixture_server(&params, false, &row_major);
+        let (rows, db_cols) = (server.db_rows_padded(), server.db_cols());
+
+        let queries = (0..9)
+            .map(|_| {
+                let query = (0..rows)
+                    .map(|_| fastrand::u64(0..params.modulus))
+                    .collect::<Vec<_>>();
+                pack_query(&params, &query)
+            })
+            .collect::<Vec<_>>();
+        let singles = queries
+            .iter()
+            .map(|q| server.answer_query(q.as_slice()))
+            .collect::<Vec<_>>();
+
+        // the instantiated sizes and sizes that are split into them
+        for k in 1..=9 {
+            let batch = queries[..k]
+                .iter()
+                .flat_map(|q| q.as_slice().iter().copied())
+                .collect::<Vec<_>>();
+            let answers = server.answer_batch(&batch, k).unwrap();
+            for (i, single) in singles[..k].iter().enumerate() {
+                assert_eq!(
+                    &answers.as_slice()[i * db_cols..(i + 1) * db_cols],
+                    single.as_slice(),
+                    "query {} of batch {}",
+                    i,
+                    k
+                );
+            }
+        }
+
+        assert_eq!(
+            server.answer_batch(&[], 0).err(),
+            Some(QueryError::EmptyBatch)
+        );
+        assert_eq!(
+            server.answer_batch(queries[0].as_slice(), 2).err(),
+            Some(QueryError::WrongLength {
+                len: rows,
+                expected: 2 * rows
+            })
+        );
+    }
+
+    #[test]
+    fn test_answer_query_numeric_exact() {
+        let params = test_params();
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let row_major = (0..db_rows * db_cols)
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let server = YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+
+        // larger than the modulus once summed, but far from 2^64
+        let query = (0..db_rows)
+            .map(|_| fastrand::u64(..1 << 40))
+            .collect::<Vec<_>>();
+        let response = server.answer_query_numeric(&query);
+
+        let expected = (0..db_cols)
+            .map(|col| {
+                (0..db_rows)
+                    .map(|row| query[row] * row_major[row * db_cols + col] as u64)
+                    .sum::<u64>()
+            })
+            .collect::<Vec<_>>();
+        assert!(expected.iter().any(|&x| x >= params.modulus));
+        assert_eq!(response.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_update_range() {
+        use crate::db::db_capacity;
+        use crate::testing::{
+            expected_item, fetch_item, fixture_client, fixture_db, fixture_server,
+        };
+
+        let params = test_params();
+        let item_size = 16;
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let num_items = db_capacity(&params, false, item_size);
+        let row_major = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let mut server = fixture_server(&params, false, &row_major);
+
+        // a run crossing a row boundary, with one short item
+        let start_index = db_cols / item_size - 2;
+        let items = (0..5)
+            .map(|i| vec![0xa0 + i as u8; if i == 3 { 5 } else { item_size }])
+            .collect::<Vec<_>>();
+        let item_refs = items.iter().map(|x| x.as_slice()).collect::<Vec<_>>();
+        server.update_range(start_index, item_size, &item_refs);
+
+        let mut expected = row_major.clone();
+        for (i, item) in items.iter().enumerate() {
+            let start = (start_index + i) * item_size;
+            expected[start..start + item_size].fill(0);
+            expected[start..start + item.len()].copy_from_slice(item);
+        }
+        assert_eq!(
+            server.db(),
+            crate::db::transpose_db(&params, false, &expected).as_slice()
+        );
+
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        // the items either side of the run are untouched
+        for index in (start_index - 1)..=(start_index + 5) {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            let want = match index.checked_sub(start_index).and_then(|i| items.get(i)) {
+                Some(written) => {
+                    let mut want = written.clone();
+                    want.resize(item_size, 0);
+                    want
+                }
+                None => expected_item(index, item_size),
+            };
+            assert_eq!(item, want);
+        }
+    }
+
+    #[test]
+    fn test_rebuilt_server_replaces_data_only() {
+        use crate::testing::{
+            fetch_item, fixture_client, fixture_db, fixture_item, fixture_server,
+        };
+
+        let params = test_params();
+        let (item_size, num_items) = (64, 1000);
+        let old_db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let new_db = fixture_db(&params, false, item_size, num_items, 1).unwrap();
+        let mut server = fixture_server(&params, false, &old_db);
+        server.set_public_seeds(PublicSeeds::default().with_seed(SEED_0, [7; 32]));
+
+        let rebuilt = server.rebuilt(new_db.iter().copied(), false);
+        assert_eq!(rebuilt.public_seeds(), server.public_seeds());
+        assert_eq!(rebuilt.pad_rows(), server.pad_rows());
+        assert_eq!(rebuilt.layout(), server.layout());
+        assert_eq!(rebuilt.db(), fixture_server(&params, false, &new_db).db());
+        // the same database given already transposed
+        let from_transposed = server.rebuilt(rebuilt.db().iter().copied(), true);
+        assert_eq!(from_transposed.db(), rebuilt.db());
+
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        for index in [0, 1, num_items - 1] {
+            let old = fetch_item(&params, false, &server, &y_client, item_size, index);
+            let new = fetch_item(&params, false, &rebuilt, &y_client, item_size, index);
+            assert_eq!(old, fixture_item(0, index, item_size));
+            assert_eq!(new, fixture_item(1, index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_query_logical_index_with_padding() {
+        use crate::db::db_capacity;
+        use crate::testing::{
+            expected_item, fetch_item, fixture_client, fixture_db, fixture_server,
+        };
+
+        let params = test_params();
+        let item_size = 16;
+        let capacity = db_capacity(&params, false, item_size);
+        let db = fixture_db(&params, false, item_size, capacity, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        for index in [0, 1, 200, capacity - 1] {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            assert_eq!(item, expected_item(index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_sparse_db_query() {
+        use crate::db::{db_capacity, db_num_bytes, write_db_item};
+        use crate::testing::{fetch_item, fixture_client, fixture_server};
+
+        let params = test_params();
+        let item_size = 64;
+        let capacity = db_capacity(&params, false, item_size);
+        let written = [3, 1000, capacity - 1];
+        let item_for = |index: usize| vec![(index % 251) as u8 + 1; item_size];
+
+        let mut db = vec![0u8; db_num_bytes(&params, false)];
+        for &index in &written {
+            write_db_item(&params, false, item_size, &mut db, index, &item_for(index)).unwrap();
+        }
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        for index in [3, 4, 1000, 5000, capacity - 1] {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            if written.contains(&index) {
+                assert_eq!(item, item_for(index));
+            } else {
+                assert!(item.iter().all(|&x| x == 0), "item {} not empty", index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_varlen_items_roundtrip() {
+        use crate::db::build_db_varlen;
+        use crate::testing::{fixture_client, fixture_item, fixture_server, plaintext_query};
+
+        let params = test_params();
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let items = (0..500)
+            .map(|i| fixture_item(0, i, [0, 1, 37, 700, db_cols][i % 5]))
+            .collect::<Vec<_>>();
+        let (db, manifest) =
+            build_db_varlen(&params, false, items.iter().map(|x| x.as_slice())).unwrap();
+        assert_eq!(manifest.len(), items.len());
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        for index in [0, 1, 2, 3, 4, 123, 499] {
+            let query = plaintext_query(&params, server.db_rows_padded(), manifest.row(index));
+            let response = server.answer_query(query.as_slice());
+            let (coeffs, _) =
+                y_client.decode_response_range(response.as_slice(), manifest.cols(index));
+            let item = coeffs.iter().map(|&x| x as u8).collect::<Vec<_>>();
+            assert_eq!(item, items[index]);
+        }
+    }
+
+    #[test]
+    fn test_check_query() {
+        use crate::testing::{fixture_db, fixture_server};
+
+        let params = test_params();
+        let db = fixture_db(&params, false, 64, 1000, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+
+        let query = vec![0u64; server.db_rows_padded()];
+        let packed = pack_query(&params, &query);
+        assert_eq!(server.check_query(packed.as_slice()), Ok(()));
+
+        let short = &packed.as_slice()[..packed.len() - 1];
+        assert_eq!(
+            server.check_query(short),
+            Err(QueryError::WrongLength {
+                len: short.len(),
+                expected: server.db_rows_padded()
+            })
+        );
+    }
+
+    #[test]
+    fn test_warmup() {
+        let params = test_params();
+        let num_bytes = crate::db::db_num_bytes(&params, false);
+        let server = YServer::<u8>::new(
+            &params,
+            (0..num_bytes).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let before = server.answer_query(packed.as_slice());
+
+        assert_eq!(server.warmup(), num_bytes);
+        assert_eq!(
+            server.answer_query(packed.as_slice()).as_slice(),
+            before.as_slice()
+        );
+    }
+
+    #[test]
+    fn test_multi_tenant_server() {
+        let params = test_params();
+        let num_bytes = crate::db::db_num_bytes(&params, false);
+        let dbs = (0..3)
+            .map(|_| (0..num_bytes).map(|_| fastrand::u8(..)).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let multi = MultiTenantServer::new(
+            &params,
+            false,
+            &dbs.iter().map(|x| x.as_slice()).collect::<Vec<_>>(),
+        )
+        .unwrap();
+        assert_eq!(multi.tenants(), 3);
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        for (tenant, db) in dbs.iter().enumerate() {
+            let single = YServer::<u8>::new(&params, db.iter().copied(), false, false, true);
+            assert_eq!(
+                multi.answer_query(tenant, packed.as_slice()).unwrap().as_slice(),
+                single.answer_query(packed.as_slice()).as_slice()
+            );
+        }
+
+        assert!(matches!(
+            multi.answer_query(3, packed.as_slice()),
+            Err(TenantError::UnknownTenant {
+                tenant: 3,
+                tenants: 3
+            })
+        ));
+        assert!(matches!(
+            MultiTenantServer::new(&params, false, &[&dbs[0][1..]]),
+            Err(TenantError::WrongSize { tenant: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_answer_query_for_tenant() {
+        use crate::db::{row_tenants, BuildDbError};
+        use crate::testing::{
+            expected_item, fetch_item_with, fixture_client, fixture_db, fixture_server,
+        };
+
+        let params = test_params();
+        let (db_rows, db_cols) = crate::db::db_dims(&params, false);
+        // two items per row, rows alternating between tenants 0 and 1
+        let item_size = db_cols / 2;
+        let num_items = 2 * db_rows;
+        let item_tenants = (0..num_items as u32).map(|i| i / 2 % 2).collect::<Vec<_>>();
+        let tags = row_tenants(&params, false, item_size, &item_tenants).unwrap();
+        assert_eq!(&tags[..3], &[Some(0), Some(1), Some(0)]);
+
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        // noiseless queries, so the decode is exact
+        let rows = server.db_rows_padded();
+        for (index, tenant, visible) in [(1, 0, true), (3, 0, false), (3, 1, true)] {
+            let item = fetch_item_with(&params, false, &y_client, rows, item_size, index, |q| {
+                let response = server.answer_query_for_tenant(q, &tags, tenant).unwrap();
+                response.as_slice().to_vec()
+            });
+            if visible {
+                assert_eq!(item, expected_item(index, item_size));
+            } else {
+                assert_eq!(item, vec![0; item_size]);
+            }
+        }
+
+        let packed = pack_query(&params, &vec![0u64; params.db_rows_padded()]);
+        assert_eq!(
+            server
+                .answer_query_for_tenant(packed.as_slice(), &tags[1..], 0)
+                .err(),
+            Some(TenantError::WrongTagCount {
+                len: db_rows - 1,
+                expected: db_rows
+            })
+        );
+        assert_eq!(
+            row_tenants(&params, false, item_size, &[0, 1]),
+            Err(BuildDbError::MixedTenantRow { row: 0 })
+        );
+    }
+
+    #[test]
+    fn test_answer_by_instance() {
+        let params = params_for_scenario_simplepir(1 << 11, 3 * 2048 * 14);
+        assert_eq!(params.instances, 3);
+        let server = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, true)).map(|_| fastrand::u8(..)),
+            true,
+            false,
+            true,
+        );
+
+        // one file per instance, each that instance's slice of the columns
+        let dir = std::env::temp_dir();
+        let paths = (0..params.instances)
+            .map(|i| dir.join(format!("ypir_instance_{}_{}", i, std::process::id())))
+            .collect::<Vec<_>>();
+        for (path, db) in paths
+            .iter()
+            .zip(server.db().chunks_exact(instance_db_bytes(&params)))
+        {
+            std::fs::write(path, db).unwrap();
+        }
+        let dbs = paths
+            .iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect::<Vec<_>>();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+        let dbs = dbs.iter().map(|db| db.as_slice()).collect::<Vec<_>>();
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        assert_eq!(
+            answer_by_instance(&params, packed.as_slice(), &dbs)
+                .unwrap()
+                .as_slice(),
+            server.answer_query(packed.as_slice()).as_slice()
+        );
+
+        assert!(matches!(
+            answer_by_instance(&params, packed.as_slice(), &dbs[..2]),
+            Err(InstanceError::Count {
+                found: 2,
+                expected: 3
+            })
+        ));
+        let short = [dbs[0], dbs[1], &dbs[2][1..]];
+        assert!(matches!(
+            answer_by_instance(&params, packed.as_slice(), &short),
+            Err(InstanceError::WrongSize { instance: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_lock_memory() {
+        let params = test_params();
+        let num_bytes = crate::db::db_num_bytes(&params, false);
+        let server = YServer::<u8>::new(
+            &params,
+            (0..num_bytes).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let before = server.answer_query(packed.as_slice());
+
+        // best effort: a low RLIMIT_MEMLOCK (or no privilege) forbids this
+        if let Err(e) = server.lock_memory() {
+            eprintln!("skipping test_lock_memory: {}", e);
+            return;
+        }
+        assert_eq!(
+            server.answer_query(packed.as_slice()).as_slice(),
+            before.as_slice()
+        );
+        server.unlock_memory().unwrap();
+    }
+
+    #[test]
+    fn test_server_from_shared() {
+        let params = test_params();
+        let owned = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, false)).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+
+        // stands in for a mapping made before forking
+        let mut mem = AlignedMemory64::new(owned.db().len() / 8);
+        as_bytes_mut(&mut mem).copy_from_slice(owned.db());
+        let region = as_bytes(Box::leak(Box::new(mem)));
+        let mut shared = YServer::<u8>::from_shared(&params, region, false, true);
+        assert!(shared.db_is_shared());
+        assert_eq!(shared.db().as_ptr(), region.as_ptr());
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        assert_eq!(
+            shared.answer_query(packed.as_slice()).as_slice(),
+            owned.answer_query(packed.as_slice()).as_slice()
+        );
+
+        // a write copies instead of touching the shared region
+        let old = shared.get_elem(3, 5);
+        shared.set_elem(3, 5, old.wrapping_add(1));
+        assert!(!shared.db_is_shared());
+        assert_eq!(shared.get_elem(3, 5), old.wrapping_add(1));
+        assert_eq!(region, owned.db());
+    }
+
+    #[test]
+    fn test_answer_query_multi() {
+        let params = test_params();
+        let num_bytes = crate::db::db_num_bytes(&params, false);
+        let servers = (0..2)
+            .map(|_| {
+                YServer::<u8>::new(
+                    &params,
+                    (0..num_bytes).map(|_| fastrand::u8(..)),
+                    false,
+                    false,
+                    true,
+                )
+            })
+            .collect::<Vec<_>>();
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+
+        let refs = servers.iter().collect::<Vec<_>>();
+        let responses = YServer::answer_query_multi(&refs, packed.as_slice()).unwrap();
+        assert_eq!(responses.len(), 2);
+        for (server, resp) in servers.iter().zip(&responses) {
+            assert_eq!(
+                resp.as_slice(),
+                server.answer_query(packed.as_slice()).as_slice()
+            );
+        }
+
+        assert!(YServer::<u8>::answer_query_multi(&[], packed.as_slice())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_db_stored_transposed() {
+        let params = test_params();
+        let row_major = (0..crate::db::db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let server = YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+        assert_eq!(
+            server.db(),
+            crate::db::transpose_db(&params, false, &row_major).as_slice()
+        );
+    }
+
+    #[test]
+    fn test_answer_query_big_endian_roundtrip() {
+        let params = test_params();
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let server = YServer::<u8>::new(
+            &params,
+            (0..db_rows * db_cols).map(|i| layout_pattern(i / db_cols, i % db_cols)),
+            false,
+            false,
+            true,
+        );
+        let mut client = Client::init(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        // a plaintext selection vector is enough to exercise the wire format
+        let target_row = 123;
+        let delta = params.modulus / params.pt_modulus;
+        let mut query = vec![0u64; params.db_rows_padded()];
+        query[target_row] = delta;
+        let packed = pack_query(&params, &query);
+
+        let query_bytes = u64s_to_bytes(packed.as_slice(), Endianness::Big);
+        let query_words = bytes_to_u64s(&query_bytes, Endianness::Big).unwrap();
+        let response = server.answer_query(&query_words);
+        let response_bytes = u64s_to_bytes(response.as_slice(), Endianness::Big);
+        let response_words = bytes_to_u64s(&response_bytes, Endianness::Big).unwrap();
+        let decoded = y_client.decode_response(&response_words);
+
+        assert_eq!(decoded.len(), db_cols);
+        for col in 0..db_cols {
+            assert_eq!(decoded[col], layout_pattern(target_row, col) as u64);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::ops::Range;
+
+use spiral_rs::aligned_memory::AlignedMemory64;
+use spiral_rs::params::Params;
+
+use crate::db::{db_dims, logical_to_physical};
+use crate::kernel::fast_batched_dot_product_avx512;
+use crate::server::DbRowsPadded;
+use crate::transpose::transpose;
+
+/// Rows held by `shard` when the database's `total_rows` rows are split
+/// across `num_shards` shards in contiguous, near-equal runs.
+///
+/// The answer to a query is a sum over rows, so a shard given its rows of the
+/// database and the matching slice of the query (`split_query`) computes a
+/// partial answer, and `combine_answers` adds the partial answers back up.
+pub fn shard_rows(total_rows: usize, num_shards: usize, shard: usize) -> Range<usize> {
+    assert!(num_shards > 0 && shard < num_shards);
+    total_rows * shard / num_shards..total_rows * (shard + 1) / num_shards
+}
+
+/// Shard holding item `index`, or `None` past the last item.
+///
+/// Only the rows of that shard matter for the item, but querying that shard
+/// alone reveals it; send every shard its slice of the query to keep the
+/// index private.
+pub fn shard_for_index(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    num_shards: usize,
+    index: usize,
+) -> Option<usize> {
+    let row = logical_to_physical(params, is_simplepir, item_size, index)?;
+    let total_rows = params.db_rows_padded();
+    (0..num_shards).find(|&shard| shard_rows(total_rows, num_shards, shard).contains(&row))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShardError {
+    /// `num_shards` is 0 or exceeds the number of rows.
+    ShardCount {
+        num_shards: usize,
+        rows: usize,
+    },
+    UnknownShard {
+        shard: usize,
+        num_shards: usize,
+    },
+    /// A shard's database is not `rows * db_cols` bytes long.
+    WrongDbSize {
+        len: usize,
+        expected: usize,
+    },
+    /// A query slice doesn't have one word per shard row.
+    WrongQueryLength {
+        len: usize,
+        expected: usize,
+    },
+    /// Answers to combine must all have `db_cols` words.
+    WrongAnswerLength {
+        len: usize,
+        expected: usize,
+    },
+    NoAnswers,
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShardError::ShardCount { num_shards, rows } => {
+                write!(f, "cannot split {} rows into {} shards", rows, num_shards)
+            }
+            ShardError::UnknownShard { shard, num_shards } => {
+                write!(f, "shard {} out of range for {} shards", shard, num_shards)
+            }
+            ShardError::WrongDbSize { len, expected } => {
+                write!(f, "shard database is {} bytes, expected {}", len, expected)
+            }
+            ShardError::WrongQueryLength { len, expected } => {
+                write!(f, "shard query has {} words, expected {}", len, expected)
+            }
+            ShardError::WrongAnswerLength { len, expected } => {
+                write!(f, "answer has {} words, expected {}", len, expected)
+            }
+            ShardError::NoAnswers => write!(f, "no answers to combine"),
+        }
+    }
+}
+
+impl std::error::Error for ShardError {}
+
+fn check_shard_count(num_shards: usize, rows: usize) -> Result<(), ShardError> {
+    if num_shards == 0 || num_shards > rows {
+        return Err(ShardError::ShardCount { num_shards, rows });
+    }
+    Ok(())
+}
+
+/// Splits a packed query (one word per database row) into each shard's
+/// slice, in shard order.
+pub fn split_query(packed_query: &[u64], num_shards: usize) -> Result<Vec<&[u64]>, ShardError> {
+    check_shard_count(num_shards, packed_query.len())?;
+    Ok((0..num_shards)
+        .map(|shard| &packed_query[shard_rows(packed_query.len(), num_shards, shard)])
+        .collect())
+}
+
+/// Adds up shards' partial answers into the answer the unsharded server
+/// would have given.
+pub fn combine_answers(params: &Params, answers: &[&[u64]]) -> Result<Vec<u64>, ShardError> {
+    let Some(first) = answers.first() else {
+        return Err(ShardError::NoAnswers);
+    };
+    let mut out = vec![0u64; first.len()];
+    for answer in answers {
+        if answer.len() != out.len() {
+            return Err(ShardError::WrongAnswerLength {
+                len: answer.len(),
+                expected: out.len(),
+            });
+        }
+        for (acc, &x) in out.iter_mut().zip(answer.iter()) {
+            *acc = ((*acc as u128 + x as u128) % params.modulus as u128) as u64;
+        }
+    }
+    Ok(out)
+}
+
+/// One shard of a u8 database: its run of rows (`shard_rows`), stored
+/// transposed, answering its slice of the query.
+pub struct ShardServer<'a> {
+    params: &'a Params,
+    rows: Range<usize>,
+    db_cols: usize,
+    db_t: Vec<u8>,
+}
+
+impl<'a> ShardServer<'a> {
+    /// `shard_db` holds the shard's rows of the row-major database, i.e.
+    /// bytes `rows.start * db_cols..rows.end * db_cols` of it.
+    pub fn new(
+        params: &'a Params,
+        is_simplepir: bool,
+        num_shards: usize,
+        shard: usize,
+        shard_db: &[u8],
+    ) -> Result<Self, ShardError> {
+        let total_rows = params.db_rows_padded();
+        check_shard_count(num_shards, total_rows)?;
+        if shard >= num_shards {
+            return Err(ShardError::UnknownShard { shard, num_shards });
+        }
+        let (_, db_cols) = db_dims(params, is_simplepir);
+        let rows = shard_rows(total_rows, num_shards, shard);
+        let expected = rows.len() * db_cols;
+        if shard_db.len() != expected {
+            return Err(ShardError::WrongDbSize {
+                len: shard_db.len(),
+                expected,
+            });
+        }
+        Ok(Self {
+            params,
+            db_t: transpose(shard_db, rows.len(), db_cols, 1),
+            rows,
+            db_cols,
+        })
+    }
+
+    pub fn rows(&self) -> Range<usize> {
+        self.rows.clone()
+    }
+
+    /// Partial answer to this shard's slice of a packed query.
+    pub fn answer_query(&self, query_slice: &[u64]) -> Result<AlignedMemory64, ShardError> {
+        let rows = self.rows.len();
+        if query_slice.len() != rows {
+            return Err(ShardError::WrongQueryLength {
+                len: query_slice.len(),
+                expected: rows,
+            });
+        }
+        let mut result = AlignedMemory64::new(self.db_cols);
+        fast_batched_dot_product_avx512::<1, u8>(
+            self.params,
+            result.as_mut_slice(),
+            query_slice,
+            rows,
+            &self.db_t,
+            rows,
+            self.db_cols,
+        );
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::YClient;
+    use crate::db::db_capacity;
+    use crate::testing::{
+        expected_item, fixture_client, fixture_db, fixture_server, plaintext_query,
+    };
+    use crate::util::test_params;
+
+    #[test]
+    fn test_sharded_answer_matches_item() {
+        let params = test_params();
+        let (db_rows, db_cols) = db_dims(&params, false);
+        let item_size = 64;
+        let num_items = db_capacity(&params, false, item_size);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+
+        let num_shards = 2;
+        let shards = (0..num_shards)
+            .map(|shard| {
+                let rows = shard_rows(db_rows, num_shards, shard);
+                let bytes = &db[rows.start * db_cols..rows.end * db_cols];
+                ShardServer::new(&params, false, num_shards, shard, bytes).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        // one item in each shard
+        for (index, shard) in [(3, 0), (num_items - 1, 1)] {
+            assert_eq!(
+                shard_for_index(&params, false, item_size, num_shards, index),
+                Some(shard)
+            );
+            let row = logical_to_physical(&params, false, item_size, index).unwrap();
+            assert!(shards[shard].rows().contains(&row));
+
+            // noiseless query, so the decode is exact
+            let packed = plaintext_query(&params, params.db_rows_padded(), row);
+
+            let slices = split_query(packed.as_slice(), num_shards).unwrap();
+            let answers = shards
+                .iter()
+                .zip(&slices)
+                .map(|(s, q)| s.answer_query(q).unwrap())
+                .collect::<Vec<_>>();
+            let combined = combine_answers(
+                &params,
+                &answers.iter().map(|a| a.as_slice()).collect::<Vec<_>>(),
+            )
+            .unwrap();
+            assert_eq!(combined, server.answer_query(packed.as_slice()).as_slice());
+
+            let start = index * item_size % db_cols;
+            let (coeffs, _) = y_client.decode_response_range(&combined, start..start + item_size);
+            let item = coeffs.iter().map(|&x| x as u8).collect::<Vec<_>>();
+            assert_eq!(item, expected_item(index, item_size));
+        }
+
+        assert_eq!(
+            split_query(&[0u64; 4], 5),
+            Err(ShardError::ShardCount {
+                num_shards: 5,
+                rows: 4
+            })
+        );
+    }
+}
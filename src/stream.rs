@@ -0,0 +1,1188 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::mpsc::{channel, sync_channel};
+
+#[cfg(feature = "aes-ctr")]
+use aes::Aes256;
+#[cfg(feature = "aes-ctr")]
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use fs2::FileExt;
+use sha2::{Digest, Sha256};
+use spiral_rs::aligned_memory::AlignedMemory64;
+use spiral_rs::params::Params;
+
+use crate::bits::{bytes_to_u64s, u64s_to_bytes, Endianness};
+use crate::db::{db_capacity, db_dims};
+use crate::kernel::fast_batched_dot_product_avx512;
+use crate::server::{db_layout, DbRowsPadded, ToM512, ToU64, YServer};
+
+/// Largest frame `read_frame` will accept, to bound allocations on bad input.
+pub const MAX_FRAME_BYTES: u64 = 1 << 32;
+
+/// Length of `query_digest`.
+pub const QUERY_DIGEST_BYTES: usize = 16;
+
+/// Short hash of a packed query, for correlating requests in logs without
+/// keeping the query. Queries are randomized encryptions, so the digest says
+/// nothing about the index and two queries for the same index differ.
+pub fn query_digest(aligned_query_packed: &[u64]) -> [u8; QUERY_DIGEST_BYTES] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ypir-query-v1");
+    for word in aligned_query_packed {
+        hasher.update(word.to_le_bytes());
+    }
+    let mut out = [0u8; QUERY_DIGEST_BYTES];
+    out.copy_from_slice(&hasher.finalize()[..QUERY_DIGEST_BYTES]);
+    out
+}
+
+/// Words `tag_response` appends to a response.
+pub const RESPONSE_TAG_WORDS: usize = QUERY_DIGEST_BYTES / 8;
+
+fn response_tag(aligned_query_packed: &[u64], response: &[u64]) -> [u64; RESPONSE_TAG_WORDS] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"ypir-response-v1");
+    hasher.update(query_digest(aligned_query_packed));
+    hasher.update((response.len() as u64).to_le_bytes());
+    for word in response {
+        hasher.update(word.to_le_bytes());
+    }
+    let hash = hasher.finalize();
+    let mut out = [0u64; RESPONSE_TAG_WORDS];
+    for (word, chunk) in out.iter_mut().zip(hash.chunks_exact(8)) {
+        *word = u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    out
+}
+
+/// Appends a tag tying `response` to the query it answers, so a client
+/// behind a relay can check with `verify_response_for_query` that it got the
+/// answer to its own query and not one meant for another.
+///
+/// The tag is an unkeyed hash: anyone holding the query can forge it. It
+/// catches crossed wires and replays, not a malicious relay.
+pub fn tag_response(aligned_query_packed: &[u64], response: &[u64]) -> Vec<u64> {
+    let mut tagged = Vec::with_capacity(response.len() + RESPONSE_TAG_WORDS);
+    tagged.extend_from_slice(response);
+    tagged.extend_from_slice(&response_tag(aligned_query_packed, response));
+    tagged
+}
+
+/// Whether `tagged_response` (from `tag_response`) answers the packed query
+/// `aligned_query_packed`.
+pub fn verify_response_for_query(aligned_query_packed: &[u64], tagged_response: &[u64]) -> bool {
+    let Some(split) = tagged_response.len().checked_sub(RESPONSE_TAG_WORDS) else {
+        return false;
+    };
+    let (response, tag) = tagged_response.split_at(split);
+    tag == response_tag(aligned_query_packed, response)
+}
+
+/// The response inside a tagged response, for decoding once verified.
+pub fn strip_response_tag(tagged_response: &[u64]) -> &[u64] {
+    &tagged_response[..tagged_response.len().saturating_sub(RESPONSE_TAG_WORDS)]
+}
+
+/// Reads a frame: a little-endian u64 byte length followed by that many bytes.
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut len_bytes = [0u8; 8];
+    r.read_exact(&mut len_bytes)?;
+    let len = u64::from_le_bytes(len_bytes);
+    if len > MAX_FRAME_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds limit of {}",
+                len, MAX_FRAME_BYTES
+            ),
+        ));
+    }
+    let mut buf = vec![0u8; len as usize];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Writes `data` as a frame (see `read_frame`).
+pub fn write_frame<W: Write>(w: &mut W, data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u64).to_le_bytes())?;
+    w.write_all(data)
+}
+
+/// Bytes of header at the start of every fragment made by `fragment`: the
+/// fragment's index and the total count, each a little-endian u32.
+pub const FRAGMENT_HEADER_BYTES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    /// `max_fragment` leaves no room for payload after the header.
+    FragmentTooSmall { max_fragment: usize },
+    /// A fragment is shorter than its header.
+    Truncated { len: usize },
+    /// Fragments disagree on how many there are, or there are more than that.
+    Inconsistent { total: usize, found: usize },
+    Duplicate { index: usize },
+    Missing { index: usize },
+}
+
+impl std::fmt::Display for FragmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FragmentError::FragmentTooSmall { max_fragment } => write!(
+                f,
+                "max_fragment of {} bytes leaves no room after the {}-byte header",
+                max_fragment, FRAGMENT_HEADER_BYTES
+            ),
+            FragmentError::Truncated { len } => {
+                write!(f, "fragment of {} bytes is shorter than its header", len)
+            }
+            FragmentError::Inconsistent { total, found } => write!(
+                f,
+                "fragments claim a total of {} but {} were given",
+                total, found
+            ),
+            FragmentError::Duplicate { index } => write!(f, "fragment {} given twice", index),
+            FragmentError::Missing { index } => write!(f, "fragment {} is missing", index),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+/// Splits `data` into fragments of at most `max_fragment` bytes, header
+/// included, for transports that cap message size. Empty `data` still makes
+/// one (header-only) fragment.
+pub fn fragment(data: &[u8], max_fragment: usize) -> Result<Vec<Vec<u8>>, FragmentError> {
+    if max_fragment <= FRAGMENT_HEADER_BYTES {
+        return Err(FragmentError::FragmentTooSmall { max_fragment });
+    }
+    let payload = max_fragment - FRAGMENT_HEADER_BYTES;
+    let total = data.len().div_ceil(payload).max(1);
+    Ok((0..total)
+        .map(|i| {
+            let chunk = &data[(i * payload).min(data.len())..((i + 1) * payload).min(data.len())];
+            let mut out = Vec::with_capacity(FRAGMENT_HEADER_BYTES + chunk.len());
+            out.extend_from_slice(&(i as u32).to_le_bytes());
+            out.extend_from_slice(&(total as u32).to_le_bytes());
+            out.extend_from_slice(chunk);
+            out
+        })
+        .collect())
+}
+
+/// Rebuilds the data split by `fragment`; fragments may arrive in any order.
+pub fn reassemble<F: AsRef<[u8]>>(fragments: &[F]) -> Result<Vec<u8>, FragmentError> {
+    if fragments.is_empty() {
+        return Err(FragmentError::Missing { index: 0 });
+    }
+    let mut parts: Vec<Option<&[u8]>> = Vec::new();
+    for frag in fragments {
+        let frag = frag.as_ref();
+        if frag.len() < FRAGMENT_HEADER_BYTES {
+            return Err(FragmentError::Truncated { len: frag.len() });
+        }
+        let index = u32::from_le_bytes(frag[..4].try_into().unwrap()) as usize;
+        let total = u32::from_le_bytes(frag[4..8].try_into().unwrap()) as usize;
+        if parts.is_empty() {
+            parts.resize(total, None);
+        }
+        if total != parts.len() || index >= total || fragments.len() > total {
+            return Err(FragmentError::Inconsistent {
+                total: parts.len(),
+                found: fragments.len(),
+            });
+        }
+        if parts[index].replace(&frag[FRAGMENT_HEADER_BYTES..]).is_some() {
+            return Err(FragmentError::Duplicate { index });
+        }
+    }
+    if let Some(index) = parts.iter().position(|p| p.is_none()) {
+        return Err(FragmentError::Missing { index });
+    }
+    Ok(parts.into_iter().flatten().flatten().copied().collect())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LengthPrefixError {
+    /// Only 4- and 8-byte prefixes are supported.
+    Width { width: usize },
+    /// The payload doesn't fit a 4-byte length.
+    TooLong { len: usize },
+    /// The blob is shorter than its prefix.
+    Truncated { len: usize, width: usize },
+    /// The prefix disagrees with the payload that follows it.
+    Mismatch { declared: u64, actual: usize },
+}
+
+impl std::fmt::Display for LengthPrefixError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LengthPrefixError::Width { width } => {
+                write!(f, "length prefix must be 4 or 8 bytes, not {}", width)
+            }
+            LengthPrefixError::TooLong { len } => {
+                write!(f, "{} bytes don't fit a 4-byte length prefix", len)
+            }
+            LengthPrefixError::Truncated { len, width } => write!(
+                f,
+                "blob of {} bytes is shorter than its {}-byte length prefix",
+                len, width
+            ),
+            LengthPrefixError::Mismatch { declared, actual } => write!(
+                f,
+                "length prefix says {} bytes, but {} follow",
+                declared, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LengthPrefixError {}
+
+/// `data` behind its length as a `width`-byte (4 or 8) big-endian integer,
+/// so a stream receiver can delimit it. Unlike `write_frame`, meant for
+/// peers that frame in network byte order.
+pub fn length_prefixed(data: &[u8], width: usize) -> Result<Vec<u8>, LengthPrefixError> {
+    let len = data.len() as u64;
+    let prefix = match width {
+        4 => u32::try_from(len)
+            .map_err(|_| LengthPrefixError::TooLong { len: data.len() })?
+            .to_be_bytes()
+            .to_vec(),
+        8 => len.to_be_bytes().to_vec(),
+        _ => return Err(LengthPrefixError::Width { width }),
+    };
+    let mut out = Vec::with_capacity(width + data.len());
+    out.extend_from_slice(&prefix);
+    out.extend_from_slice(data);
+    Ok(out)
+}
+
+/// The payload of a `length_prefixed` blob, checked against its prefix.
+pub fn strip_length_prefix(blob: &[u8], width: usize) -> Result<&[u8], LengthPrefixError> {
+    if width != 4 && width != 8 {
+        return Err(LengthPrefixError::Width { width });
+    }
+    if blob.len() < width {
+        return Err(LengthPrefixError::Truncated {
+            len: blob.len(),
+            width,
+        });
+    }
+    let (prefix, payload) = blob.split_at(width);
+    let declared = prefix.iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+    if declared != payload.len() as u64 {
+        return Err(LengthPrefixError::Mismatch {
+            declared,
+            actual: payload.len(),
+        });
+    }
+    Ok(payload)
+}
+
+const TRANSCRIPT_MAGIC: &[u8; 8] = b"ypirtx01";
+
+/// One query and its response, with the fingerprint of the params they were
+/// made under, for diagnosing a failed decode offline. Holds nothing secret:
+/// whoever replays it decodes with their own client, which must hold the
+/// keys the query was made with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub fingerprint: [u8; 32],
+    pub query: Vec<u8>,
+    pub response: Vec<u8>,
+}
+
+impl Transcript {
+    /// A magic tag, the fingerprint, then the query and the response as
+    /// frames (see `write_frame`).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // magic, fingerprint, two frame lengths
+        let header = TRANSCRIPT_MAGIC.len() + 32 + 16;
+        let mut out = Vec::with_capacity(header + self.query.len() + self.response.len());
+        out.extend_from_slice(TRANSCRIPT_MAGIC);
+        out.extend_from_slice(&self.fingerprint);
+        write_frame(&mut out, &self.query).unwrap();
+        write_frame(&mut out, &self.response).unwrap();
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let mut r = bytes;
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != TRANSCRIPT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a transcript",
+            ));
+        }
+        let mut fingerprint = [0u8; 32];
+        r.read_exact(&mut fingerprint)?;
+        let query = read_frame(&mut r)?;
+        let response = read_frame(&mut r)?;
+        if !r.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} trailing bytes after transcript", r.len()),
+            ));
+        }
+        Ok(Self {
+            fingerprint,
+            query,
+            response,
+        })
+    }
+}
+
+/// Reads one framed packed query from `r`, answers it, and writes the framed
+/// response to `w`. Words are serialized in `endianness` order.
+pub fn answer_stream<T, R, W>(
+    server: &YServer<'_, T>,
+    r: &mut R,
+    w: &mut W,
+    endianness: Endianness,
+) -> io::Result<()>
+where
+    T: Sized + Copy + ToU64 + Default,
+    *const T: ToM512,
+    R: Read,
+    W: Write,
+{
+    let query_bytes = read_frame(r)?;
+    let query = bytes_to_u64s(&query_bytes, endianness).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            "query length must be a multiple of 8",
+        )
+    })?;
+    let response: AlignedMemory64 = server.answer_query(&query);
+    write_frame(w, &u64s_to_bytes(response.as_slice(), endianness))?;
+    w.flush()
+}
+
+/// Runs `process` on each block produced by `read`, double-buffered: the
+/// next block is read on a background thread while the current one is
+/// processed, so the total time approaches max(io, compute).
+///
+/// `read` fills the buffer it's given and returns `Ok(false)` once there
+/// are no more blocks. A read error stops the loop and is returned.
+pub fn prefetch_blocks<R, P>(mut read: R, mut process: P) -> io::Result<()>
+where
+    R: FnMut(&mut Vec<u8>) -> io::Result<bool> + Send,
+    P: FnMut(&[u8]),
+{
+    // two buffers circulate: one being filled, one being processed
+    let (full_tx, full_rx) = sync_channel::<io::Result<Vec<u8>>>(1);
+    let (empty_tx, empty_rx) = channel::<Vec<u8>>();
+    empty_tx.send(Vec::new()).unwrap();
+    empty_tx.send(Vec::new()).unwrap();
+
+    std::thread::scope(move |s| {
+        s.spawn(move || {
+            for mut buf in empty_rx {
+                let res = read(&mut buf).map(|more| more.then_some(buf));
+                match res {
+                    Ok(Some(buf)) => {
+                        if full_tx.send(Ok(buf)).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => return,
+                    Err(e) => {
+                        let _ = full_tx.send(Err(e));
+                        return;
+                    }
+                }
+            }
+        });
+
+        // returning drops `empty_tx` and `full_rx`, which stops the reader
+        for block in full_rx.iter() {
+            let block = block?;
+            process(&block);
+            let _ = empty_tx.send(block);
+        }
+        Ok(())
+    })
+}
+
+/// Decrypts a database stored encrypted at rest as its blocks are read, so
+/// the plaintext only ever exists in memory. The implementation holds the
+/// key; `offset` is the byte position of `block` in the stored database, so
+/// blocks decrypt independently (as with a counter-mode cipher).
+pub trait DecryptingReader: Sync {
+    fn decrypt_block(&self, offset: u64, block: &mut [u8]);
+}
+
+/// AES-256 in CTR mode with a big-endian 128-bit counter starting at `nonce`.
+/// CTR is its own inverse, so `decrypt_block` over the whole plaintext
+/// database at offset 0 also produces the file to store.
+#[cfg(feature = "aes-ctr")]
+#[derive(Clone)]
+pub struct AesCtrReader {
+    key: [u8; 32],
+    nonce: [u8; 16],
+}
+
+#[cfg(feature = "aes-ctr")]
+impl AesCtrReader {
+    pub fn new(key: [u8; 32], nonce: [u8; 16]) -> Self {
+        Self { key, nonce }
+    }
+}
+
+#[cfg(feature = "aes-ctr")]
+impl DecryptingReader for AesCtrReader {
+    fn decrypt_block(&self, offset: u64, block: &mut [u8]) {
+        let mut cipher = ctr::Ctr128BE::<Aes256>::new(&self.key.into(), &self.nonce.into());
+        cipher.seek(offset);
+        cipher.apply_keystream(block);
+    }
+}
+
+/// Answers a packed query against a database read from `reader` instead of
+/// held in memory, `cols_per_block` columns at a time (see `prefetch_blocks`).
+///
+/// `reader` must supply the u8 database in the transposed layout the server
+/// stores with `pad_rows` (see `db_layout`). Equal to `YServer::answer_query`
+/// on the same database.
+pub fn answer_column_blocks<R: Read + Send>(
+    params: &Params,
+    is_simplepir: bool,
+    aligned_query_packed: &[u64],
+    reader: R,
+    cols_per_block: usize,
+) -> io::Result<AlignedMemory64> {
+    answer_blocks_impl(
+        params,
+        is_simplepir,
+        aligned_query_packed,
+        reader,
+        cols_per_block,
+        |_, _| {},
+    )
+}
+
+// Database files shared between processes are guarded by advisory locks:
+// `update_item_in_file` holds an exclusive lock while it writes an item, and
+// `answer_file` and `read_item_from_file` hold a shared lock for their whole
+// read. So a locking reader sees every item either entirely before or
+// entirely after any update, and two updates never interleave. The locks are
+// advisory: processes that read or write the file without them (e.g. through
+// an mmap handed to `YServer::from_shared`) are not covered, and a server
+// built from the file keeps the snapshot it loaded.
+
+/// `answer_column_blocks` over a database file (in the layout it requires),
+/// holding a shared lock on the file for the whole pass.
+pub fn answer_file(
+    params: &Params,
+    is_simplepir: bool,
+    aligned_query_packed: &[u64],
+    file: &File,
+    cols_per_block: usize,
+) -> io::Result<AlignedMemory64> {
+    FileExt::lock_shared(file)?;
+    let result = answer_column_blocks(
+        params,
+        is_simplepir,
+        aligned_query_packed,
+        io::BufReader::new(file),
+        cols_per_block,
+    );
+    FileExt::unlock(file)?;
+    result
+}
+
+/// File offset of each byte of item `index`, in a database file laid out as
+/// `answer_column_blocks` reads it.
+fn item_file_offsets(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    file: &File,
+    index: usize,
+) -> io::Result<Vec<u64>> {
+    let capacity = db_capacity(params, is_simplepir, item_size);
+    if index >= capacity {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "item index {} out of range for a database of {} items",
+                index, capacity
+            ),
+        ));
+    }
+    let layout = db_layout(params, is_simplepir, true, 1);
+    let len = file.metadata()?.len();
+    if len != layout.total_bytes() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "database file is {} bytes, expected {}",
+                len,
+                layout.total_bytes()
+            ),
+        ));
+    }
+    Ok((index * item_size..(index + 1) * item_size)
+        .map(|offset| layout.offset(offset / layout.db_cols, offset % layout.db_cols) as u64)
+        .collect())
+}
+
+/// Overwrites item `index` (zero-padded to `item_size`) in place in a
+/// database file read by `answer_file`, under an exclusive lock. The item's
+/// bytes are scattered one per column, so this suits occasional updates, not
+/// rebuilding the file.
+pub fn update_item_in_file(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    file: &File,
+    index: usize,
+    item: &[u8],
+) -> io::Result<()> {
+    if item.len() > item_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "item is {} bytes, larger than the item size of {} bytes",
+                item.len(),
+                item_size
+            ),
+        ));
+    }
+    let offsets = item_file_offsets(params, is_simplepir, item_size, file, index)?;
+    FileExt::lock_exclusive(file)?;
+    let mut writer = file;
+    let result = offsets.iter().enumerate().try_for_each(|(i, &offset)| {
+        writer.seek(SeekFrom::Start(offset))?;
+        writer.write_all(&[item.get(i).copied().unwrap_or(0)])
+    });
+    FileExt::unlock(file)?;
+    result
+}
+
+/// Item `index` of a database file, read under a shared lock.
+pub fn read_item_from_file(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    file: &File,
+    index: usize,
+) -> io::Result<Vec<u8>> {
+    let offsets = item_file_offsets(params, is_simplepir, item_size, file, index)?;
+    FileExt::lock_shared(file)?;
+    let mut reader = file;
+    let result = offsets
+        .iter()
+        .map(|&offset| {
+            let mut byte = [0u8; 1];
+            reader.seek(SeekFrom::Start(offset))?;
+            reader.read_exact(&mut byte)?;
+            Ok(byte[0])
+        })
+        .collect();
+    FileExt::unlock(file)?;
+    result
+}
+
+/// `answer_column_blocks` over an encrypted database: each block is
+/// decrypted with `decryptor` on the reading thread, before the kernel sees it.
+pub fn answer_encrypted_column_blocks<R: Read + Send, D: DecryptingReader>(
+    params: &Params,
+    is_simplepir: bool,
+    aligned_query_packed: &[u64],
+    reader: R,
+    decryptor: &D,
+    cols_per_block: usize,
+) -> io::Result<AlignedMemory64> {
+    answer_blocks_impl(
+        params,
+        is_simplepir,
+        aligned_query_packed,
+        reader,
+        cols_per_block,
+        |offset, block| decryptor.decrypt_block(offset, block),
+    )
+}
+
+/// Answers a packed query against a database that is never materialized:
+/// `provider(col)` returns transposed column `col` (`db_rows_padded` bytes,
+/// padding rows included) when the kernel gets to it, for databases computed
+/// on the fly. Columns are requested once each, in order. Equal to
+/// `YServer::answer_query` on the same database.
+pub fn answer_with_provider(
+    params: &Params,
+    is_simplepir: bool,
+    aligned_query_packed: &[u64],
+    mut provider: impl FnMut(usize) -> io::Result<Vec<u8>>,
+) -> io::Result<AlignedMemory64> {
+    let (_, db_cols) = db_dims(params, is_simplepir);
+    let db_rows_padded = params.db_rows_padded();
+    assert_eq!(aligned_query_packed.len(), db_rows_padded);
+
+    let mut result = AlignedMemory64::new(db_cols);
+    let out = result.as_mut_slice();
+    for col in 0..db_cols {
+        let column = provider(col)?;
+        if column.len() != db_rows_padded {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "column {} is {} bytes, expected {}",
+                    col,
+                    column.len(),
+                    db_rows_padded
+                ),
+            ));
+        }
+        fast_batched_dot_product_avx512::<1, u8>(
+            params,
+            &mut out[col..col + 1],
+            aligned_query_packed,
+            db_rows_padded,
+            &column,
+            db_rows_padded,
+            1,
+        );
+    }
+    Ok(result)
+}
+
+fn answer_blocks_impl<R: Read + Send>(
+    params: &Params,
+    is_simplepir: bool,
+    aligned_query_packed: &[u64],
+    mut reader: R,
+    cols_per_block: usize,
+    decrypt: impl Fn(u64, &mut [u8]) + Sync,
+) -> io::Result<AlignedMemory64> {
+    assert!(cols_per_block > 0);
+    let (_, db_cols) = db_dims(params, is_simplepir);
+    let db_rows_padded = params.db_rows_padded();
+    assert_eq!(aligned_query_packed.len(), db_rows_padded);
+
+    let mut result = AlignedMemory64::new(db_cols);
+    let out = result.as_mut_slice();
+
+    let mut cols_read = 0;
+    let mut cols_done = 0;
+    prefetch_blocks(
+        |buf| {
+            if cols_read == db_cols {
+                return Ok(false);
+            }
+            let cols = cols_per_block.min(db_cols - cols_read);
+            buf.resize(cols * db_rows_padded, 0);
+            reader.read_exact(buf)?;
+            decrypt((cols_read * db_rows_padded) as u64, buf);
+            cols_read += cols;
+            Ok(true)
+        },
+        |block| {
+            let cols = block.len() / db_rows_padded;
+            fast_batched_dot_product_avx512::<1, u8>(
+                params,
+                &mut out[cols_done..cols_done + cols],
+                aligned_query_packed,
+                db_rows_padded,
+                block,
+                db_rows_padded,
+                cols,
+            );
+            cols_done += cols;
+        },
+    )?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::client::{pack_query, YClient};
+    use crate::scheme::SEED_0;
+    use crate::util::test_params;
+    use spiral_rs::client::Client;
+
+    #[test]
+    fn test_answer_stream() {
+        let params = test_params();
+        let db_rows = 1 << (params.db_dim_1 + params.poly_len_log2);
+        let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+        let server = YServer::<u8>::new(
+            &params,
+            (0..db_rows * db_cols).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+
+        // two requests back to back on the same "pipe"
+        let mut input = Vec::new();
+        for _ in 0..2 {
+            write_frame(
+                &mut input,
+                &u64s_to_bytes(packed.as_slice(), Endianness::Little),
+            )
+            .unwrap();
+        }
+        let mut input = io::Cursor::new(input);
+        let mut output = Vec::new();
+        answer_stream(&server, &mut input, &mut output, Endianness::Little).unwrap();
+        answer_stream(&server, &mut input, &mut output, Endianness::Little).unwrap();
+
+        let expected = u64s_to_bytes(
+            server.answer_query(packed.as_slice()).as_slice(),
+            Endianness::Little,
+        );
+        let mut output = io::Cursor::new(output);
+        assert_eq!(read_frame(&mut output).unwrap(), expected);
+        assert_eq!(read_frame(&mut output).unwrap(), expected);
+
+        // a truncated frame is an error, not a short read
+        let mut truncated = Vec::new();
+        write_frame(&mut truncated, &[0u8; 16]).unwrap();
+        truncated.truncate(12);
+        assert!(answer_stream(
+            &server,
+            &mut io::Cursor::new(truncated),
+            &mut Vec::new(),
+            Endianness::Little
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_fragment_query_roundtrip() {
+        use crate::db::{db_capacity, logical_to_physical};
+        use crate::testing::{
+            expected_item, fixture_client, fixture_db, fixture_server, plaintext_query,
+        };
+
+        let params = test_params();
+        let item_size = 64;
+        let num_items = db_capacity(&params, false, item_size);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let index = 1234;
+        let row = logical_to_physical(&params, false, item_size, index).unwrap();
+        let packed = plaintext_query(&params, server.db_rows_padded(), row);
+        let bytes = u64s_to_bytes(packed.as_slice(), Endianness::Little);
+
+        let mut fragments = fragment(&bytes, 1500).unwrap();
+        assert!(fragments.len() > 1);
+        assert!(fragments.iter().all(|f| f.len() <= 1500));
+        fragments.reverse(); // arrival order doesn't matter
+        let rebuilt = reassemble(&fragments).unwrap();
+        assert_eq!(rebuilt, bytes);
+
+        let words = bytes_to_u64s(&rebuilt, Endianness::Little).unwrap();
+        let response = server.answer_query(&words);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        let start = index * item_size % server.db_cols();
+        let (coeffs, _) =
+            y_client.decode_response_range(response.as_slice(), start..start + item_size);
+        let item = coeffs.iter().map(|&x| x as u8).collect::<Vec<_>>();
+        assert_eq!(item, expected_item(index, item_size));
+
+        fragments.remove(0);
+        assert_eq!(
+            reassemble(&fragments),
+            Err(FragmentError::Missing {
+                index: fragments.len()
+            })
+        );
+        fragments.push(fragments[0].clone());
+        assert!(matches!(
+            reassemble(&fragments),
+            Err(FragmentError::Duplicate { .. })
+        ));
+        assert_eq!(
+            fragment(&bytes, FRAGMENT_HEADER_BYTES),
+            Err(FragmentError::FragmentTooSmall {
+                max_fragment: FRAGMENT_HEADER_BYTES
+            })
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_roundtrip() {
+        let data = (0..1000).map(|_| fastrand::u8(..)).collect::<Vec<_>>();
+        for width in [4, 8] {
+            let blob = length_prefixed(&data, width).unwrap();
+            assert_eq!(blob.len(), width + data.len());
+            let mut len_bytes = [0u8; 8];
+            len_bytes[8 - width..].copy_from_slice(&blob[..width]);
+            assert_eq!(u64::from_be_bytes(len_bytes), data.len() as u64);
+            assert_eq!(strip_length_prefix(&blob, width).unwrap(), data);
+
+            assert_eq!(
+                strip_length_prefix(&blob[..blob.len() - 1], width),
+                Err(LengthPrefixError::Mismatch {
+                    declared: data.len() as u64,
+                    actual: data.len() - 1
+                })
+            );
+            assert!(strip_length_prefix(&blob[..width - 1], width).is_err());
+        }
+        assert_eq!(
+            length_prefixed(&data, 2),
+            Err(LengthPrefixError::Width { width: 2 })
+        );
+    }
+
+    #[test]
+    fn test_query_digest_randomized() {
+        let params = test_params();
+        let mut client = Client::init(&params);
+        client.generate_secret_keys();
+        let y_client = YClient::new(&mut client, &params);
+
+        let target_row = 5;
+        let q1 = pack_query(
+            &params,
+            &y_client.generate_query(SEED_0, params.db_dim_1, false, target_row),
+        );
+        let q2 = pack_query(
+            &params,
+            &y_client.generate_query(SEED_0, params.db_dim_1, false, target_row),
+        );
+
+        assert_eq!(query_digest(q1.as_slice()), query_digest(q1.as_slice()));
+        assert_ne!(query_digest(q1.as_slice()), query_digest(q2.as_slice()));
+    }
+
+    #[test]
+    fn test_answer_with_provider() {
+        use crate::db::db_capacity;
+        use crate::testing::{
+            expected_item, fetch_item_with, fixture_client, fixture_db, fixture_server,
+            plaintext_query,
+        };
+
+        let params = test_params();
+        let (_, db_cols) = db_dims(&params, false);
+        let db_rows_padded = params.db_rows_padded();
+        let item_size = 64;
+        let num_items = db_capacity(&params, false, item_size);
+        let row_major = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &row_major);
+        let column = |col: usize| {
+            let mut column = row_major[col..]
+                .iter()
+                .step_by(db_cols)
+                .copied()
+                .collect::<Vec<_>>();
+            column.resize(db_rows_padded, 0);
+            column
+        };
+
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        let index = 3 * db_cols / item_size + 5;
+        let mut requested = Vec::new();
+        let item = fetch_item_with(
+            &params,
+            false,
+            &y_client,
+            db_rows_padded,
+            item_size,
+            index,
+            |q| {
+                let response = answer_with_provider(&params, false, q, |col| {
+                    requested.push(col);
+                    Ok(column(col))
+                })
+                .unwrap();
+                assert_eq!(response.as_slice(), server.answer_query(q).as_slice());
+                response.as_slice().to_vec()
+            },
+        );
+        assert_eq!(item, expected_item(index, item_size));
+        assert_eq!(requested, (0..db_cols).collect::<Vec<_>>());
+
+        let packed = plaintext_query(&params, db_rows_padded, 0);
+        let err = answer_with_provider(&params, false, packed.as_slice(), |_| Ok(vec![0; 3]));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_verify_response_for_query() {
+        use crate::testing::{
+            expected_item, fetch_item_with, fixture_client, fixture_db, fixture_server,
+            plaintext_query,
+        };
+
+        let params = test_params();
+        let (item_size, num_items) = (64, 1000);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let rows = server.db_rows_padded();
+
+        let queries = [3, 7].map(|row| plaintext_query(&params, rows, row));
+        let tagged = queries
+            .iter()
+            .map(|q| tag_response(q.as_slice(), server.answer_query(q.as_slice()).as_slice()))
+            .collect::<Vec<_>>();
+
+        for (i, query) in queries.iter().enumerate() {
+            assert!(verify_response_for_query(query.as_slice(), &tagged[i]));
+            assert!(!verify_response_for_query(query.as_slice(), &tagged[1 - i]));
+        }
+        assert_eq!(
+            strip_response_tag(&tagged[0]),
+            server.answer_query(queries[0].as_slice()).as_slice()
+        );
+
+        let query = queries[0].as_slice();
+        let mut corrupted = tagged[0].clone();
+        corrupted[0] ^= 1;
+        assert!(!verify_response_for_query(query, &corrupted));
+        assert!(!verify_response_for_query(query, &[]));
+
+        // a verified response decodes once the tag is stripped
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        let index = 100;
+        let item = fetch_item_with(&params, false, &y_client, rows, item_size, index, |q| {
+            let tagged = tag_response(q, server.answer_query(q).as_slice());
+            assert!(verify_response_for_query(q, &tagged));
+            strip_response_tag(&tagged).to_vec()
+        });
+        assert_eq!(item, expected_item(index, item_size));
+    }
+
+    #[test]
+    fn test_transcript_replay() {
+        use crate::testing::{expected_item, fixture_client, fixture_db, fixture_server};
+
+        let params = test_params();
+        let (item_size, num_items) = (64, 1000);
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+
+        // item 96 starts row 3
+        let (row, index) = (3, 96);
+        let query = pack_query(
+            &params,
+            &y_client.generate_query(SEED_0, params.db_dim_1, true, row),
+        );
+        let response = server.answer_query(query.as_slice());
+
+        let transcript = Transcript {
+            fingerprint: crate::params::params_fingerprint(&params, false, 8),
+            query: u64s_to_bytes(query.as_slice(), Endianness::Little),
+            response: u64s_to_bytes(response.as_slice(), Endianness::Little),
+        };
+        let bytes = transcript.to_bytes();
+        let replayed = Transcript::from_bytes(&bytes).unwrap();
+        assert_eq!(replayed, transcript);
+
+        // answering the recorded query again reproduces the recorded answer
+        let replayed_query = bytes_to_u64s(&replayed.query, Endianness::Little).unwrap();
+        let recorded = bytes_to_u64s(&replayed.response, Endianness::Little).unwrap();
+        let answer = server.answer_query(&replayed_query);
+        assert_eq!(answer.as_slice(), &recorded[..]);
+
+        let (coeffs, _) = y_client.decode_response_range(&recorded, 0..item_size);
+        let item = coeffs.iter().map(|&x| x as u8).collect::<Vec<_>>();
+        assert_eq!(item, expected_item(index, item_size));
+
+        assert!(Transcript::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Transcript::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+        assert!(Transcript::from_bytes(&bytes[1..]).is_err());
+    }
+
+    #[test]
+    fn test_answer_column_blocks() {
+        let params = test_params();
+        let row_major = (0..crate::db::db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let server = YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let expected = server.answer_query(packed.as_slice());
+
+        // 2048 columns: a block size that doesn't divide them leaves a short last block
+        for cols_per_block in [1, 300, 2048] {
+            let response = answer_column_blocks(
+                &params,
+                false,
+                packed.as_slice(),
+                io::Cursor::new(server.db()),
+                cols_per_block,
+            )
+            .unwrap();
+            assert_eq!(response.as_slice(), expected.as_slice());
+        }
+
+        // a truncated database is an error
+        let short = &server.db()[..server.db().len() - 1];
+        assert!(
+            answer_column_blocks(&params, false, packed.as_slice(), short, 300).is_err()
+        );
+    }
+
+    #[test]
+    fn test_file_locking() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let params = test_params();
+        let item_size = 64;
+        let server = YServer::<u8>::new(
+            &params,
+            (0..crate::db::db_num_bytes(&params, false)).map(|_| fastrand::u8(..)),
+            false,
+            false,
+            true,
+        );
+        let path = std::env::temp_dir().join(format!("ypir_locked_{}", std::process::id()));
+        std::fs::write(&path, server.db()).unwrap();
+        let open = || File::options().read(true).write(true).open(&path).unwrap();
+        let (a, b) = (open(), open());
+
+        // a write lock on one handle blocks writers on the other
+        FileExt::lock_exclusive(&a).unwrap();
+        assert!(FileExt::try_lock_exclusive(&b).is_err());
+        assert!(FileExt::try_lock_shared(&b).is_err());
+        FileExt::unlock(&a).unwrap();
+        FileExt::try_lock_exclusive(&b).unwrap();
+        FileExt::unlock(&b).unwrap();
+
+        // readers see the item wholly old or wholly new
+        let index = 5;
+        let (old, new) = (vec![0x11u8; item_size], vec![0xeeu8; item_size]);
+        update_item_in_file(&params, false, item_size, &a, index, &old).unwrap();
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..50 {
+                    let item = if i % 2 == 0 { &new } else { &old };
+                    update_item_in_file(&params, false, item_size, &a, index, item).unwrap();
+                }
+                done.store(true, Ordering::Release);
+            });
+            while !done.load(Ordering::Acquire) {
+                let item = read_item_from_file(&params, false, item_size, &b, index).unwrap();
+                assert!(item == old || item == new);
+            }
+        });
+        assert_eq!(
+            read_item_from_file(&params, false, item_size, &b, index).unwrap(),
+            old
+        );
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let mut updated = server;
+        updated.update_item(index, item_size, &old);
+        let response = answer_file(&params, false, packed.as_slice(), &b, 300).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            response.as_slice(),
+            updated.answer_query(packed.as_slice()).as_slice()
+        );
+    }
+
+    // toy keystream that depends on the offset, like a real CTR cipher
+    struct XorOffset(u8);
+
+    impl DecryptingReader for XorOffset {
+        fn decrypt_block(&self, offset: u64, block: &mut [u8]) {
+            for (i, b) in block.iter_mut().enumerate() {
+                *b ^= self.0 ^ (offset + i as u64).wrapping_mul(31) as u8;
+            }
+        }
+    }
+
+    fn check_encrypted_answer<D: DecryptingReader>(decryptor: &D) {
+        let params = test_params();
+        let row_major = (0..crate::db::db_num_bytes(&params, false))
+            .map(|_| fastrand::u8(..))
+            .collect::<Vec<_>>();
+        let server = YServer::<u8>::new(&params, row_major.iter().copied(), false, false, true);
+
+        // what goes on disk
+        let mut encrypted = server.db().to_vec();
+        decryptor.decrypt_block(0, &mut encrypted);
+        assert_ne!(encrypted, server.db());
+
+        let query = (0..params.db_rows_padded())
+            .map(|_| fastrand::u64(0..params.modulus))
+            .collect::<Vec<_>>();
+        let packed = pack_query(&params, &query);
+        let expected = server.answer_query(packed.as_slice());
+        let response = answer_encrypted_column_blocks(
+            &params,
+            false,
+            packed.as_slice(),
+            io::Cursor::new(encrypted),
+            decryptor,
+            300,
+        )
+        .unwrap();
+        assert_eq!(response.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn test_answer_encrypted_column_blocks() {
+        check_encrypted_answer(&XorOffset(0x5a));
+        #[cfg(feature = "aes-ctr")]
+        check_encrypted_answer(&AesCtrReader::new([7u8; 32], [9u8; 16]));
+    }
+
+    #[test]
+    fn test_prefetch_blocks_overlaps_io() {
+        let blocks = 10;
+
+        // each read reports its start; processing block k waits until the
+        // read after it has started, which only a reader running ahead of
+        // the processing can satisfy (a serial loop would time out here)
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let mut read_count = 0;
+        let mut latest_started = 0;
+        let mut processed = Vec::new();
+        prefetch_blocks(
+            |buf| {
+                started_tx.send(read_count).unwrap();
+                if read_count == blocks {
+                    return Ok(false);
+                }
+                buf.clear();
+                buf.push(read_count as u8);
+                read_count += 1;
+                Ok(true)
+            },
+            |block| {
+                let k = block[0] as usize;
+                while latest_started <= k {
+                    latest_started = started_rx
+                        .recv_timeout(Duration::from_secs(30))
+                        .unwrap_or_else(|_| panic!("no read started while block {} ran", k));
+                }
+                processed.push(block[0]);
+            },
+        )
+        .unwrap();
+
+        assert_eq!(processed, (0..blocks as u8).collect::<Vec<_>>());
+        // the final read, which found no more blocks, overlapped the last one
+        assert_eq!(latest_started, blocks);
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::Instant;
+
+use spiral_rs::aligned_memory::AlignedMemory64;
+use spiral_rs::client::Client;
+use spiral_rs::params::Params;
+
+use crate::client::{pack_query, YClient};
+use crate::db::{build_db, db_capacity, logical_to_physical, span_row_ranges, BuildDbError};
+use crate::measurement::{Percentiles, RoundtripLatency};
+use crate::scheme::SEED_0;
+use crate::server::YServer;
+
+/// Contents of item `index` in a fixture database: the little-endian bytes
+/// of `seed + index`, repeated to `item_size` bytes. With seed 0, item `i`
+/// is just `i` repeated.
+pub fn fixture_item(seed: u64, index: usize, item_size: usize) -> Vec<u8> {
+    let word = seed.wrapping_add(index as u64).to_le_bytes();
+    word.iter().copied().cycle().take(item_size).collect()
+}
+
+/// The oracle for golden tests: item `index` of a seed-0 fixture database.
+pub fn expected_item(index: usize, item_size: usize) -> Vec<u8> {
+    fixture_item(0, index, item_size)
+}
+
+/// A deterministic row-major database of `num_items` fixture items (see
+/// `fixture_item`), for tests that need known contents without building
+/// them by hand.
+pub fn fixture_db(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    num_items: usize,
+    seed: u64,
+) -> Result<Vec<u8>, BuildDbError> {
+    let items = (0..num_items)
+        .map(|i| fixture_item(seed, i, item_size))
+        .collect::<Vec<_>>();
+    build_db(
+        params,
+        is_simplepir,
+        item_size,
+        items.iter().map(|x| x.as_slice()),
+    )
+}
+
+/// A u8 server over the row-major database `db`, with padded rows.
+pub fn fixture_server<'a>(params: &'a Params, is_simplepir: bool, db: &[u8]) -> YServer<'a, u8> {
+    YServer::<u8>::new(params, db.iter().copied(), is_simplepir, false, true)
+}
+
+/// A client for `params` with freshly generated secret keys; wrap it in a
+/// `YClient` to decode.
+pub fn fixture_client(params: &Params) -> Client<'_> {
+    let mut client = Client::init(params);
+    client.generate_secret_keys();
+    client
+}
+
+/// A noiseless packed query for database row `row`: the one-hot selection
+/// vector scaled by `modulus / pt_modulus`, which an encrypted query
+/// decrypts to. Its answer decodes exactly, so tests can compare bytes.
+/// `rows` is the query length (`server.db_rows_padded()`).
+pub fn plaintext_query(params: &Params, rows: usize, row: usize) -> AlignedMemory64 {
+    let mut query = vec![0u64; rows];
+    query[row] = params.modulus / params.pt_modulus;
+    pack_query(params, &query)
+}
+
+/// Item `index` fetched with `plaintext_query`s, one per row it touches,
+/// each answered by `answer` and decoded by `client`.
+pub fn fetch_item_with(
+    params: &Params,
+    is_simplepir: bool,
+    client: &YClient,
+    rows: usize,
+    item_size: usize,
+    index: usize,
+    mut answer: impl FnMut(&[u64]) -> Vec<u64>,
+) -> Vec<u8> {
+    let spans = span_row_ranges(params, is_simplepir, item_size, index, 1).unwrap();
+    let mut item = Vec::with_capacity(item_size);
+    for (row, cols) in spans {
+        let response = answer(plaintext_query(params, rows, row).as_slice());
+        let (coeffs, _) = client.decode_response_range(&response, cols);
+        item.extend(coeffs.iter().map(|&x| x as u8));
+    }
+    item
+}
+
+/// `fetch_item_with` answering on `server` with `answer_query`.
+pub fn fetch_item(
+    params: &Params,
+    is_simplepir: bool,
+    server: &YServer<u8>,
+    client: &YClient,
+    item_size: usize,
+    index: usize,
+) -> Vec<u8> {
+    let rows = server.db_rows_padded();
+    fetch_item_with(params, is_simplepir, client, rows, item_size, index, |q| {
+        server.answer_query(q).as_slice().to_vec()
+    })
+}
+
+/// Times `num_iters` full round trips (packed query generation, answer, and
+/// extraction of the item's columns) against a fixture database for
+/// `params`, cycling through its items, and reports per-phase percentiles.
+/// The database and client keys are set up before timing starts.
+pub fn benchmark_roundtrip(
+    params: &Params,
+    is_simplepir: bool,
+    item_size: usize,
+    num_iters: usize,
+) -> Result<RoundtripLatency, BuildDbError> {
+    assert!(num_iters > 0, "num_iters must be > 0");
+    let num_items = db_capacity(params, is_simplepir, item_size).min(1024);
+    let db = fixture_db(params, is_simplepir, item_size, num_items, 0)?;
+    let server = fixture_server(params, is_simplepir, &db);
+    let mut client = fixture_client(params);
+    let y_client = YClient::new(&mut client, params);
+    let db_cols = server.db_cols();
+
+    let elapsed_ms = |start: Instant| start.elapsed().as_secs_f64() * 1000.;
+    let (mut query_ms, mut answer_ms, mut extract_ms) = (vec![], vec![], vec![]);
+    for i in 0..num_iters {
+        let index = i % num_items;
+        let row = logical_to_physical(params, is_simplepir, item_size, index).unwrap();
+
+        let start = Instant::now();
+        let query = y_client.generate_query(SEED_0, params.db_dim_1, true, row);
+        let packed = pack_query(params, &query);
+        query_ms.push(elapsed_ms(start));
+
+        let start = Instant::now();
+        let response = server.answer_query(packed.as_slice());
+        answer_ms.push(elapsed_ms(start));
+
+        let start = Instant::now();
+        let col = index * item_size % db_cols;
+        let cols = col..(col + item_size).min(db_cols);
+        std::hint::black_box(y_client.decode_response_range(response.as_slice(), cols));
+        extract_ms.push(elapsed_ms(start));
+    }
+
+    Ok(RoundtripLatency {
+        query: Percentiles::from_samples_ms(&query_ms),
+        answer: Percentiles::from_samples_ms(&answer_ms),
+        extract: Percentiles::from_samples_ms(&extract_ms),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_params;
+
+    #[test]
+    fn test_fixture_db_reproducible() {
+        let params = test_params();
+        let (item_size, num_items) = (64, 10_000);
+        let db = fixture_db(&params, false, item_size, num_items, 5).unwrap();
+        assert_eq!(db, fixture_db(&params, false, item_size, num_items, 5).unwrap());
+        assert_ne!(db, fixture_db(&params, false, item_size, num_items, 6).unwrap());
+        assert_eq!(fixture_item(0, 3, 4), vec![3, 0, 0, 0]);
+
+        let server = fixture_server(&params, false, &db);
+        let mut client = fixture_client(&params);
+        let y_client = YClient::new(&mut client, &params);
+        for index in [0, 1, 4321, num_items - 1] {
+            let item = fetch_item(&params, false, &server, &y_client, item_size, index);
+            assert_eq!(item, fixture_item(5, index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_fixture_get_item_matches_expected() {
+        let params = test_params();
+        let (item_size, num_items) = (100, 20_000); // items straddle rows
+        let db = fixture_db(&params, false, item_size, num_items, 0).unwrap();
+        let server = fixture_server(&params, false, &db);
+        for index in [0, 1, 20, 12_345, num_items - 1] {
+            assert_eq!(server.get_item(index, item_size), expected_item(index, item_size));
+        }
+    }
+
+    #[test]
+    fn test_benchmark_roundtrip_percentiles() {
+        let latency = benchmark_roundtrip(&test_params(), false, 64, 8).unwrap();
+        for phase in [latency.query, latency.answer, latency.extract] {
+            assert!(phase.p50_ms > 0., "{:?}", latency);
+            assert!(phase.p50_ms <= phase.p95_ms && phase.p95_ms <= phase.p99_ms);
+        }
+
+        let p = Percentiles::from_samples_ms(&(1..=100).map(|x| x as f64).collect::<Vec<_>>());
+        assert_eq!((p.p50_ms, p.p95_ms, p.p99_ms), (50., 95., 99.));
+    }
+}
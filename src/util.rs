@@ -159,6 +159,27 @@ pub fn test_params() -> Params {
     params
 }
 
+/// `test_params` with its two CRT factors listed in the opposite order.
+pub fn test_params_reordered_moduli() -> Params {
+    crate::params::ext_params_from_json(
+        r#"{
+        "n": 1,
+        "nu_1": 0,
+        "nu_2": 0,
+        "p": 256,
+        "q2_bits": 22,
+        "t_gsw": 3,
+        "t_conv": 2,
+        "t_exp_left": 2,
+        "t_exp_right": 2,
+        "instances": 1,
+        "db_item_size": 0,
+        "version": 2,
+        "moduli": ["249561089", "268369921"]
+    }"#,
+    )
+}
+
 pub fn multiply_matrices_raw_not_transposed<T>(
     params: &Params,
     a: &[u64],
@@ -0,0 +1,68 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ypir::client::pack_query;
+use ypir::db::db_num_bytes;
+use ypir::server::{DbRowsPadded, ServerMemory, YServer};
+use ypir::util::test_params;
+
+// counts live heap bytes and their high-water mark
+struct Counting;
+
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(live, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let live = LIVE.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(live, Ordering::SeqCst);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+#[test]
+fn server_memory_estimate() {
+    let params = test_params();
+    let num_threads = 4;
+    let estimate = ServerMemory::new(&params, false, true, 1, num_threads).total();
+
+    let base = LIVE.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+
+    let db = (0..db_num_bytes(&params, false))
+        .map(|_| fastrand::u8(..))
+        .collect::<Vec<_>>();
+    let server = YServer::<u8>::new(&params, db.iter().copied(), false, false, true);
+    let query = (0..params.db_rows_padded())
+        .map(|_| fastrand::u64(0..params.modulus))
+        .collect::<Vec<_>>();
+    let packed = pack_query(&params, &query);
+    std::thread::scope(|s| {
+        for _ in 0..num_threads {
+            s.spawn(|| server.answer_query(packed.as_slice()));
+        }
+    });
+    drop(db);
+
+    let measured = PEAK.load(Ordering::SeqCst) - base;
+    assert!(
+        estimate >= measured / 2 && estimate <= measured * 2,
+        "estimated {} bytes, measured {}",
+        estimate,
+        measured
+    );
+}
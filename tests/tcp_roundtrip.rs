@@ -0,0 +1,90 @@
+#![cfg(feature = "net")]
+
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+use ypir::bits::{bytes_to_u64s, u64s_to_bytes, Endianness};
+use ypir::db::db_num_bytes;
+use ypir::params::params_for_scenario;
+use ypir::server::YServer;
+use ypir::stream::{read_frame, write_frame};
+
+const NUM_ITEMS: usize = 2048 * 2048;
+const ITEM_SIZE_BITS: usize = 8;
+
+#[test]
+fn tcp_roundtrip() {
+    let params = params_for_scenario(NUM_ITEMS, ITEM_SIZE_BITS);
+    let dir = std::env::temp_dir().join(format!("ypir_tcp_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("db.bin");
+    let exchange_path = dir.join("exchange.bin");
+
+    let db = (0..db_num_bytes(&params, false))
+        .map(|_| fastrand::u8(..))
+        .collect::<Vec<_>>();
+    std::fs::write(&db_path, &db).unwrap();
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_server_tcp"))
+        .args([&NUM_ITEMS.to_string(), &ITEM_SIZE_BITS.to_string()])
+        .arg("--db")
+        .arg(&db_path)
+        .args(["--addr", "127.0.0.1:0", "--once"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(server.stdout.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    let addr = line
+        .trim()
+        .strip_prefix("listening on ")
+        .unwrap_or_else(|| panic!("unexpected server output: {:?}", line))
+        .to_owned();
+
+    // a query of the wrong length closes that connection, not the server
+    let mut bad = TcpStream::connect(&addr).unwrap();
+    write_frame(&mut bad, &[0u8; 8]).unwrap();
+    let mut rest = Vec::new();
+    bad.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    let row = 7;
+    let client = Command::new(env!("CARGO_BIN_EXE_client_tcp"))
+        .args([&NUM_ITEMS.to_string(), &ITEM_SIZE_BITS.to_string()])
+        .args(["--addr", &addr, "--row", &row.to_string(), "--exchange-out"])
+        .arg(&exchange_path)
+        .output()
+        .unwrap();
+    assert!(client.status.success(), "client failed: {:?}", client);
+    assert!(server.wait().unwrap().success());
+
+    // the response on the wire is exactly what an in-process server returns
+    let mut exchange = Cursor::new(std::fs::read(&exchange_path).unwrap());
+    let query = read_frame(&mut exchange).unwrap();
+    let response = read_frame(&mut exchange).unwrap();
+    let local = YServer::<u8>::new(&params, db.iter().copied(), false, false, true);
+    let expected = local.answer_query(&bytes_to_u64s(&query, Endianness::Little).unwrap());
+    assert_eq!(
+        response,
+        u64s_to_bytes(expected.as_slice(), Endianness::Little)
+    );
+
+    // and the client decoded it to the row's bytes
+    let stdout = String::from_utf8(client.stdout).unwrap();
+    let db_cols = 1 << (params.db_dim_2 + params.poly_len_log2);
+    assert!(stdout.contains(&format!("received {} words", db_cols)));
+    let row_hex = db[row * db_cols..(row + 1) * db_cols]
+        .iter()
+        .map(|x| format!("{:02x}", x))
+        .collect::<String>();
+    assert!(
+        stdout.contains(&format!("row {}: {}\n", row, row_hex)),
+        "{}",
+        stdout
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}